@@ -0,0 +1,90 @@
+//! Compares stepping through a loop-heavy program, which takes the fast
+//! path for the hot RV32I subset, against executing the same program
+//! through the general decode path.
+//!
+//! Run with `cargo bench`.
+
+use std::time::{Duration, Instant};
+
+use riscv_emulator::asm::assemble;
+use riscv_emulator::bus::Bus;
+use riscv_emulator::memory::Memory;
+use riscv_emulator::processor::{Processor, StepResult};
+use riscv_emulator::trap::Trap;
+
+/// The number of times the loop body runs.
+const ITERATIONS: u32 = 1_000_000;
+
+/// The number of times each path is timed, keeping the fastest.
+const RUNS: usize = 5;
+
+/// Sums a word of memory into another, `ITERATIONS` times.
+const PROGRAM: &str = "
+    lui x1, 0xf4             # x1 = 1,000,000
+    addi x1, x1, 0x240
+    addi x5, x0, 7
+    sw x5, 0x200(x0)
+    lw x3, 0x200(x0)         # loop:
+    add x4, x4, x3
+    sw x4, 0x204(x0)
+    addi x1, x1, -1
+    bne x1, x0, -16          # bne x1, x0, loop
+    ebreak
+";
+
+fn setup() -> (Processor, Bus) {
+    let mut mem = Memory::new(1024);
+
+    for (i, instr) in assemble(PROGRAM).unwrap().iter().enumerate() {
+        mem.write(i * 4, &instr.to_le_bytes());
+    }
+
+    (Processor::new(), Bus::new(mem))
+}
+
+fn fast_path() -> Bus {
+    let (mut proc, mut bus) = setup();
+
+    while let StepResult::Continued = proc.step(&mut bus) {}
+
+    bus
+}
+
+fn general_path() -> Bus {
+    let (mut proc, mut bus) = setup();
+
+    loop {
+        let instr = proc.fetch(&mut bus).unwrap();
+
+        match proc.execute(&instr, &mut bus) {
+            Ok(()) => (),
+            Err(Trap::Breakpoint(_)) => break,
+            Err(trap) => panic!("unexpected trap: {trap}"),
+        }
+    }
+
+    bus
+}
+
+fn time(run: fn() -> Bus) -> Duration {
+    (0 .. RUNS)
+        .map(|_| {
+            let start = Instant::now();
+            let mut bus = run();
+            let elapsed = start.elapsed();
+
+            assert_eq!(bus.read(0x204, 4) as u32, ITERATIONS.wrapping_mul(7));
+            elapsed
+        })
+        .min()
+        .unwrap()
+}
+
+fn main() {
+    let fast = time(fast_path);
+    let general = time(general_path);
+
+    println!("fast path:    {fast:?}");
+    println!("general path: {general:?}");
+    println!("speedup:      {:.2}x", general.as_secs_f64() / fast.as_secs_f64());
+}
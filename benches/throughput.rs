@@ -0,0 +1,129 @@
+//! Measures decode and execute throughput, to catch performance
+//! regressions as features land:
+//!
+//! - `decode`: decoding a large buffer of mixed instructions.
+//! - `arithmetic_loop`: steps per second through a tight ALU loop.
+//! - `memory_loop`: steps per second through a load/store-heavy loop.
+//!
+//! Run with `cargo bench --bench throughput`.
+
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, Criterion, Throughput};
+
+use riscv_emulator::asm::assemble;
+use riscv_emulator::decode::Decoder;
+use riscv_emulator::emulator::{Emulator, EmulatorConfig, HaltReason};
+use riscv_emulator::instruction::Instruction;
+
+/// The number of instructions in the decode buffer.
+const DECODE_COUNT: usize = 64 * 1024;
+
+/// The number of times the arithmetic loop body runs.
+const ARITHMETIC_ITERATIONS: u64 = 100_000;
+
+/// Mixes the result of every ALU operation into the next, counting `x1`
+/// down from `ARITHMETIC_ITERATIONS`.
+const ARITHMETIC_LOOP: &str = "
+    lui x1, 0x18             # x1 = 100,000
+    addi x1, x1, 0x6a0
+    add x2, x2, x1           # loop:
+    xor x3, x3, x2
+    slli x4, x3, 3
+    sub x5, x4, x2
+    mul x6, x5, x3
+    addi x1, x1, -1
+    bne x1, x0, -24          # bne x1, x0, loop
+    ebreak
+";
+
+/// The number of words in the memory loop's array.
+const MEMORY_WORDS: u64 = 4096;
+
+/// The number of passes the memory loop makes over its array.
+const MEMORY_PASSES: u64 = 10;
+
+/// Increments every word of a 16 KiB array at 0x1000, `MEMORY_PASSES`
+/// times.
+const MEMORY_LOOP: &str = "
+    addi x7, x0, 10          # x7 = passes
+    lui x1, 0x1              # outer: x1 = 0x1000
+    lui x2, 0x5              # x2 = 0x5000
+    lw x3, 0(x1)             # inner:
+    addi x3, x3, 1
+    sw x3, 0(x1)
+    addi x1, x1, 4
+    bne x1, x2, -16          # bne x1, x2, inner
+    addi x7, x7, -1
+    bne x7, x0, -32          # bne x7, x0, outer
+    ebreak
+";
+
+/// Returns an emulator with `program` loaded at address zero.
+fn emulator(program: &str) -> Emulator {
+    let mut emu = Emulator::build(EmulatorConfig {
+        mem_size: 0x8000,
+        ..EmulatorConfig::default()
+    });
+
+    for (i, instr) in assemble(program).unwrap().iter().enumerate() {
+        emu.load(i as u32 * 4, &instr.to_le_bytes());
+    }
+
+    emu
+}
+
+/// Benchmarks running `program` to its `ebreak`, which takes `steps`
+/// steps.
+fn bench_program(c: &mut Criterion, name: &str, program: &str, steps: u64) {
+    let mut group = c.benchmark_group(name);
+    group.throughput(Throughput::Elements(steps));
+
+    group.bench_function("run", |b| {
+        b.iter_batched(
+            || emulator(program),
+            |mut emu| assert_eq!(emu.run(steps), HaltReason::Ebreak),
+            BatchSize::LargeInput,
+        );
+    });
+
+    group.finish();
+}
+
+fn decode(c: &mut Criterion) {
+    let instrs: Vec<Instruction> = assemble(ARITHMETIC_LOOP)
+        .unwrap()
+        .into_iter()
+        .chain(assemble(MEMORY_LOOP).unwrap())
+        .cycle()
+        .take(DECODE_COUNT)
+        .map(Instruction::new)
+        .collect();
+
+    let mut group = c.benchmark_group("decode");
+    group.throughput(Throughput::Elements(DECODE_COUNT as u64));
+
+    group.bench_function("decode", |b| {
+        b.iter(|| {
+            for instr in &instrs {
+                black_box(Decoder::decode(black_box(instr)));
+            }
+        });
+    });
+
+    group.finish();
+}
+
+fn arithmetic_loop(c: &mut Criterion) {
+    // Two setup instructions, the loop body, and the ebreak.
+    let steps = 2 + 7 * ARITHMETIC_ITERATIONS + 1;
+    bench_program(c, "arithmetic_loop", ARITHMETIC_LOOP, steps);
+}
+
+fn memory_loop(c: &mut Criterion) {
+    // The pass counter, each pass's setup, inner loop and counter, and
+    // the ebreak.
+    let steps = 1 + MEMORY_PASSES * (2 + 5 * MEMORY_WORDS + 2) + 1;
+    bench_program(c, "memory_loop", MEMORY_LOOP, steps);
+}
+
+criterion_group!(benches, decode, arithmetic_loop, memory_loop);
+criterion_main!(benches);
@@ -1,40 +1,397 @@
-use std::{fs::File, io::{Error, Read}, process};
+use std::{fs::{self, File}, io::{self, BufRead, Error, Read, Write}, process};
 
-use clap::Parser;
+use clap::{Parser, Subcommand};
 
+use riscv_emulator::asm;
+use riscv_emulator::decode::Decoder;
+use riscv_emulator::disasm;
+use riscv_emulator::elf::ElfError;
 use riscv_emulator::emulator::{
+    ElfLoadError,
     Emulator,
     EmulatorConfig,
+    HaltReason,
 };
+use riscv_emulator::instruction::Instruction;
+use riscv_emulator::syscall::LinuxSyscalls;
+use riscv_emulator::trace::{self, TraceReader};
+use riscv_emulator::test_finisher::{
+    TestFinisher,
+    TEST_FINISHER_BASE,
+    TEST_FINISHER_SIZE,
+};
+use riscv_emulator::uart::{stdout_output, Uart, UART_BASE, UART_SIZE};
 
 #[derive(Debug, Parser)]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
 struct Args {
-    /// The RISC-V ELF binary to execute
-    #[arg()]
-    input_file: String,
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// The RISC-V ELF executable to run, or a raw binary to load at
+    /// address zero
+    #[arg(required = true)]
+    input_file: Option<String>,
 
     /// The size of the emulator's memory in bytes
     #[arg(short, long, default_value_t = 1024)]
     memory_size: usize,
+
+    /// The maximum number of instructions to execute before halting
+    #[arg(long, default_value_t = 10_000_000)]
+    max_steps: u64,
+
+    /// Write a binary trace of every step to a file
+    #[arg(long)]
+    trace: Option<String>,
+
+    /// Set a register of the first hart before execution, by ABI name or
+    /// number, e.g. `--reg a0=5 --reg x11=0x10`
+    #[arg(long = "reg", value_name = "REG=VALUE", value_parser = parse_preset)]
+    registers: Vec<(usize, u32)>,
+
+    /// After the run, write how many times each instruction was executed
+    /// to a file as CSV, most frequent first
+    #[arg(long, value_name = "FILE")]
+    profile_csv: Option<String>,
+
+    /// Step through the program interactively: show the instruction at
+    /// `pc`, then press enter to execute it, `r` to run to completion or
+    /// `q` to quit
+    #[arg(long)]
+    step: bool,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Assemble instructions into a little-endian binary
+    Encode {
+        /// The assembly to encode, one instruction per line
+        #[arg(required_unless_present = "input")]
+        source: Option<String>,
+
+        /// Read the assembly from a file instead
+        #[arg(short, long, conflicts_with = "source")]
+        input: Option<String>,
+
+        /// Write the binary to a file instead of stdout
+        #[arg(short, long)]
+        output: Option<String>,
+    },
+
+    /// Print a binary trace written with `--trace` as text
+    Trace {
+        /// The trace file to read
+        file: String,
+    },
 }
 
 fn main() {
     let args = Args::parse();
 
+    match args.command {
+        Some(Command::Encode { source, input, output }) => {
+            encode(source, input, output);
+            return;
+        },
+
+        Some(Command::Trace { file }) => {
+            print_trace(&file);
+            return;
+        },
+
+        None => (),
+    }
+
+    let input_file = args.input_file.expect("input file is required");
+
     let config = EmulatorConfig { 
         mem_size: args.memory_size,
-        proc_count: 1,
+        ..EmulatorConfig::default()
+    };
+
+    let mut emu = Emulator::try_build(config)
+        .unwrap_or_else(|err| {
+            eprintln!("Error: {err}");
+            process::exit(1);
+        });
+
+    // Files that aren't ELF executables are run as raw binaries.
+    let raw = match emu.load_elf_from_path(&input_file) {
+        Ok(_) => None,
+
+        Err(ElfLoadError::Elf(ElfError::NotElf)) => {
+            let data = dev_read_input_file(&input_file).unwrap_or_else(|err| {
+                eprintln!("Error: {err}");
+                process::exit(1);
+            });
+
+            Some(data)
+        },
+
+        Err(err) => {
+            eprintln!("Error: {input_file}: {err}");
+            process::exit(1);
+        },
+    };
+
+    // The heap starts after the program, aligned to 16 bytes.
+    let program_end = match &raw {
+        Some(data) => data.len() as u32,
+        None => emu.loaded_end(),
+    };
+
+    let heap_start = program_end.next_multiple_of(16);
+    emu.set_syscall_handler(Box::new(LinuxSyscalls::stdio(heap_start)));
+    emu.bus.register(TEST_FINISHER_BASE, TEST_FINISHER_SIZE, Box::new(TestFinisher::new()));
+
+    // In step mode, stdin takes commands, so the guest gets no input.
+    let uart = match args.step {
+        true => Uart::new(Box::new(|| None), stdout_output()),
+        false => Uart::stdio(),
     };
 
-    let mut emu = Emulator::build(config);
+    emu.bus.register(UART_BASE, UART_SIZE, Box::new(uart));
 
-    let data = dev_read_input_file(&args.input_file)
+    if let Some(path) = args.trace {
+        let file = File::create(&path).unwrap_or_else(|err| {
+            eprintln!("Error: {err}");
+            process::exit(1);
+        });
+
+        emu.hart_mut(0).enable_trace(trace::file_sink(file));
+    }
+
+    // Loading the program doesn't touch the registers, so the presets
+    // are still set when it starts.
+    apply_presets(&mut emu, &args.registers);
+
+    if args.profile_csv.is_some() {
+        emu.hart_mut(0).enable_profile();
+    }
+
+    let reason = match &raw {
+        Some(data) if args.step => {
+            emu.load(0x00, data);
+            step_or_quit(&mut emu, args.max_steps)
+        },
+
+        None if args.step => step_or_quit(&mut emu, args.max_steps),
+
+        Some(data) => emu.dev_start(data, args.max_steps),
+
+        None => {
+            let reason = emu.run(args.max_steps);
+            println!("{}", emu.hart(0));
+            reason
+        },
+    };
+
+    if let Some(path) = args.profile_csv {
+        if let Err(err) = write_profile(&emu, &path) {
+            eprintln!("Error: {err}");
+            process::exit(1);
+        }
+    }
+
+    match reason {
+        HaltReason::StepLimit => {
+            eprintln!(
+                "Execution halted: step limit of {} reached",
+                args.max_steps,
+            );
+        },
+
+        HaltReason::UnhandledTrap(trap) => {
+            eprintln!("Execution halted: unhandled trap: {trap}");
+        },
+
+        HaltReason::Ebreak => {
+            eprintln!("Execution halted: ebreak");
+        },
+
+        HaltReason::Breakpoint => {
+            eprintln!("Execution halted: breakpoint");
+        },
+
+        HaltReason::Exit(code) => {
+            process::exit(code as i32);
+        },
+
+        HaltReason::OutOfFuel => {
+            eprintln!("Execution halted: out of fuel");
+        },
+
+        HaltReason::Reset => {
+            eprintln!("Execution halted: reset requested");
+        },
+
+        HaltReason::Timeout => {
+            eprintln!("Execution halted: timed out");
+        },
+    }
+}
+
+/// Steps through the program interactively on stdin and stdout, then
+/// prints the first hart. Exits if the user quits.
+fn step_or_quit(emu: &mut Emulator, max_steps: u64) -> HaltReason {
+    let reason = step_interactively(emu, max_steps, io::stdin().lock(), io::stdout())
         .unwrap_or_else(|err| {
             eprintln!("Error: {err}");
             process::exit(1);
         });
 
-    emu.dev_start(&data);
+    println!("{}", emu.hart(0));
+
+    reason.unwrap_or_else(|| process::exit(0))
+}
+
+/// Runs up to `max_steps` steps, one at a time. Before each step, shows
+/// the instruction at the first hart's `pc` and reads a command: an empty
+/// line executes the step and shows the registers it changed, `r` runs the
+/// remaining steps without stopping, and `q` quits. The end of the input
+/// also quits.
+///
+/// Returns why the run halted, or `None` if the user quit.
+fn step_interactively(
+    emu: &mut Emulator,
+    max_steps: u64,
+    mut input: impl BufRead,
+    mut output: impl Write,
+) -> Result<Option<HaltReason>, Error> {
+    let mut line = String::new();
+
+    for taken in 0 .. max_steps {
+        let pc = emu.hart(0).pc;
+        writeln!(output, "{pc:#010x}: {}", instruction_at(emu, pc))?;
+        write!(output, "[enter] step, [r] run, [q] quit > ")?;
+        output.flush()?;
+
+        line.clear();
+
+        if input.read_line(&mut line)? == 0 {
+            writeln!(output)?;
+            return Ok(None);
+        }
+
+        match line.trim() {
+            "" => (),
+            "r" => return Ok(Some(emu.run(max_steps - taken))),
+            "q" => return Ok(None),
+
+            command => {
+                writeln!(output, "unknown command `{command}`")?;
+                continue;
+            },
+        }
+
+        let before = emu.hart(0).reg_x.snapshot();
+        let reason = emu.run(1);
+
+        for (index, old, new) in emu.hart(0).reg_x.diff(&before) {
+            let name = asm::register_name(index).unwrap_or("?");
+            writeln!(output, "  x{index:<2} {name:<4}  {old:#010x} -> {new:#010x}")?;
+        }
+
+        if reason != HaltReason::StepLimit {
+            return Ok(Some(reason));
+        }
+    }
+
+    Ok(Some(HaltReason::StepLimit))
+}
+
+/// Disassembles the word in memory at `addr`, rendering words that can't
+/// be decoded as data.
+fn instruction_at(emu: &Emulator, addr: u32) -> String {
+    let bytes = emu.memory().read(addr as usize, 4);
+
+    let Some(&[b0, b1, b2, b3]) = bytes.get(.. 4) else {
+        return String::from("<illegal>");
+    };
+
+    let word = u32::from_le_bytes([b0, b1, b2, b3]);
+    let instr = Instruction::new(word);
+
+    match Decoder::decode(&instr) {
+        Some(_) => disasm::disassemble(&instr),
+        None => format!("{:<12} {word:#010x}", ".word"),
+    }
+}
+
+fn encode(source: Option<String>, input: Option<String>, output: Option<String>) {
+    let source = match (source, input) {
+        (Some(source), _) => source,
+
+        (None, Some(path)) => fs::read_to_string(&path).unwrap_or_else(|err| {
+            eprintln!("Error: {err}");
+            process::exit(1);
+        }),
+
+        (None, None) => unreachable!("clap requires a source or input file"),
+    };
+
+    let instrs = asm::assemble(&source).unwrap_or_else(|err| {
+        eprintln!("Error: {err}");
+        process::exit(1);
+    });
+
+    let bytes: Vec<u8> = instrs
+        .iter()
+        .flat_map(|instr| instr.to_le_bytes())
+        .collect();
+
+    let written = match output {
+        Some(path) => fs::write(path, &bytes),
+        None => io::stdout().write_all(&bytes),
+    };
+
+    if let Err(err) = written {
+        eprintln!("Error: {err}");
+        process::exit(1);
+    }
+}
+
+fn print_trace(path: &str) {
+    let bytes = dev_read_input_file(path).unwrap_or_else(|err| {
+        eprintln!("Error: {err}");
+        process::exit(1);
+    });
+
+    for event in TraceReader::new(&bytes) {
+        println!("{}", trace::to_text(&event));
+    }
+}
+
+/// Parses a register preset of the form `REG=VALUE`, where the value is
+/// decimal or `0x`-prefixed hexadecimal, optionally negated.
+fn parse_preset(preset: &str) -> Result<(usize, u32), String> {
+    let (name, value) = preset
+        .split_once('=')
+        .ok_or_else(|| format!("expected REG=VALUE, found `{preset}`"))?;
+
+    let index = asm::register_index(name.trim())
+        .ok_or_else(|| format!("invalid register `{name}`"))?;
+
+    let value = value.trim();
+
+    asm::parse_imm(value)
+        .map(|parsed| (index, parsed as u32))
+        .map_err(|_| format!("invalid value `{value}`"))
+}
+
+/// Writes register presets to the first hart. Writes to `x0` are ignored,
+/// as it's hardwired to zero.
+fn apply_presets(emu: &mut Emulator, presets: &[(usize, u32)]) {
+    for &(index, value) in presets {
+        emu.hart_mut(0).reg_x.write(index, value);
+    }
+}
+
+/// Writes the first hart's profile to `path` as CSV.
+fn write_profile(emu: &Emulator, path: &str) -> Result<(), Error> {
+    let csv = emu.hart(0).profile().map(|profile| profile.to_csv()).unwrap_or_default();
+    fs::write(path, csv)
 }
 
 fn dev_read_input_file(path: &str) -> Result<Vec<u8>, Error> {
@@ -43,3 +400,145 @@ fn dev_read_input_file(path: &str) -> Result<Vec<u8>, Error> {
     file?.read_to_end(&mut buf)?;
     Ok(buf)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        apply_presets,
+        parse_preset,
+        step_interactively,
+        write_profile,
+        asm,
+        Emulator,
+        EmulatorConfig,
+        HaltReason,
+    };
+
+    /// Returns an emulator loaded with the assembled `source`.
+    fn emulator_with(source: &str) -> Emulator {
+        let mut emu = Emulator::build(EmulatorConfig::default());
+
+        for (i, instr) in asm::assemble(source).unwrap().iter().enumerate() {
+            emu.load(i as u32 * 4, &instr.to_le_bytes());
+        }
+
+        emu
+    }
+
+    /// Steps through `emu` with the given input, returning the result and
+    /// the output.
+    fn step_with_input(emu: &mut Emulator, input: &str) -> (Option<HaltReason>, String) {
+        let mut output = Vec::new();
+        let reason = step_interactively(emu, 100, input.as_bytes(), &mut output).unwrap();
+
+        (reason, String::from_utf8(output).unwrap())
+    }
+
+    #[test]
+    fn parses_abi_and_numeric_register_names() {
+        assert_eq!(parse_preset("a0=5"), Ok((10, 5)));
+        assert_eq!(parse_preset("x11=0x10"), Ok((11, 0x10)));
+        assert_eq!(parse_preset("t0=-1"), Ok((5, 0xffffffff)));
+    }
+
+    #[test]
+    fn rejects_malformed_presets() {
+        assert!(parse_preset("a0").is_err());
+        assert!(parse_preset("q0=1").is_err());
+        assert!(parse_preset("a0=five").is_err());
+    }
+
+    #[test]
+    fn preset_register_is_visible_at_program_start() {
+        let mut emu = Emulator::build(EmulatorConfig::default());
+        let program = asm::assemble("addi a1, a0, 1").unwrap();
+        emu.load(0x00, &program[0].to_le_bytes());
+
+        apply_presets(&mut emu, &[(10, 5), (0, 7)]);
+        emu.run(1);
+
+        assert_eq!(emu.hart(0).reg_x.read(11), 6);
+        assert_eq!(emu.hart(0).reg_x.read(0), 0);
+    }
+
+    #[test]
+    fn step_shows_instruction_and_changed_registers() {
+        let mut emu = emulator_with("
+            addi a0, zero, 5
+            addi a1, a0, 1
+        ");
+
+        let (reason, output) = step_with_input(&mut emu, "\n");
+
+        assert_eq!(reason, None);
+        assert_eq!(emu.hart(0).pc, 0x04);
+        assert_eq!(output, concat!(
+            "0x00000000: addi         x10, x0, 0x00000005\n",
+            "[enter] step, [r] run, [q] quit > ",
+            "  x10 a0    0x00000000 -> 0x00000005\n",
+            "0x00000004: addi         x11, x10, 0x00000001\n",
+            "[enter] step, [r] run, [q] quit > \n",
+        ));
+    }
+
+    #[test]
+    fn step_shows_illegal_words_as_data() {
+        let mut emu = Emulator::build(EmulatorConfig::default());
+        emu.load(0x00, &0x80000033u32.to_le_bytes());
+
+        let (reason, output) = step_with_input(&mut emu, "q\n");
+
+        assert_eq!(reason, None);
+        assert!(output.starts_with("0x00000000: .word        0x80000033\n"));
+    }
+
+    #[test]
+    fn run_command_runs_to_completion() {
+        let mut emu = emulator_with("
+            addi a0, zero, 5
+            addi a1, a0, 1
+            ebreak
+        ");
+
+        let (reason, _) = step_with_input(&mut emu, "\nr\n");
+
+        assert_eq!(reason, Some(HaltReason::Ebreak));
+        assert_eq!(emu.hart(0).reg_x.read(11), 6);
+    }
+
+    #[test]
+    fn quit_command_stops_without_stepping() {
+        let mut emu = emulator_with("addi a0, zero, 5");
+
+        let (reason, output) = step_with_input(&mut emu, "x\nq\n");
+
+        assert_eq!(reason, None);
+        assert_eq!(emu.hart(0).pc, 0x00);
+        assert!(output.contains("unknown command `x`"));
+    }
+
+    #[test]
+    fn writes_profile_csv_sorted_by_count() {
+        let mut emu = Emulator::build(EmulatorConfig::default());
+        let program = asm::assemble("
+            addi a0, a0, 1
+            add a1, a0, a0
+            addi a0, a0, 1
+            ebreak
+        ").unwrap();
+
+        for (i, instr) in program.iter().enumerate() {
+            emu.load(i as u32 * 4, &instr.to_le_bytes());
+        }
+
+        emu.hart_mut(0).enable_profile();
+        emu.run(10);
+
+        let path = std::env::temp_dir().join(format!("profile-{}.csv", std::process::id()));
+        write_profile(&emu, path.to_str().unwrap()).unwrap();
+        let csv = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(csv, "mnemonic,count\naddi,2\nadd,1\n");
+    }
+}
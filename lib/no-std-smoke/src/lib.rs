@@ -0,0 +1,38 @@
+//! A `no_std` crate that exercises the emulator's core types, so that
+//! building it for a bare-metal target verifies the library doesn't
+//! depend on `std`.
+
+#![cfg_attr(not(test), no_std)]
+
+use riscv_emulator::emulator::{
+    Emulator,
+    EmulatorConfig,
+    HaltReason,
+};
+
+/// Runs a small program and returns the value left in `x1`.
+pub fn smoke() -> (HaltReason, u32) {
+    let mut emu = Emulator::build(EmulatorConfig {
+        mem_size: 64,
+        ..EmulatorConfig::default()
+    });
+
+    // addi x1, x0, 42
+    // jal x0, 0
+    emu.load(0x00, &0x02a00093u32.to_le_bytes());
+    emu.load(0x04, &0x0000006fu32.to_le_bytes());
+
+    let reason = emu.run(4);
+
+    (reason, emu.hart(0).reg_x.read(1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{smoke, HaltReason};
+
+    #[test]
+    fn runs_program_without_std() {
+        assert_eq!(smoke(), (HaltReason::StepLimit, 42));
+    }
+}
@@ -1,5 +1,20 @@
+//! Arithmetic Logic Unit
+//! The arithmetic, comparison, logical and shift operations instructions
+//! perform, and the `AluOps` trait for plugging an alternative ALU into a
+//! processor.
+
+use core::fmt::Debug;
+
 use crate::op::{ Op, Op::* };
 
+/// The arithmetic, comparison, logical and shift operations a processor
+/// performs, so alternative implementations such as a saturating or an
+/// instrumented ALU can be plugged into it.
+pub trait AluOps: Debug {
+    /// Performs an ALU operation on operands `x` and `y`.
+    fn run(&mut self, op: &Op, x: i32, y: i32) -> i32;
+}
+
 /// Arithmetic Logic Unit (ALU)
 /// Responsible for performing arithmetic, comparison, logical and 
 /// shift operations.
@@ -12,6 +27,43 @@ impl Alu {
         Alu {}
     }
 
+    /// Returns whether `x` is less than `y` as signed integers.
+    ///
+    /// ```
+    /// use riscv_emulator::alu::Alu;
+    ///
+    /// assert!(Alu::less_than(-1, 0));
+    /// assert!(!Alu::less_than(0, -1));
+    /// ```
+    pub fn less_than(x: i32, y: i32) -> bool {
+        x < y
+    }
+
+    /// Returns whether `x` is less than `y` as unsigned integers.
+    ///
+    /// ```
+    /// use riscv_emulator::alu::Alu;
+    ///
+    /// // -1 is 0xffffffff, the largest unsigned value.
+    /// assert!(Alu::less_than_unsigned(0, -1));
+    /// assert!(!Alu::less_than_unsigned(-1, 0));
+    /// ```
+    pub fn less_than_unsigned(x: i32, y: i32) -> bool {
+        (x as u32) < y as u32
+    }
+
+    /// Returns whether `x` and `y` are equal.
+    ///
+    /// ```
+    /// use riscv_emulator::alu::Alu;
+    ///
+    /// assert!(Alu::equal(7, 7));
+    /// assert!(!Alu::equal(7, -7));
+    /// ```
+    pub fn equal(x: i32, y: i32) -> bool {
+        x == y
+    }
+
     /// Performs an ALU operation on operands `x` and `y`.
     pub fn run(&self, op: &Op, x: i32, y: i32) -> i32 {
         match op {
@@ -25,34 +77,90 @@ impl Alu {
                 x.wrapping_sub(y)
             },
 
+            // Clamps to the range of `i32` rather than wrapping.
+            SaturatingAdd => {
+                x.saturating_add(y)
+            },
+
+            SaturatingSub => {
+                x.saturating_sub(y)
+            },
+
+            ArithmeticMul => {
+                x.wrapping_mul(y)
+            },
+
+            ArithmeticMulHigh => {
+                ((x as i64 * y as i64) >> 32) as i32
+            },
+
+            ArithmeticMulHighSignedUnsigned => {
+                ((x as i64 * y as u32 as i64) >> 32) as i32
+            },
+
+            ArithmeticMulHighUnsigned => {
+                ((x as u32 as u64 * y as u32 as u64) >> 32) as i32
+            },
+
+            // Division by zero doesn't trap. The quotient has all bits set
+            // and the remainder is the dividend. Signed overflow gives the
+            // dividend and a remainder of zero.
+            ArithmeticDiv => {
+                match y {
+                    0 => -1,
+                    _ => x.wrapping_div(y),
+                }
+            },
+
+            ArithmeticDivUnsigned => {
+                match y {
+                    0 => -1,
+                    _ => (x as u32 / y as u32) as i32,
+                }
+            },
+
+            ArithmeticRem => {
+                match y {
+                    0 => x,
+                    _ => x.wrapping_rem(y),
+                }
+            },
+
+            ArithmeticRemUnsigned => {
+                match y {
+                    0 => x,
+                    _ => (x as u32 % y as u32) as i32,
+                }
+            },
+
             BranchEqual => {
-                (x == y) as i32
+                Self::equal(x, y) as i32
             },
 
             BranchGreaterThanOrEqualTo => {
-                (x >= y) as i32
+                !Self::less_than(x, y) as i32
             },
 
             BranchGreaterThanOrEqualToUnsigned => {
-                (x as u32 >= y as u32) as i32
+                !Self::less_than_unsigned(x, y) as i32
             },
 
             BranchLessThan
                 | SetLessThan
                 | SetLessThanImmediate => 
             {
-                (x < y) as i32
+                Self::less_than(x, y) as i32
             },
 
             BranchLessThanUnsigned
                 | SetLessThanImmediateUnsigned
                 | SetLessThanUnsigned => 
             {
-                ((x as u32) < y as u32) as i32
+                Self::less_than_unsigned(x, y) as i32
             },
 
             BranchNotEqual => {
-                (x != y) as i32
+                !Self::equal(x, y) as i32
             },
 
             LogicalAnd
@@ -96,6 +204,13 @@ impl Alu {
     }
 }
 
+/// Wrapping arithmetic, as the ISA specifies.
+impl AluOps for Alu {
+    fn run(&mut self, op: &Op, x: i32, y: i32) -> i32 {
+        Alu::run(self, op, x, y)
+    }
+}
+
 impl Default for Alu {
     fn default() -> Self {
         Alu::new()
@@ -103,6 +218,7 @@ impl Default for Alu {
 }
 
 #[cfg(test)]
+#[allow(clippy::default_constructed_unit_structs, clippy::unnecessary_cast)]
 mod tests {
     use super::{ Alu, Op::* };
 
@@ -210,6 +326,117 @@ mod tests {
         }
     }
 
+    mod saturating {
+        use super::*;
+
+        #[test]
+        fn add_clamps_at_maximum() {
+            assert_eq!(
+                Alu::default().run(&SaturatingAdd, i32::MAX, 1),
+                i32::MAX,
+            );
+        }
+
+        #[test]
+        fn add_clamps_at_minimum() {
+            assert_eq!(
+                Alu::default().run(&SaturatingAdd, i32::MIN, -1),
+                i32::MIN,
+            );
+        }
+
+        #[test]
+        fn sub_clamps_at_both_ends() {
+            assert_eq!(
+                Alu::default().run(&SaturatingSub, i32::MIN, 1),
+                i32::MIN,
+            );
+            assert_eq!(
+                Alu::default().run(&SaturatingSub, i32::MAX, -1),
+                i32::MAX,
+            );
+        }
+
+        #[test]
+        fn matches_wrapping_ops_without_overflow() {
+            assert_eq!(Alu::default().run(&SaturatingAdd, 50, -20), 30);
+            assert_eq!(Alu::default().run(&SaturatingSub, 50, -20), 70);
+        }
+    }
+
+    mod mul {
+        use super::*;
+
+        #[test]
+        fn keeps_low_bits_of_product() {
+            assert_eq!(
+                Alu::default().run(&ArithmeticMul, 0x10000, 0x10001),
+                0x10000,
+            );
+        }
+
+        #[test]
+        fn high_bits_of_signed_product() {
+            assert_eq!(
+                Alu::default().run(&ArithmeticMulHigh, -1, -1),
+                0,
+            );
+            assert_eq!(
+                Alu::default().run(&ArithmeticMulHigh, i32::MIN, 2),
+                -1,
+            );
+        }
+
+        #[test]
+        fn high_bits_of_signed_by_unsigned_product() {
+            assert_eq!(
+                Alu::default().run(&ArithmeticMulHighSignedUnsigned, -1, -1),
+                -1,
+            );
+        }
+
+        #[test]
+        fn high_bits_of_unsigned_product() {
+            assert_eq!(
+                Alu::default().run(&ArithmeticMulHighUnsigned, -1, -1) as u32,
+                0xfffffffe,
+            );
+        }
+    }
+
+    mod div {
+        use super::*;
+
+        #[test]
+        fn rounds_towards_zero() {
+            assert_eq!(Alu::default().run(&ArithmeticDiv, -7, 2), -3);
+            assert_eq!(Alu::default().run(&ArithmeticRem, -7, 2), -1);
+        }
+
+        #[test]
+        fn divides_unsigned() {
+            assert_eq!(
+                Alu::default().run(&ArithmeticDivUnsigned, -2, 2) as u32,
+                0x7fffffff,
+            );
+            assert_eq!(Alu::default().run(&ArithmeticRemUnsigned, -1, 16), 15);
+        }
+
+        #[test]
+        fn division_by_zero_does_not_trap() {
+            assert_eq!(Alu::default().run(&ArithmeticDiv, 7, 0), -1);
+            assert_eq!(Alu::default().run(&ArithmeticDivUnsigned, 7, 0), -1);
+            assert_eq!(Alu::default().run(&ArithmeticRem, 7, 0), 7);
+            assert_eq!(Alu::default().run(&ArithmeticRemUnsigned, 7, 0), 7);
+        }
+
+        #[test]
+        fn signed_overflow_wraps_around() {
+            assert_eq!(Alu::default().run(&ArithmeticDiv, i32::MIN, -1), i32::MIN);
+            assert_eq!(Alu::default().run(&ArithmeticRem, i32::MIN, -1), 0);
+        }
+    }
+
     mod beq {
         use super::*;
 
@@ -998,4 +1225,32 @@ mod tests {
             );
         }
     }
-}
\ No newline at end of file
+
+    mod compare {
+        use super::*;
+
+        const VALUES: [i32; 6] = [0, 1, -1, 2, i32::MAX, i32::MIN];
+
+        #[test]
+        fn matches_run() {
+            let alu = Alu::default();
+
+            for x in VALUES {
+                for y in VALUES {
+                    assert_eq!(Alu::less_than(x, y) as i32, alu.run(&SetLessThan, x, y));
+                    assert_eq!(
+                        Alu::less_than_unsigned(x, y) as i32,
+                        alu.run(&SetLessThanUnsigned, x, y),
+                    );
+                    assert_eq!(Alu::equal(x, y) as i32, alu.run(&BranchEqual, x, y));
+                }
+            }
+        }
+
+        #[test]
+        fn unsigned_comparison_treats_negatives_as_large() {
+            assert!(Alu::less_than(-1, 0));
+            assert!(!Alu::less_than_unsigned(-1, 0));
+        }
+    }
+}
@@ -0,0 +1,249 @@
+//! Static Analysis
+//! Splits raw machine code into basic blocks, the nodes of a control-flow
+//! graph.
+
+use alloc::collections::BTreeSet;
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::decode::Decoder;
+use crate::instruction::Instruction;
+use crate::op::Op::*;
+
+/// A straight-line run of instructions with a single entry and exit.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct BasicBlock {
+    /// The address of the first instruction in the block.
+    pub start: u32,
+
+    /// The address just past the last instruction in the block.
+    pub end: u32,
+
+    /// The addresses control can transfer to after the block. Indirect
+    /// jumps (`jalr`) have no statically known successors.
+    pub successors: Vec<u32>,
+}
+
+/// Splits the machine code in `bytes`, loaded at `base_addr`, into basic
+/// blocks ordered by address.
+///
+/// Blocks end after a terminator (see `Op::is_terminator`) and before any
+/// address that is the target of a branch or jump. A word that can't be
+/// decoded ends the current block and isn't part of any block, so the
+/// block before it has no successors.
+pub fn analyze_basic_blocks(bytes: &[u8], base_addr: u32) -> Vec<BasicBlock> {
+    let instrs: Vec<(u32, Option<Instruction>)> = bytes
+        .chunks_exact(4)
+        .enumerate()
+        .map(|(i, word)| {
+            let addr = base_addr.wrapping_add(i as u32 * 4);
+            let instr = Instruction::new(
+                u32::from_le_bytes([word[0], word[1], word[2], word[3]])
+            );
+
+            (addr, Decoder::decode(&instr).map(|_| instr))
+        })
+        .collect();
+
+    // Every branch or jump target starts a new block.
+    let leaders: BTreeSet<u32> = instrs
+        .iter()
+        .filter_map(|(addr, instr)| {
+            let instr = instr.as_ref()?;
+            static_target(*addr, instr)
+        })
+        .collect();
+
+    let mut blocks = Vec::new();
+    let mut start: Option<u32> = None;
+
+    for (addr, instr) in &instrs {
+        let addr = *addr;
+        let next = addr.wrapping_add(4);
+
+        let Some(instr) = instr else {
+            // Close the current block before the undecodable word.
+            if let Some(start) = start.take() {
+                blocks.push(BasicBlock { start, end: addr, successors: vec![] });
+            }
+            continue;
+        };
+
+        if let Some(current) = start {
+            if leaders.contains(&addr) {
+                blocks.push(BasicBlock {
+                    start: current,
+                    end: addr,
+                    successors: vec![addr],
+                });
+                start = None;
+            }
+        }
+
+        let current = *start.get_or_insert(addr);
+
+        if Decoder::decode(instr).is_some_and(|op| op.is_terminator()) {
+            blocks.push(BasicBlock {
+                start: current,
+                end: next,
+                successors: successors(addr, instr).unwrap_or_default(),
+            });
+            start = None;
+        }
+    }
+
+    if let (Some(start), Some((last, _))) = (start, instrs.last()) {
+        blocks.push(BasicBlock {
+            start,
+            end: last.wrapping_add(4),
+            successors: vec![],
+        });
+    }
+
+    blocks
+}
+
+/// Returns the addresses control can transfer to after the instruction at
+/// `addr`: the taken target then the fall-through address for a branch,
+/// the target for `jal`, and the fall-through address for instructions
+/// that aren't terminators.
+///
+/// Returns None if the successors aren't statically known, as for `jalr`
+/// and the system instructions that end blocks, or if the instruction
+/// can't be decoded.
+pub fn successors(addr: u32, instr: &Instruction) -> Option<Vec<u32>> {
+    let next = addr.wrapping_add(4);
+
+    if !Decoder::decode(instr)?.is_terminator() {
+        return Some(vec![next]);
+    }
+
+    let target = static_target(addr, instr)?;
+
+    match instr.is_branch() {
+        true => Some(vec![target, next]),
+        false => Some(vec![target]),
+    }
+}
+
+/// Returns the target of a branch or direct jump, or None if the
+/// instruction isn't one or its target depends on a register.
+fn static_target(addr: u32, instr: &Instruction) -> Option<u32> {
+    match Decoder::decode(instr)? {
+        JumpAndLink => Some(addr.wrapping_add_signed(instr.as_j()?.imm)),
+        _ if instr.is_branch() => Some(addr.wrapping_add_signed(instr.as_b()?.imm)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{analyze_basic_blocks, successors, BasicBlock, Instruction};
+
+    /// Assembles words into little-endian bytes.
+    fn bytes(instrs: &[u32]) -> Vec<u8> {
+        instrs
+            .iter()
+            .flat_map(|instr| instr.to_le_bytes())
+            .collect()
+    }
+
+    #[test]
+    fn splits_at_forward_branch() {
+        let program = bytes(&[
+            0x00100093, // addi x1, x0, 1
+            0x00008463, // beq x1, x0, 8
+            0x00200113, // addi x2, x0, 2
+            0x00300193, // addi x3, x0, 3
+        ]);
+
+        assert_eq!(
+            analyze_basic_blocks(&program, 0x1000),
+            [
+                BasicBlock { start: 0x1000, end: 0x1008, successors: vec![0x100c, 0x1008] },
+                BasicBlock { start: 0x1008, end: 0x100c, successors: vec![0x100c] },
+                BasicBlock { start: 0x100c, end: 0x1010, successors: vec![] },
+            ],
+        );
+    }
+
+    #[test]
+    fn ends_block_after_jump() {
+        let program = bytes(&[
+            0xffdff06f, // jal x0, -4
+            0x00008067, // jalr x0, 0(x1)
+        ]);
+
+        assert_eq!(
+            analyze_basic_blocks(&program, 0x04),
+            [
+                BasicBlock { start: 0x04, end: 0x08, successors: vec![0x00] },
+                BasicBlock { start: 0x08, end: 0x0c, successors: vec![] },
+            ],
+        );
+    }
+
+    #[test]
+    fn undecodable_word_ends_block() {
+        let program = bytes(&[
+            0x00100093, // addi x1, x0, 1
+            0x00000000, // (undecodable)
+            0x00200113, // addi x2, x0, 2
+        ]);
+
+        assert_eq!(
+            analyze_basic_blocks(&program, 0x00),
+            [
+                BasicBlock { start: 0x00, end: 0x04, successors: vec![] },
+                BasicBlock { start: 0x08, end: 0x0c, successors: vec![] },
+            ],
+        );
+    }
+
+    #[test]
+    fn ends_block_after_ecall() {
+        let program = bytes(&[
+            0x00000073, // ecall
+            0x00100093, // addi x1, x0, 1
+        ]);
+
+        assert_eq!(
+            analyze_basic_blocks(&program, 0x00),
+            [
+                BasicBlock { start: 0x00, end: 0x04, successors: vec![] },
+                BasicBlock { start: 0x04, end: 0x08, successors: vec![] },
+            ],
+        );
+    }
+
+    #[test]
+    fn branch_has_taken_and_fall_through_successors() {
+        // beq x1, x0, 8
+        let instr = Instruction::new(0x00008463);
+        assert_eq!(successors(0x100, &instr), Some(vec![0x108, 0x104]));
+    }
+
+    #[test]
+    fn direct_jump_has_one_successor() {
+        // jal x0, -4
+        let instr = Instruction::new(0xffdff06f);
+        assert_eq!(successors(0x100, &instr), Some(vec![0xfc]));
+    }
+
+    #[test]
+    fn indirect_jump_has_unknown_successors() {
+        // jalr x0, 0(x1)
+        assert_eq!(successors(0x100, &Instruction::new(0x00008067)), None);
+    }
+
+    #[test]
+    fn non_terminator_falls_through() {
+        // addi x1, x0, 1
+        assert_eq!(successors(0x100, &Instruction::new(0x00100093)), Some(vec![0x104]));
+    }
+
+    #[test]
+    fn empty_input_has_no_blocks() {
+        assert!(analyze_basic_blocks(&[], 0x00).is_empty());
+    }
+}
@@ -0,0 +1,438 @@
+//! Assembler
+//! Parses assembly source, one instruction per line, into operations and
+//! operands and encodes them to machine code.
+//!
+//! Registers may be written by number (`x5`) or ABI name (`t0`). Memory
+//! operands are written as `offset(register)`, immediates may be decimal
+//! or `0x`-prefixed hexadecimal, and `#` starts a comment. Labels and
+//! pseudo-instructions aren't supported.
+
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+
+use core::fmt::Display;
+
+use crate::encode::{encode, Operands};
+use crate::instruction::{Instruction, InstructionFormat};
+use crate::op::{Op, OpCategory};
+use crate::register::ABI_NAMES;
+
+/// The `fence` ordering used when no operands are given, `fence iorw, iorw`.
+const FENCE_IORW: i32 = 0x0ff;
+
+/// An error parsing a single line of assembly.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum ParseError {
+    /// The mnemonic isn't a known operation.
+    UnknownMnemonic(String),
+
+    /// The operation takes a different number of operands.
+    OperandCount { expected: usize, found: usize },
+
+    /// An operand isn't a valid register.
+    InvalidRegister(String),
+
+    /// An operand isn't a valid immediate.
+    InvalidImmediate(String),
+
+    /// An immediate doesn't fit the operation's immediate field.
+    ImmediateOutOfRange(i32),
+
+    /// An operand isn't a memory operand of the form `offset(register)`.
+    InvalidAddress(String),
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            ParseError::UnknownMnemonic(mnemonic) => {
+                write!(f, "unknown mnemonic `{mnemonic}`")
+            },
+
+            ParseError::OperandCount { expected, found } => {
+                write!(f, "expected {expected} operands, found {found}")
+            },
+
+            ParseError::InvalidRegister(operand) => {
+                write!(f, "invalid register `{operand}`")
+            },
+
+            ParseError::InvalidImmediate(operand) => {
+                write!(f, "invalid immediate `{operand}`")
+            },
+
+            ParseError::ImmediateOutOfRange(imm) => {
+                write!(f, "immediate {imm} is out of range")
+            },
+
+            ParseError::InvalidAddress(operand) => {
+                write!(f, "invalid memory operand `{operand}`")
+            },
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for ParseError {}
+
+/// An error assembling source, along with the line it occurred on.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct AsmError {
+    /// The line number, starting from 1.
+    pub line: usize,
+
+    /// What was wrong with the line.
+    pub error: ParseError,
+}
+
+impl Display for AsmError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "line {}: {}", self.line, self.error)
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for AsmError {}
+
+/// Assembles source into 32-bit instructions, in order. Blank lines and
+/// comments produce no instructions.
+pub fn assemble(source: &str) -> Result<Vec<u32>, AsmError> {
+    let mut instrs = Vec::new();
+
+    for (index, line) in source.lines().enumerate() {
+        let parsed = parse_line(line).map_err(|error| AsmError {
+            line: index + 1,
+            error,
+        })?;
+
+        if let Some((op, operands)) = parsed {
+            instrs.push(encode(&op, &operands));
+        }
+    }
+
+    Ok(instrs)
+}
+
+/// Parses a line of assembly into an operation and its operands, or None
+/// if the line is blank or only a comment.
+pub fn parse_line(line: &str) -> Result<Option<(Op, Operands)>, ParseError> {
+    let line = match line.split_once('#') {
+        Some((code, _)) => code,
+        None => line,
+    }.trim();
+
+    if line.is_empty() {
+        return Ok(None);
+    }
+
+    let (mnemonic, rest) = line
+        .split_once(char::is_whitespace)
+        .unwrap_or((line, ""));
+
+    let op = Op::from_mnemonic(mnemonic)
+        .ok_or_else(|| ParseError::UnknownMnemonic(mnemonic.to_string()))?;
+
+    let args: Vec<&str> = match rest.trim() {
+        "" => Vec::new(),
+        rest => rest.split(',').map(str::trim).collect(),
+    };
+
+    let operands = parse_operands(&op, &args)?;
+
+    if !op.imm_fits(operands.imm) {
+        return Err(ParseError::ImmediateOutOfRange(operands.imm));
+    }
+
+    Ok(Some((op, operands)))
+}
+
+/// Parses the operands of an operation according to its format.
+fn parse_operands(op: &Op, args: &[&str]) -> Result<Operands, ParseError> {
+    let format = Instruction::new(encode(op, &Operands::none())).format();
+
+    match (op.category(), format) {
+        (OpCategory::System, _) => {
+            expect_count(args, 0)?;
+
+            match op {
+                Op::Fence => Ok(Operands::i(0, 0, FENCE_IORW)),
+                _ => Ok(Operands::none()),
+            }
+        },
+
+        // csrrw rd, csr, rs1 / csrrwi rd, csr, uimm
+        (OpCategory::Csr, _) => {
+            expect_count(args, 3)?;
+
+            let rs1 = match op {
+                Op::CsrReadClearImmediate
+                    | Op::CsrReadSetImmediate
+                    | Op::CsrReadWriteImmediate => parse_uimm(args[2])?,
+
+                _ => parse_reg(args[2])?,
+            };
+
+            Ok(Operands::i(parse_reg(args[0])?, rs1, parse_imm(args[1])?))
+        },
+
+        // lw rd, offset(rs1) / jalr rd, offset(rs1)
+        (OpCategory::Load | OpCategory::Jump, Some(InstructionFormat::I)) => {
+            expect_count(args, 2)?;
+            let (imm, rs1) = parse_address(args[1])?;
+            Ok(Operands::i(parse_reg(args[0])?, rs1, imm))
+        },
+
+        // sw rs2, offset(rs1)
+        (OpCategory::Store, _) => {
+            expect_count(args, 2)?;
+            let (imm, rs1) = parse_address(args[1])?;
+            Ok(Operands::s(rs1, parse_reg(args[0])?, imm))
+        },
+
+        // Custom operations have no standard format, but are laid out as
+        // R-type.
+        (_, Some(InstructionFormat::R) | None) => {
+            expect_count(args, 3)?;
+            Ok(Operands::r(parse_reg(args[0])?, parse_reg(args[1])?, parse_reg(args[2])?))
+        },
+
+        (_, Some(InstructionFormat::I)) => {
+            expect_count(args, 3)?;
+            Ok(Operands::i(parse_reg(args[0])?, parse_reg(args[1])?, parse_signed_imm(args[2])?))
+        },
+
+        (_, Some(InstructionFormat::B)) => {
+            expect_count(args, 3)?;
+            Ok(Operands::s(parse_reg(args[0])?, parse_reg(args[1])?, parse_signed_imm(args[2])?))
+        },
+
+        (_, _) => {
+            expect_count(args, 2)?;
+            Ok(Operands::u(parse_reg(args[0])?, parse_imm(args[1])?))
+        },
+    }
+}
+
+/// Checks that there are exactly `expected` operands.
+fn expect_count(args: &[&str], expected: usize) -> Result<(), ParseError> {
+    match args.len() {
+        found if found == expected => Ok(()),
+        found => Err(ParseError::OperandCount { expected, found }),
+    }
+}
+
+/// Returns the index of an `x` register given by number (`x5`) or ABI name
+/// (`t0`, or `fp` for `s0`), or None if it isn't a register.
+pub fn register_index(name: &str) -> Option<usize> {
+    match name {
+        "fp" => Some(8),
+
+        _ => match name.strip_prefix('x') {
+            Some(number) => number.parse().ok().filter(|&index| index < 32),
+            None => ABI_NAMES.iter().position(|&abi_name| abi_name == name),
+        },
+    }
+}
+
+/// Returns the ABI name of an `x` register given by index, or None if it
+/// isn't a register.
+pub fn register_name(index: usize) -> Option<&'static str> {
+    ABI_NAMES.get(index).copied()
+}
+
+/// Parses a register by number (`x5`) or ABI name (`t0`, or `fp` for `s0`).
+fn parse_reg(operand: &str) -> Result<usize, ParseError> {
+    register_index(operand).ok_or_else(|| ParseError::InvalidRegister(operand.to_string()))
+}
+
+/// Parses a decimal or `0x`-prefixed hexadecimal immediate, optionally
+/// negated.
+///
+/// Any value that fits in 32 bits is accepted as a two's-complement bit
+/// pattern, so `0xffffffff` parses as -1. Signed instruction fields use
+/// `parse_signed_imm`, which doesn't.
+pub fn parse_imm(operand: &str) -> Result<i32, ParseError> {
+    match parse_literal(operand)? {
+        (true, value) => Ok((value as i32).wrapping_neg()),
+        (false, value) => Ok(value as i32),
+    }
+}
+
+/// Parses an immediate like `parse_imm`, but rejects values outside the
+/// range of `i32` rather than reinterpreting their bits.
+fn parse_signed_imm(operand: &str) -> Result<i32, ParseError> {
+    match parse_literal(operand)? {
+        (true, value) if value <= 1 << 31 => Ok((value as i32).wrapping_neg()),
+        (false, value) if value <= i32::MAX as u32 => Ok(value as i32),
+        _ => Err(ParseError::InvalidImmediate(operand.to_string())),
+    }
+}
+
+/// Parses the 5-bit unsigned immediate of the CSR immediate instructions.
+fn parse_uimm(operand: &str) -> Result<usize, ParseError> {
+    match parse_imm(operand)? {
+        uimm @ 0 ..= 31 => Ok(uimm as usize),
+        uimm => Err(ParseError::ImmediateOutOfRange(uimm)),
+    }
+}
+
+/// Parses a decimal or `0x`-prefixed hexadecimal literal, returning
+/// whether it's negated and its magnitude.
+fn parse_literal(operand: &str) -> Result<(bool, u32), ParseError> {
+    let (negative, digits) = match operand.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, operand),
+    };
+
+    let value = match digits.strip_prefix("0x") {
+        Some(hex) => u32::from_str_radix(hex, 16).ok(),
+        None => digits.parse::<u32>().ok(),
+    };
+
+    value
+        .map(|value| (negative, value))
+        .ok_or_else(|| ParseError::InvalidImmediate(operand.to_string()))
+}
+
+/// Parses a memory operand of the form `offset(register)`, where the
+/// offset may be omitted.
+fn parse_address(operand: &str) -> Result<(i32, usize), ParseError> {
+    let invalid = || ParseError::InvalidAddress(operand.to_string());
+
+    let (offset, reg) = operand
+        .strip_suffix(')')
+        .and_then(|operand| operand.split_once('('))
+        .ok_or_else(invalid)?;
+
+    let offset = match offset.trim() {
+        "" => 0,
+        offset => parse_signed_imm(offset)?,
+    };
+
+    Ok((offset, parse_reg(reg.trim())?))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::decode::Decoder;
+    use crate::instruction::Instruction;
+    use crate::op::Op::*;
+
+    use super::{
+        assemble,
+        parse_line,
+        register_index,
+        register_name,
+        AsmError,
+        Operands,
+        ParseError,
+    };
+
+    #[test]
+    fn assembles_lines_that_decode_to_the_same_ops() {
+        let instrs = assemble("
+            addi a0, zero, 5    # a0 = 5
+            sw a0, -8(sp)
+            beq t0, x6, 0x10
+        ").unwrap();
+
+        assert_eq!(instrs, [0x00500513, 0xfea12c23, 0x00628863]);
+        assert_eq!(
+            instrs
+                .iter()
+                .map(|&instr| Decoder::decode(&Instruction::new(instr)))
+                .collect::<Vec<_>>(),
+            [Some(ArithmeticAddImmediate), Some(StoreWord), Some(BranchEqual)],
+        );
+    }
+
+    #[test]
+    fn parses_operand_forms() {
+        assert_eq!(
+            parse_line("lw s1, (fp)"),
+            Ok(Some((LoadWord, Operands::i(9, 8, 0)))),
+        );
+        assert_eq!(
+            parse_line("csrrsi x1, 0x300, 8"),
+            Ok(Some((CsrReadSetImmediate, Operands::i(1, 8, 0x300)))),
+        );
+        assert_eq!(
+            parse_line("lui t6, 0x12345"),
+            Ok(Some((LoadUpperImmediate, Operands::u(31, 0x12345)))),
+        );
+        assert_eq!(
+            parse_line("sadd a0, a1, a2"),
+            Ok(Some((SaturatingAdd, Operands::r(10, 11, 12)))),
+        );
+        assert_eq!(parse_line("ecall"), Ok(Some((SystemEcall, Operands::none()))));
+        assert_eq!(parse_line("  # comment"), Ok(None));
+    }
+
+    #[test]
+    fn register_names_round_trip() {
+        for index in 0 .. 32 {
+            assert_eq!(register_name(index).and_then(register_index), Some(index));
+        }
+
+        assert_eq!(register_name(10), Some("a0"));
+        assert_eq!(register_name(32), None);
+    }
+
+    #[test]
+    fn reports_errors_with_line_number() {
+        assert_eq!(
+            assemble("nop\n"),
+            Err(AsmError {
+                line: 1,
+                error: ParseError::UnknownMnemonic("nop".to_string()),
+            }),
+        );
+        assert_eq!(
+            assemble("ecall\nadd x1, x2, x32"),
+            Err(AsmError {
+                line: 2,
+                error: ParseError::InvalidRegister("x32".to_string()),
+            }),
+        );
+        assert_eq!(
+            parse_line("add x1, x2"),
+            Err(ParseError::OperandCount { expected: 3, found: 2 }),
+        );
+        assert_eq!(
+            parse_line("addi x1, x2, 4096"),
+            Err(ParseError::ImmediateOutOfRange(4096)),
+        );
+    }
+
+    #[test]
+    fn rejects_csr_uimm_above_31() {
+        assert_eq!(
+            parse_line("csrrwi x1, 0x300, 31"),
+            Ok(Some((CsrReadWriteImmediate, Operands::i(1, 31, 0x300)))),
+        );
+        assert_eq!(
+            parse_line("csrrwi x1, 0x300, 40"),
+            Err(ParseError::ImmediateOutOfRange(40)),
+        );
+        assert_eq!(
+            parse_line("csrrci x1, 0x300, -1"),
+            Err(ParseError::ImmediateOutOfRange(-1)),
+        );
+    }
+
+    #[test]
+    fn rejects_signed_immediates_outside_i32() {
+        assert_eq!(
+            parse_line("addi x1, x0, 0xffffffff"),
+            Err(ParseError::InvalidImmediate("0xffffffff".to_string())),
+        );
+        assert_eq!(
+            parse_line("lw x1, 0x80000000(x2)"),
+            Err(ParseError::InvalidImmediate("0x80000000".to_string())),
+        );
+        assert_eq!(
+            parse_line("addi x1, x0, -1"),
+            Ok(Some((ArithmeticAddImmediate, Operands::i(1, 0, -1)))),
+        );
+    }
+}
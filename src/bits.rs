@@ -0,0 +1,108 @@
+//! Bit manipulation helpers shared by the decoder, encoder and anything
+//! else that needs to pull apart instruction fields.
+
+/// Sign-extends the low `bits` bits of a 32-bit value.
+///
+/// `bits` must be between 1 and 32 inclusive. A width of 32 returns the
+/// value reinterpreted as a signed integer.
+pub fn sign_extend(value: u32, bits: u32) -> i32 {
+    assert!(
+        (1 ..= 32).contains(&bits),
+        "Field width must be between 1 and 32 bits."
+    );
+
+    let shift = 32 - bits;
+    ((value << shift) as i32) >> shift
+}
+
+/// Sign-extends the low `bits` bits of a 64-bit value.
+///
+/// `bits` must be between 1 and 64 inclusive. A width of 64 returns the
+/// value reinterpreted as a signed integer.
+pub fn sign_extend64(value: u64, bits: u32) -> i64 {
+    assert!(
+        (1 ..= 64).contains(&bits),
+        "Field width must be between 1 and 64 bits."
+    );
+
+    let shift = 64 - bits;
+    ((value << shift) as i64) >> shift
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{sign_extend, sign_extend64};
+
+    mod sign_extend {
+        use super::*;
+
+        #[test]
+        fn extends_negative_12_bit_field() {
+            assert_eq!(sign_extend(0xff4, 12), -12);
+        }
+
+        #[test]
+        fn preserves_positive_12_bit_field() {
+            assert_eq!(sign_extend(0x7ff, 12), 2047);
+        }
+
+        #[test]
+        fn extends_negative_20_bit_field() {
+            assert_eq!(sign_extend(0xfffff, 20), -1);
+        }
+
+        #[test]
+        fn ignores_bits_above_field() {
+            assert_eq!(sign_extend(0xffff_f005, 12), 5);
+        }
+
+        #[test]
+        fn extends_single_bit_field() {
+            assert_eq!(sign_extend(0x01, 1), -1);
+            assert_eq!(sign_extend(0x00, 1), 0);
+        }
+
+        #[test]
+        fn full_width_reinterprets_value() {
+            assert_eq!(sign_extend(0x8000_0000, 32), i32::MIN);
+            assert_eq!(sign_extend(0x7fff_ffff, 32), i32::MAX);
+        }
+
+        #[test]
+        #[should_panic]
+        fn panics_on_zero_width() {
+            sign_extend(0x00, 0);
+        }
+
+        #[test]
+        #[should_panic]
+        fn panics_on_width_larger_than_value() {
+            sign_extend(0x00, 33);
+        }
+    }
+
+    mod sign_extend64 {
+        use super::*;
+
+        #[test]
+        fn extends_negative_12_bit_field() {
+            assert_eq!(sign_extend64(0xff4, 12), -12);
+        }
+
+        #[test]
+        fn extends_negative_32_bit_field() {
+            assert_eq!(sign_extend64(0xffff_ffff, 32), -1);
+        }
+
+        #[test]
+        fn full_width_reinterprets_value() {
+            assert_eq!(sign_extend64(0x8000_0000_0000_0000, 64), i64::MIN);
+        }
+
+        #[test]
+        #[should_panic]
+        fn panics_on_zero_width() {
+            sign_extend64(0x00, 0);
+        }
+    }
+}
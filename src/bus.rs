@@ -0,0 +1,751 @@
+//! System Bus
+//! Routes loads and stores to the CLINT, to a memory-mapped device
+//! registered over an address range, or otherwise to main memory.
+
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+
+use core::fmt::Debug;
+
+use crate::clint::{Clint, CLINT_BASE};
+use crate::emulator::HaltReason;
+use crate::isa::Endianness;
+use crate::memory::Memory;
+
+/// An error from a bus access.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum BusError {
+    /// A write touched a write-protected range.
+    WriteProtected(u32),
+
+    /// A read touched an address that isn't mapped to memory or a device.
+    Unmapped(u32),
+
+    /// The device at the given address failed the access.
+    Device(u32),
+
+    /// An access starting at the given address straddled a boundary
+    /// between regions, under `StraddlePolicy::Fault`.
+    Straddle(u32),
+}
+
+/// An error a device returns for an access it can't complete, such as one
+/// to a register it doesn't have. The bus reports it as
+/// `BusError::Device`, which the processor raises as an access fault.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct DeviceError;
+
+/// What a read of an unmapped address does. An address is unmapped if it
+/// isn't in main memory, the CLINT or a registered device.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum UnmappedReadPolicy {
+    /// Fail with `BusError::Unmapped`, so the processor raises an access
+    /// fault.
+    #[default]
+    Fault,
+
+    /// Read as zero.
+    Zero,
+
+    /// Read as the given value, truncated to the size of the read, so
+    /// stray reads stand out.
+    Poison(u64),
+
+    /// Wrap around to the start of main memory.
+    Wrap,
+}
+
+/// What an access does if it straddles a boundary between regions, such
+/// as a word load from the last bytes of memory before a device. Main
+/// memory, the CLINT and each device are separate regions.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum StraddlePolicy {
+    /// Fail with `BusError::Straddle`, so the processor raises an access
+    /// fault.
+    #[default]
+    Fault,
+
+    /// Split the access into byte accesses, each to the region containing
+    /// it. A write that fails part way leaves the earlier bytes written.
+    Split,
+}
+
+/// A memory-mapped device that can be attached to the bus.
+///
+/// Offsets are relative to the base address the device was registered at,
+/// and sizes are in bytes (1, 2, 4 or 8).
+pub trait MmioDevice {
+    /// Reads `size` bytes from the device at `offset`.
+    fn read(&mut self, offset: u32, size: usize) -> Result<u64, DeviceError>;
+
+    /// Writes the low `size` bytes of `value` to the device at `offset`.
+    fn write(&mut self, offset: u32, size: usize, value: u64) -> Result<(), DeviceError>;
+
+    /// Returns and clears the device's request to halt the emulator, such
+    /// as after a write to a poweroff register. Most devices never make one.
+    fn take_halt(&mut self) -> Option<HaltReason> {
+        None
+    }
+}
+
+/// A device mapped over `[base, base + size)`.
+struct MmioRegion {
+    base: u32,
+    size: u32,
+    latency: u64,
+    device: Box<dyn MmioDevice>,
+}
+
+impl MmioRegion {
+    /// Returns whether the region contains the given address.
+    fn contains(&self, addr: u32) -> bool {
+        addr.wrapping_sub(self.base) < self.size
+    }
+}
+
+/// The system bus, which owns main memory, the CLINT and any memory-mapped
+/// devices. Addresses that don't belong to a device are forwarded to memory.
+///
+/// Every access costs the latency of the region it lands in, in cycles.
+/// Cycles beyond the first are accumulated as stall cycles, which the
+/// processor collects after each step.
+pub struct Bus {
+    pub memory: Memory,
+    pub clint: Clint,
+
+    /// The access latency of main memory in cycles.
+    pub memory_latency: u64,
+
+    /// The access latency of the CLINT in cycles.
+    pub clint_latency: u64,
+
+    /// What reads of unmapped addresses do.
+    pub unmapped_read: UnmappedReadPolicy,
+
+    /// What accesses that straddle regions do.
+    pub straddle: StraddlePolicy,
+
+    /// The byte order of values in main memory. The CLINT and devices
+    /// always see values, not bytes, so aren't affected.
+    pub endianness: Endianness,
+
+    regions: Vec<MmioRegion>,
+    protected: Vec<(u32, u32)>,
+    stall_cycles: u64,
+}
+
+impl Bus {
+    /// Creates a new bus backed by the given memory, with a single-hart
+    /// CLINT and no other devices.
+    pub fn new(memory: Memory) -> Self {
+        Self {
+            memory,
+            clint: Clint::new(1),
+            memory_latency: 1,
+            clint_latency: 1,
+            unmapped_read: UnmappedReadPolicy::default(),
+            straddle: StraddlePolicy::default(),
+            endianness: Endianness::default(),
+            regions: Vec::new(),
+            protected: Vec::new(),
+            stall_cycles: 0,
+        }
+    }
+
+    /// Maps a device over `size` bytes starting at `base`, with an access
+    /// latency of one cycle.
+    ///
+    /// Panics if the range is empty or overlaps a device that has already
+    /// been registered.
+    pub fn register(&mut self, base: u32, size: u32, device: Box<dyn MmioDevice>) {
+        self.register_with_latency(base, size, 1, device);
+    }
+
+    /// Maps a device over `size` bytes starting at `base`, with the given
+    /// access latency in cycles.
+    ///
+    /// Panics if the range is empty or overlaps a device that has already
+    /// been registered.
+    pub fn register_with_latency(
+        &mut self,
+        base: u32,
+        size: u32,
+        latency: u64,
+        device: Box<dyn MmioDevice>,
+    ) {
+        if size == 0 {
+            panic!("Device region cannot be empty.");
+        }
+
+        let end = base as u64 + size as u64;
+
+        if self.regions.iter().any(|region| {
+            (base as u64) < region.base as u64 + region.size as u64
+                && (region.base as u64) < end
+        }) {
+            panic!("Device region {base:#010x}..{end:#010x} overlaps an existing device.");
+        }
+
+        self.regions.push(MmioRegion { base, size, latency, device });
+    }
+
+    /// Write-protects `[start, end)`, so that writes touching it fail until
+    /// it's unprotected. Reads are unaffected.
+    pub fn protect(&mut self, start: u32, end: u32) {
+        if !self.protected.contains(&(start, end)) {
+            self.protected.push((start, end));
+        }
+    }
+
+    /// Removes write protection from `[start, end)`, which must match a
+    /// range passed to `protect`. Does nothing if it isn't protected.
+    pub fn unprotect(&mut self, start: u32, end: u32) {
+        self.protected.retain(|range| *range != (start, end));
+    }
+
+    /// Returns whether any byte of `[addr, addr + size)` is write-protected.
+    pub fn is_protected(&self, addr: u32, size: usize) -> bool {
+        let end = addr as u64 + size as u64;
+
+        self.protected
+            .iter()
+            .any(|&(start, stop)| (addr as u64) < stop as u64 && (start as u64) < end)
+    }
+
+    /// Returns whether `addr` is in main memory, rather than the CLINT or
+    /// a device region.
+    pub fn is_memory(&self, addr: u32) -> bool {
+        !self.clint.contains(addr) && !self.regions.iter().any(|region| region.contains(addr))
+    }
+
+    /// Returns the stall cycles accumulated since the last call, resetting
+    /// the count to zero.
+    pub fn take_stall_cycles(&mut self) -> u64 {
+        core::mem::take(&mut self.stall_cycles)
+    }
+
+    /// Reads `size` bytes starting at `addr`, returned as a value in the
+    /// bus's byte order, applying `unmapped_read` if any byte of the read
+    /// is unmapped.
+    ///
+    /// This is how the processor reads. It fails if the device at `addr`
+    /// fails the read, or under the `Fault` policy.
+    pub fn try_read(&mut self, addr: u32, size: usize) -> Result<u64, BusError> {
+        let mapped = (0 .. size as u32).all(|i| {
+            let addr = addr.wrapping_add(i);
+
+            self.clint.contains(addr)
+                || self.regions.iter().any(|region| region.contains(addr))
+                || (addr as u64) < self.memory.len() as u64
+        });
+
+        if mapped {
+            return self.dispatch_read(addr, size);
+        }
+
+        let mask = match size {
+            8 => u64::MAX,
+            size => (1 << (size * 8)) - 1,
+        };
+
+        match self.unmapped_read {
+            UnmappedReadPolicy::Fault => Err(BusError::Unmapped(addr)),
+            UnmappedReadPolicy::Zero => Ok(0),
+            UnmappedReadPolicy::Poison(value) => Ok(value & mask),
+            UnmappedReadPolicy::Wrap => self.dispatch_read(addr, size),
+        }
+    }
+
+    /// Reads `size` bytes starting at `addr`, returned as a value in the
+    /// bus's byte order.
+    ///
+    /// Reads past the end of memory wrap around to its start, whatever
+    /// `unmapped_read` is, and a device failing the read reads as zero, so
+    /// the host can always inspect the machine.
+    pub fn read(&mut self, addr: u32, size: usize) -> u64 {
+        self.dispatch_read(addr, size).unwrap_or(0)
+    }
+
+    /// Reads like `dispatch_region_read`, handling reads that straddle
+    /// regions according to `straddle`.
+    fn dispatch_read(&mut self, addr: u32, size: usize) -> Result<u64, BusError> {
+        if !self.straddles(addr, size) {
+            return self.dispatch_region_read(addr, size);
+        }
+
+        match self.straddle {
+            StraddlePolicy::Fault => Err(BusError::Straddle(addr)),
+
+            StraddlePolicy::Split => {
+                let mut value = 0;
+
+                for i in 0 .. size {
+                    let byte = self.dispatch_region_read(addr.wrapping_add(i as u32), 1)? & 0xff;
+
+                    value |= match self.endianness {
+                        Endianness::Little => byte << (i * 8),
+                        Endianness::Big => byte << ((size - 1 - i) * 8),
+                    };
+                }
+
+                Ok(value)
+            },
+        }
+    }
+
+    /// Reads from the CLINT, the device at `addr`, or otherwise memory,
+    /// wrapping around past its end.
+    fn dispatch_region_read(&mut self, addr: u32, size: usize) -> Result<u64, BusError> {
+        if self.clint.contains(addr) {
+            self.stall(self.clint_latency);
+            return Ok(self.clint.read(addr - CLINT_BASE, size));
+        }
+
+        match self.region_mut(addr) {
+            Some(region) => {
+                let offset = addr - region.base;
+                let latency = region.latency;
+                let value = region.device.read(offset, size);
+                self.stall(latency);
+                value.map_err(|_| BusError::Device(addr))
+            },
+
+            None => {
+                self.stall(self.memory_latency);
+
+                let bytes = self.memory.read(addr as usize, size);
+                let fold = |value, byte: &u8| value << 8 | *byte as u64;
+
+                Ok(
+                    match self.endianness {
+                        Endianness::Little => bytes.iter().rev().fold(0, fold),
+                        Endianness::Big => bytes.iter().fold(0, fold),
+                    }
+                )
+            },
+        }
+    }
+
+    /// Writes the low `size` bytes of `value` starting at `addr` in the
+    /// bus's byte order.
+    ///
+    /// Fails without writing anything if the write touches a
+    /// write-protected range, or if the device at `addr` fails the write.
+    /// A write split under `StraddlePolicy::Split` stops at the first byte
+    /// that fails, leaving the bytes before it written.
+    pub fn write(&mut self, addr: u32, size: usize, value: u64) -> Result<(), BusError> {
+        if self.is_protected(addr, size) {
+            return Err(BusError::WriteProtected(addr));
+        }
+
+        if !self.straddles(addr, size) {
+            return self.dispatch_write(addr, size, value);
+        }
+
+        match self.straddle {
+            StraddlePolicy::Fault => Err(BusError::Straddle(addr)),
+
+            StraddlePolicy::Split => {
+                let bytes = match self.endianness {
+                    Endianness::Little => value.to_le_bytes(),
+                    Endianness::Big => (value << (64 - size * 8)).to_be_bytes(),
+                };
+
+                for (i, byte) in bytes[.. size].iter().enumerate() {
+                    self.dispatch_write(addr.wrapping_add(i as u32), 1, *byte as u64)?;
+                }
+
+                Ok(())
+            },
+        }
+    }
+
+    /// Writes to the CLINT, the device at `addr`, or otherwise memory.
+    fn dispatch_write(&mut self, addr: u32, size: usize, value: u64) -> Result<(), BusError> {
+        if self.clint.contains(addr) {
+            self.stall(self.clint_latency);
+            self.clint.write(addr - CLINT_BASE, size, value);
+            return Ok(());
+        }
+
+        match self.region_mut(addr) {
+            Some(region) => {
+                let offset = addr - region.base;
+                let latency = region.latency;
+                let result = region.device.write(offset, size, value);
+                self.stall(latency);
+                result.map_err(|_| BusError::Device(addr))?;
+            },
+
+            None => {
+                self.stall(self.memory_latency);
+
+                match self.endianness {
+                    Endianness::Little => self.memory.write(
+                        addr as usize,
+                        &value.to_le_bytes()[.. size],
+                    ),
+
+                    Endianness::Big => self.memory.write(
+                        addr as usize,
+                        &value.to_be_bytes()[8 - size ..],
+                    ),
+                }
+            },
+        }
+
+        Ok(())
+    }
+
+    /// Returns and clears the first pending halt request from a device.
+    pub fn take_halt(&mut self) -> Option<HaltReason> {
+        self.regions
+            .iter_mut()
+            .find_map(|region| region.device.take_halt())
+    }
+
+    /// Accounts for an access with the given latency. The first cycle of
+    /// every access is part of the instruction's own cycle.
+    fn stall(&mut self, latency: u64) {
+        self.stall_cycles += latency.saturating_sub(1);
+    }
+
+    /// Returns whether `[addr, addr + size)` touches more than one of main
+    /// memory, the CLINT and the devices.
+    fn straddles(&self, addr: u32, size: usize) -> bool {
+        let target = |addr: u32| match self.clint.contains(addr) {
+            true => Some(usize::MAX),
+            false => self.regions.iter().position(|region| region.contains(addr)),
+        };
+
+        let first = target(addr);
+
+        (1 .. size as u32).any(|i| target(addr.wrapping_add(i)) != first)
+    }
+
+    /// Returns the device region containing the given address, if any.
+    fn region_mut(&mut self, addr: u32) -> Option<&mut MmioRegion> {
+        self.regions
+            .iter_mut()
+            .find(|region| region.contains(addr))
+    }
+}
+
+impl Debug for Bus {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("Bus")
+            .field("memory", &self.memory)
+            .field("clint", &self.clint)
+            .field("memory_latency", &self.memory_latency)
+            .field("clint_latency", &self.clint_latency)
+            .field("unmapped_read", &self.unmapped_read)
+            .field("straddle", &self.straddle)
+            .field("protected", &self.protected)
+            .field(
+                "regions",
+                &self.regions
+                    .iter()
+                    .map(|region| (region.base, region.size, region.latency))
+                    .collect::<Vec<_>>(),
+            )
+            .finish()
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    use super::{
+        Bus,
+        BusError,
+        DeviceError,
+        Endianness,
+        Memory,
+        MmioDevice,
+        StraddlePolicy,
+        UnmappedReadPolicy,
+    };
+
+    /// The writes recorded by a `MockDevice`, as `(offset, size, value)`.
+    pub(crate) type WriteLog = Rc<RefCell<Vec<(u32, usize, u64)>>>;
+
+    /// A device that records every write and answers every read with a
+    /// fixed value.
+    pub(crate) struct MockDevice {
+        pub(crate) writes: WriteLog,
+        pub(crate) read_value: u64,
+    }
+
+    impl MmioDevice for MockDevice {
+        fn read(&mut self, _offset: u32, _size: usize) -> Result<u64, DeviceError> {
+            Ok(self.read_value)
+        }
+
+        fn write(&mut self, offset: u32, size: usize, value: u64) -> Result<(), DeviceError> {
+            self.writes.borrow_mut().push((offset, size, value));
+            Ok(())
+        }
+    }
+
+    /// A device that fails every access to one offset, and reads as zero
+    /// everywhere else.
+    pub(crate) struct FaultyDevice {
+        pub(crate) fault_offset: u32,
+    }
+
+    impl MmioDevice for FaultyDevice {
+        fn read(&mut self, offset: u32, _size: usize) -> Result<u64, DeviceError> {
+            match offset == self.fault_offset {
+                true => Err(DeviceError),
+                false => Ok(0),
+            }
+        }
+
+        fn write(&mut self, offset: u32, _size: usize, _value: u64) -> Result<(), DeviceError> {
+            match offset == self.fault_offset {
+                true => Err(DeviceError),
+                false => Ok(()),
+            }
+        }
+    }
+
+    fn bus_with_mock(read_value: u64) -> (Bus, WriteLog) {
+        let writes = Rc::new(RefCell::new(Vec::new()));
+        let mut bus = Bus::new(Memory::new(256));
+
+        bus.register(
+            0x1000,
+            0x100,
+            Box::new(MockDevice { writes: writes.clone(), read_value }),
+        );
+
+        (bus, writes)
+    }
+
+    #[test]
+    fn reads_memory_little_endian() {
+        let mut bus = Bus::new(Memory::new(256));
+        bus.memory.write(0x10, &[0x78, 0x56, 0x34, 0x12]);
+        assert_eq!(bus.read(0x10, 4), 0x12345678);
+    }
+
+    #[test]
+    fn writes_memory_little_endian() {
+        let mut bus = Bus::new(Memory::new(256));
+        bus.write(0x10, 2, 0xbeef).unwrap();
+        assert_eq!(bus.memory.read(0x10, 2), &[0xef, 0xbe]);
+    }
+
+    #[test]
+    fn big_endian_memory_stores_most_significant_byte_first() {
+        let mut bus = Bus::new(Memory::new(256));
+        bus.endianness = Endianness::Big;
+        bus.write(0x10, 4, 0x12345678).unwrap();
+        assert_eq!(bus.memory.read(0x10, 4), &[0x12, 0x34, 0x56, 0x78]);
+        assert_eq!(bus.read(0x10, 2), 0x1234);
+    }
+
+    #[test]
+    fn dispatches_writes_to_device_at_offset() {
+        let (mut bus, writes) = bus_with_mock(0x00);
+        bus.write(0x1008, 4, 0xcafe).unwrap();
+        assert_eq!(*writes.borrow(), &[(0x08, 4, 0xcafe)]);
+    }
+
+    #[test]
+    fn dispatches_reads_to_device() {
+        let (mut bus, _) = bus_with_mock(0xdead);
+        assert_eq!(bus.read(0x10fc, 4), 0xdead);
+    }
+
+    #[test]
+    fn addresses_outside_device_go_to_memory() {
+        let (mut bus, writes) = bus_with_mock(0xdead);
+        bus.write(0x1100, 1, 0xff).unwrap();
+        assert!(writes.borrow().is_empty());
+        assert_eq!(bus.read(0x1100, 1), 0xff);
+    }
+
+    #[test]
+    fn dispatches_clint_accesses() {
+        let mut bus = Bus::new(Memory::new(256));
+        bus.clint.mtime = 0x2a;
+        assert_eq!(bus.read(0x0200_bff8, 4), 0x2a);
+        bus.write(0x0200_4000, 8, 0x10).unwrap();
+        assert_eq!(bus.clint.mtimecmp[0], 0x10);
+    }
+
+    #[test]
+    fn accumulates_stall_cycles_beyond_first() {
+        let mut bus = Bus::new(Memory::new(256));
+
+        bus.register_with_latency(
+            0x1000,
+            0x100,
+            10,
+            Box::new(MockDevice { writes: Rc::default(), read_value: 0x00 }),
+        );
+
+        bus.read(0x00, 4);
+        assert_eq!(bus.take_stall_cycles(), 0);
+
+        bus.read(0x1000, 4);
+        bus.write(0x1000, 4, 0x00).unwrap();
+        assert_eq!(bus.take_stall_cycles(), 18);
+        assert_eq!(bus.take_stall_cycles(), 0);
+    }
+
+    #[test]
+    fn protected_range_rejects_writes() {
+        let mut bus = Bus::new(Memory::new(256));
+        bus.memory.write(0x20, &[0xaa]);
+        bus.protect(0x20, 0x30);
+
+        assert_eq!(bus.write(0x20, 1, 0xff), Err(BusError::WriteProtected(0x20)));
+        assert_eq!(bus.read(0x20, 1), 0xaa);
+    }
+
+    #[test]
+    fn write_overlapping_protected_range_is_rejected() {
+        let mut bus = Bus::new(Memory::new(256));
+        bus.protect(0x20, 0x30);
+
+        assert!(bus.write(0x1e, 4, 0x00).is_err());
+        assert!(bus.write(0x30, 4, 0x00).is_ok());
+    }
+
+    #[test]
+    fn unprotecting_restores_writes() {
+        let mut bus = Bus::new(Memory::new(256));
+        bus.protect(0x20, 0x30);
+        bus.unprotect(0x20, 0x30);
+
+        assert_eq!(bus.write(0x20, 1, 0xff), Ok(()));
+        assert_eq!(bus.read(0x20, 1), 0xff);
+    }
+
+    #[test]
+    fn device_errors_are_reported_with_address() {
+        let mut bus = Bus::new(Memory::new(256));
+        bus.register(0x1000, 0x100, Box::new(FaultyDevice { fault_offset: 0x08 }));
+
+        assert_eq!(bus.write(0x1008, 4, 0xff), Err(BusError::Device(0x1008)));
+        assert_eq!(bus.try_read(0x1008, 4), Err(BusError::Device(0x1008)));
+        assert_eq!(bus.write(0x1004, 4, 0xff), Ok(()));
+        assert_eq!(bus.read(0x1008, 4), 0);
+    }
+
+    #[test]
+    fn unmapped_read_faults_by_default() {
+        let mut bus = Bus::new(Memory::new(256));
+        assert_eq!(bus.try_read(0x200, 4), Err(BusError::Unmapped(0x200)));
+
+        // Straddling the end of memory.
+        assert_eq!(bus.try_read(0xfe, 4), Err(BusError::Unmapped(0xfe)));
+        assert_eq!(bus.try_read(0xfc, 4), Ok(0));
+    }
+
+    #[test]
+    fn unmapped_read_returns_zero_under_zero_policy() {
+        let mut bus = Bus::new(Memory::new(256));
+        bus.memory.write(0x00, &[0xaa]);
+        bus.unmapped_read = UnmappedReadPolicy::Zero;
+
+        assert_eq!(bus.try_read(0x100, 1), Ok(0));
+    }
+
+    #[test]
+    fn unmapped_read_returns_truncated_poison() {
+        let mut bus = Bus::new(Memory::new(256));
+        bus.unmapped_read = UnmappedReadPolicy::Poison(0xdeadbeef);
+
+        assert_eq!(bus.try_read(0x100, 4), Ok(0xdeadbeef));
+        assert_eq!(bus.try_read(0x100, 2), Ok(0xbeef));
+    }
+
+    #[test]
+    fn device_reads_are_mapped() {
+        let (mut bus, _) = bus_with_mock(0xdead);
+        assert_eq!(bus.try_read(0x10fc, 4), Ok(0xdead));
+    }
+
+    /// Returns a bus with 256 bytes of memory followed directly by a
+    /// `MockDevice`, with memory ending `0xaa, 0xbb`.
+    fn bus_with_device_after_memory(straddle: StraddlePolicy) -> (Bus, WriteLog) {
+        let writes = Rc::new(RefCell::new(Vec::new()));
+        let mut bus = Bus::new(Memory::new(256));
+
+        bus.register(0x100, 0x100, Box::new(MockDevice { writes: writes.clone(), read_value: 0xcc }));
+        bus.memory.write(0xfe, &[0xaa, 0xbb]);
+        bus.straddle = straddle;
+
+        (bus, writes)
+    }
+
+    #[test]
+    fn straddling_access_faults_by_default() {
+        let (mut bus, writes) = bus_with_device_after_memory(StraddlePolicy::default());
+
+        assert_eq!(bus.try_read(0xfe, 4), Err(BusError::Straddle(0xfe)));
+        assert_eq!(bus.write(0xfe, 4, 0x11223344), Err(BusError::Straddle(0xfe)));
+
+        assert_eq!(bus.memory.read(0xfe, 2), [0xaa, 0xbb]);
+        assert!(writes.borrow().is_empty());
+    }
+
+    #[test]
+    fn split_policy_sends_each_byte_to_its_region() {
+        let (mut bus, writes) = bus_with_device_after_memory(StraddlePolicy::Split);
+
+        assert_eq!(bus.try_read(0xfe, 4), Ok(0xccccbbaa));
+
+        assert_eq!(bus.write(0xfe, 4, 0x11223344), Ok(()));
+        assert_eq!(bus.memory.read(0xfe, 2), [0x44, 0x33]);
+        assert_eq!(*writes.borrow(), [(0x00, 1, 0x22), (0x01, 1, 0x11)]);
+    }
+
+    #[test]
+    fn split_policy_respects_big_endian_memory() {
+        let (mut bus, writes) = bus_with_device_after_memory(StraddlePolicy::Split);
+        bus.endianness = Endianness::Big;
+
+        assert_eq!(bus.try_read(0xfe, 4), Ok(0xaabbcccc));
+
+        assert_eq!(bus.write(0xfe, 4, 0x11223344), Ok(()));
+        assert_eq!(bus.memory.read(0xfe, 2), [0x11, 0x22]);
+        assert_eq!(*writes.borrow(), [(0x00, 1, 0x33), (0x01, 1, 0x44)]);
+    }
+
+    #[test]
+    fn split_write_keeps_bytes_written_before_a_fault() {
+        let mut bus = Bus::new(Memory::new(256));
+        bus.register(0x100, 0x100, Box::new(FaultyDevice { fault_offset: 0x00 }));
+        bus.memory.write(0xfe, &[0xaa, 0xbb]);
+        bus.straddle = StraddlePolicy::Split;
+
+        assert_eq!(bus.write(0xfe, 4, 0x11223344), Err(BusError::Device(0x100)));
+        assert_eq!(bus.memory.read(0xfe, 2), [0x44, 0x33]);
+    }
+
+    #[test]
+    fn accesses_within_one_region_do_not_straddle() {
+        let (mut bus, _) = bus_with_device_after_memory(StraddlePolicy::default());
+
+        assert_eq!(bus.try_read(0xfc, 4), Ok(0xbbaa0000));
+        assert_eq!(bus.try_read(0x100, 4), Ok(0xcc));
+    }
+
+    #[test]
+    #[should_panic]
+    fn panics_on_overlapping_devices() {
+        let (mut bus, writes) = bus_with_mock(0x00);
+
+        bus.register(
+            0x10f0,
+            0x20,
+            Box::new(MockDevice { writes, read_value: 0x00 }),
+        );
+    }
+}
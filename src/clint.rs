@@ -0,0 +1,207 @@
+//! Core-Local Interruptor (CLINT)
+//! Provides the machine timer (`mtime` / `mtimecmp`) and the machine
+//! software interrupt (`msip`) registers for each hart, using the
+//! SiFive-compatible memory map.
+//!
+//! `mtime` is not tied to wall-clock time. It only advances when
+//! `tick` is called, so runs are reproducible.
+
+use alloc::vec;
+use alloc::vec::Vec;
+
+/// The base address the CLINT is mapped at.
+pub const CLINT_BASE: u32 = 0x0200_0000;
+
+/// The size of the CLINT's address range in bytes.
+pub const CLINT_SIZE: u32 = 0x0001_0000;
+
+/// Offset of the per-hart 32-bit `msip` registers.
+const MSIP: u32 = 0x0000;
+
+/// Offset of the per-hart 64-bit `mtimecmp` registers.
+const MTIMECMP: u32 = 0x4000;
+
+/// Offset of the shared 64-bit `mtime` register.
+const MTIME: u32 = 0xbff8;
+
+/// Offset just past `mtime`. The rest of the CLINT's range is unmapped.
+const MTIME_END: u32 = MTIME + 8;
+
+/// The CLINT's timer and software interrupt state.
+#[derive(Debug)]
+pub struct Clint {
+    /// The current time, in ticks.
+    pub mtime: u64,
+
+    /// The per-hart timer compare values.
+    pub mtimecmp: Vec<u64>,
+
+    /// The per-hart software interrupt pending bits.
+    pub msip: Vec<bool>,
+}
+
+impl Clint {
+    /// Creates a new CLINT for the given number of harts, with `mtime` at
+    /// zero and no timer or software interrupts pending.
+    pub fn new(hart_count: usize) -> Self {
+        Self {
+            mtime: 0,
+            mtimecmp: vec![u64::MAX; hart_count],
+            msip: vec![false; hart_count],
+        }
+    }
+
+    /// Returns whether the given address belongs to the CLINT.
+    pub fn contains(&self, addr: u32) -> bool {
+        addr.wrapping_sub(CLINT_BASE) < CLINT_SIZE
+    }
+
+    /// Advances `mtime` by the given number of ticks.
+    pub fn tick(&mut self, ticks: u64) {
+        self.mtime = self.mtime.wrapping_add(ticks);
+    }
+
+    /// Returns whether the given hart has a timer interrupt pending.
+    pub fn timer_pending(&self, hart: usize) -> bool {
+        self.mtimecmp
+            .get(hart)
+            .is_some_and(|&mtimecmp| self.mtime >= mtimecmp)
+    }
+
+    /// Returns whether the given hart has a software interrupt pending.
+    pub fn software_pending(&self, hart: usize) -> bool {
+        self.msip
+            .get(hart)
+            .is_some_and(|&msip| msip)
+    }
+
+    /// Reads `size` bytes at the given offset from the CLINT's base address.
+    /// Unmapped offsets read as zero.
+    pub fn read(&self, offset: u32, size: usize) -> u64 {
+        match self.register(offset) {
+            Some((value, shift)) => value >> shift & mask(size),
+            None => 0,
+        }
+    }
+
+    /// Writes the low `size` bytes of `value` at the given offset from the
+    /// CLINT's base address. Writes to unmapped offsets are ignored.
+    pub fn write(&mut self, offset: u32, size: usize, value: u64) {
+        let Some((current, shift)) = self.register(offset) else {
+            return;
+        };
+
+        let mask = mask(size) << shift;
+        let value = current & !mask | value << shift & mask;
+
+        match offset {
+            MSIP .. MTIMECMP => {
+                self.msip[(offset - MSIP) as usize / 4] = value & 0x01 != 0;
+            },
+
+            MTIMECMP .. MTIME => {
+                self.mtimecmp[(offset - MTIMECMP) as usize / 8] = value;
+            },
+
+            _ => self.mtime = value,
+        }
+    }
+
+    /// Returns the value of the register containing the given offset, along
+    /// with the bit position of the offset within it.
+    fn register(&self, offset: u32) -> Option<(u64, u32)> {
+        match offset {
+            MSIP .. MTIMECMP => {
+                let hart = (offset - MSIP) as usize / 4;
+                let value = *self.msip.get(hart)? as u64;
+                Some((value, (offset - MSIP) % 4 * 8))
+            },
+
+            MTIMECMP .. MTIME => {
+                let hart = (offset - MTIMECMP) as usize / 8;
+                let value = *self.mtimecmp.get(hart)?;
+                Some((value, (offset - MTIMECMP) % 8 * 8))
+            },
+
+            MTIME .. MTIME_END => {
+                Some((self.mtime, (offset - MTIME) * 8))
+            },
+
+            _ => None,
+        }
+    }
+}
+
+/// Returns a mask covering the low `size` bytes of a 64-bit value.
+fn mask(size: usize) -> u64 {
+    match size {
+        8 => u64::MAX,
+        _ => (1 << (size * 8)) - 1,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Clint, MTIME, MTIMECMP, MSIP};
+
+    #[test]
+    fn advances_mtime_by_ticks() {
+        let mut clint = Clint::new(1);
+        clint.tick(3);
+        clint.tick(4);
+        assert_eq!(clint.mtime, 7);
+    }
+
+    #[test]
+    fn reads_mtime_in_halves() {
+        let mut clint = Clint::new(1);
+        clint.mtime = 0x1122_3344_5566_7788;
+        assert_eq!(clint.read(MTIME, 4), 0x5566_7788);
+        assert_eq!(clint.read(MTIME + 4, 4), 0x1122_3344);
+    }
+
+    #[test]
+    fn writes_mtimecmp_in_halves() {
+        let mut clint = Clint::new(2);
+        clint.write(MTIMECMP + 8, 4, 0x10);
+        clint.write(MTIMECMP + 12, 4, 0x00);
+        assert_eq!(clint.mtimecmp, [u64::MAX, 0x10]);
+    }
+
+    #[test]
+    fn raises_timer_interrupt_when_mtime_reaches_mtimecmp() {
+        let mut clint = Clint::new(1);
+        clint.mtimecmp[0] = 5;
+        clint.tick(4);
+        assert!(!clint.timer_pending(0));
+        clint.tick(1);
+        assert!(clint.timer_pending(0));
+    }
+
+    #[test]
+    fn sets_msip_from_low_bit() {
+        let mut clint = Clint::new(1);
+        clint.write(MSIP, 4, 0x01);
+        assert!(clint.software_pending(0));
+        assert_eq!(clint.read(MSIP, 4), 0x01);
+    }
+
+    #[test]
+    fn offsets_past_mtime_are_unmapped() {
+        let mut clint = Clint::new(1);
+        clint.mtime = u64::MAX;
+
+        clint.write(MTIME + 8, 4, 0x01);
+        assert_eq!(clint.read(MTIME + 8, 4), 0x00);
+        assert_eq!(clint.read(0xc000, 4), 0x00);
+        assert_eq!(clint.mtime, u64::MAX);
+    }
+
+    #[test]
+    fn ignores_registers_of_missing_harts() {
+        let mut clint = Clint::new(1);
+        clint.write(MTIMECMP + 8, 8, 0x00);
+        assert_eq!(clint.read(MTIMECMP + 8, 8), 0x00);
+        assert!(!clint.timer_pending(1));
+    }
+}
@@ -0,0 +1,112 @@
+//! Control and Status Registers (CSRs)
+//! Addresses of the CSRs implemented by the processor.
+
+/// Machine status register.
+pub const MSTATUS: usize = 0x300;
+
+/// Machine ISA register, which reports the register width and enabled
+/// extensions. It's read-only, and writes to it are ignored.
+pub const MISA: usize = 0x301;
+
+/// Machine interrupt-enable register.
+pub const MIE: usize = 0x304;
+
+/// Machine trap-handler base address.
+pub const MTVEC: usize = 0x305;
+
+/// Supervisor trap-handler base address.
+pub const STVEC: usize = 0x105;
+
+/// Supervisor scratch register, for use by trap handlers.
+pub const SSCRATCH: usize = 0x140;
+
+/// Supervisor exception program counter.
+pub const SEPC: usize = 0x141;
+
+/// Supervisor trap cause.
+pub const SCAUSE: usize = 0x142;
+
+/// Supervisor bad address or instruction.
+pub const STVAL: usize = 0x143;
+
+/// Machine exception delegation register. Exceptions whose cause bit is
+/// set are taken in S-mode when raised in S-mode or U-mode.
+pub const MEDELEG: usize = 0x302;
+
+/// Machine interrupt delegation register, the `medeleg` of interrupts.
+pub const MIDELEG: usize = 0x303;
+
+/// Machine scratch register, for use by trap handlers.
+pub const MSCRATCH: usize = 0x340;
+
+/// Machine exception program counter.
+pub const MEPC: usize = 0x341;
+
+/// Machine trap cause.
+pub const MCAUSE: usize = 0x342;
+
+/// Machine bad address or instruction.
+pub const MTVAL: usize = 0x343;
+
+/// Machine interrupt pending.
+pub const MIP: usize = 0x344;
+
+/// The CSRs that guest code is allowed to write.
+pub const READ_WRITE: [usize; 15] = [
+    STVEC,
+    SSCRATCH,
+    SEPC,
+    SCAUSE,
+    STVAL,
+    MSTATUS,
+    MEDELEG,
+    MIDELEG,
+    MIE,
+    MTVEC,
+    MSCRATCH,
+    MEPC,
+    MCAUSE,
+    MTVAL,
+    MIP,
+];
+
+/// Returns whether the CSR at the given address is implemented. Accessing
+/// any other CSR is an illegal instruction.
+pub fn is_implemented(addr: usize) -> bool {
+    addr == MISA || READ_WRITE.contains(&addr)
+}
+
+/// Returns the lowest privilege level that may access the CSR at the given
+/// address, encoded as in `mstatus.MPP`. It's held in bits 9:8 of the
+/// address.
+pub fn min_privilege(addr: usize) -> u32 {
+    (addr >> 8 & 0x03) as u32
+}
+
+/// `mstatus` supervisor interrupt-enable bit.
+pub const MSTATUS_SIE: u32 = 1 << 1;
+
+/// `mstatus` machine interrupt-enable bit.
+pub const MSTATUS_MIE: u32 = 1 << 3;
+
+/// `mstatus` supervisor previous interrupt-enable bit.
+pub const MSTATUS_SPIE: u32 = 1 << 5;
+
+/// `mstatus` machine previous interrupt-enable bit.
+pub const MSTATUS_MPIE: u32 = 1 << 7;
+
+/// `mstatus` supervisor previous privilege bit, set if the trap was taken
+/// from S-mode and clear if it was taken from U-mode.
+pub const MSTATUS_SPP: u32 = 1 << 8;
+
+/// `mstatus` machine previous privilege field.
+pub const MSTATUS_MPP: u32 = 0x03 << 11;
+
+/// `misa` MXL field value for a 32-bit register width.
+pub const MISA_MXL_32: u32 = 0x01 << 30;
+
+/// `mtvec` mode field, which selects direct or vectored interrupts.
+pub const MTVEC_MODE: u32 = 0x03;
+
+/// `mtvec` mode in which interrupts vector to `BASE + 4 * cause`.
+pub const MTVEC_MODE_VECTORED: u32 = 0x01;
@@ -1,3 +1,7 @@
+//! Decoder
+//! Decodes raw instructions into the operations they perform.
+
+use crate::isa::Extension;
 use crate::instruction::{
     Instruction,
     InstructionFormat::*,
@@ -7,12 +11,154 @@ use crate::op::{
     Op::*,
 };
 
+/// The `pause` hint from Zihintpause, encoded as `fence w, 0`.
+const PAUSE: u32 = 0x0100000f;
+
+/// The fields that identify an operation in `Decoder::table`. A field
+/// of `None` may take any value.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct DecodeKey {
+    pub opcode: u8,
+    pub funct3: Option<u8>,
+    pub funct7: Option<u8>,
+
+    /// The whole 12-bit immediate, for the operations it tells apart, such
+    /// as `ecall` and `ebreak`. These also require `rd` and `rs1` to be
+    /// zero.
+    pub funct12: Option<u16>,
+}
+
+impl DecodeKey {
+    /// Creates a key matching any instruction with the given opcode.
+    const fn new(opcode: u8) -> Self {
+        Self {
+            opcode,
+            funct3: None,
+            funct7: None,
+            funct12: None,
+        }
+    }
+
+    /// Returns the key with `funct3` fixed to the given value.
+    const fn funct3(self, funct3: u8) -> Self {
+        Self { funct3: Some(funct3), ..self }
+    }
+
+    /// Returns the key with `funct7` fixed to the given value.
+    const fn funct7(self, funct7: u8) -> Self {
+        Self { funct7: Some(funct7), ..self }
+    }
+
+    /// Returns the key with `funct12` fixed to the given value.
+    const fn funct12(self, funct12: u16) -> Self {
+        Self { funct12: Some(funct12), ..self }
+    }
+
+    /// Returns whether `instr` has every field the key fixes.
+    pub fn matches(&self, instr: &Instruction) -> bool {
+        let raw = instr.raw();
+
+        raw & 0x7f == self.opcode as u32
+            && self.funct3.is_none_or(|funct3| raw >> 12 & 0x07 == funct3 as u32)
+            && self.funct7.is_none_or(|funct7| raw >> 25 == funct7 as u32)
+            && self.funct12.is_none_or(|funct12| {
+                raw >> 20 == funct12 as u32 && raw >> 7 & 0x1f == 0 && raw >> 15 & 0x1f == 0
+            })
+    }
+
+    /// Returns the instruction with the key's fields set and every other
+    /// field zero.
+    pub fn canonical(&self) -> Instruction {
+        Instruction::new(
+            self.opcode as u32
+                | (self.funct3.unwrap_or(0) as u32) << 12
+                | (self.funct7.unwrap_or(0) as u32) << 25
+                | (self.funct12.unwrap_or(0) as u32) << 20
+        )
+    }
+}
+
+/// Every operation the decoder recognises and the fields that identify
+/// it, in the order the decoder tries them, so more specific keys come
+/// before the keys they overlap. `decode` must agree with the first key
+/// that matches each instruction, which the tests check.
+const TABLE: [(DecodeKey, Op); 59] = [
+    (DecodeKey::new(0x63).funct3(0x00),                BranchEqual),
+    (DecodeKey::new(0x63).funct3(0x01),                BranchNotEqual),
+    (DecodeKey::new(0x63).funct3(0x04),                BranchLessThan),
+    (DecodeKey::new(0x63).funct3(0x05),                BranchGreaterThanOrEqualTo),
+    (DecodeKey::new(0x63).funct3(0x06),                BranchLessThanUnsigned),
+    (DecodeKey::new(0x63).funct3(0x07),                BranchGreaterThanOrEqualToUnsigned),
+    (DecodeKey::new(0x03).funct3(0x00),                LoadByte),
+    (DecodeKey::new(0x03).funct3(0x01),                LoadHalf),
+    (DecodeKey::new(0x03).funct3(0x02),                LoadWord),
+    (DecodeKey::new(0x03).funct3(0x04),                LoadByteUnsigned),
+    (DecodeKey::new(0x03).funct3(0x05),                LoadHalfUnsigned),
+    (DecodeKey::new(0x0f).funct3(0x00).funct12(0x010), Pause),
+    (DecodeKey::new(0x0f).funct3(0x00),                Fence),
+    (DecodeKey::new(0x0f).funct3(0x01),                FenceI),
+    (DecodeKey::new(0x13).funct3(0x00),                ArithmeticAddImmediate),
+    (DecodeKey::new(0x13).funct3(0x01),                ShiftLeftLogicalImmediate),
+    (DecodeKey::new(0x13).funct3(0x02),                SetLessThanImmediate),
+    (DecodeKey::new(0x13).funct3(0x03),                SetLessThanImmediateUnsigned),
+    (DecodeKey::new(0x13).funct3(0x04),                LogicalExclusiveOrImmediate),
+    (DecodeKey::new(0x13).funct3(0x05).funct7(0x00),   ShiftRightLogicalImmediate),
+    (DecodeKey::new(0x13).funct3(0x05).funct7(0x20),   ShiftRightArithmeticImmediate),
+    (DecodeKey::new(0x13).funct3(0x06),                LogicalOrImmediate),
+    (DecodeKey::new(0x13).funct3(0x07),                LogicalAndImmediate),
+    (DecodeKey::new(0x67).funct3(0x00),                JumpAndLinkRegister),
+    (DecodeKey::new(0x73).funct3(0x00).funct12(0x000), SystemEcall),
+    (DecodeKey::new(0x73).funct3(0x00).funct12(0x001), SystemEbreak),
+    (DecodeKey::new(0x73).funct3(0x00).funct12(0x102), SystemSupervisorReturn),
+    (DecodeKey::new(0x73).funct3(0x00).funct12(0x105), SystemWaitForInterrupt),
+    (DecodeKey::new(0x73).funct3(0x00).funct12(0x302), SystemMachineReturn),
+    (DecodeKey::new(0x73).funct3(0x01),                CsrReadWrite),
+    (DecodeKey::new(0x73).funct3(0x02),                CsrReadSet),
+    (DecodeKey::new(0x73).funct3(0x03),                CsrReadClear),
+    (DecodeKey::new(0x73).funct3(0x05),                CsrReadWriteImmediate),
+    (DecodeKey::new(0x73).funct3(0x06),                CsrReadSetImmediate),
+    (DecodeKey::new(0x73).funct3(0x07),                CsrReadClearImmediate),
+    (DecodeKey::new(0x6f),                             JumpAndLink),
+    (DecodeKey::new(0x33).funct3(0x00).funct7(0x00),   ArithmeticAdd),
+    (DecodeKey::new(0x33).funct3(0x00).funct7(0x20),   ArithmeticSub),
+    (DecodeKey::new(0x33).funct3(0x01).funct7(0x00),   ShiftLeftLogical),
+    (DecodeKey::new(0x33).funct3(0x02).funct7(0x00),   SetLessThan),
+    (DecodeKey::new(0x33).funct3(0x03).funct7(0x00),   SetLessThanUnsigned),
+    (DecodeKey::new(0x33).funct3(0x04).funct7(0x00),   LogicalExclusiveOr),
+    (DecodeKey::new(0x33).funct3(0x05).funct7(0x00),   ShiftRightLogical),
+    (DecodeKey::new(0x33).funct3(0x05).funct7(0x20),   ShiftRightArithmetic),
+    (DecodeKey::new(0x33).funct3(0x06).funct7(0x00),   LogicalOr),
+    (DecodeKey::new(0x33).funct3(0x07).funct7(0x00),   LogicalAnd),
+    (DecodeKey::new(0x33).funct3(0x00).funct7(0x01),   ArithmeticMul),
+    (DecodeKey::new(0x33).funct3(0x01).funct7(0x01),   ArithmeticMulHigh),
+    (DecodeKey::new(0x33).funct3(0x02).funct7(0x01),   ArithmeticMulHighSignedUnsigned),
+    (DecodeKey::new(0x33).funct3(0x03).funct7(0x01),   ArithmeticMulHighUnsigned),
+    (DecodeKey::new(0x33).funct3(0x04).funct7(0x01),   ArithmeticDiv),
+    (DecodeKey::new(0x33).funct3(0x05).funct7(0x01),   ArithmeticDivUnsigned),
+    (DecodeKey::new(0x33).funct3(0x06).funct7(0x01),   ArithmeticRem),
+    (DecodeKey::new(0x33).funct3(0x07).funct7(0x01),   ArithmeticRemUnsigned),
+    (DecodeKey::new(0x23).funct3(0x00),                StoreByte),
+    (DecodeKey::new(0x23).funct3(0x01),                StoreHalf),
+    (DecodeKey::new(0x23).funct3(0x02),                StoreWord),
+    (DecodeKey::new(0x17),                             AddUpperImmediateProgramCounter),
+    (DecodeKey::new(0x37),                             LoadUpperImmediate),
+];
+
 /// Decodes an instruction into an operation.
 pub struct Decoder;
 
 impl Decoder {
+    /// Returns every operation the decoder recognises, with the fields
+    /// that identify it. Compressed instructions aren't included, as they
+    /// decode through their expansions.
+    pub fn table() -> impl Iterator<Item = (DecodeKey, Op)> {
+        TABLE.into_iter()
+    }
+
+    /// Decodes an instruction, returning None if it isn't a supported
+    /// operation.
     pub fn decode(instr: &Instruction) -> Option<Op> {
-        match instr.format() {
+        match instr.format()? {
             B => Decoder::decode_instr_b(instr),
             I => Decoder::decode_instr_i(instr),
             J => Decoder::decode_instr_j(instr),
@@ -22,6 +168,110 @@ impl Decoder {
         }
     }
 
+    /// Returns whether a decodable instruction has non-canonical values in
+    /// fields the ISA reserves, which the lenient `decode` ignores:
+    ///
+    /// - `fence` and `fence.i` with a nonzero rd or rs1.
+    /// - `fence.i` with a nonzero immediate.
+    /// - `slli` with a nonzero upper immediate, which on RV32 would be a
+    ///   shift amount of 32 or more.
+    ///
+    /// Strict decoding treats these as illegal, to validate that a
+    /// toolchain emits canonical encodings.
+    pub fn is_reserved(instr: &Instruction) -> bool {
+        let raw = instr.raw();
+        let rd = raw >> 7 & 0x1f;
+        let rs1 = raw >> 15 & 0x1f;
+
+        match Decoder::decode(instr) {
+            Some(Fence | Pause) => rd != 0 || rs1 != 0,
+            Some(FenceI) => rd != 0 || rs1 != 0 || raw >> 20 != 0,
+            Some(ShiftLeftLogicalImmediate) => raw >> 25 != 0,
+            _ => false,
+        }
+    }
+
+    /// Returns whether every register an instruction reads or writes is
+    /// below `count`, such as the 16 registers of RV32E. Instructions that
+    /// can't be decoded reference no registers.
+    pub fn uses_registers_below(instr: &Instruction, count: usize) -> bool {
+        instr
+            .source_regs()
+            .into_iter()
+            .chain(instr.dest_reg())
+            .all(|reg| reg < count)
+    }
+
+    /// Decodes an instruction like `decode`, but also returns None if it
+    /// has non-canonical values in reserved fields. See `is_reserved`.
+    pub fn decode_strict(instr: &Instruction) -> Option<Op> {
+        Decoder::decode(instr).filter(|_| !Decoder::is_reserved(instr))
+    }
+
+    /// Expands a compressed (16-bit) instruction into its 32-bit
+    /// equivalent, returning None if it isn't a supported encoding.
+    ///
+    /// Only `c.nop`, `c.ebreak`, `c.jr` and `c.jalr` are supported.
+    pub fn expand(halfword: u16) -> Option<Instruction> {
+        let rs1 = (halfword >> 7 & 0x1f) as u32;
+        let rs2 = halfword >> 2 & 0x1f;
+
+        let raw = match (halfword >> 12, rs1, rs2, halfword & 0x03) {
+            // c.nop => addi x0, x0, 0
+            _ if halfword == 0x0001     => 0x00000013,
+            // c.ebreak => ebreak
+            (0x09, 0x00, 0x00, 0x02)    => 0x00100073,
+            // c.jr rs1 => jalr x0, 0(rs1)
+            (0x08, rs1, 0x00, 0x02) if rs1 != 0
+                                        => rs1 << 15 | 0x00000067,
+            // c.jalr rs1 => jalr x1, 0(rs1)
+            (0x09, rs1, 0x00, 0x02)     => rs1 << 15 | 0x000000e7,
+            _                           => return None,
+        };
+
+        Some(Instruction::new(raw))
+    }
+
+    /// Decodes a compressed (16-bit) instruction into the operation it
+    /// expands to.
+    pub fn decode_compressed(halfword: u16) -> Option<Op> {
+        Decoder::decode(&Decoder::expand(halfword)?)
+    }
+
+    /// Returns the extension an instruction belongs to, or None if its
+    /// encoding isn't recognised.
+    ///
+    /// Besides the operations that can be decoded, this recognises the
+    /// encoding spaces of the A, F, D and C extensions, so instructions from
+    /// them can be reported by extension rather than as illegal.
+    pub fn extension(instr: &Instruction) -> Option<Extension> {
+        if let Some(op) = Decoder::decode(instr) {
+            return Some(op.extension());
+        }
+
+        let raw = instr.raw();
+
+        // The all-zero halfword is illegal in every extension.
+        if raw & 0xffff == 0 {
+            return None;
+        }
+
+        // The funct3 of FP loads and stores holds the width, and the low
+        // bits of funct7 of other FP operations hold the format.
+        match (raw & 0x7f, raw >> 12 & 0x07, raw >> 25 & 0x03) {
+            (opcode, _, _) if opcode & 0x03 != 0x03
+                                        => Some(Extension::C),
+            (0x2f, _, _)                => Some(Extension::A),
+            (0x07 | 0x27, 0x02, _)      => Some(Extension::F),
+            (0x07 | 0x27, 0x03, _)      => Some(Extension::D),
+            (0x43 | 0x47 | 0x4b | 0x4f | 0x53, _, 0x00)
+                                        => Some(Extension::F),
+            (0x43 | 0x47 | 0x4b | 0x4f | 0x53, _, 0x01)
+                                        => Some(Extension::D),
+            _                           => None,
+        }
+    }
+
     /// Decodes a B-type instruction.
     #[inline]
     fn decode_instr_b(instr: &Instruction) -> Option<Op> {
@@ -39,12 +289,14 @@ impl Decoder {
     /// Decodes an I-type instruction.
     #[inline]
     fn decode_instr_i(instr: &Instruction) -> Option<Op> {
-        match (instr.opcode(), instr.funct3()?, (instr.imm()? >> 0x05 & 0x7f)) {
+        match (instr.opcode(), instr.funct3()?, instr.imm_funct7()?) {
             (0x03, 0x00, _)     => Some(LoadByte),
             (0x03, 0x01, _)     => Some(LoadHalf),
             (0x03, 0x02, _)     => Some(LoadWord),
             (0x03, 0x04, _)     => Some(LoadByteUnsigned),
             (0x03, 0x05, _)     => Some(LoadHalfUnsigned),
+            (0x0f, 0x00, _) if instr.raw() == PAUSE
+                                => Some(Pause),
             (0x0f, 0x00, _)     => Some(Fence),
             (0x0f, 0x01, _)     => Some(FenceI),
             (0x13, 0x00, _)     => Some(ArithmeticAddImmediate),
@@ -57,18 +309,39 @@ impl Decoder {
             (0x13, 0x06, _)     => Some(LogicalOrImmediate),
             (0x13, 0x07, _)     => Some(LogicalAndImmediate),
             (0x67, 0x00, _)     => Some(JumpAndLinkRegister),
-            // (0x73, 0x00, 0x00)  => Some(SystemEcall),
-            // (0x73, 0x00, 0x01)  => Some(SystemEbreak),
-            // (0x73, 0x01, _)     => Some(CsrReadWrite),
-            // (0x73, 0x02, _)     => Some(CsrReadSet),
-            // (0x73, 0x03, _)     => Some(CsrReadClear),
-            // (0x73, 0x05, _)     => Some(CsrReadWriteImmediate),
-            // (0x73, 0x06, _)     => Some(CsrReadSetImmediate),
-            // (0x73, 0x07, _)     => Some(CsrReadClearImmediate),
+            (0x73, _, _)        => Decoder::decode_system(instr),
             _                   => None,
         }
     }
 
+    /// Decodes an instruction in the system opcode space (`0x73`).
+    ///
+    /// The CSR instructions are distinguished by funct3. The remaining
+    /// instructions all have funct3, rd and rs1 set to zero and are
+    /// distinguished by the full 12-bit immediate (funct12).
+    #[inline]
+    fn decode_system(instr: &Instruction) -> Option<Op> {
+        let raw = instr.raw();
+        let funct12 = raw >> 20;
+        let rd = raw >> 7 & 0x1f;
+        let rs1 = raw >> 15 & 0x1f;
+
+        match (instr.funct3()?, funct12, rd, rs1) {
+            (0x00, 0x000, 0x00, 0x00)   => Some(SystemEcall),
+            (0x00, 0x001, 0x00, 0x00)   => Some(SystemEbreak),
+            (0x00, 0x102, 0x00, 0x00)   => Some(SystemSupervisorReturn),
+            (0x00, 0x105, 0x00, 0x00)   => Some(SystemWaitForInterrupt),
+            (0x00, 0x302, 0x00, 0x00)   => Some(SystemMachineReturn),
+            (0x01, _, _, _)             => Some(CsrReadWrite),
+            (0x02, _, _, _)             => Some(CsrReadSet),
+            (0x03, _, _, _)             => Some(CsrReadClear),
+            (0x05, _, _, _)             => Some(CsrReadWriteImmediate),
+            (0x06, _, _, _)             => Some(CsrReadSetImmediate),
+            (0x07, _, _, _)             => Some(CsrReadClearImmediate),
+            _                           => None,
+        }
+    }
+
     /// Decodes a J-type instruction.
     #[inline]
     fn decode_instr_j(instr: &Instruction) -> Option<Op> {
@@ -84,14 +357,22 @@ impl Decoder {
         match (instr.opcode(), instr.funct3()?, instr.funct7()?) {
             (0x33, 0x00, 0x00)  => Some(ArithmeticAdd),
             (0x33, 0x00, 0x20)  => Some(ArithmeticSub),
-            (0x33, 0x01, _)     => Some(ShiftLeftLogical),
-            (0x33, 0x02, _)     => Some(SetLessThan),
-            (0x33, 0x03, _)     => Some(SetLessThanUnsigned),
-            (0x33, 0x04, _)     => Some(LogicalExclusiveOr),
+            (0x33, 0x01, 0x00)  => Some(ShiftLeftLogical),
+            (0x33, 0x02, 0x00)  => Some(SetLessThan),
+            (0x33, 0x03, 0x00)  => Some(SetLessThanUnsigned),
+            (0x33, 0x04, 0x00)  => Some(LogicalExclusiveOr),
             (0x33, 0x05, 0x00)  => Some(ShiftRightLogical),
             (0x33, 0x05, 0x20)  => Some(ShiftRightArithmetic),
-            (0x33, 0x06, _)     => Some(LogicalOr),
-            (0x33, 0x07, _)     => Some(LogicalAnd),
+            (0x33, 0x06, 0x00)  => Some(LogicalOr),
+            (0x33, 0x07, 0x00)  => Some(LogicalAnd),
+            (0x33, 0x00, 0x01)  => Some(ArithmeticMul),
+            (0x33, 0x01, 0x01)  => Some(ArithmeticMulHigh),
+            (0x33, 0x02, 0x01)  => Some(ArithmeticMulHighSignedUnsigned),
+            (0x33, 0x03, 0x01)  => Some(ArithmeticMulHighUnsigned),
+            (0x33, 0x04, 0x01)  => Some(ArithmeticDiv),
+            (0x33, 0x05, 0x01)  => Some(ArithmeticDivUnsigned),
+            (0x33, 0x06, 0x01)  => Some(ArithmeticRem),
+            (0x33, 0x07, 0x01)  => Some(ArithmeticRemUnsigned),
             _                   => None,
         }
     }
@@ -116,5 +397,328 @@ impl Decoder {
             _                   => None,
         }
     }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{DecodeKey, Decoder, Extension, Instruction, Op, Op::*};
+
+    mod table {
+        use std::collections::BTreeSet;
+
+        use super::*;
+
+        #[test]
+        fn lists_each_operation_once() {
+            let ops: BTreeSet<Op> = Decoder::table().map(|(_, op)| op).collect();
+
+            assert_eq!(Decoder::table().count(), 59);
+            assert_eq!(ops.len(), 59);
+        }
+
+        #[test]
+        fn canonical_encodings_decode_to_their_operation() {
+            for (key, op) in Decoder::table() {
+                assert_eq!(Decoder::decode(&key.canonical()), Some(op), "{key:?}");
+            }
+        }
+
+        #[test]
+        fn decode_agrees_with_first_matching_key() {
+            const OPCODES: [u32; 11] = [
+                0x03, 0x0f, 0x13, 0x17, 0x23, 0x33, 0x37, 0x63, 0x67, 0x6f, 0x73,
+            ];
+
+            let mut state: u32 = 0x2545f491;
+            let mut words: Vec<u32> = Decoder::table()
+                .map(|(key, _)| key.canonical().raw())
+                .collect();
+
+            for i in 0 .. 100_000 {
+                // xorshift32
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+
+                let word = state & !0x7f | OPCODES[i % OPCODES.len()];
+
+                // Clear rd and rs1 in some words, so keys on funct12 match.
+                words.push(match i % 4 {
+                    0 => word & !(0x1f << 7 | 0x1f << 15),
+                    _ => word,
+                });
+            }
+
+            for word in words {
+                let instr = Instruction::new(word);
+                let expected = Decoder::table()
+                    .find(|(key, _)| key.matches(&instr))
+                    .map(|(_, op)| op);
+
+                assert_eq!(Decoder::decode(&instr), expected, "{word:#010x}");
+            }
+        }
+
+        #[test]
+        fn slli_key_allows_any_upper_immediate() {
+            // slli x1, x1, 32
+            let instr = Instruction::new(0x02009093);
+            let key = Decoder::table()
+                .find(|&(_, op)| op == ShiftLeftLogicalImmediate)
+                .unwrap()
+                .0;
+
+            assert!(key.matches(&instr));
+            assert_eq!(Decoder::decode(&instr), Some(ShiftLeftLogicalImmediate));
+        }
+
+        #[test]
+        fn contains_expected_mappings() {
+            let find = |op| Decoder::table().find(|&(_, entry)| entry == op).unwrap().0;
+
+            assert_eq!(find(ArithmeticSub), DecodeKey {
+                opcode: 0x33,
+                funct3: Some(0x00),
+                funct7: Some(0x20),
+                funct12: None,
+            });
+            assert_eq!(find(ArithmeticMul).funct7, Some(0x01));
+            assert_eq!(find(SystemEbreak).funct12, Some(0x001));
+            assert_eq!(find(JumpAndLink).funct3, None);
+            assert_eq!(find(LoadUpperImmediate).canonical().raw(), 0x00000037);
+        }
+    }
+
+    mod fence {
+        use super::*;
+
+        #[test]
+        fn decodes_pause() {
+            // pause
+            assert_eq!(
+                Decoder::decode(&Instruction::new(0x0100000f)),
+                Some(Pause),
+            );
+        }
+
+        #[test]
+        fn decodes_generic_fence_as_fence() {
+            // fence iorw, iorw
+            assert_eq!(
+                Decoder::decode(&Instruction::new(0x0ff0000f)),
+                Some(Fence),
+            );
+        }
+
+        #[test]
+        fn decodes_fence_w_with_successor_as_fence() {
+            // fence w, w
+            assert_eq!(
+                Decoder::decode(&Instruction::new(0x0110000f)),
+                Some(Fence),
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_unknown_opcode() {
+        assert_eq!(Decoder::decode(&Instruction::new(0x00000000)), None);
+    }
+
+    mod registers_below {
+        use super::*;
+
+        fn fits_rv32e(instr: u32) -> bool {
+            Decoder::uses_registers_below(&Instruction::new(instr), 16)
+        }
+
+        #[test]
+        fn accepts_instructions_using_x0_to_x15() {
+            // add x15, x14, x13
+            assert!(fits_rv32e(0x00d707b3));
+            // csrrwi x1, mstatus, 20 (the uimm isn't a register)
+            assert!(fits_rv32e(0x300a50f3));
+        }
+
+        #[test]
+        fn rejects_upper_registers_in_any_position() {
+            // addi x20, x0, 1
+            assert!(!fits_rv32e(0x00100a13));
+            // add x1, x16, x2
+            assert!(!fits_rv32e(0x002800b3));
+            // sw x31, 0(x1)
+            assert!(!fits_rv32e(0x01f0a023));
+        }
+    }
+
+    mod strict {
+        use super::*;
+
+        fn decode_strict(instr: u32) -> Option<Op> {
+            Decoder::decode_strict(&Instruction::new(instr))
+        }
+
+        #[test]
+        fn rejects_fence_with_nonzero_rd() {
+            // fence iorw, iorw with rd = x1
+            assert_eq!(Decoder::decode(&Instruction::new(0x0ff0008f)), Some(Fence));
+            assert_eq!(decode_strict(0x0ff0008f), None);
+        }
+
+        #[test]
+        fn rejects_fence_i_with_nonzero_rs1() {
+            // fence.i with rs1 = x2
+            assert_eq!(decode_strict(0x0001100f), None);
+        }
+
+        #[test]
+        fn rejects_slli_with_shift_amount_above_31() {
+            // slli x1, x1, 32
+            assert_eq!(decode_strict(0x02009093), None);
+        }
+
+        #[test]
+        fn accepts_canonical_encodings() {
+            // fence iorw, iorw
+            assert_eq!(decode_strict(0x0ff0000f), Some(Fence));
+            // fence.i
+            assert_eq!(decode_strict(0x0000100f), Some(FenceI));
+            // slli x1, x1, 31
+            assert_eq!(decode_strict(0x01f09093), Some(ShiftLeftLogicalImmediate));
+        }
+    }
+
+    mod compressed {
+        use super::*;
+
+        #[test]
+        fn decodes_c_ebreak() {
+            assert_eq!(Decoder::decode_compressed(0x9002), Some(SystemEbreak));
+        }
+
+        #[test]
+        fn decodes_c_ret() {
+            // c.jr x1
+            assert_eq!(
+                Decoder::expand(0x8082).map(|instr| instr.raw()),
+                Some(0x00008067),
+            );
+            assert_eq!(Decoder::decode_compressed(0x8082), Some(JumpAndLinkRegister));
+        }
+
+        #[test]
+        fn expands_c_jalr_with_link() {
+            // c.jalr x5 => jalr x1, 0(x5)
+            assert_eq!(
+                Decoder::expand(0x9282).map(|instr| instr.raw()),
+                Some(0x000280e7),
+            );
+        }
+
+        #[test]
+        fn expands_c_nop() {
+            assert_eq!(Decoder::decode_compressed(0x0001), Some(ArithmeticAddImmediate));
+        }
+
+        #[test]
+        fn rejects_c_jr_of_x0() {
+            assert_eq!(Decoder::expand(0x8002), None);
+        }
+    }
+
+    mod extension {
+        use super::*;
+
+        fn extension(instr: u32) -> Option<Extension> {
+            Decoder::extension(&Instruction::new(instr))
+        }
+
+        #[test]
+        fn tags_decoded_ops() {
+            // addi x1, x0, 5
+            assert_eq!(extension(0x00500093), Some(Extension::I));
+            // mul x5, x6, x7
+            assert_eq!(extension(0x027302b3), Some(Extension::M));
+        }
+
+        #[test]
+        fn recognises_undecodable_extensions() {
+            // amoadd.w x1, x3, (x2)
+            assert_eq!(extension(0x003120af), Some(Extension::A));
+            // flw f1, 0(x2)
+            assert_eq!(extension(0x00012087), Some(Extension::F));
+            // c.li x1, 1
+            assert_eq!(extension(0x00004085), Some(Extension::C));
+        }
+
+        #[test]
+        fn rejects_all_zero_word() {
+            assert_eq!(extension(0x00000000), None);
+        }
+    }
+
+    mod system {
+        use super::*;
+
+        fn decode(instr: u32) -> Option<Op> {
+            Decoder::decode(&Instruction::new(instr))
+        }
 
-}
\ No newline at end of file
+        #[test]
+        fn decodes_ecall() {
+            assert_eq!(decode(0x00000073), Some(SystemEcall));
+        }
+
+        #[test]
+        fn decodes_ebreak() {
+            assert_eq!(decode(0x00100073), Some(SystemEbreak));
+        }
+
+        #[test]
+        fn decodes_mret() {
+            assert_eq!(decode(0x30200073), Some(SystemMachineReturn));
+        }
+
+        #[test]
+        fn decodes_sret() {
+            assert_eq!(decode(0x10200073), Some(SystemSupervisorReturn));
+        }
+
+        #[test]
+        fn decodes_wfi() {
+            assert_eq!(decode(0x10500073), Some(SystemWaitForInterrupt));
+        }
+
+        #[test]
+        fn decodes_csr_ops() {
+            // csrrw x1, mstatus, x2
+            assert_eq!(decode(0x300110f3), Some(CsrReadWrite));
+            // csrrs x1, mstatus, x2
+            assert_eq!(decode(0x300120f3), Some(CsrReadSet));
+            // csrrc x1, mstatus, x2
+            assert_eq!(decode(0x300130f3), Some(CsrReadClear));
+            // csrrwi x1, mstatus, 2
+            assert_eq!(decode(0x300150f3), Some(CsrReadWriteImmediate));
+            // csrrsi x1, mstatus, 2
+            assert_eq!(decode(0x300160f3), Some(CsrReadSetImmediate));
+            // csrrci x1, mstatus, 2
+            assert_eq!(decode(0x300170f3), Some(CsrReadClearImmediate));
+        }
+
+        #[test]
+        fn rejects_unknown_funct12() {
+            assert_eq!(decode(0x00200073), None);
+        }
+
+        #[test]
+        fn rejects_ecall_with_nonzero_rd() {
+            // ecall with rd = x1
+            assert_eq!(decode(0x000000f3), None);
+        }
+
+        #[test]
+        fn rejects_reserved_funct3() {
+            assert_eq!(decode(0x00004073), None);
+        }
+    }
+}
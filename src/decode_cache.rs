@@ -0,0 +1,153 @@
+//! Decode Cache
+//! A direct-mapped cache of fetched instructions, keyed by address, so a
+//! processor can skip the bus read for code it has already fetched.
+//!
+//! Caching raises the question of what happens when code is overwritten.
+//! The ISA only guarantees that a hart sees its own stores to instruction
+//! memory after a `fence.i`, but many simple programs expect new code to
+//! be seen immediately, so both behaviours are offered.
+
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::instruction::Instruction;
+
+/// The number of entries in the cache.
+const ENTRIES: usize = 1024;
+
+/// How the decode cache handles stores that overwrite cached instructions.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum CodeCoherence {
+    /// Stores invalidate any cached instruction they overwrite, so new code
+    /// is always executed, with or without a `fence.i`.
+    #[default]
+    Relaxed,
+
+    /// Stores don't touch the cache, so the old instruction keeps executing
+    /// until a `fence.i` flushes it, as the ISA permits.
+    Strict,
+}
+
+/// A direct-mapped cache of fetched instructions.
+#[derive(Clone, Debug)]
+pub struct DecodeCache {
+    /// How stores to cached instructions are handled.
+    pub coherence: CodeCoherence,
+
+    /// The address and instruction held in each entry, indexed by the
+    /// address in halfwords.
+    entries: Vec<Option<(u32, Instruction)>>,
+}
+
+impl DecodeCache {
+    /// Creates an empty cache that handles stores according to
+    /// `coherence`.
+    pub fn new(coherence: CodeCoherence) -> Self {
+        Self {
+            coherence,
+            entries: vec![None; ENTRIES],
+        }
+    }
+
+    /// Returns the instruction cached for `addr`, if any.
+    pub fn get(&self, addr: u32) -> Option<Instruction> {
+        match self.entries[Self::index(addr)] {
+            Some((tag, instr)) if tag == addr => Some(instr),
+            _ => None,
+        }
+    }
+
+    /// Caches `instr` as the instruction at `addr`, evicting whatever
+    /// shared its entry.
+    pub fn insert(&mut self, addr: u32, instr: Instruction) {
+        self.entries[Self::index(addr)] = Some((addr, instr));
+    }
+
+    /// Records a store of `size` bytes to `addr`. Under
+    /// `CodeCoherence::Relaxed`, this invalidates every cached instruction
+    /// the store overlaps. Under `CodeCoherence::Strict`, it does nothing.
+    pub fn store(&mut self, addr: u32, size: usize) {
+        if self.coherence == CodeCoherence::Strict {
+            return;
+        }
+
+        // An instruction is at most four bytes long and starts on a
+        // halfword boundary, so those starting up to three bytes before the
+        // store can overlap it.
+        let start = addr.wrapping_sub(2) & !0x01;
+        let end = addr.wrapping_add(size as u32);
+
+        for i in 0 .. end.wrapping_sub(start).div_ceil(2) {
+            let candidate = start.wrapping_add(i * 2);
+            let index = Self::index(candidate);
+
+            if self.entries[index].is_some_and(|(tag, _)| tag == candidate) {
+                self.entries[index] = None;
+            }
+        }
+    }
+
+    /// Invalidates every entry, as `fence.i` does.
+    pub fn flush(&mut self) {
+        self.entries.fill(None);
+    }
+
+    /// Returns the entry an address maps to.
+    fn index(addr: u32) -> usize {
+        (addr >> 1) as usize % ENTRIES
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CodeCoherence, DecodeCache, Instruction, ENTRIES};
+
+    /// Returns a cache holding `addi x0, x0, 0` at each of `addrs`.
+    fn cache(coherence: CodeCoherence, addrs: &[u32]) -> DecodeCache {
+        let mut cache = DecodeCache::new(coherence);
+
+        for &addr in addrs {
+            cache.insert(addr, Instruction::new(0x00000013));
+        }
+
+        cache
+    }
+
+    #[test]
+    fn returns_cached_instruction_for_its_address_only() {
+        let cache = cache(CodeCoherence::Relaxed, &[0x100]);
+
+        assert_eq!(cache.get(0x100), Some(Instruction::new(0x00000013)));
+        assert_eq!(cache.get(0x104), None);
+        assert_eq!(cache.get(0x100 + 2 * ENTRIES as u32), None);
+    }
+
+    #[test]
+    fn relaxed_store_invalidates_overlapping_instructions() {
+        let mut cache = cache(CodeCoherence::Relaxed, &[0x0fc, 0x100, 0x104, 0x108]);
+
+        // A byte store to the last byte of the instruction at 0x100.
+        cache.store(0x103, 1);
+
+        assert!(cache.get(0x0fc).is_some());
+        assert!(cache.get(0x100).is_none());
+        assert!(cache.get(0x104).is_some());
+
+        // A word store straddling the instructions at 0x104 and 0x108.
+        cache.store(0x106, 4);
+
+        assert!(cache.get(0x104).is_none());
+        assert!(cache.get(0x108).is_none());
+    }
+
+    #[test]
+    fn strict_store_leaves_cache_untouched() {
+        let mut cache = cache(CodeCoherence::Strict, &[0x100]);
+
+        cache.store(0x100, 4);
+        assert!(cache.get(0x100).is_some());
+
+        cache.flush();
+        assert!(cache.get(0x100).is_none());
+    }
+}
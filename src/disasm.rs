@@ -0,0 +1,452 @@
+//! Disassembly
+//! Renders instructions as assembly, preferring the standard
+//! pseudo-instructions where an encoding matches one.
+
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+
+use crate::decode::Decoder;
+use crate::instruction::{Instruction, FENCE_I, FENCE_O, FENCE_R, FENCE_W};
+use crate::op::Op::*;
+
+/// The `fm` of `fence.tso`.
+const FENCE_FM_TSO: u8 = 0x08;
+
+/// Disassembles an instruction, rendering it as a pseudo-instruction
+/// if it matches one and falling back to the base instruction otherwise.
+pub fn disassemble(instr: &Instruction) -> String {
+    fence(instr)
+        .or_else(|| pseudo(instr))
+        .unwrap_or_else(|| instr.to_string())
+}
+
+/// Disassembles a sequence of instructions starting at `addr`, returning
+/// each line with its address.
+///
+/// As well as the single-instruction pseudo-instructions `disassemble`
+/// recognises, this renders an `auipc` followed by a `jalr` through the
+/// register it wrote as one `call` or `tail` line with the combined
+/// target, as the assembler emits them for far calls and tail calls.
+pub fn disassemble_sequence(addr: u32, instrs: &[u32]) -> Vec<(u32, String)> {
+    let mut lines = Vec::with_capacity(instrs.len());
+    let mut i = 0;
+
+    while i < instrs.len() {
+        let pc = addr.wrapping_add(i as u32 * 4);
+        let instr = Instruction::new(instrs[i]);
+
+        let pair = instrs
+            .get(i + 1)
+            .and_then(|&next| far_jump(pc, &instr, &Instruction::new(next)));
+
+        match pair {
+            Some(line) => {
+                lines.push((pc, line));
+                i += 2;
+            },
+
+            None => {
+                lines.push((pc, disassemble(&instr)));
+                i += 1;
+            },
+        }
+    }
+
+    lines
+}
+
+/// Renders an `auipc rd, hi` at `pc` followed by a `jalr ra, lo(rd)` as
+/// `call`, or followed by a `jalr x0, lo(rd)` as `tail`, or returns `None`
+/// if the pair is neither.
+fn far_jump(pc: u32, auipc: &Instruction, jalr: &Instruction) -> Option<String> {
+    if Decoder::decode(auipc)? != AddUpperImmediateProgramCounter
+        || Decoder::decode(jalr)? != JumpAndLinkRegister
+    {
+        return None;
+    }
+
+    let base = auipc.rd()?;
+
+    if base == 0 || jalr.rs1()? != base {
+        return None;
+    }
+
+    let mnemonic = match jalr.rd()? {
+        0 => "tail",
+        1 => "call",
+        _ => return None,
+    };
+
+    let target = pc
+        .wrapping_add((auipc.imm()? as u32) << 12)
+        .wrapping_add(jalr.imm()? as u32);
+
+    Some(format!(
+        // call target
+        "{:<12} {:#010x}",
+        mnemonic,
+        target,
+    ))
+}
+
+/// Renders a `fence` in the `fence pred, succ` form, or as `fence`,
+/// `fence.tso` or `pause` where an encoding matches one, or returns `None`
+/// if the instruction isn't a fence.
+fn fence(instr: &Instruction) -> Option<String> {
+    if let Some(Pause) = Decoder::decode(instr) {
+        return Some("pause".to_string());
+    }
+
+    let (pred, succ) = (instr.fence_pred()?, instr.fence_succ()?);
+    let all = FENCE_I | FENCE_O | FENCE_R | FENCE_W;
+    let rw = FENCE_R | FENCE_W;
+
+    match (instr.fence_fm()?, pred, succ) {
+        (0, p, s) if p == all && s == all => Some("fence".to_string()),
+        (FENCE_FM_TSO, p, s) if p == rw && s == rw => Some("fence.tso".to_string()),
+
+        _ => Some(format!(
+            // fence pred, succ
+            "{:<12} {}, {}",
+            "fence",
+            fence_set(pred),
+            fence_set(succ),
+        )),
+    }
+}
+
+/// Renders a fence ordering set as the letters of its bits, such as `rw`.
+fn fence_set(set: u8) -> String {
+    let letters: String = [(FENCE_I, 'i'), (FENCE_O, 'o'), (FENCE_R, 'r'), (FENCE_W, 'w')]
+        .iter()
+        .filter(|&&(bit, _)| set & bit != 0)
+        .map(|&(_, letter)| letter)
+        .collect();
+
+    match letters.is_empty() {
+        true => "0".to_string(),
+        false => letters,
+    }
+}
+
+/// Disassembles a compressed (16-bit) instruction using its compressed
+/// mnemonic, or returns `None` if it isn't a supported encoding.
+pub fn disassemble_compressed(halfword: u16) -> Option<String> {
+    let instr = Decoder::expand(halfword)?;
+    let rs1 = instr.rs1()?;
+
+    match (Decoder::decode(&instr)?, instr.rd()?) {
+        (ArithmeticAddImmediate, _) => Some("c.nop".to_string()),
+        (SystemEbreak, _) => Some("c.ebreak".to_string()),
+        (JumpAndLinkRegister, 0) if rs1 == 1 => Some("c.ret".to_string()),
+
+        (JumpAndLinkRegister, 0) => Some(format!(
+            // c.jr rs1
+            "{:<12} x{}",
+            "c.jr",
+            rs1,
+        )),
+
+        (JumpAndLinkRegister, _) => Some(format!(
+            // c.jalr rs1
+            "{:<12} x{}",
+            "c.jalr",
+            rs1,
+        )),
+
+        _ => None,
+    }
+}
+
+/// Returns the pseudo-instruction rendering of an instruction, or `None`
+/// if it doesn't match any of the recognised pseudo-instructions.
+pub fn pseudo(instr: &Instruction) -> Option<String> {
+    let op = Decoder::decode(instr)?;
+    let rd = instr.rd();
+    let rs1 = instr.rs1();
+    let rs2 = instr.rs2();
+    let imm = instr.imm();
+
+    match (op, rd, rs1, rs2, imm) {
+        // Branches and jumps
+
+        (ArithmeticAddImmediate, Some(0), Some(0), _, Some(0)) => {
+            Some("nop".to_string())
+        },
+
+        (BranchEqual, _, Some(rs1), Some(0), Some(imm)) => Some(format!(
+            // beqz rs1, imm
+            "{:<12} x{}, {:#010x}",
+            "beqz",
+            rs1,
+            imm,
+        )),
+
+        (BranchNotEqual, _, Some(rs1), Some(0), Some(imm)) => Some(format!(
+            // bnez rs1, imm
+            "{:<12} x{}, {:#010x}",
+            "bnez",
+            rs1,
+            imm,
+        )),
+
+        (JumpAndLink, Some(0), _, _, Some(imm)) => Some(format!(
+            // j imm
+            "{:<12} {:#010x}",
+            "j",
+            imm,
+        )),
+
+        (JumpAndLinkRegister, Some(0), Some(1), _, Some(0)) => {
+            Some("ret".to_string())
+        },
+
+        (JumpAndLinkRegister, Some(0), Some(rs1), _, Some(0)) => Some(format!(
+            // jr rs1
+            "{:<12} x{}",
+            "jr",
+            rs1,
+        )),
+
+        // Register arithmetic
+
+        (ArithmeticAddImmediate, Some(rd), Some(rs1), _, Some(0)) => Some(format!(
+            // mv rd, rs1
+            "{:<12} x{}, x{}",
+            "mv",
+            rd,
+            rs1,
+        )),
+
+        (ArithmeticSub, Some(rd), Some(0), Some(rs2), _) => Some(format!(
+            // neg rd, rs2
+            "{:<12} x{}, x{}",
+            "neg",
+            rd,
+            rs2,
+        )),
+
+        (LogicalExclusiveOrImmediate, Some(rd), Some(rs1), _, Some(-1)) => Some(format!(
+            // not rd, rs1
+            "{:<12} x{}, x{}",
+            "not",
+            rd,
+            rs1,
+        )),
+
+        (SetLessThanImmediateUnsigned, Some(rd), Some(rs1), _, Some(1)) => Some(format!(
+            // seqz rd, rs1
+            "{:<12} x{}, x{}",
+            "seqz",
+            rd,
+            rs1,
+        )),
+
+        (SetLessThanUnsigned, Some(rd), Some(0), Some(rs2), _) => Some(format!(
+            // snez rd, rs2
+            "{:<12} x{}, x{}",
+            "snez",
+            rd,
+            rs2,
+        )),
+
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{disassemble, disassemble_compressed, disassemble_sequence, Instruction};
+
+    /// Disassembles a raw instruction and collapses the column padding so
+    /// that tests don't depend on it.
+    fn disasm(instr: u32) -> String {
+        disassemble(&Instruction::new(instr))
+            .split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    mod branch_and_jump {
+        use super::*;
+
+        #[test]
+        fn recognises_nop() {
+            // addi x0, x0, 0
+            assert_eq!(disasm(0x00000013), "nop");
+        }
+
+        #[test]
+        fn recognises_beqz() {
+            // beq x9, x0, 20
+            assert_eq!(disasm(0x00048a63), "beqz x9, 0x00000014");
+        }
+
+        #[test]
+        fn recognises_j() {
+            // jal x0, 64
+            assert_eq!(disasm(0x0400006f), "j 0x00000040");
+        }
+
+        #[test]
+        fn recognises_ret() {
+            // jalr x0, 0(x1)
+            assert_eq!(disasm(0x00008067), "ret");
+        }
+
+        #[test]
+        fn recognises_jr() {
+            // jalr x0, 0(x5)
+            assert_eq!(disasm(0x00028067), "jr x5");
+        }
+
+        #[test]
+        fn renders_link_register_other_than_ra() {
+            // jal x5, 16
+            assert_eq!(disasm(0x010002ef), "jal x5, 0x00000010");
+            // jalr x5, 0(x6)
+            assert_eq!(disasm(0x000302e7), "jalr x5, 0(x6)");
+        }
+    }
+
+    mod sequence {
+        use super::*;
+
+        /// Disassembles a sequence at `addr`, collapsing the column padding
+        /// as `disasm` does.
+        fn disasm_seq(addr: u32, instrs: &[u32]) -> Vec<(u32, String)> {
+            disassemble_sequence(addr, instrs)
+                .into_iter()
+                .map(|(pc, text)| (pc, text.split_whitespace().collect::<Vec<_>>().join(" ")))
+                .collect()
+        }
+
+        #[test]
+        fn recognises_call_with_resolved_target() {
+            assert_eq!(disasm_seq(0x100, &[
+                0x00000013, // nop
+                0x00001097, // auipc x1, 0x1
+                0xff0080e7, // jalr x1, -16(x1)
+                0x00008067, // ret
+            ]), [
+                (0x100, "nop".to_string()),
+                (0x104, "call 0x000010f4".to_string()),
+                (0x10c, "ret".to_string()),
+            ]);
+        }
+
+        #[test]
+        fn recognises_tail() {
+            assert_eq!(disasm_seq(0x200, &[
+                0x00000317, // auipc x6, 0
+                0x04030067, // jalr x0, 64(x6)
+            ]), [(0x200, "tail 0x00000240".to_string())]);
+        }
+
+        #[test]
+        fn jalr_through_another_register_is_not_a_pair() {
+            assert_eq!(disasm_seq(0x00, &[
+                0x00001097, // auipc x1, 0x1
+                0x04030067, // jalr x0, 64(x6)
+            ]).len(), 2);
+        }
+    }
+
+    mod fence {
+        use super::*;
+
+        #[test]
+        fn renders_pred_and_succ() {
+            // fence rw, rw
+            assert_eq!(disasm(0x0330000f), "fence rw, rw");
+            // fence r, w
+            assert_eq!(disasm(0x0210000f), "fence r, w");
+            // fence io, 0
+            assert_eq!(disasm(0x0c00000f), "fence io, 0");
+        }
+
+        #[test]
+        fn special_cases_full_fence_tso_and_pause() {
+            assert_eq!(disasm(0x0ff0000f), "fence");
+            assert_eq!(disasm(0x8330000f), "fence.tso");
+            assert_eq!(disasm(0x0100000f), "pause");
+        }
+    }
+
+    mod compressed {
+        use super::*;
+
+        fn disasm_c(halfword: u16) -> Option<String> {
+            disassemble_compressed(halfword)
+                .map(|text| text.split_whitespace().collect::<Vec<_>>().join(" "))
+        }
+
+        #[test]
+        fn uses_compressed_mnemonics() {
+            assert_eq!(disasm_c(0x0001).as_deref(), Some("c.nop"));
+            assert_eq!(disasm_c(0x9002).as_deref(), Some("c.ebreak"));
+            assert_eq!(disasm_c(0x8082).as_deref(), Some("c.ret"));
+            assert_eq!(disasm_c(0x8282).as_deref(), Some("c.jr x5"));
+            assert_eq!(disasm_c(0x9282).as_deref(), Some("c.jalr x5"));
+        }
+
+        #[test]
+        fn unsupported_encoding_has_no_rendering() {
+            // c.li x1, 1
+            assert_eq!(disasm_c(0x4085), None);
+        }
+    }
+
+    mod register_arithmetic {
+        use super::*;
+
+        #[test]
+        fn recognises_mv() {
+            // addi x5, x6, 0
+            assert_eq!(disasm(0x00030293), "mv x5, x6");
+        }
+
+        #[test]
+        fn recognises_neg() {
+            // sub x5, x0, x3
+            assert_eq!(disasm(0x403002b3), "neg x5, x3");
+        }
+
+        #[test]
+        fn recognises_seqz() {
+            // sltiu x5, x6, 1
+            assert_eq!(disasm(0x00133293), "seqz x5, x6");
+        }
+
+        #[test]
+        fn recognises_snez() {
+            // sltu x5, x0, x6
+            assert_eq!(disasm(0x006032b3), "snez x5, x6");
+        }
+
+        #[test]
+        fn recognises_not() {
+            // xori x5, x6, -1
+            assert_eq!(disasm(0xfff34293), "not x5, x6");
+        }
+
+        #[test]
+        fn non_matching_sub_is_not_a_pseudo() {
+            // sub x5, x7, x3
+            assert_eq!(disasm(0x403382b3), "sub x5, x7, x3");
+        }
+
+        #[test]
+        fn non_matching_sltiu_is_not_a_pseudo() {
+            // sltiu x5, x6, 2
+            assert_eq!(disasm(0x00233293), "sltiu x5, x6, 0x00000002");
+        }
+
+        #[test]
+        fn illegal_word_is_data() {
+            // OP with funct7 0x40, which no instruction uses
+            assert_eq!(disasm(0x80000033), ".word 0x80000033");
+        }
+    }
+}
@@ -0,0 +1,280 @@
+//! ELF
+//! Parses the parts of 32-bit little-endian RISC-V ELF executables needed
+//! to run them: the entry point and the segments to load into memory.
+//!
+//! Segments are loaded at their physical address, as the emulator has no
+//! MMU. Sections, symbols and relocations are ignored.
+
+use alloc::vec::Vec;
+use core::fmt::Display;
+
+/// The magic number at the start of every ELF file.
+const MAGIC: [u8; 4] = [0x7f, b'E', b'L', b'F'];
+
+/// `EI_CLASS` for 32-bit files.
+const CLASS_32: u8 = 1;
+
+/// `EI_CLASS` for 64-bit files.
+const CLASS_64: u8 = 2;
+
+/// `EI_DATA` for little-endian files.
+const DATA_LITTLE_ENDIAN: u8 = 1;
+
+/// `e_machine` for RISC-V.
+const MACHINE_RISCV: u16 = 243;
+
+/// `p_type` of a segment to load into memory.
+const PT_LOAD: u32 = 1;
+
+/// The size of the ELF32 file header.
+const HEADER_SIZE: usize = 52;
+
+/// The size of an ELF32 program header.
+const PROGRAM_HEADER_SIZE: usize = 32;
+
+/// An error parsing an ELF file.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ElfError {
+    /// The file doesn't start with the ELF magic number.
+    NotElf,
+
+    /// The file is a 64-bit ELF, which a 32-bit emulator can't run.
+    WrongClass,
+
+    /// The file is big-endian, which RISC-V executables never are.
+    WrongEndianness,
+
+    /// The file is for another architecture, given by its `e_machine`.
+    WrongArchitecture(u16),
+
+    /// The file ends in the middle of a header or segment.
+    Truncated,
+
+    /// The entry point isn't aligned to the processors' `IALIGN`, given in
+    /// bits.
+    MisalignedEntry { entry: u32, ialign: u32 },
+}
+
+impl Display for ElfError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            ElfError::NotElf => write!(f, "not an ELF file"),
+            ElfError::WrongClass => write!(f, "64-bit ELF files can't run on a 32-bit emulator"),
+            ElfError::WrongEndianness => write!(f, "big-endian ELF files aren't supported"),
+
+            ElfError::WrongArchitecture(machine) => {
+                write!(f, "ELF file is for machine {machine}, not RISC-V ({MACHINE_RISCV})")
+            },
+
+            ElfError::Truncated => write!(f, "ELF file is truncated"),
+
+            ElfError::MisalignedEntry { entry, ialign } => {
+                write!(f, "entry point {entry:#010x} isn't aligned to {ialign} bits")
+            },
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for ElfError {}
+
+/// A segment to load into memory.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Segment<'a> {
+    /// The physical address to load the segment at.
+    pub addr: u32,
+
+    /// The bytes to load, from the file.
+    pub data: &'a [u8],
+
+    /// The size of the segment in memory, which may be larger than `data`,
+    /// such as for `.bss`. The rest is zeroed.
+    pub mem_size: u32,
+}
+
+/// A parsed ELF executable.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Elf<'a> {
+    /// The address execution starts from.
+    pub entry: u32,
+
+    /// The segments to load, in file order.
+    pub segments: Vec<Segment<'a>>,
+}
+
+/// Parses a 32-bit little-endian RISC-V ELF executable.
+pub fn parse(bytes: &[u8]) -> Result<Elf<'_>, ElfError> {
+    if bytes.get(.. 4) != Some(&MAGIC) {
+        return Err(ElfError::NotElf);
+    }
+
+    match bytes.get(4) {
+        Some(&CLASS_32) => (),
+        Some(&CLASS_64) => return Err(ElfError::WrongClass),
+        Some(_) => return Err(ElfError::NotElf),
+        None => return Err(ElfError::Truncated),
+    }
+
+    match bytes.get(5) {
+        Some(&DATA_LITTLE_ENDIAN) => (),
+        Some(_) => return Err(ElfError::WrongEndianness),
+        None => return Err(ElfError::Truncated),
+    }
+
+    if bytes.len() < HEADER_SIZE {
+        return Err(ElfError::Truncated);
+    }
+
+    let machine = half(bytes, 18)?;
+
+    if machine != MACHINE_RISCV {
+        return Err(ElfError::WrongArchitecture(machine));
+    }
+
+    let entry = word(bytes, 24)?;
+    let phoff = word(bytes, 28)? as usize;
+    let phentsize = half(bytes, 42)? as usize;
+    let phnum = half(bytes, 44)? as usize;
+
+    if phnum > 0 && phentsize < PROGRAM_HEADER_SIZE {
+        return Err(ElfError::Truncated);
+    }
+
+    let mut segments = Vec::new();
+
+    for i in 0 .. phnum {
+        let header = i
+            .checked_mul(phentsize)
+            .and_then(|offset| offset.checked_add(phoff))
+            .ok_or(ElfError::Truncated)?;
+
+        if word(bytes, header)? != PT_LOAD {
+            continue;
+        }
+
+        let field = |offset: usize| {
+            word(bytes, header.checked_add(offset).ok_or(ElfError::Truncated)?)
+        };
+
+        let offset = field(4)? as usize;
+        let addr = field(12)?;
+        let file_size = field(16)? as usize;
+        let mem_size = field(20)?;
+
+        let data = offset
+            .checked_add(file_size)
+            .and_then(|end| bytes.get(offset .. end))
+            .ok_or(ElfError::Truncated)?;
+
+        segments.push(Segment { addr, data, mem_size });
+    }
+
+    Ok(Elf { entry, segments })
+}
+
+/// Reads a little-endian halfword at `offset`.
+fn half(bytes: &[u8], offset: usize) -> Result<u16, ElfError> {
+    match offset.checked_add(2).and_then(|end| bytes.get(offset .. end)) {
+        Some(&[b0, b1]) => Ok(u16::from_le_bytes([b0, b1])),
+        _ => Err(ElfError::Truncated),
+    }
+}
+
+/// Reads a little-endian word at `offset`.
+fn word(bytes: &[u8], offset: usize) -> Result<u32, ElfError> {
+    match offset.checked_add(4).and_then(|end| bytes.get(offset .. end)) {
+        Some(&[b0, b1, b2, b3]) => Ok(u32::from_le_bytes([b0, b1, b2, b3])),
+        _ => Err(ElfError::Truncated),
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::{parse, ElfError, Segment, HEADER_SIZE, PROGRAM_HEADER_SIZE};
+
+    /// Builds a RISC-V ELF32 executable with one loadable segment holding
+    /// `data` at `addr`, `mem_size` bytes long in memory.
+    pub(crate) fn executable(entry: u32, addr: u32, data: &[u8], mem_size: u32) -> Vec<u8> {
+        let mut elf = vec![0; HEADER_SIZE + PROGRAM_HEADER_SIZE];
+        let mut put = |offset: usize, bytes: &[u8]| {
+            elf[offset .. offset + bytes.len()].copy_from_slice(bytes);
+        };
+
+        put(0, &[0x7f, b'E', b'L', b'F', 1, 1, 1]);
+        // e_type = ET_EXEC, e_machine = EM_RISCV
+        put(16, &2u16.to_le_bytes());
+        put(18, &243u16.to_le_bytes());
+        put(24, &entry.to_le_bytes());
+        put(28, &(HEADER_SIZE as u32).to_le_bytes());
+        put(42, &(PROGRAM_HEADER_SIZE as u16).to_le_bytes());
+        put(44, &1u16.to_le_bytes());
+
+        let header = HEADER_SIZE;
+        let offset = (HEADER_SIZE + PROGRAM_HEADER_SIZE) as u32;
+        put(header, &1u32.to_le_bytes());
+        put(header + 4, &offset.to_le_bytes());
+        put(header + 8, &addr.to_le_bytes());
+        put(header + 12, &addr.to_le_bytes());
+        put(header + 16, &(data.len() as u32).to_le_bytes());
+        put(header + 20, &mem_size.to_le_bytes());
+
+        elf.extend_from_slice(data);
+        elf
+    }
+
+    #[test]
+    fn parses_entry_and_loadable_segment() {
+        let bytes = executable(0x104, 0x100, &[1, 2, 3, 4], 8);
+        let elf = parse(&bytes).unwrap();
+
+        assert_eq!(elf.entry, 0x104);
+        assert_eq!(elf.segments, [Segment { addr: 0x100, data: &[1, 2, 3, 4], mem_size: 8 }]);
+    }
+
+    #[test]
+    fn rejects_files_without_magic() {
+        assert_eq!(parse(b"\x7fELG"), Err(ElfError::NotElf));
+        assert_eq!(parse(&[]), Err(ElfError::NotElf));
+    }
+
+    #[test]
+    fn rejects_64_bit_class() {
+        let mut bytes = executable(0, 0, &[], 0);
+        bytes[4] = 2;
+
+        assert_eq!(parse(&bytes), Err(ElfError::WrongClass));
+    }
+
+    #[test]
+    fn rejects_big_endian() {
+        let mut bytes = executable(0, 0, &[], 0);
+        bytes[5] = 2;
+
+        assert_eq!(parse(&bytes), Err(ElfError::WrongEndianness));
+    }
+
+    #[test]
+    fn rejects_other_architectures() {
+        let mut bytes = executable(0, 0, &[], 0);
+        // EM_X86_64
+        bytes[18 .. 20].copy_from_slice(&62u16.to_le_bytes());
+
+        assert_eq!(parse(&bytes), Err(ElfError::WrongArchitecture(62)));
+    }
+
+    #[test]
+    fn rejects_program_headers_past_the_address_space() {
+        let mut bytes = executable(0, 0, &[], 0);
+        bytes[28 .. 32].copy_from_slice(&u32::MAX.to_le_bytes());
+
+        assert_eq!(parse(&bytes), Err(ElfError::Truncated));
+    }
+
+    #[test]
+    fn rejects_truncated_files() {
+        let bytes = executable(0, 0x100, &[1, 2, 3, 4], 4);
+
+        assert_eq!(parse(&bytes[.. 20]), Err(ElfError::Truncated));
+        assert_eq!(parse(&bytes[.. bytes.len() - 1]), Err(ElfError::Truncated));
+    }
+}
@@ -1,6 +1,21 @@
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+
+use core::fmt::{Debug, Display};
+
+use crate::bus::{Bus, StraddlePolicy};
+use crate::clint::Clint;
+use crate::decode_cache::CodeCoherence;
+use crate::elf::{self, ElfError};
+#[cfg(feature = "std")]
 use crate::instruction::Instruction;
+use crate::isa::{IsaProfile, Xlen};
 use crate::memory::Memory;
-use crate::processor::Processor;
+use crate::processor::{EbreakMode, HartState, Processor, StepResult};
+use crate::sbi::{self, SbiHandler};
+use crate::syscall::{SyscallHandler, SyscallOutcome};
+use crate::timing::CostTable;
+use crate::trap::{Interrupt, Trap};
 
 #[derive(Debug)]
 pub struct EmulatorConfig {
@@ -9,26 +24,742 @@ pub struct EmulatorConfig {
 
     /// The number of processors.
     pub proc_count: usize,
+
+    /// The number of ticks `mtime` advances by per step. Time is a function
+    /// of executed steps only, so identical runs are reproducible.
+    pub mtime_tick: u64,
+
+    /// The number of recent steps each processor records in its event
+    /// log, or `None` to disable event logging.
+    pub event_log_capacity: Option<usize>,
+
+    /// What executing `ebreak` does on each processor.
+    pub ebreak_mode: EbreakMode,
+
+    /// Whether division and remainder by zero trap on each processor,
+    /// rather than returning the result the ISA defines.
+    pub trap_on_divide_by_zero: bool,
+
+    /// Whether each processor rejects instructions with non-canonical
+    /// values in reserved fields as illegal.
+    pub strict_decode: bool,
+
+    /// Whether each processor caches fetched instructions, and if so,
+    /// whether its stores to cached code are seen before a `fence.i`.
+    /// `None` disables the cache.
+    pub decode_cache: Option<CodeCoherence>,
+
+    /// A pattern to initialize each processor's general-purpose registers
+    /// to instead of zero, so reads of uninitialized registers stand out.
+    /// `x0` is always zero.
+    pub register_poison: Option<u32>,
+
+    /// The number of cycles each operation costs on each processor.
+    pub cost_table: CostTable,
+
+    /// The ISA each processor implements: the extensions it executes, and
+    /// the byte order of data in memory. Only 32-bit profiles are
+    /// supported.
+    pub isa: IsaProfile,
+
+    /// What the bus does with accesses that span more than one region,
+    /// such as a word load across the end of memory into a device.
+    pub straddle: StraddlePolicy,
+
+    /// The number of consecutive instructions `run` executes on each
+    /// processor before switching to the next. One interleaves the
+    /// processors instruction by instruction; larger quanta give other
+    /// deterministic interleavings. Must be greater than zero.
+    pub schedule_quantum: u64,
+}
+
+impl Default for EmulatorConfig {
+    fn default() -> Self {
+        Self {
+            mem_size: 1024,
+            proc_count: 1,
+            mtime_tick: 1,
+            event_log_capacity: None,
+            ebreak_mode: EbreakMode::Halt,
+            strict_decode: false,
+            trap_on_divide_by_zero: false,
+            decode_cache: None,
+            register_poison: None,
+            cost_table: CostTable::uniform(),
+            isa: IsaProfile::default(),
+            straddle: StraddlePolicy::Fault,
+            schedule_quantum: 1,
+        }
+    }
+}
+
+/// The number of steps `run_with_timeout` executes between reads of the
+/// clock.
+#[cfg(feature = "std")]
+const TIMEOUT_CHECK_INTERVAL: u64 = 1024;
+
+/// The Linux system call number for `exit`, passed in `a7`.
+const SYSCALL_EXIT: u32 = 93;
+
+/// An invalid emulator configuration.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ConfigError {
+    /// `mem_size` was zero.
+    ZeroMemory,
+
+    /// `proc_count` was zero.
+    ZeroProcessors,
+
+    /// The ISA profile's `xlen` isn't supported.
+    UnsupportedXlen(Xlen),
+
+    /// `schedule_quantum` was zero.
+    ZeroQuantum,
+}
+
+impl Display for ConfigError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            ConfigError::ZeroMemory => write!(f, "memory size must be greater than zero"),
+            ConfigError::ZeroProcessors => write!(f, "processor count must be greater than zero"),
+
+            ConfigError::UnsupportedXlen(xlen) => {
+                write!(f, "XLEN {} is not supported", xlen.bits())
+            },
+
+            ConfigError::ZeroQuantum => write!(f, "schedule quantum must be greater than zero"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for ConfigError {}
+
+/// An error loading several blobs into memory at once.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum LoadError {
+    /// The blobs at the two base addresses would overlap.
+    Overlap { first: u32, second: u32 },
+
+    /// The entry point isn't aligned to the processors' `IALIGN`, given in
+    /// bits.
+    MisalignedEntry { entry: u32, ialign: u32 },
+}
+
+impl Display for LoadError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            LoadError::Overlap { first, second } => write!(
+                f,
+                "blobs loaded at {first:#010x} and {second:#010x} overlap",
+            ),
+
+            LoadError::MisalignedEntry { entry, ialign } => write!(
+                f,
+                "entry point {entry:#010x} isn't aligned to {ialign} bits",
+            ),
+        }
+    }
 }
 
+#[cfg(feature = "std")]
+impl std::error::Error for LoadError {}
+
+/// An error loading an ELF file from the host's file system.
+#[cfg(feature = "std")]
 #[derive(Debug)]
+pub enum ElfLoadError {
+    /// The file couldn't be read.
+    Io(std::io::Error),
+
+    /// The file isn't a RISC-V ELF executable the emulator can run.
+    Elf(ElfError),
+}
+
+#[cfg(feature = "std")]
+impl Display for ElfLoadError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            ElfLoadError::Io(err) => write!(f, "{err}"),
+            ElfLoadError::Elf(err) => write!(f, "{err}"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for ElfLoadError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ElfLoadError::Io(err) => Some(err),
+            ElfLoadError::Elf(err) => Some(err),
+        }
+    }
+}
+
+/// The reason the emulator stopped running.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum HaltReason {
+    /// The maximum number of steps was executed.
+    StepLimit,
+
+    /// A processor raised a trap with no handler installed.
+    UnhandledTrap(Trap),
+
+    /// A processor executed an `ebreak` in `EbreakMode::Halt`.
+    Ebreak,
+
+    /// A processor executed an `ebreak` in `EbreakMode::Debugger`, so
+    /// control should pass to the debugger.
+    Breakpoint,
+
+    /// A processor made the Linux `exit` system call with no handler
+    /// installed. Contains the exit code from `a0`.
+    Exit(u32),
+
+    /// The fuel set with `Emulator::set_fuel` ran out.
+    OutOfFuel,
+
+    /// A device, such as the test finisher, requested a reset. The
+    /// emulator doesn't reset itself, so it's up to the caller to restart.
+    Reset,
+
+    /// The wall-clock budget given to `Emulator::run_with_timeout` ran out.
+    Timeout,
+}
+
 pub struct Emulator {
-    pub memory: Memory,
+    pub bus: Bus,
     pub proc: Vec<Processor>,
+    mtime_tick: u64,
+
+    /// The number of consecutive instructions `run` executes on each
+    /// processor before switching to the next.
+    schedule_quantum: u64,
+    syscall_handler: Option<Box<dyn SyscallHandler>>,
+
+    /// The handler for SBI calls made with `ecall` from S-mode, if any.
+    sbi_handler: Option<Box<dyn SbiHandler>>,
+
+    /// The number of processor steps left before halting, or `None` for no
+    /// limit.
+    fuel: Option<u64>,
+
+    /// Everything loaded with `load`, in order, as the initial memory
+    /// image to restore on `reset_and_reload`.
+    image: Vec<(u32, Vec<u8>)>,
+
+    /// The address processors start from after a reset.
+    entry: u32,
+
+    /// The address of the device tree blob, if one was loaded, which is
+    /// passed to every processor in `a1` at reset.
+    dtb: Option<u32>,
 }
 
 impl Emulator {
+    /// Builds an emulator from the given configuration.
+    ///
+    /// Panics if the configuration is invalid. See `try_build` for a
+    /// checked version.
     pub fn build(config: EmulatorConfig) -> Self {
-        Self {
-            memory: Memory::new(config.mem_size),
+        match Self::try_build(config) {
+            Ok(emu) => emu,
+            Err(err) => panic!("Invalid emulator configuration: {err}."),
+        }
+    }
+
+    /// Builds an emulator from the given configuration, or returns an error
+    /// if the configuration is invalid.
+    pub fn try_build(config: EmulatorConfig) -> Result<Self, ConfigError> {
+        if config.mem_size == 0 {
+            return Err(ConfigError::ZeroMemory);
+        }
+
+        if config.proc_count == 0 {
+            return Err(ConfigError::ZeroProcessors);
+        }
+
+        if config.isa.xlen != Xlen::Rv32 {
+            return Err(ConfigError::UnsupportedXlen(config.isa.xlen));
+        }
+
+        if config.schedule_quantum == 0 {
+            return Err(ConfigError::ZeroQuantum);
+        }
+
+        let mut bus = Bus::new(Memory::new(config.mem_size));
+        bus.clint = Clint::new(config.proc_count);
+        bus.endianness = config.isa.endianness;
+        bus.straddle = config.straddle;
+
+        Ok(Self {
+            bus,
             proc: (0 .. config.proc_count)
-                .map(|_i| Processor::new())
+                .map(|_i| {
+                    let mut proc = Processor::new();
+                    proc.ebreak_mode = config.ebreak_mode;
+                    proc.strict_decode = config.strict_decode;
+                    proc.trap_on_divide_by_zero = config.trap_on_divide_by_zero;
+
+                    if let Some(coherence) = config.decode_cache {
+                        proc.enable_decode_cache(coherence);
+                    }
+                    proc.reg_x.set_poison(config.register_poison);
+                    proc.cost_table = config.cost_table;
+                    proc.extensions = config.isa.extensions;
+
+                    if let Some(capacity) = config.event_log_capacity {
+                        proc.enable_event_log(capacity);
+                    }
+
+                    proc
+                })
                 .collect(),
+            mtime_tick: config.mtime_tick,
+            schedule_quantum: config.schedule_quantum,
+            syscall_handler: None,
+            sbi_handler: None,
+            fuel: None,
+            image: Vec::new(),
+            entry: 0x00,
+            dtb: None,
+        })
+    }
+
+    /// Installs a handler for system calls made with `ecall` when the
+    /// processor has no trap handler of its own.
+    ///
+    /// Without one, only the Linux `exit` system call is recognised.
+    pub fn set_syscall_handler(&mut self, handler: Box<dyn SyscallHandler>) {
+        self.syscall_handler = Some(handler);
+    }
+
+    /// Installs a handler for SBI calls made with `ecall` from S-mode when
+    /// the processor has no M-mode trap handler, standing in for the
+    /// firmware.
+    ///
+    /// Without one, S-mode `ecall`s are unhandled traps.
+    pub fn set_sbi_handler(&mut self, handler: Box<dyn SbiHandler>) {
+        self.sbi_handler = Some(handler);
+    }
+
+    /// Limits the emulator to `fuel` more processor steps across all future
+    /// calls to `run`, after which it halts with `HaltReason::OutOfFuel`.
+    /// Fuel is unlimited until this is called.
+    pub fn set_fuel(&mut self, fuel: u64) {
+        self.fuel = Some(fuel);
+    }
+
+    /// Adds `fuel` more processor steps, if fuel is limited.
+    pub fn add_fuel(&mut self, fuel: u64) {
+        if let Some(remaining) = self.fuel.as_mut() {
+            *remaining = remaining.saturating_add(fuel);
+        }
+    }
+
+    /// Returns the number of processor steps left, or `None` if fuel is
+    /// unlimited.
+    pub fn remaining_fuel(&self) -> Option<u64> {
+        self.fuel
+    }
+
+    /// Returns the processor with the given hart index.
+    ///
+    /// Panics if there is no such hart.
+    pub fn hart(&self, index: usize) -> &Processor {
+        match self.proc.get(index) {
+            Some(proc) => proc,
+            None => panic!("Hart {index} does not exist."),
+        }
+    }
+
+    /// Returns the processor with the given hart index mutably.
+    ///
+    /// Panics if there is no such hart.
+    pub fn hart_mut(&mut self, index: usize) -> &mut Processor {
+        match self.proc.get_mut(index) {
+            Some(proc) => proc,
+            None => panic!("Hart {index} does not exist."),
+        }
+    }
+
+    /// Returns the estimated number of instructions retired per cycle
+    /// across all processors, or `None` if none have run for any cycles.
+    ///
+    /// This is only as accurate as the configured cost table.
+    pub fn ipc(&self) -> Option<f64> {
+        let instret: u64 = self.proc.iter().map(|proc| proc.instret).sum();
+        let cycle: u64 = self.proc.iter().map(|proc| proc.cycle).sum();
+
+        match cycle {
+            0 => None,
+            cycle => Some(instret as f64 / cycle as f64),
+        }
+    }
+
+    /// Returns main memory.
+    pub fn memory(&self) -> &Memory {
+        &self.bus.memory
+    }
+
+    /// Returns main memory mutably.
+    pub fn memory_mut(&mut self) -> &mut Memory {
+        &mut self.bus.memory
+    }
+
+    /// Loads a program or data into memory at the given address.
+    ///
+    /// A copy is kept as part of the initial memory image, which
+    /// `reset_and_reload` restores.
+    pub fn load(&mut self, addr: u32, data: &[u8]) {
+        self.bus.memory.write(addr as usize, data);
+        self.image.push((addr, data.to_vec()));
+
+        for proc in self.proc.iter_mut() {
+            proc.flush_decode_cache();
+        }
+    }
+
+    /// Loads a device tree blob into memory at the given address, as-is,
+    /// and sets up every processor to boot with it following the Linux
+    /// convention: `a0` holds the hart index and `a1` the address of the
+    /// blob. Resets set these registers up again.
+    pub fn load_dtb(&mut self, addr: u32, dtb: &[u8]) {
+        self.load(addr, dtb);
+        self.dtb = Some(addr);
+        self.set_boot_registers();
+    }
+
+    /// Resets every processor to start from the entry point, which is
+    /// zero unless set by `load_many` or `load_elf`, and resets the CLINT.
+    /// Memory is left as it is. If a device tree blob was loaded, the boot
+    /// registers are set up again.
+    pub fn reset(&mut self) {
+        for proc in self.proc.iter_mut() {
+            proc.reset(self.entry);
+        }
+
+        self.bus.clint = Clint::new(self.proc.len());
+        self.set_boot_registers();
+    }
+
+    /// Writes each processor's hart index to `a0` and the device tree
+    /// blob's address to `a1`, if a blob was loaded.
+    fn set_boot_registers(&mut self) {
+        let Some(dtb) = self.dtb else {
+            return;
+        };
+
+        for (hart, proc) in self.proc.iter_mut().enumerate() {
+            proc.reg_x.write(10, hart as u32);
+            proc.reg_x.write(11, dtb);
+        }
+    }
+
+    /// Resets like `reset`, and also restores memory to its initial image:
+    /// everything is zeroed, clearing `.bss`, and everything loaded with
+    /// `load` is loaded again, undoing any changes to `.data` or code.
+    pub fn reset_and_reload(&mut self) {
+        let len = self.bus.memory.len();
+        self.bus.memory.fill(0, len, 0x00);
+
+        for (addr, data) in &self.image {
+            self.bus.memory.write(*addr as usize, data);
+        }
+
+        self.reset();
+    }
+
+    /// Loads several blobs into memory, each at its own address, and sets
+    /// every processor's `pc` to `entry`.
+    ///
+    /// Returns an error, without loading anything, if any two blobs would
+    /// overlap, or if `entry` isn't aligned to `IALIGN`, as every reset
+    /// would then fault on its first fetch. Empty blobs never overlap
+    /// anything.
+    pub fn load_many(&mut self, blobs: &[(u32, &[u8])], entry: u32) -> Result<(), LoadError> {
+        let ialign = self.hart(0).ialign();
+
+        if !entry.is_multiple_of(ialign / 8) {
+            return Err(LoadError::MisalignedEntry { entry, ialign });
+        }
+
+        // The end of each range is exclusive, so is computed in 64 bits in
+        // case a blob ends at the top of the address space.
+        let range = |&(addr, data): &(u32, &[u8])| (addr as u64, addr as u64 + data.len() as u64);
+
+        for (i, first) in blobs.iter().enumerate() {
+            for second in &blobs[i + 1 ..] {
+                let (first_start, first_end) = range(first);
+                let (second_start, second_end) = range(second);
+
+                if first_start < second_end && second_start < first_end {
+                    return Err(LoadError::Overlap {
+                        first: first.0,
+                        second: second.0,
+                    });
+                }
+            }
+        }
+
+        for &(addr, data) in blobs {
+            self.load(addr, data);
+        }
+
+        for proc in self.proc.iter_mut() {
+            proc.pc = entry;
+        }
+
+        self.entry = entry;
+        Ok(())
+    }
+
+    /// Loads the segments of a RISC-V ELF executable into memory, zeroing
+    /// any part of a segment not stored in the file, such as `.bss`, and
+    /// sets every processor's `pc` to the entry point, which is returned.
+    ///
+    /// Returns an error, without loading anything, if the file can't be
+    /// parsed.
+    pub fn load_elf(&mut self, bytes: &[u8]) -> Result<u32, ElfError> {
+        let elf = elf::parse(bytes)?;
+        let ialign = self.hart(0).ialign();
+
+        if !elf.entry.is_multiple_of(ialign / 8) {
+            return Err(ElfError::MisalignedEntry { entry: elf.entry, ialign });
+        }
+
+        for segment in &elf.segments {
+            self.load(segment.addr, segment.data);
+
+            // `p_memsz` comes from the file, so zeroing more than the whole
+            // memory, which would only wrap around, isn't allocated.
+            let zeroed = (segment.mem_size as usize)
+                .saturating_sub(segment.data.len())
+                .min(self.bus.memory.len());
+
+            if zeroed > 0 {
+                let end = segment.addr.wrapping_add(segment.data.len() as u32);
+                self.load(end, &alloc::vec![0; zeroed]);
+            }
+        }
+
+        for proc in self.proc.iter_mut() {
+            proc.pc = elf.entry;
+        }
+
+        self.entry = elf.entry;
+        Ok(elf.entry)
+    }
+
+    /// Reads a RISC-V ELF executable from `path` and loads it like
+    /// `load_elf`, returning its entry point.
+    #[cfg(feature = "std")]
+    pub fn load_elf_from_path<P>(&mut self, path: P) -> Result<u32, ElfLoadError>
+    where
+        P: AsRef<std::path::Path>,
+    {
+        let bytes = std::fs::read(path).map_err(ElfLoadError::Io)?;
+        self.load_elf(&bytes).map_err(ElfLoadError::Elf)
+    }
+
+    /// Returns the address just past the highest byte loaded with `load`,
+    /// or zero if nothing has been, such as to place a heap after a
+    /// program.
+    pub fn loaded_end(&self) -> u32 {
+        self.image
+            .iter()
+            .map(|(addr, data)| addr.saturating_add(data.len() as u32))
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Runs the processors until one of them halts or `max_steps` steps
+    /// have been executed. Each step advances every running processor by
+    /// one instruction and `mtime` by the configured tick. Halted
+    /// processors are skipped until a software interrupt wakes them.
+    ///
+    /// Steps are taken a quantum at a time: each processor in turn executes
+    /// its instructions for the quantum, then `mtime` advances by the whole
+    /// quantum, so interrupts are only seen between quanta. Each run starts
+    /// a new quantum with hart zero.
+    pub fn run(&mut self, max_steps: u64) -> HaltReason {
+        let mut steps = 0;
+
+        while steps < max_steps {
+            let quantum = self.schedule_quantum.min(max_steps - steps);
+
+            for hart in 0 .. self.proc.len() {
+                for _ in 0 .. quantum {
+                    if self.proc[hart].state == HartState::Halted {
+                        break;
+                    }
+
+                    if let Err(reason) = self.step_hart(hart) {
+                        return reason;
+                    }
+                }
+            }
+
+            for _ in 0 .. quantum {
+                self.bus.clint.tick(self.mtime_tick);
+                self.update_clint_interrupts();
+            }
+
+            steps += quantum;
+        }
+
+        HaltReason::StepLimit
+    }
+
+    /// Runs the processors like `run`, with no step limit, until one of
+    /// them halts or `timeout` has elapsed, which halts with
+    /// `HaltReason::Timeout`.
+    ///
+    /// The clock is only read every `TIMEOUT_CHECK_INTERVAL` steps, so the
+    /// run may overshoot `timeout` slightly. Where runs must be
+    /// reproducible, use `run` instead.
+    #[cfg(feature = "std")]
+    pub fn run_with_timeout(&mut self, timeout: std::time::Duration) -> HaltReason {
+        let start = std::time::Instant::now();
+
+        loop {
+            match self.run(TIMEOUT_CHECK_INTERVAL) {
+                HaltReason::StepLimit if start.elapsed() >= timeout => {
+                    return HaltReason::Timeout;
+                },
+
+                HaltReason::StepLimit => (),
+                reason => return reason,
+            }
+        }
+    }
+
+    /// Advances every running processor by one instruction and `mtime` by
+    /// the configured tick, as one step of `run` does, returning each
+    /// processor's result indexed by hart.
+    ///
+    /// Halted processors aren't stepped and report `StepResult::Stopped`,
+    /// while processors waiting for an interrupt report `Idle`. A
+    /// processor whose step would make `run` stop reports
+    /// `StepResult::Halted` with the reason, and the others still step.
+    pub fn step_all(&mut self) -> Vec<StepResult> {
+        let results = (0 .. self.proc.len())
+            .map(|hart| {
+                if self.proc[hart].state == HartState::Halted {
+                    return StepResult::Stopped;
+                }
+
+                match self.step_hart(hart) {
+                    Ok(result) => result,
+                    Err(reason) => StepResult::Halted(reason),
+                }
+            })
+            .collect();
+
+        self.bus.clint.tick(self.mtime_tick);
+        self.update_clint_interrupts();
+
+        results
+    }
+
+    /// Steps a single processor, consuming fuel and servicing system calls
+    /// and device halt requests. Returns the step's result, or the reason
+    /// the emulator should stop.
+    fn step_hart(&mut self, hart: usize) -> Result<StepResult, HaltReason> {
+        match self.fuel.as_mut() {
+            Some(0) => return Err(HaltReason::OutOfFuel),
+            Some(fuel) => *fuel -= 1,
+            None => (),
+        }
+
+        let proc = &mut self.proc[hart];
+        let result = proc.step(&mut self.bus);
+
+        if let Some(reason) = self.bus.take_halt() {
+            return Err(reason);
+        }
+
+        match result {
+            StepResult::Continued
+                | StepResult::Trapped(_)
+                | StepResult::Idle
+                | StepResult::Stopped => Ok(result),
+
+            StepResult::Halted(
+                reason @ HaltReason::UnhandledTrap(Trap::EnvironmentCallFromMMode)
+            ) => {
+                let number = proc.reg_x.read(17);
+                let args: [u32; 6] = core::array::from_fn(|i| proc.reg_x.read(10 + i));
+
+                let outcome = match self.syscall_handler.as_mut() {
+                    Some(handler) => handler.syscall(number, args, &mut self.bus),
+                    None if number == SYSCALL_EXIT => SyscallOutcome::Exit(args[0]),
+                    None => SyscallOutcome::Unsupported,
+                };
+
+                match outcome {
+                    SyscallOutcome::Return(value) => {
+                        proc.reg_x.write(10, value);
+                        proc.pc = proc.pc.wrapping_add(0x04);
+                        Ok(StepResult::Continued)
+                    },
+
+                    SyscallOutcome::Exit(code) => Err(HaltReason::Exit(code)),
+                    SyscallOutcome::Unsupported => Err(reason),
+                }
+            },
+
+            StepResult::Halted(
+                reason @ HaltReason::UnhandledTrap(Trap::EnvironmentCallFromSMode)
+            ) => {
+                let Some(handler) = self.sbi_handler.as_mut() else {
+                    return Err(reason);
+                };
+
+                let extension = proc.reg_x.read(17);
+                let function = proc.reg_x.read(16);
+                let args: [u32; 6] = core::array::from_fn(|i| proc.reg_x.read(10 + i));
+
+                let ret = handler.sbi_call(extension, function, args, &mut self.bus);
+
+                proc.reg_x.write(10, ret.error as u32);
+                if !sbi::is_legacy_extension(extension) {
+                    proc.reg_x.write(11, ret.value);
+                }
+                proc.pc = proc.pc.wrapping_add(0x04);
+                Ok(StepResult::Continued)
+            },
+
+            StepResult::Halted(reason) => Err(reason),
+            StepResult::Breakpoint => Err(HaltReason::Breakpoint),
+        }
+    }
+
+    /// Raises or clears each processor's timer and software interrupts to
+    /// match the state of the CLINT. A pending software interrupt wakes a
+    /// halted processor.
+    fn update_clint_interrupts(&mut self) {
+        for (hart, proc) in self.proc.iter_mut().enumerate() {
+            if proc.state == HartState::Halted && self.bus.clint.software_pending(hart) {
+                proc.wake();
+            }
+
+            for (pending, interrupt) in [
+                (self.bus.clint.timer_pending(hart), Interrupt::MachineTimer),
+                (self.bus.clint.software_pending(hart), Interrupt::MachineSoftware),
+            ] {
+                if pending {
+                    proc.raise_interrupt(interrupt);
+                } else {
+                    proc.clear_interrupt(interrupt);
+                }
+            }
         }
     }
 
     // Just for testing purposes. Will delete later.
-    pub fn dev_start(&mut self, obj_data: &[u8]) {
+    #[cfg(feature = "std")]
+    pub fn dev_start(&mut self, obj_data: &[u8], max_steps: u64) -> HaltReason {
         println!("Instructions:\n");
 
         obj_data
@@ -41,27 +772,1110 @@ impl Emulator {
                 );
 
                 println!("{instr}");
+            });
+
+        self.load(0x00, obj_data);
+
+        let reason = self.run(max_steps);
+
+        println!("\n{}", self.hart(0));
+
+        reason
+    }
+}
+
+impl Debug for Emulator {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("Emulator")
+            .field("bus", &self.bus)
+            .field("proc", &self.proc)
+            .field("mtime_tick", &self.mtime_tick)
+            .field("schedule_quantum", &self.schedule_quantum)
+            .field("syscall_handler", &self.syscall_handler.is_some())
+            .field("sbi_handler", &self.sbi_handler.is_some())
+            .field("fuel", &self.fuel)
+            .field("entry", &self.entry)
+            .field("dtb", &self.dtb)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::{
+        ConfigError,
+        CostTable,
+        ElfLoadError,
+        Emulator,
+        EmulatorConfig,
+        HaltReason,
+        Interrupt,
+        EbreakMode,
+        LoadError,
+        HartState,
+        IsaProfile,
+        Processor,
+        StepResult,
+        Trap,
+        Xlen,
+    };
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    use crate::csr::{MIP, MTVEC};
+    use crate::isa::{Endianness, Extension};
+    use crate::syscall::LinuxSyscalls;
+    use crate::encode::{encode, Operands};
+    use crate::op::{Op, Op::*};
+
+    fn emulator() -> Emulator {
+        Emulator::build(EmulatorConfig::default())
+    }
+
+    /// Assembles a program into memory at address zero.
+    pub(crate) fn load_program(emu: &mut Emulator, program: &[(Op, Operands)]) {
+        for (i, (op, operands)) in program.iter().enumerate() {
+            emu.load(i as u32 * 4, &encode(op, operands).to_le_bytes());
+        }
+    }
+
+    /// Assembles a program at address zero, runs it until `pc` leaves the
+    /// program and returns the final state of the processor.
+    ///
+    /// Panics if the program halts or runs for too long.
+    pub(crate) fn run_program(program: &[(Op, Operands)]) -> Processor {
+        let mut emu = emulator();
+        let end = program.len() as u32 * 4;
+
+        load_program(&mut emu, program);
+
+        for _ in 0 .. 10_000 {
+            if emu.hart(0).pc >= end {
+                return emu.proc.swap_remove(0);
+            }
+
+            match emu.run(1) {
+                HaltReason::StepLimit => (),
+                reason => panic!("Program halted: {reason:?}"),
+            }
+        }
+
+        panic!("Program did not finish within the step limit.");
+    }
+
+    mod exit {
+        use super::*;
+
+        #[test]
+        fn halts_with_exit_code_on_exit_syscall() {
+            let mut emu = emulator();
+
+            load_program(&mut emu, &[
+                // li a7, 93
+                (ArithmeticAddImmediate, Operands::i(17, 0, 93)),
+                // li a0, 42
+                (ArithmeticAddImmediate, Operands::i(10, 0, 42)),
+                (SystemEcall, Operands::none()),
+            ]);
+
+            assert_eq!(emu.run(10), HaltReason::Exit(42));
+        }
+
+        #[test]
+        fn other_syscalls_are_unhandled_traps() {
+            let mut emu = emulator();
 
-                self.proc[0].execute(&instr);
+            load_program(&mut emu, &[
+                // li a7, 64
+                (ArithmeticAddImmediate, Operands::i(17, 0, 64)),
+                (SystemEcall, Operands::none()),
+            ]);
+
+            assert_eq!(
+                emu.run(10),
+                HaltReason::UnhandledTrap(Trap::EnvironmentCallFromMMode),
+            );
+        }
+
+        #[test]
+        fn handler_services_write_and_continues() {
+            let output: Rc<RefCell<Vec<u8>>> = Rc::default();
+            let sink = output.clone();
+            let mut emu = emulator();
+
+            emu.set_syscall_handler(Box::new(LinuxSyscalls::new(
+                0x200,
+                Box::new(move |_, bytes| sink.borrow_mut().extend_from_slice(bytes)),
+            )));
+
+            emu.load(0x100, b"hi");
+
+            load_program(&mut emu, &[
+                // li a7, 64
+                (ArithmeticAddImmediate, Operands::i(17, 0, 64)),
+                // li a0, 1
+                (ArithmeticAddImmediate, Operands::i(10, 0, 1)),
+                // li a1, 0x100
+                (ArithmeticAddImmediate, Operands::i(11, 0, 0x100)),
+                // li a2, 2
+                (ArithmeticAddImmediate, Operands::i(12, 0, 2)),
+                (SystemEcall, Operands::none()),
+                // mv a3, a0
+                (ArithmeticAddImmediate, Operands::i(13, 10, 0)),
+                // li a7, 93
+                (ArithmeticAddImmediate, Operands::i(17, 0, 93)),
+                (SystemEcall, Operands::none()),
+            ]);
+
+            assert_eq!(emu.run(20), HaltReason::Exit(2));
+            assert_eq!(*output.borrow(), b"hi");
+        }
+    }
+
+    mod try_build {
+        use super::*;
+
+        #[test]
+        fn rejects_zero_memory() {
+            let result = Emulator::try_build(EmulatorConfig {
+                mem_size: 0,
+                ..EmulatorConfig::default()
             });
 
-        println!(
-            "\nProgram Counter:\t{:#010x} / {}", 
-            self.proc[0].pc,
-            self.proc[0].pc, 
-        );
+            assert_eq!(result.unwrap_err(), ConfigError::ZeroMemory);
+        }
+
+        #[test]
+        fn rejects_zero_processors() {
+            let result = Emulator::try_build(EmulatorConfig {
+                proc_count: 0,
+                ..EmulatorConfig::default()
+            });
 
-        println!("\nRegisters:\n");
+            assert_eq!(result.unwrap_err(), ConfigError::ZeroProcessors);
+        }
 
-        (0 .. self.proc[0].reg_x.len() / 2)
-            .for_each(|i| {
-                println!(
-                    "x{:<2}  ( 0x{:08x} )    x{:<2}  ( 0x{:08x} )",
-                    i,
-                    self.proc[0].reg_x.read(i),
-                    i + 16,
-                    self.proc[0].reg_x.read(i + 16),
-                );
+        #[test]
+        fn accepts_default_config() {
+            assert!(Emulator::try_build(EmulatorConfig::default()).is_ok());
+        }
+
+        #[test]
+        #[should_panic]
+        fn build_panics_on_invalid_config() {
+            Emulator::build(EmulatorConfig {
+                proc_count: 0,
+                ..EmulatorConfig::default()
             });
+        }
+    }
+
+    mod run {
+        use super::*;
+
+        #[test]
+        fn stops_self_loop_at_step_limit() {
+            let mut emu = emulator();
+
+            // jal x0, 0
+            emu.load(0x00, &0x0000006fu32.to_le_bytes());
+
+            assert_eq!(emu.run(10), HaltReason::StepLimit);
+            assert_eq!(emu.hart(0).pc, 0x00);
+        }
+
+        #[test]
+        fn executes_the_requested_number_of_steps() {
+            let mut emu = emulator();
+
+            // addi x1, x1, 1
+            // jal x0, -4
+            emu.load(0x00, &0x00108093u32.to_le_bytes());
+            emu.load(0x04, &0xffdff06fu32.to_le_bytes());
+
+            emu.run(6);
+            assert_eq!(emu.hart(0).reg_x.read(1), 3);
+        }
+
+        #[test]
+        fn halts_on_unhandled_trap() {
+            let mut emu = emulator();
+
+            // add x0, x0, x0 with a reserved funct7 (unsupported)
+            emu.load(0x00, &0x80000033u32.to_le_bytes());
+
+            assert_eq!(
+                emu.run(10),
+                HaltReason::UnhandledTrap(Trap::IllegalInstruction(0x80000033)),
+            );
+        }
+
+        /// Runs `ebreak` under the given mode with a handler installed that
+        /// spins forever.
+        fn run_ebreak(ebreak_mode: EbreakMode) -> HaltReason {
+            let mut emu = Emulator::build(EmulatorConfig {
+                ebreak_mode,
+                ..EmulatorConfig::default()
+            });
+
+            // ebreak
+            // jal x0, 0
+            emu.load(0x00, &0x00100073u32.to_le_bytes());
+            emu.load(0x100, &0x0000006fu32.to_le_bytes());
+            emu.hart_mut(0).csr.write(MTVEC, 0x100);
+
+            emu.run(10)
+        }
+
+        #[test]
+        fn halts_on_ebreak_by_default() {
+            assert_eq!(run_ebreak(EbreakMode::default()), HaltReason::Ebreak);
+        }
+
+        #[test]
+        fn stops_for_debugger_on_ebreak() {
+            assert_eq!(run_ebreak(EbreakMode::Debugger), HaltReason::Breakpoint);
+        }
+
+        #[test]
+        fn traps_on_ebreak_in_trap_mode() {
+            assert_eq!(run_ebreak(EbreakMode::Trap), HaltReason::StepLimit);
+        }
+    }
+
+    mod run_with_timeout {
+        use super::*;
+
+        use std::time::Duration;
+
+        #[test]
+        fn returns_normally_before_the_timeout() {
+            let mut emu = emulator();
+
+            // ebreak
+            emu.load(0x00, &0x00100073u32.to_le_bytes());
+
+            assert_eq!(emu.run_with_timeout(Duration::from_secs(10)), HaltReason::Ebreak);
+        }
+
+        #[test]
+        fn halts_self_loop_at_the_timeout() {
+            let mut emu = emulator();
+
+            // jal x0, 0
+            emu.load(0x00, &0x0000006fu32.to_le_bytes());
+
+            assert_eq!(emu.run_with_timeout(Duration::from_millis(20)), HaltReason::Timeout);
+            assert!(emu.hart(0).instret > 0);
+        }
+    }
+
+    mod program {
+        use super::*;
+
+        #[test]
+        fn adds_to_loaded_immediate() {
+            let proc = run_program(&[
+                // li a0, 5
+                (ArithmeticAddImmediate, Operands::i(10, 0, 5)),
+                // addi a0, a0, 3
+                (ArithmeticAddImmediate, Operands::i(10, 10, 3)),
+            ]);
+
+            assert_eq!(proc.reg_x.read(10), 8);
+        }
+
+        #[test]
+        fn sums_a_countdown_loop() {
+            let proc = run_program(&[
+                // li t0, 5
+                (ArithmeticAddImmediate, Operands::i(5, 0, 5)),
+                // loop: add a0, a0, t0
+                (ArithmeticAdd, Operands::r(10, 10, 5)),
+                // addi t0, t0, -1
+                (ArithmeticAddImmediate, Operands::i(5, 5, -1)),
+                // bnez t0, loop
+                (BranchNotEqual, Operands::s(5, 0, -8)),
+                // sw a0, 0x100(zero)
+                (StoreWord, Operands::s(0, 10, 0x100)),
+                // lw a1, 0x100(zero)
+                (LoadWord, Operands::i(11, 0, 0x100)),
+            ]);
+
+            assert_eq!(proc.reg_x.read(10), 15);
+            assert_eq!(proc.reg_x.read(11), 15);
+            assert_eq!(proc.instret, 1 + 5 * 3 + 2);
+        }
+    }
+
+    mod load_many {
+        use super::*;
+
+        #[test]
+        fn places_each_blob_at_its_address() {
+            let mut emu = emulator();
+
+            assert_eq!(
+                emu.load_many(&[(0x100, &[1, 2, 3, 4]), (0x200, &[5, 6])], 0x200),
+                Ok(()),
+            );
+            assert_eq!(emu.memory().read(0x100, 4), [1, 2, 3, 4]);
+            assert_eq!(emu.memory().read(0x200, 2), [5, 6]);
+            assert_eq!(emu.hart(0).pc, 0x200);
+        }
+
+        #[test]
+        fn rejects_overlapping_blobs() {
+            let mut emu = emulator();
+
+            assert_eq!(
+                emu.load_many(&[(0x100, &[1, 2, 3, 4]), (0x102, &[5, 6])], 0x100),
+                Err(LoadError::Overlap { first: 0x100, second: 0x102 }),
+            );
+            assert_eq!(emu.memory().read(0x100, 4), [0; 4]);
+            assert_eq!(emu.hart(0).pc, 0);
+        }
+
+        #[test]
+        fn rejects_misaligned_entry() {
+            let mut emu = emulator();
+
+            assert_eq!(
+                emu.load_many(&[(0x100, &[1, 2, 3, 4])], 0x101),
+                Err(LoadError::MisalignedEntry { entry: 0x101, ialign: 32 }),
+            );
+            assert_eq!(emu.memory().read(0x100, 4), [0; 4]);
+            assert_eq!(emu.hart(0).pc, 0);
+        }
+
+        #[test]
+        fn adjacent_blobs_do_not_overlap() {
+            let mut emu = emulator();
+            assert!(emu.load_many(&[(0x104, &[5, 6]), (0x100, &[1, 2, 3, 4])], 0).is_ok());
+        }
+    }
+
+    mod event_log {
+        use super::*;
+
+        #[test]
+        fn is_enabled_by_config() {
+            let mut emu = Emulator::build(EmulatorConfig {
+                event_log_capacity: Some(2),
+                ..EmulatorConfig::default()
+            });
+
+            // jal x0, 0
+            emu.load(0x00, &0x0000006fu32.to_le_bytes());
+            emu.run(5);
+
+            assert_eq!(emu.hart(0).event_log().unwrap().len(), 2);
+        }
+    }
+
+    mod extensions {
+        use super::*;
+
+        #[test]
+        fn reports_m_instructions_under_rv32i() {
+            let mut emu = Emulator::build(EmulatorConfig {
+                isa: IsaProfile::rv32i(),
+                ..EmulatorConfig::default()
+            });
+
+            load_program(&mut emu, &[
+                (ArithmeticAddImmediate, Operands::i(1, 0, 3)),
+                (ArithmeticMul, Operands::r(2, 1, 1)),
+            ]);
+
+            assert_eq!(
+                emu.run(10),
+                HaltReason::UnhandledTrap(
+                    Trap::UnsupportedExtension(Extension::M, 0x02108133),
+                ),
+            );
+            assert_eq!(emu.hart(0).pc, 0x04);
+        }
+
+        #[test]
+        fn rejects_m_instructions_by_default() {
+            let mut emu = emulator();
+            load_program(&mut emu, &[
+                (ArithmeticAddImmediate, Operands::i(1, 0, 3)),
+                (ArithmeticMul, Operands::r(2, 1, 1)),
+            ]);
+
+            assert_eq!(
+                emu.run(10),
+                HaltReason::UnhandledTrap(
+                    Trap::UnsupportedExtension(Extension::M, 0x02108133),
+                ),
+            );
+            assert_eq!(emu.hart(0).reg_x.read(2), 0);
+        }
+
+        #[test]
+        fn rv32im_profile_executes_mul_but_not_fadd_s() {
+            let mut emu = Emulator::build(EmulatorConfig {
+                isa: IsaProfile::rv32im(),
+                ..EmulatorConfig::default()
+            });
+
+            load_program(&mut emu, &[
+                (ArithmeticAddImmediate, Operands::i(1, 0, 3)),
+                (ArithmeticMul, Operands::r(2, 1, 1)),
+            ]);
+            emu.load(0x08, &0x002081d3u32.to_le_bytes()); // fadd.s f3, f1, f2
+
+            assert_eq!(
+                emu.run(10),
+                HaltReason::UnhandledTrap(
+                    Trap::UnsupportedExtension(Extension::F, 0x002081d3),
+                ),
+            );
+            assert_eq!(emu.hart(0).reg_x.read(2), 9);
+        }
+
+        #[test]
+        fn big_endian_profile_stores_data_big_endian() {
+            let mut emu = Emulator::build(EmulatorConfig {
+                isa: IsaProfile {
+                    endianness: Endianness::Big,
+                    ..IsaProfile::rv32im()
+                },
+                ..EmulatorConfig::default()
+            });
+
+            load_program(&mut emu, &[
+                (LoadUpperImmediate, Operands::u(1, 0x12345)),
+                (StoreWord, Operands::s(0, 1, 0x100)),
+            ]);
+            emu.run(2);
+
+            assert_eq!(emu.memory().read(0x100, 4), [0x12, 0x34, 0x50, 0x00]);
+        }
+
+        #[test]
+        fn rv64_profile_is_rejected() {
+            let config = EmulatorConfig {
+                isa: IsaProfile { xlen: Xlen::Rv64, ..IsaProfile::rv32i() },
+                ..EmulatorConfig::default()
+            };
+
+            assert_eq!(
+                Emulator::try_build(config).err(),
+                Some(ConfigError::UnsupportedXlen(Xlen::Rv64)),
+            );
+        }
+    }
+
+    mod timing {
+        use super::*;
+
+        #[test]
+        fn costly_divides_lower_ipc() {
+            let mut emu = Emulator::build(EmulatorConfig {
+                isa: IsaProfile::rv32im(),
+                cost_table: CostTable::nominal(),
+                ..EmulatorConfig::default()
+            });
+
+            load_program(&mut emu, &[
+                (ArithmeticAddImmediate, Operands::i(1, 0, 100)),
+                (ArithmeticAddImmediate, Operands::i(2, 0, 7)),
+                (ArithmeticDiv, Operands::r(3, 1, 2)),
+                (ArithmeticDiv, Operands::r(4, 3, 2)),
+            ]);
+            emu.run(4);
+
+            let proc = emu.hart(0);
+            assert_eq!(proc.reg_x.read(4), 2);
+            assert_eq!(proc.instret, 4);
+            assert_eq!(proc.cycle, 1 + 1 + 32 + 32);
+            assert_eq!(emu.ipc(), Some(4.0 / 66.0));
+        }
+
+        #[test]
+        fn ipc_is_none_before_running() {
+            assert_eq!(emulator().ipc(), None);
+        }
+    }
+
+    mod register_poison {
+        use super::*;
+
+        #[test]
+        fn poisons_registers_until_written() {
+            let mut emu = Emulator::build(EmulatorConfig {
+                register_poison: Some(0xdeadbeef),
+                ..EmulatorConfig::default()
+            });
+
+            assert_eq!(emu.hart(0).reg_x.read(0), 0x00);
+            assert_eq!(emu.hart(0).reg_x.read(5), 0xdeadbeef);
+
+            // addi x5, x0, 1
+            emu.load(0x00, &0x00100293u32.to_le_bytes());
+            emu.run(1);
+
+            assert_eq!(emu.hart(0).reg_x.read(0), 0x00);
+            assert_eq!(emu.hart(0).reg_x.read(5), 0x01);
+            assert_eq!(emu.hart(0).reg_x.read(6), 0xdeadbeef);
+        }
+    }
+
+    mod fuel {
+        use super::*;
+
+        /// Creates an emulator running an infinite loop of `addi`s.
+        fn counting_loop() -> Emulator {
+            let mut emu = emulator();
+
+            load_program(&mut emu, &[
+                (ArithmeticAddImmediate, Operands::i(1, 1, 1)),
+                (JumpAndLink, Operands::u(0, -4)),
+            ]);
+
+            emu
+        }
+
+        #[test]
+        fn halts_after_executing_exactly_the_fuel() {
+            let mut emu = counting_loop();
+            emu.set_fuel(3);
+
+            assert_eq!(emu.run(100), HaltReason::OutOfFuel);
+            assert_eq!(emu.hart(0).instret, 3);
+            assert_eq!(emu.hart(0).reg_x.read(1), 2);
+            assert_eq!(emu.remaining_fuel(), Some(0));
+        }
+
+        #[test]
+        fn persists_across_runs_and_can_be_refilled() {
+            let mut emu = counting_loop();
+            emu.set_fuel(3);
+
+            assert_eq!(emu.run(2), HaltReason::StepLimit);
+            assert_eq!(emu.run(2), HaltReason::OutOfFuel);
+            assert_eq!(emu.hart(0).instret, 3);
+
+            emu.add_fuel(2);
+            assert_eq!(emu.run(100), HaltReason::OutOfFuel);
+            assert_eq!(emu.hart(0).instret, 5);
+        }
+
+        #[test]
+        fn is_unlimited_by_default() {
+            let mut emu = counting_loop();
+            emu.add_fuel(1);
+            assert_eq!(emu.remaining_fuel(), None);
+            assert_eq!(emu.run(100), HaltReason::StepLimit);
+        }
+    }
+
+    mod test_finisher {
+        use super::*;
+
+        use crate::test_finisher::{TestFinisher, TEST_FINISHER_BASE, TEST_FINISHER_SIZE};
+
+        #[test]
+        fn halts_with_code_written_to_finisher() {
+            let mut emu = emulator();
+            emu.bus.register(
+                TEST_FINISHER_BASE,
+                TEST_FINISHER_SIZE,
+                Box::new(TestFinisher::new()),
+            );
+
+            // Fail with exit code 7: (7 << 16) | 0x3333
+            load_program(&mut emu, &[
+                (LoadUpperImmediate, Operands::u(1, 0x100)),
+                (LoadUpperImmediate, Operands::u(2, 0x73)),
+                (ArithmeticAddImmediate, Operands::i(2, 2, 0x333)),
+                (StoreWord, Operands::s(1, 2, 0)),
+                (JumpAndLink, Operands::u(0, 0)),
+            ]);
+
+            assert_eq!(emu.run(100), HaltReason::Exit(7));
+            assert_eq!(emu.hart(0).pc, 0x10);
+        }
+    }
+
+    mod uart {
+        use super::*;
+
+        use std::io::Cursor;
+
+        use crate::uart::{reader_input, Uart, UART_BASE, UART_SIZE};
+
+        #[test]
+        fn guest_reads_byte_from_input() {
+            let mut emu = emulator();
+            let uart = Uart::new(reader_input(Cursor::new(b"A".to_vec())), Box::new(|_| ()));
+            emu.bus.register(UART_BASE, UART_SIZE, Box::new(uart));
+
+            load_program(&mut emu, &[
+                (LoadUpperImmediate, Operands::u(1, 0x10000)),
+                (LoadByteUnsigned, Operands::i(2, 1, 5)),
+                (LoadByte, Operands::i(3, 1, 0)),
+                (LoadByteUnsigned, Operands::i(4, 1, 5)),
+            ]);
+            emu.run(4);
+
+            // The line status reports data ready, then not ready once the
+            // byte has been read.
+            assert_eq!(emu.hart(0).reg_x.read(2) & 0x01, 0x01);
+            assert_eq!(emu.hart(0).reg_x.read(3), b'A' as u32);
+            assert_eq!(emu.hart(0).reg_x.read(4) & 0x01, 0x00);
+        }
+    }
+
+    mod sbi {
+        use super::*;
+
+        use crate::processor::PrivilegeMode;
+        use crate::sbi::SbiConsole;
+
+        #[test]
+        fn supervisor_ecall_writes_to_console() {
+            let output = Rc::new(RefCell::new(Vec::new()));
+            let sink = output.clone();
+
+            let mut emu = emulator();
+            emu.set_sbi_handler(Box::new(SbiConsole::new(
+                Box::new(move |byte| sink.borrow_mut().push(byte)),
+            )));
+
+            load_program(&mut emu, &[
+                (ArithmeticAddImmediate, Operands::i(17, 0, 1)),
+                (ArithmeticAddImmediate, Operands::i(10, 0, b'!' as i32)),
+                (SystemEcall, Operands::none()),
+            ]);
+            emu.hart_mut(0).privilege = PrivilegeMode::Supervisor;
+
+            assert_eq!(emu.run(3), HaltReason::StepLimit);
+            assert_eq!(*output.borrow(), b"!");
+            assert_eq!(emu.hart(0).reg_x.read(10), 0);
+            assert_eq!(emu.hart(0).pc, 0x0c);
+        }
+
+        #[test]
+        fn legacy_putchar_preserves_a1() {
+            let mut emu = emulator();
+            emu.set_sbi_handler(Box::new(SbiConsole::new(Box::new(|_| ()))));
+
+            load_program(&mut emu, &[
+                (ArithmeticAddImmediate, Operands::i(17, 0, 1)),
+                (ArithmeticAddImmediate, Operands::i(11, 0, 0x123)),
+                (SystemEcall, Operands::none()),
+            ]);
+            emu.hart_mut(0).privilege = PrivilegeMode::Supervisor;
+
+            assert_eq!(emu.run(3), HaltReason::StepLimit);
+            assert_eq!(emu.hart(0).reg_x.read(11), 0x123);
+        }
+
+        #[test]
+        fn supervisor_ecall_is_unhandled_without_handler() {
+            let mut emu = emulator();
+            load_program(&mut emu, &[(SystemEcall, Operands::none())]);
+            emu.hart_mut(0).privilege = PrivilegeMode::Supervisor;
+
+            assert_eq!(
+                emu.run(1),
+                HaltReason::UnhandledTrap(Trap::EnvironmentCallFromSMode),
+            );
+        }
+    }
+
+    mod reset {
+        use super::*;
+
+        #[test]
+        fn reload_restores_mutated_data() {
+            let mut emu = emulator();
+
+            load_program(&mut emu, &[
+                // sw x0, 0x100(x0)
+                (StoreWord, Operands::s(0, 0, 0x100)),
+                // sw x0, 0x104(x0)
+                (StoreWord, Operands::s(0, 0, 0x104)),
+                // addi x1, x0, 1
+                (ArithmeticAddImmediate, Operands::i(1, 0, 1)),
+                // sw x1, 0x200(x0)
+                (StoreWord, Operands::s(0, 1, 0x200)),
+            ]);
+
+            // .data
+            emu.load(0x100, &[0xde, 0xad, 0xbe, 0xef]);
+
+            emu.run(4);
+            assert_eq!(emu.memory().read(0x100, 4), [0; 4]);
+            assert_eq!(emu.memory().read(0x200, 1), [1]);
+
+            emu.reset_and_reload();
+
+            assert_eq!(emu.memory().read(0x100, 4), [0xde, 0xad, 0xbe, 0xef]);
+            assert_eq!(emu.memory().read(0x200, 1), [0]);
+            assert_eq!(emu.hart(0).pc, 0x00);
+            assert_eq!(emu.hart(0).reg_x.read(1), 0);
+            assert_eq!(emu.hart(0).instret, 0);
+        }
+
+        #[test]
+        fn reset_keeps_memory_and_restarts_at_entry() {
+            let mut emu = emulator();
+            emu.load_many(&[(0x40, &0x00100093u32.to_le_bytes())], 0x40).unwrap();
+
+            emu.run(1);
+            emu.memory_mut().write(0x100, &[0xff]);
+            emu.reset();
+
+            assert_eq!(emu.hart(0).pc, 0x40);
+            assert_eq!(emu.hart(0).reg_x.read(1), 0);
+            assert_eq!(emu.memory().read(0x100, 1), [0xff]);
+        }
+    }
+
+    mod elf {
+        use crate::elf::ElfError;
+        use crate::elf::tests::executable;
+
+        use super::*;
+
+        #[test]
+        fn loads_segments_and_starts_at_entry() {
+            let mut emu = emulator();
+            emu.memory_mut().write(0x104, &[0xff; 4]);
+
+            let bytes = executable(0x100, 0x100, &[0x13, 0x00, 0x00, 0x00], 8);
+
+            assert_eq!(emu.load_elf(&bytes), Ok(0x100));
+            assert_eq!(emu.memory().read(0x100, 8), [0x13, 0, 0, 0, 0, 0, 0, 0]);
+            assert_eq!(emu.hart(0).pc, 0x100);
+            assert_eq!(emu.loaded_end(), 0x108);
+
+            emu.run(1);
+            emu.reset();
+            assert_eq!(emu.hart(0).pc, 0x100);
+        }
+
+        #[test]
+        fn reports_parse_errors_from_path() {
+            let path = std::env::temp_dir().join(format!("not-elf-{}", std::process::id()));
+            std::fs::write(&path, b"#!/bin/sh\n").unwrap();
+
+            let result = emulator().load_elf_from_path(&path);
+            std::fs::remove_file(&path).unwrap();
+
+            match result {
+                Err(ElfLoadError::Elf(ElfError::NotElf)) => (),
+                result => panic!("expected NotElf, got {result:?}"),
+            }
+        }
+
+        #[test]
+        fn reports_missing_file() {
+            let result = emulator().load_elf_from_path("/nonexistent/program.elf");
+
+            assert!(matches!(result, Err(ElfLoadError::Io(_))));
+        }
+
+        #[test]
+        fn rejects_64_bit_elf_with_clear_error() {
+            let mut bytes = executable(0, 0, &[], 0);
+            bytes[4] = 2;
+
+            let err = emulator().load_elf(&bytes).unwrap_err();
+
+            assert_eq!(err, ElfError::WrongClass);
+            assert_eq!(err.to_string(), "64-bit ELF files can't run on a 32-bit emulator");
+        }
+
+        #[test]
+        fn clamps_zeroing_to_memory_size() {
+            let mut emu = emulator();
+            let bytes = executable(0x100, 0x100, &[0x13, 0x00, 0x00, 0x00], u32::MAX);
+
+            assert_eq!(emu.load_elf(&bytes), Ok(0x100));
+            assert_eq!(emu.loaded_end(), 0x104 + emu.memory().len() as u32);
+        }
+
+        #[test]
+        fn rejects_misaligned_entry() {
+            let mut emu = emulator();
+            let bytes = executable(0x102, 0x100, &[0x13, 0x00, 0x00, 0x00], 4);
+
+            assert_eq!(
+                emu.load_elf(&bytes),
+                Err(ElfError::MisalignedEntry { entry: 0x102, ialign: 32 }),
+            );
+            assert_eq!(emu.hart(0).pc, 0x00);
+        }
+    }
+
+    mod dtb {
+        use super::*;
+
+        const DTB: [u8; 8] = [0xd0, 0x0d, 0xfe, 0xed, 0x00, 0x00, 0x00, 0x08];
+
+        #[test]
+        fn boot_registers_hold_hart_and_dtb_address() {
+            let mut emu = Emulator::build(EmulatorConfig {
+                proc_count: 2,
+                ..EmulatorConfig::default()
+            });
+
+            emu.load_dtb(0x300, &DTB);
+
+            assert_eq!(emu.memory().read(0x300, DTB.len()), DTB);
+
+            for hart in 0 .. 2 {
+                assert_eq!(emu.hart(hart).reg_x.read(10), hart as u32);
+                assert_eq!(emu.hart(hart).reg_x.read(11), 0x300);
+            }
+        }
+
+        #[test]
+        fn reset_restores_boot_registers() {
+            let mut emu = emulator();
+            emu.load_dtb(0x300, &DTB);
+
+            emu.hart_mut(0).reg_x.write(10, 0xff);
+            emu.hart_mut(0).reg_x.write(11, 0xff);
+            emu.reset();
+
+            assert_eq!(emu.hart(0).reg_x.read(10), 0);
+            assert_eq!(emu.hart(0).reg_x.read(11), 0x300);
+        }
+    }
+
+    mod schedule {
+        use super::*;
+
+        /// Returns two harts running the same straight-line program, with
+        /// each step taking one unit of fuel.
+        fn two_harts(schedule_quantum: u64) -> Emulator {
+            let mut emu = Emulator::build(EmulatorConfig {
+                proc_count: 2,
+                schedule_quantum,
+                ..EmulatorConfig::default()
+            });
+
+            load_program(&mut emu, &[(ArithmeticAddImmediate, Operands::i(1, 1, 1)); 16]);
+            emu
+        }
+
+        #[test]
+        fn hart_runs_whole_quantum_before_switching() {
+            let mut emu = two_harts(3);
+            emu.set_fuel(4);
+
+            assert_eq!(emu.run(100), HaltReason::OutOfFuel);
+            assert_eq!(emu.hart(0).reg_x.read(1), 3);
+            assert_eq!(emu.hart(1).reg_x.read(1), 1);
+        }
+
+        #[test]
+        fn quantum_of_one_interleaves_instructions() {
+            let mut emu = two_harts(1);
+            emu.set_fuel(4);
+
+            assert_eq!(emu.run(100), HaltReason::OutOfFuel);
+            assert_eq!(emu.hart(0).reg_x.read(1), 2);
+            assert_eq!(emu.hart(1).reg_x.read(1), 2);
+        }
+
+        #[test]
+        fn step_limit_cuts_the_last_quantum_short() {
+            let mut emu = two_harts(3);
+
+            assert_eq!(emu.run(4), HaltReason::StepLimit);
+            assert_eq!(emu.hart(0).reg_x.read(1), 4);
+            assert_eq!(emu.hart(1).reg_x.read(1), 4);
+            assert_eq!(emu.bus.clint.mtime, 4);
+        }
+
+        #[test]
+        fn zero_quantum_is_rejected() {
+            let result = Emulator::try_build(EmulatorConfig {
+                schedule_quantum: 0,
+                ..EmulatorConfig::default()
+            });
+
+            assert_eq!(result.err(), Some(ConfigError::ZeroQuantum));
+        }
+    }
+
+    mod step_all {
+        use super::*;
+
+        fn two_harts() -> Emulator {
+            let mut emu = Emulator::build(EmulatorConfig {
+                proc_count: 2,
+                ..EmulatorConfig::default()
+            });
+
+            // addi x1, x0, 1
+            emu.load(0x00, &0x00100093u32.to_le_bytes());
+            // ebreak
+            emu.load(0x100, &0x00100073u32.to_le_bytes());
+            emu.hart_mut(1).pc = 0x100;
+            emu
+        }
+
+        #[test]
+        fn steps_each_hart_once() {
+            let mut emu = two_harts();
+
+            assert_eq!(
+                emu.step_all(),
+                [StepResult::Continued, StepResult::Halted(HaltReason::Ebreak)],
+            );
+            assert_eq!(emu.hart(0).pc, 0x04);
+            assert_eq!(emu.hart(0).reg_x.read(1), 1);
+            assert_eq!(emu.bus.clint.mtime, 1);
+        }
+
+        #[test]
+        fn reports_halted_harts_as_stopped() {
+            let mut emu = two_harts();
+            emu.hart_mut(1).state = HartState::Halted;
+
+            assert_eq!(emu.step_all(), [StepResult::Continued, StepResult::Stopped]);
+            assert_eq!(emu.hart(1).pc, 0x100);
+        }
+
+        #[test]
+        fn reports_waiting_harts_as_idle() {
+            let mut emu = two_harts();
+            emu.hart_mut(1).state = HartState::WaitingForInterrupt;
+
+            assert_eq!(emu.step_all(), [StepResult::Continued, StepResult::Idle]);
+            assert_eq!(emu.hart(1).pc, 0x100);
+        }
+    }
+
+    mod hart {
+        use super::*;
+
+        #[test]
+        fn hart_mut_sets_pc_for_next_run() {
+            let mut emu = emulator();
+
+            // addi x1, x0, 42
+            emu.load(0x40, &0x02a00093u32.to_le_bytes());
+
+            emu.hart_mut(0).pc = 0x40;
+            emu.run(1);
+
+            assert_eq!(emu.hart(0).pc, 0x44);
+            assert_eq!(emu.hart(0).reg_x.read(1), 42);
+        }
+
+        #[test]
+        fn software_interrupt_wakes_halted_hart() {
+            let mut emu = Emulator::build(EmulatorConfig {
+                proc_count: 2,
+                ..EmulatorConfig::default()
+            });
+
+            // Hart 0 sets hart 1's msip, then spins.
+            load_program(&mut emu, &[
+                (LoadUpperImmediate, Operands::u(1, 0x2000)),
+                (ArithmeticAddImmediate, Operands::i(2, 0, 1)),
+                (StoreWord, Operands::s(1, 2, 4)),
+                (JumpAndLink, Operands::u(0, 0)),
+            ]);
+
+            // addi x5, x0, 7
+            emu.load(0x100, &0x00700293u32.to_le_bytes());
+            emu.hart_mut(1).pc = 0x100;
+            emu.hart_mut(1).state = HartState::Halted;
+
+            emu.run(2);
+            assert_eq!(emu.hart(1).state, HartState::Halted);
+            assert_eq!(emu.hart(1).pc, 0x100);
+
+            emu.run(2);
+            assert_eq!(emu.hart(1).state, HartState::Running);
+            assert_eq!(emu.hart(1).reg_x.read(5), 7);
+        }
+
+        #[test]
+        #[should_panic]
+        fn panics_on_missing_hart() {
+            emulator().hart(1);
+        }
+
+        #[test]
+        fn memory_mut_writes_are_visible() {
+            let mut emu = emulator();
+            emu.memory_mut().write(0x10, &[0xab]);
+            assert_eq!(emu.memory().read(0x10, 1), &[0xab]);
+        }
+    }
+
+    mod mtime {
+        use super::*;
+
+        /// Runs a counting loop that reads `mtime` into x2 on every
+        /// iteration.
+        fn run_counter(mtime_tick: u64) -> Emulator {
+            let mut emu = Emulator::build(EmulatorConfig {
+                mtime_tick,
+                ..EmulatorConfig::default()
+            });
+
+            // lui x3, 0x0200c
+            // addi x1, x1, 1
+            // lw x2, -8(x3)
+            // jal x0, -8
+            for (i, instr) in [0x0200c1b7u32, 0x00108093, 0xff81a103, 0xff9ff06f]
+                .iter()
+                .enumerate()
+            {
+                emu.load(i as u32 * 4, &instr.to_le_bytes());
+            }
+
+            emu.run(100);
+            emu
+        }
+
+        #[test]
+        fn advances_by_tick_per_step() {
+            let emu = run_counter(3);
+            assert_eq!(emu.bus.clint.mtime, 300);
+        }
+
+        #[test]
+        fn identical_runs_are_reproducible() {
+            let first = run_counter(7);
+            let second = run_counter(7);
+
+            assert_eq!(first.bus.clint.mtime, second.bus.clint.mtime);
+            assert_eq!(first.hart(0).pc, second.hart(0).pc);
+
+            for i in 0 .. first.hart(0).reg_x.len() {
+                assert_eq!(
+                    first.hart(0).reg_x.read(i),
+                    second.hart(0).reg_x.read(i),
+                );
+            }
+        }
+
+        #[test]
+        fn raises_timer_interrupt_at_mtimecmp() {
+            let mut emu = emulator();
+            emu.bus.clint.mtimecmp[0] = 5;
+
+            // jal x0, 0
+            emu.load(0x00, &0x0000006fu32.to_le_bytes());
+
+            emu.run(4);
+            assert_eq!(emu.hart(0).csr.read(MIP) & Interrupt::MachineTimer.mask(), 0);
+            emu.run(1);
+            assert_ne!(emu.hart(0).csr.read(MIP) & Interrupt::MachineTimer.mask(), 0);
+        }
     }
 }
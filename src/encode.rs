@@ -0,0 +1,385 @@
+//! Encoder
+//! Turns operations and their operands back into 32-bit machine code,
+//! the inverse of the decoder.
+
+use core::fmt::Display;
+
+use crate::op::{
+    Op,
+    Op::*,
+    OpCategory,
+};
+
+/// An error encoding an operation.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum EncodeError {
+    /// The immediate doesn't fit the operation's immediate field.
+    ImmediateOutOfRange { op: Op, imm: i32 },
+
+    /// The offset of a branch or `jal` is odd. Their offsets are encoded
+    /// in multiples of two bytes, so the low bit can't be represented.
+    OddOffset { op: Op, imm: i32 },
+}
+
+impl Display for EncodeError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            EncodeError::ImmediateOutOfRange { op, imm } => {
+                write!(f, "immediate {imm} is out of range for `{op}`")
+            },
+
+            EncodeError::OddOffset { op, imm } => {
+                write!(f, "offset {imm} for `{op}` isn't a multiple of two")
+            },
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for EncodeError {}
+
+/// The operands of an instruction. Fields that an operation's format
+/// doesn't have are ignored when encoding.
+///
+/// For U-type operations `imm` is the upper 20 bits, for CSR operations
+/// `imm` is the CSR address, and for the immediate CSR forms `rs1` holds
+/// the 5-bit immediate.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct Operands {
+    pub rd: usize,
+    pub rs1: usize,
+    pub rs2: usize,
+    pub imm: i32,
+}
+
+impl Operands {
+    /// Operands for an R-type operation.
+    pub fn r(rd: usize, rs1: usize, rs2: usize) -> Self {
+        Self { rd, rs1, rs2, imm: 0 }
+    }
+
+    /// Operands for an I-type operation.
+    pub fn i(rd: usize, rs1: usize, imm: i32) -> Self {
+        Self { rd, rs1, rs2: 0, imm }
+    }
+
+    /// Operands for an S-type or B-type operation.
+    pub fn s(rs1: usize, rs2: usize, imm: i32) -> Self {
+        Self { rd: 0, rs1, rs2, imm }
+    }
+
+    /// Operands for a U-type or J-type operation.
+    pub fn u(rd: usize, imm: i32) -> Self {
+        Self { rd, rs1: 0, rs2: 0, imm }
+    }
+
+    /// Operands for an operation that takes none.
+    pub fn none() -> Self {
+        Self::default()
+    }
+}
+
+/// Encodes an operation and its operands as a 32-bit instruction, or
+/// fails if the immediate doesn't fit the operation's immediate field.
+pub fn try_encode(op: &Op, operands: &Operands) -> Result<u32, EncodeError> {
+    let pc_relative = op.category() == OpCategory::Branch || *op == JumpAndLink;
+
+    if pc_relative && operands.imm & 0x01 != 0 {
+        return Err(EncodeError::OddOffset {
+            op: *op,
+            imm: operands.imm,
+        });
+    }
+
+    if !op.imm_fits(operands.imm) {
+        return Err(EncodeError::ImmediateOutOfRange {
+            op: *op,
+            imm: operands.imm,
+        });
+    }
+
+    Ok(encode(op, operands))
+}
+
+/// Encodes an operation and its operands as a 32-bit instruction.
+///
+/// Immediates that don't fit the operation's immediate field are
+/// truncated. Use `try_encode` to reject them instead.
+pub fn encode(op: &Op, operands: &Operands) -> u32 {
+    let Operands { rd, rs1, rs2, imm } = *operands;
+
+    match op {
+        BranchEqual                         => encode_b(0x63, 0x00, rs1, rs2, imm),
+        BranchNotEqual                      => encode_b(0x63, 0x01, rs1, rs2, imm),
+        BranchLessThan                      => encode_b(0x63, 0x04, rs1, rs2, imm),
+        BranchGreaterThanOrEqualTo          => encode_b(0x63, 0x05, rs1, rs2, imm),
+        BranchLessThanUnsigned              => encode_b(0x63, 0x06, rs1, rs2, imm),
+        BranchGreaterThanOrEqualToUnsigned  => encode_b(0x63, 0x07, rs1, rs2, imm),
+
+        LoadByte                            => encode_i(0x03, 0x00, rd, rs1, imm),
+        LoadHalf                            => encode_i(0x03, 0x01, rd, rs1, imm),
+        LoadWord                            => encode_i(0x03, 0x02, rd, rs1, imm),
+        LoadByteUnsigned                    => encode_i(0x03, 0x04, rd, rs1, imm),
+        LoadHalfUnsigned                    => encode_i(0x03, 0x05, rd, rs1, imm),
+        Fence                               => encode_i(0x0f, 0x00, rd, rs1, imm),
+        FenceI                              => encode_i(0x0f, 0x01, rd, rs1, imm),
+        Pause                               => 0x0100000f,
+        ArithmeticAddImmediate              => encode_i(0x13, 0x00, rd, rs1, imm),
+        ShiftLeftLogicalImmediate           => encode_i(0x13, 0x01, rd, rs1, imm & 0x1f),
+        SetLessThanImmediate                => encode_i(0x13, 0x02, rd, rs1, imm),
+        SetLessThanImmediateUnsigned        => encode_i(0x13, 0x03, rd, rs1, imm),
+        LogicalExclusiveOrImmediate         => encode_i(0x13, 0x04, rd, rs1, imm),
+        ShiftRightLogicalImmediate          => encode_i(0x13, 0x05, rd, rs1, imm & 0x1f),
+        ShiftRightArithmeticImmediate       => encode_i(0x13, 0x05, rd, rs1, imm & 0x1f | 0x400),
+        LogicalOrImmediate                  => encode_i(0x13, 0x06, rd, rs1, imm),
+        LogicalAndImmediate                 => encode_i(0x13, 0x07, rd, rs1, imm),
+        JumpAndLinkRegister                 => encode_i(0x67, 0x00, rd, rs1, imm),
+
+        SystemEcall                         => encode_i(0x73, 0x00, 0, 0, 0x000),
+        SystemEbreak                        => encode_i(0x73, 0x00, 0, 0, 0x001),
+        SystemSupervisorReturn              => encode_i(0x73, 0x00, 0, 0, 0x102),
+        SystemWaitForInterrupt              => encode_i(0x73, 0x00, 0, 0, 0x105),
+        SystemMachineReturn                 => encode_i(0x73, 0x00, 0, 0, 0x302),
+        CsrReadWrite                        => encode_i(0x73, 0x01, rd, rs1, imm),
+        CsrReadSet                          => encode_i(0x73, 0x02, rd, rs1, imm),
+        CsrReadClear                        => encode_i(0x73, 0x03, rd, rs1, imm),
+        CsrReadWriteImmediate               => encode_i(0x73, 0x05, rd, rs1, imm),
+        CsrReadSetImmediate                 => encode_i(0x73, 0x06, rd, rs1, imm),
+        CsrReadClearImmediate               => encode_i(0x73, 0x07, rd, rs1, imm),
+
+        JumpAndLink                         => encode_j(0x6f, rd, imm),
+
+        ArithmeticAdd                       => encode_r(0x33, 0x00, 0x00, rd, rs1, rs2),
+        ArithmeticSub                       => encode_r(0x33, 0x00, 0x20, rd, rs1, rs2),
+        ShiftLeftLogical                    => encode_r(0x33, 0x01, 0x00, rd, rs1, rs2),
+        SetLessThan                         => encode_r(0x33, 0x02, 0x00, rd, rs1, rs2),
+        SetLessThanUnsigned                 => encode_r(0x33, 0x03, 0x00, rd, rs1, rs2),
+        LogicalExclusiveOr                  => encode_r(0x33, 0x04, 0x00, rd, rs1, rs2),
+        ShiftRightLogical                   => encode_r(0x33, 0x05, 0x00, rd, rs1, rs2),
+        ShiftRightArithmetic                => encode_r(0x33, 0x05, 0x20, rd, rs1, rs2),
+        LogicalOr                           => encode_r(0x33, 0x06, 0x00, rd, rs1, rs2),
+        LogicalAnd                          => encode_r(0x33, 0x07, 0x00, rd, rs1, rs2),
+        ArithmeticMul                       => encode_r(0x33, 0x00, 0x01, rd, rs1, rs2),
+        ArithmeticMulHigh                   => encode_r(0x33, 0x01, 0x01, rd, rs1, rs2),
+        ArithmeticMulHighSignedUnsigned     => encode_r(0x33, 0x02, 0x01, rd, rs1, rs2),
+        ArithmeticMulHighUnsigned           => encode_r(0x33, 0x03, 0x01, rd, rs1, rs2),
+        ArithmeticDiv                       => encode_r(0x33, 0x04, 0x01, rd, rs1, rs2),
+        ArithmeticDivUnsigned               => encode_r(0x33, 0x05, 0x01, rd, rs1, rs2),
+        ArithmeticRem                       => encode_r(0x33, 0x06, 0x01, rd, rs1, rs2),
+        ArithmeticRemUnsigned               => encode_r(0x33, 0x07, 0x01, rd, rs1, rs2),
+
+        SaturatingAdd                       => encode_r(0x0b, 0x00, 0x00, rd, rs1, rs2),
+        SaturatingSub                       => encode_r(0x0b, 0x00, 0x20, rd, rs1, rs2),
+
+        StoreByte                           => encode_s(0x23, 0x00, rs1, rs2, imm),
+        StoreHalf                           => encode_s(0x23, 0x01, rs1, rs2, imm),
+        StoreWord                           => encode_s(0x23, 0x02, rs1, rs2, imm),
+
+        AddUpperImmediateProgramCounter     => encode_u(0x17, rd, imm),
+        LoadUpperImmediate                  => encode_u(0x37, rd, imm),
+    }
+}
+
+/// Encodes a B-type instruction.
+#[inline]
+fn encode_b(opcode: u32, funct3: u32, rs1: usize, rs2: usize, imm: i32) -> u32 {
+    let imm = imm as u32;
+
+    // imm[12]
+    (imm >> 12 & 0x01) << 31
+        // imm[5:10]
+        | (imm >> 5 & 0x3f) << 25
+        | reg(rs2) << 20
+        | reg(rs1) << 15
+        | funct3 << 12
+        // imm[1:4]
+        | (imm >> 1 & 0x0f) << 8
+        // imm[11]
+        | (imm >> 11 & 0x01) << 7
+        | opcode
+}
+
+/// Encodes an I-type instruction.
+#[inline]
+fn encode_i(opcode: u32, funct3: u32, rd: usize, rs1: usize, imm: i32) -> u32 {
+    (imm as u32 & 0xfff) << 20
+        | reg(rs1) << 15
+        | funct3 << 12
+        | reg(rd) << 7
+        | opcode
+}
+
+/// Encodes a J-type instruction.
+#[inline]
+fn encode_j(opcode: u32, rd: usize, imm: i32) -> u32 {
+    let imm = imm as u32;
+
+    // imm[20]
+    (imm >> 20 & 0x01) << 31
+        // imm[1:10]
+        | (imm >> 1 & 0x3ff) << 21
+        // imm[11]
+        | (imm >> 11 & 0x01) << 20
+        // imm[12:19]
+        | (imm >> 12 & 0xff) << 12
+        | reg(rd) << 7
+        | opcode
+}
+
+/// Encodes an R-type instruction.
+#[inline]
+fn encode_r(opcode: u32, funct3: u32, funct7: u32, rd: usize, rs1: usize, rs2: usize) -> u32 {
+    funct7 << 25
+        | reg(rs2) << 20
+        | reg(rs1) << 15
+        | funct3 << 12
+        | reg(rd) << 7
+        | opcode
+}
+
+/// Encodes an S-type instruction.
+#[inline]
+fn encode_s(opcode: u32, funct3: u32, rs1: usize, rs2: usize, imm: i32) -> u32 {
+    let imm = imm as u32;
+
+    // imm[5:11]
+    (imm >> 5 & 0x7f) << 25
+        | reg(rs2) << 20
+        | reg(rs1) << 15
+        | funct3 << 12
+        // imm[0:4]
+        | (imm & 0x1f) << 7
+        | opcode
+}
+
+/// Encodes a U-type instruction.
+#[inline]
+fn encode_u(opcode: u32, rd: usize, imm: i32) -> u32 {
+    (imm as u32 & 0xfffff) << 12
+        | reg(rd) << 7
+        | opcode
+}
+
+/// Masks a register number to the 5-bit register field.
+#[inline]
+fn reg(index: usize) -> u32 {
+    (index & 0x1f) as u32
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::decode::Decoder;
+    use crate::instruction::Instruction;
+
+    use super::{encode, try_encode, EncodeError, Op, Op::*, Operands};
+
+    /// Encodes an operation and checks that it decodes back to the same
+    /// operation.
+    fn round_trip(op: Op, operands: Operands) -> Instruction {
+        let instr = Instruction::new(encode(&op, &operands));
+        assert_eq!(Decoder::decode(&instr), Some(op));
+        instr
+    }
+
+    #[test]
+    fn encodes_r_type() {
+        // sub x5, x7, x3
+        assert_eq!(encode(&ArithmeticSub, &Operands::r(5, 7, 3)), 0x403382b3);
+    }
+
+    #[test]
+    fn encodes_i_type() {
+        // addi x1, x0, 5
+        assert_eq!(encode(&ArithmeticAddImmediate, &Operands::i(1, 0, 5)), 0x00500093);
+    }
+
+    #[test]
+    fn encodes_negative_b_type_offset() {
+        // bne x5, x5, -12
+        assert_eq!(encode(&BranchNotEqual, &Operands::s(5, 5, -12)), 0xfe529ae3);
+    }
+
+    #[test]
+    fn encodes_negative_j_type_offset() {
+        // jal x0, -4
+        assert_eq!(encode(&JumpAndLink, &Operands::u(0, -4)), 0xffdff06f);
+    }
+
+    #[test]
+    fn round_trips_fields() {
+        let instr = round_trip(StoreWord, Operands::s(1, 2, -8));
+        assert_eq!(instr.rs1(), Some(1));
+        assert_eq!(instr.rs2(), Some(2));
+        assert_eq!(instr.imm(), Some(-8));
+
+        let instr = round_trip(BranchLessThanUnsigned, Operands::s(3, 4, 2048));
+        assert_eq!(instr.imm(), Some(2048));
+
+        let instr = round_trip(LoadUpperImmediate, Operands::u(10, -1));
+        assert_eq!(instr.rd(), Some(10));
+        assert_eq!(instr.imm(), Some(-1));
+    }
+
+    #[test]
+    fn round_trips_shifts() {
+        let instr = round_trip(ShiftRightArithmeticImmediate, Operands::i(1, 2, 31));
+        assert_eq!(instr.imm().map(|imm| imm & 0x1f), Some(31));
+        round_trip(ShiftRightLogicalImmediate, Operands::i(1, 2, 31));
+    }
+
+    #[test]
+    fn round_trips_multiply_and_divide() {
+        // mul x5, x6, x7
+        assert_eq!(round_trip(ArithmeticMul, Operands::r(5, 6, 7)).raw(), 0x027302b3);
+        round_trip(ArithmeticMulHighSignedUnsigned, Operands::r(1, 2, 3));
+        round_trip(ArithmeticRemUnsigned, Operands::r(1, 2, 3));
+    }
+
+    #[test]
+    fn rejects_out_of_range_immediate() {
+        assert_eq!(
+            try_encode(&ArithmeticAddImmediate, &Operands::i(1, 2, 2048)),
+            Err(EncodeError::ImmediateOutOfRange { op: ArithmeticAddImmediate, imm: 2048 }),
+        );
+        assert_eq!(
+            try_encode(&ArithmeticAddImmediate, &Operands::i(1, 2, -2048)),
+            Ok(encode(&ArithmeticAddImmediate, &Operands::i(1, 2, -2048))),
+        );
+    }
+
+    #[test]
+    fn rejects_odd_branch_and_jump_offsets() {
+        assert_eq!(
+            try_encode(&BranchEqual, &Operands::s(1, 2, 7)),
+            Err(EncodeError::OddOffset { op: BranchEqual, imm: 7 }),
+        );
+        assert_eq!(
+            try_encode(&JumpAndLink, &Operands::u(1, -3)),
+            Err(EncodeError::OddOffset { op: JumpAndLink, imm: -3 }),
+        );
+
+        // jalr adds its offset to a register, so it may be odd.
+        assert!(try_encode(&JumpAndLinkRegister, &Operands::i(0, 1, 3)).is_ok());
+    }
+
+    #[test]
+    fn encoded_offsets_decode_unchanged() {
+        for imm in [-4096, -2, 2, 2048, 4094] {
+            let instr = round_trip(BranchNotEqual, Operands::s(1, 2, imm));
+            assert_eq!(instr.imm(), Some(imm));
+        }
+
+        for imm in [-0x10_0000, -2, 2, 0x800, 0x0f_fffe] {
+            let instr = round_trip(JumpAndLink, Operands::u(1, imm));
+            assert_eq!(instr.imm(), Some(imm));
+        }
+    }
+
+    #[test]
+    fn round_trips_system() {
+        round_trip(SystemEcall, Operands::none());
+        round_trip(SystemMachineReturn, Operands::none());
+        round_trip(Pause, Operands::none());
+        round_trip(CsrReadSet, Operands::i(1, 2, 0x300));
+    }
+}
@@ -0,0 +1,141 @@
+//! Event Log
+//! A ring buffer of the most recent steps a processor took, for working
+//! out after a trap or halt how the machine got there.
+
+use alloc::collections::VecDeque;
+
+use crate::instruction::NOP;
+use crate::trap::Trap;
+
+/// A record of a single step.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct Event {
+    /// The value of `pc` at the start of the step.
+    pub pc: u32,
+
+    /// The raw instruction fetched, or `None` if the step took an
+    /// interrupt before fetching.
+    pub instr: Option<u32>,
+
+    /// The register written and its new value, if any.
+    pub reg_write: Option<(usize, u32)>,
+
+    /// The address, size in bytes and value of the store made, if any.
+    pub mem_write: Option<(u32, usize, u32)>,
+
+    /// The trap taken during the step, if any.
+    pub trap: Option<Trap>,
+}
+
+impl Event {
+    /// Creates an event for a step starting at `pc`, with nothing recorded.
+    pub fn new(pc: u32) -> Self {
+        Self {
+            pc,
+            ..Self::default()
+        }
+    }
+
+    /// Returns whether the step executed the canonical `nop`, so traces
+    /// and profiles can tell padding apart from real `addi`s.
+    pub fn is_nop(&self) -> bool {
+        self.instr == Some(NOP)
+    }
+}
+
+/// A fixed-capacity log of events. Once full, recording an event discards
+/// the oldest one.
+#[derive(Clone, Debug)]
+pub struct EventLog {
+    events: VecDeque<Event>,
+    capacity: usize,
+}
+
+impl EventLog {
+    /// Creates an empty log that holds up to `capacity` events.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            events: VecDeque::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    /// Records an event, discarding the oldest one if the log is full.
+    pub fn push(&mut self, event: Event) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        if self.events.len() == self.capacity {
+            self.events.pop_front();
+        }
+
+        self.events.push_back(event);
+    }
+
+    /// Returns the recorded events, oldest first.
+    pub fn events(&self) -> impl Iterator<Item = &Event> {
+        self.events.iter()
+    }
+
+    /// Returns up to the last `n` events, oldest first.
+    pub fn tail(&self, n: usize) -> impl Iterator<Item = &Event> {
+        self.events.iter().skip(self.events.len().saturating_sub(n))
+    }
+
+    /// Returns the number of recorded events.
+    pub fn len(&self) -> usize {
+        self.events.len()
+    }
+
+    /// Returns whether no events have been recorded.
+    pub fn is_empty(&self) -> bool {
+        self.events.is_empty()
+    }
+
+    /// Discards all recorded events.
+    pub fn clear(&mut self) {
+        self.events.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Event, EventLog};
+
+    #[test]
+    fn discards_oldest_when_full() {
+        let mut log = EventLog::new(2);
+
+        for pc in [0x00, 0x04, 0x08] {
+            log.push(Event::new(pc));
+        }
+
+        assert_eq!(
+            log.events().map(|event| event.pc).collect::<Vec<_>>(),
+            [0x04, 0x08],
+        );
+    }
+
+    #[test]
+    fn tail_returns_most_recent_events() {
+        let mut log = EventLog::new(8);
+
+        for pc in [0x00, 0x04, 0x08] {
+            log.push(Event::new(pc));
+        }
+
+        assert_eq!(
+            log.tail(2).map(|event| event.pc).collect::<Vec<_>>(),
+            [0x04, 0x08],
+        );
+        assert_eq!(log.tail(10).count(), 3);
+    }
+
+    #[test]
+    fn zero_capacity_records_nothing() {
+        let mut log = EventLog::new(0);
+        log.push(Event::new(0x00));
+        assert!(log.is_empty());
+    }
+}
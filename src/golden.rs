@@ -0,0 +1,217 @@
+//! Golden Traces
+//! Records the steps a program takes as plain text and compares them
+//! against a recorded "golden" trace, for regression testing instruction
+//! semantics.
+//!
+//! A golden trace has a line per step, giving `pc` at the start of the
+//! step and the register written, if any:
+//!
+//! ```text
+//! pc=0x00000000 x1=0x00000005
+//! pc=0x00000004 -
+//! ```
+//!
+//! Blank lines and lines starting with `#` are ignored, so goldens can be
+//! annotated. To regenerate a golden, write out the output of `record`.
+
+use alloc::boxed::Box;
+use alloc::format;
+use alloc::rc::Rc;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::cell::RefCell;
+
+use crate::emulator::{Emulator, HaltReason};
+use crate::event_log::Event;
+use crate::trace::TraceReader;
+
+/// The number of matching steps shown before a divergence.
+const CONTEXT_STEPS: usize = 3;
+
+/// The first step at which a run differed from its golden trace.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Divergence {
+    /// The index of the step, counting from zero.
+    pub step: usize,
+
+    /// The step in the golden trace, or `None` if the run took more steps.
+    pub expected: Option<String>,
+
+    /// The step in the run, or `None` if the run took fewer steps.
+    pub actual: Option<String>,
+
+    /// Up to `CONTEXT_STEPS` matching steps before the divergence, oldest
+    /// first.
+    pub context: Vec<String>,
+}
+
+impl core::fmt::Display for Divergence {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        writeln!(f, "trace diverged at step {}:", self.step)?;
+
+        for line in &self.context {
+            writeln!(f, "    {line}")?;
+        }
+
+        writeln!(f, "  - {}", self.expected.as_deref().unwrap_or("(end of trace)"))?;
+        write!(f, "  + {}", self.actual.as_deref().unwrap_or("(end of trace)"))
+    }
+}
+
+/// Renders an event as a line of a golden trace.
+pub fn to_line(event: &Event) -> String {
+    match event.reg_write {
+        Some((reg, value)) => format!("pc={:#010x} x{reg}={value:#010x}", event.pc),
+        None => format!("pc={:#010x} -", event.pc),
+    }
+}
+
+/// Runs the emulator for up to `max_steps` steps, returning why it halted
+/// and the trace of hart zero in the golden format.
+///
+/// This replaces any binary trace enabled on hart zero.
+pub fn record(emu: &mut Emulator, max_steps: u64) -> (HaltReason, String) {
+    let bytes = Rc::new(RefCell::new(Vec::new()));
+    let sink = bytes.clone();
+
+    emu.hart_mut(0).enable_trace(Box::new(move |record| {
+        sink.borrow_mut().extend_from_slice(record);
+    }));
+
+    let reason = emu.run(max_steps);
+    let bytes = bytes.borrow();
+    let mut trace = String::new();
+
+    for event in TraceReader::new(&bytes) {
+        trace += &to_line(&event);
+        trace.push('\n');
+    }
+
+    (reason, trace)
+}
+
+/// Compares a trace against a golden one, returning the first step at
+/// which they differ, if any.
+pub fn compare(golden: &str, actual: &str) -> Result<(), Divergence> {
+    let steps = |trace: &str| -> Vec<String> {
+        trace
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(String::from)
+            .collect()
+    };
+
+    let expected = steps(golden);
+    let actual = steps(actual);
+
+    for step in 0 .. expected.len().max(actual.len()) {
+        let (expected_line, actual_line) = (expected.get(step), actual.get(step));
+
+        if expected_line != actual_line {
+            return Err(Divergence {
+                step,
+                expected: expected_line.cloned(),
+                actual: actual_line.cloned(),
+                context: expected[step.saturating_sub(CONTEXT_STEPS) .. step].to_vec(),
+            });
+        }
+    }
+
+    Ok(())
+}
+
+/// Runs the emulator for up to `max_steps` steps and compares the trace
+/// of hart zero against `golden`.
+pub fn check(emu: &mut Emulator, max_steps: u64, golden: &str) -> Result<(), Divergence> {
+    let (_, trace) = record(emu, max_steps);
+    compare(golden, &trace)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{check, compare, record, to_line, Divergence, Event};
+    use crate::emulator::{Emulator, EmulatorConfig, HaltReason};
+    use crate::emulator::tests::load_program;
+    use crate::encode::Operands;
+    use crate::op::Op::*;
+
+    /// Returns an emulator loaded with a short program that ends in an
+    /// `ebreak`.
+    fn emulator() -> Emulator {
+        let mut emu = Emulator::build(EmulatorConfig::default());
+
+        load_program(&mut emu, &[
+            (ArithmeticAddImmediate, Operands::i(1, 0, 5)),
+            (ArithmeticAddImmediate, Operands::i(2, 1, 3)),
+            (StoreWord, Operands::s(0, 2, 0x100)),
+            (ArithmeticAdd, Operands::r(3, 1, 2)),
+            (SystemEbreak, Operands::none()),
+        ]);
+
+        emu
+    }
+
+    const GOLDEN: &str = "\
+# addi, addi, sw, add, ebreak
+pc=0x00000000 x1=0x00000005
+pc=0x00000004 x2=0x00000008
+pc=0x00000008 -
+pc=0x0000000c x3=0x0000000d
+pc=0x00000010 -
+";
+
+    #[test]
+    fn renders_steps_as_lines() {
+        let event = Event { pc: 0x100, reg_write: Some((1, 5)), ..Event::default() };
+
+        assert_eq!(to_line(&event), "pc=0x00000100 x1=0x00000005");
+        assert_eq!(to_line(&Event::new(0x104)), "pc=0x00000104 -");
+    }
+
+    #[test]
+    fn records_in_golden_format() {
+        let (reason, trace) = record(&mut emulator(), 100);
+
+        assert_eq!(reason, HaltReason::Ebreak);
+        assert_eq!(compare(GOLDEN, &trace), Ok(()));
+    }
+
+    #[test]
+    fn matching_run_passes() {
+        assert_eq!(check(&mut emulator(), 100, GOLDEN), Ok(()));
+    }
+
+    #[test]
+    fn altered_golden_reports_divergent_step() {
+        let golden = GOLDEN.replace("x3=0x0000000d", "x3=0x0000000e");
+        let divergence = check(&mut emulator(), 100, &golden).unwrap_err();
+
+        assert_eq!(divergence, Divergence {
+            step: 3,
+            expected: Some("pc=0x0000000c x3=0x0000000e".into()),
+            actual: Some("pc=0x0000000c x3=0x0000000d".into()),
+            context: vec![
+                "pc=0x00000000 x1=0x00000005".into(),
+                "pc=0x00000004 x2=0x00000008".into(),
+                "pc=0x00000008 -".into(),
+            ],
+        });
+
+        assert!(divergence.to_string().starts_with("trace diverged at step 3:"));
+    }
+
+    #[test]
+    fn reports_runs_of_different_lengths() {
+        let (_, trace) = record(&mut emulator(), 100);
+        let truncated: String = trace.lines().take(2).map(|line| line.to_owned() + "\n").collect();
+
+        let divergence = compare(&truncated, &trace).unwrap_err();
+        assert_eq!(divergence.step, 2);
+        assert_eq!(divergence.expected, None);
+        assert_eq!(divergence.actual.as_deref(), Some("pc=0x00000008 -"));
+
+        let divergence = compare(&trace, &truncated).unwrap_err();
+        assert_eq!(divergence.actual, None);
+    }
+}
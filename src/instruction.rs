@@ -1,12 +1,35 @@
-use std::fmt::Display;
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
 
+use core::fmt::Display;
+
+use crate::bits::sign_extend;
 use crate::decode::Decoder;
+use crate::encode::Operands;
+use crate::op::{Op, OpCategory};
 
 use InstructionFormat::*;
 
+/// The canonical `nop` encoding, `addi x0, x0, 0`.
+pub const NOP: u32 = 0x00000013;
+
+/// The `fence` ordering bit for device input.
+pub const FENCE_I: u8 = 0x08;
+
+/// The `fence` ordering bit for device output.
+pub const FENCE_O: u8 = 0x04;
+
+/// The `fence` ordering bit for memory reads.
+pub const FENCE_R: u8 = 0x02;
+
+/// The `fence` ordering bit for memory writes.
+pub const FENCE_W: u8 = 0x01;
+
 /// RISC-V's instruction formats, which indicate how instructions
 /// are encoded and the fields that they contain.
-#[derive(Debug, Eq, PartialEq)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
 pub enum InstructionFormat {
     /// ## B-type instruction format (Branch)
     /// 
@@ -73,6 +96,22 @@ pub enum InstructionFormat {
     U,
 }
 
+impl InstructionFormat {
+    /// Returns the format of instructions with the given opcode, or None
+    /// if the opcode doesn't belong to a supported format.
+    pub fn from_opcode(opcode: u8) -> Option<Self> {
+        match opcode {
+            0x03 | 0x0f | 0x13 | 0x67 | 0x73 => Some(I),
+            0x23 => Some(S),
+            0x33 => Some(R),
+            0x17 | 0x37 => Some(U),
+            0x63 => Some(B),
+            0x6f => Some(J),
+            _ => None,
+        }
+    }
+}
+
 /// A 32-bit RISC-V instruction.
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
 pub struct Instruction {
@@ -80,30 +119,316 @@ pub struct Instruction {
     instr: u32
 }
 
+/// The resolved fields of a B-type instruction.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct BType {
+    pub funct3: u8,
+    pub rs1: usize,
+    pub rs2: usize,
+    pub imm: i32,
+}
+
+/// The resolved fields of an I-type instruction.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct IType {
+    pub rd: usize,
+    pub funct3: u8,
+    pub rs1: usize,
+    pub imm: i32,
+}
+
+/// The resolved fields of a J-type instruction.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct JType {
+    pub rd: usize,
+    pub imm: i32,
+}
+
+/// The resolved fields of an R-type instruction.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct RType {
+    pub rd: usize,
+    pub funct3: u8,
+    pub rs1: usize,
+    pub rs2: usize,
+    pub funct7: u8,
+}
+
+/// The resolved fields of an S-type instruction.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct SType {
+    pub funct3: u8,
+    pub rs1: usize,
+    pub rs2: usize,
+    pub imm: i32,
+}
+
+/// The resolved fields of a U-type instruction.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct UType {
+    pub rd: usize,
+    pub imm: i32,
+}
+
 impl Instruction {
     /// Creates a new instruction from an unsigned 32-bit integer.
     pub fn new(instr: u32) -> Self {
         Instruction { instr }
     }
 
-    /// Returns the format of the instruction.
-    pub fn format(&self) -> InstructionFormat {
-        match self.opcode() {
-            0x03 | 0x0f | 0x13 | 0x67 | 0x73 => I,
-            0x23 => S,
-            0x33 => R,
-            0x17 | 0x37 => U,
-            0x63 => B,
-            0x6f => J,
-            _ => todo!(
-                "Invalid instruction format handler not yet implemented"
-            ),
+    /// Returns the raw 32-bit value of the instruction.
+    pub fn raw(&self) -> u32 {
+        self.instr
+    }
+
+    /// Returns whether the instruction is compressed (16-bit). 32-bit
+    /// instructions have the low two bits set, and anything else is a
+    /// compressed instruction in the low halfword.
+    pub fn is_compressed(&self) -> bool {
+        self.instr & 0x03 != 0x03
+    }
+
+    /// Returns the length of the instruction in bytes, which is 2 for a
+    /// compressed instruction and 4 otherwise.
+    pub fn byte_len(&self) -> u32 {
+        match self.is_compressed() {
+            true => 0x02,
+            false => 0x04,
         }
     }
 
-    /// Returns the mnemonic associated with the instruction.
-    pub fn mnemonic(&self) -> String {
-        Decoder::decode(self).unwrap().to_string()
+    /// Returns the address of the next sequential instruction, if this one
+    /// is at `pc`. This is where execution falls through to when control
+    /// flow isn't taken.
+    pub fn next_pc(&self, pc: u32) -> u32 {
+        pc.wrapping_add(self.byte_len())
+    }
+
+    /// Returns the instruction word broken into the labelled fields of its
+    /// format, from the lowest bits up, with the bit range of each, e.g.
+    /// `opcode[6:0]=0x33 rd[11:7]=x5 funct3[14:12]=0x0 ...`. Formats with
+    /// an immediate end with its decoded value. Words without a format
+    /// only have their opcode explained.
+    pub fn explain(&self) -> String {
+        let bits = |hi: u32, lo: u32| self.instr >> lo & ((1 << (hi - lo + 1)) - 1);
+        let hex = |name: &str, hi: u32, lo: u32| format!("{name}[{hi}:{lo}]={:#x}", bits(hi, lo));
+        let reg = |name: &str, hi: u32, lo: u32| format!("{name}[{hi}:{lo}]=x{}", bits(hi, lo));
+
+        let mut fields = vec![hex("opcode", 6, 0)];
+
+        match self.format() {
+            Some(R) => fields.extend([
+                reg("rd", 11, 7),
+                hex("funct3", 14, 12),
+                reg("rs1", 19, 15),
+                reg("rs2", 24, 20),
+                hex("funct7", 31, 25),
+            ]),
+
+            Some(I) => fields.extend([
+                reg("rd", 11, 7),
+                hex("funct3", 14, 12),
+                reg("rs1", 19, 15),
+                hex("imm", 31, 20),
+            ]),
+
+            Some(S | B) => fields.extend([
+                hex("imm", 11, 7),
+                hex("funct3", 14, 12),
+                reg("rs1", 19, 15),
+                reg("rs2", 24, 20),
+                hex("imm", 31, 25),
+            ]),
+
+            Some(U | J) => fields.extend([
+                reg("rd", 11, 7),
+                hex("imm", 31, 12),
+            ]),
+
+            None => (),
+        }
+
+        if let Some(imm) = self.imm() {
+            fields.push(format!("imm={imm}"));
+        }
+
+        fields.join(" ")
+    }
+
+    /// Returns the format of the instruction, or None if the opcode
+    /// doesn't belong to a supported format.
+    pub fn format(&self) -> Option<InstructionFormat> {
+        InstructionFormat::from_opcode(self.opcode())
+    }
+
+    /// Returns the mnemonic associated with the instruction, or None if
+    /// it can't be decoded.
+    pub fn mnemonic(&self) -> Option<String> {
+        Decoder::decode(self).map(|op| op.to_string())
+    }
+
+    /// Returns whether two instructions perform the same operation on the
+    /// same operands, even if they're encoded differently. Instructions
+    /// that can't be decoded are never the same operation.
+    ///
+    /// Computational instructions that write `x0` have no effect, so their
+    /// source operands don't matter.
+    pub fn same_operation(&self, other: &Instruction) -> bool {
+        match (Decoder::decode(self), Decoder::decode(other)) {
+            (Some(op), Some(other_op)) if op == other_op => {
+                self.operands(&op) == other.operands(&other_op)
+            },
+            _ => false,
+        }
+    }
+
+    /// Returns the operands that affect the result of the given operation,
+    /// with fields the operation ignores left as zero.
+    fn operands(&self, op: &Op) -> Operands {
+        let imm = self.imm().unwrap_or(0);
+
+        let writes_only_rd = matches!(
+            op.category(),
+            OpCategory::Arithmetic
+                | OpCategory::Logical
+                | OpCategory::Shift
+                | OpCategory::Multiply
+        );
+
+        if writes_only_rd && self.field_rd() == 0 {
+            return Operands::none();
+        }
+
+        match op {
+            Op::ShiftLeftLogicalImmediate
+                | Op::ShiftRightArithmeticImmediate
+                | Op::ShiftRightLogicalImmediate =>
+            {
+                Operands::i(
+                    self.field_rd(),
+                    self.field_rs1(),
+                    self.shamt().unwrap_or(0) as i32,
+                )
+            },
+
+            Op::Pause
+                | Op::SystemEbreak
+                | Op::SystemEcall
+                | Op::SystemMachineReturn
+                | Op::SystemSupervisorReturn
+                | Op::SystemWaitForInterrupt => Operands::none(),
+
+            _ => Operands {
+                rd: self.rd().unwrap_or(0),
+                rs1: self.rs1().unwrap_or(0),
+                rs2: self.rs2().unwrap_or(0),
+                imm,
+            },
+        }
+    }
+
+    /// Returns the registers the instruction reads, in operand order.
+    ///
+    /// `x0` is never included, as it always reads as zero and so can't
+    /// carry a dependency, and a register read twice is only listed once.
+    /// Instructions that can't be decoded read no registers.
+    pub fn source_regs(&self) -> Vec<usize> {
+        let regs = match Decoder::decode(self).map(|op| (op.category(), op)) {
+            None => Vec::new(),
+
+            Some((OpCategory::System, _)) => Vec::new(),
+
+            // The rs1 field of the immediate CSR instructions is a uimm.
+            Some((
+                OpCategory::Csr,
+                Op::CsrReadClearImmediate
+                    | Op::CsrReadSetImmediate
+                    | Op::CsrReadWriteImmediate,
+            )) => Vec::new(),
+
+            Some(_) => match self.format() {
+                Some(B | R | S) => Vec::from([self.field_rs1(), self.field_rs2()]),
+                Some(I) => Vec::from([self.field_rs1()]),
+                Some(J | U) | None => Vec::new(),
+            },
+        };
+
+        let mut sources = Vec::with_capacity(regs.len());
+
+        for reg in regs {
+            if reg != 0 && !sources.contains(&reg) {
+                sources.push(reg);
+            }
+        }
+
+        sources
+    }
+
+    /// Returns the register the instruction writes, or None if it doesn't
+    /// write one.
+    ///
+    /// An instruction whose `rd` is `x0` discards its result, so writes
+    /// no register. Instructions that can't be decoded write no register.
+    pub fn dest_reg(&self) -> Option<usize> {
+        match Decoder::decode(self)?.category() {
+            OpCategory::Branch | OpCategory::Store | OpCategory::System => None,
+            _ => self.rd().filter(|&rd| rd != 0),
+        }
+    }
+
+    /// Returns whether the instruction is a conditional branch.
+    pub fn is_branch(&self) -> bool {
+        self.has_category(OpCategory::Branch)
+    }
+
+    /// Returns whether the instruction is an unconditional jump
+    /// (`jal` or `jalr`).
+    pub fn is_jump(&self) -> bool {
+        self.has_category(OpCategory::Jump)
+    }
+
+    /// Returns whether the instruction is a memory load.
+    pub fn is_load(&self) -> bool {
+        self.has_category(OpCategory::Load)
+    }
+
+    /// Returns whether the instruction is a memory store.
+    pub fn is_store(&self) -> bool {
+        self.has_category(OpCategory::Store)
+    }
+
+    /// Returns whether the instruction is the canonical `nop` encoding,
+    /// `addi x0, x0, 0`. Other instructions without effect, such as
+    /// `addi x0, x1, 5`, aren't canonical nops.
+    pub fn is_nop(&self) -> bool {
+        self.instr == NOP
+    }
+
+    /// Returns whether the instruction can change `pc` to something other
+    /// than the next instruction.
+    pub fn is_control_flow(&self) -> bool {
+        self.is_branch() || self.is_jump()
+    }
+
+    /// Returns whether the instruction decodes to a system operation
+    /// without operands, such as `mret`.
+    fn has_no_operands(&self) -> bool {
+        matches!(
+            Decoder::decode(self),
+            Some(
+                Op::SystemEbreak
+                | Op::SystemEcall
+                | Op::SystemMachineReturn
+                | Op::SystemSupervisorReturn
+                | Op::SystemWaitForInterrupt
+            )
+        )
+    }
+
+    /// Returns whether the instruction decodes to an operation in the
+    /// given category.
+    fn has_category(&self, category: OpCategory) -> bool {
+        Decoder::decode(self).is_some_and(|op| op.category() == category)
     }
 
     /// Returns the instruction's opcode field.
@@ -115,8 +440,8 @@ impl Instruction {
     /// or None if the instruction doesn't have an rd field.
     pub fn rd(&self) -> Option<usize> {
         match self.format() {
-            I | J | R | U => {
-                Some((self.instr >> 7 & 0x1f) as usize)
+            Some(I | J | R | U) => {
+                Some(self.field_rd())
             },
             _ => None,
         }
@@ -126,8 +451,8 @@ impl Instruction {
     /// or None if the instruction doesn't have an funct3 field.
     pub fn funct3(&self) -> Option<u8> {
         match self.format() {
-            B | I | R | S => {
-                Some((self.instr >> 12 & 0x07) as u8)
+            Some(B | I | R | S) => {
+                Some(self.field_funct3())
             },
             _ => None,
         }
@@ -137,8 +462,8 @@ impl Instruction {
     /// or None if the instruction doesn't have an funct7 field.
     pub fn funct7(&self) -> Option<u8> {
         match self.format() {
-            R => {
-                Some((self.instr >> 25 & 0x7f) as u8)
+            Some(R) => {
+                Some(self.field_funct7())
             },
             _ => None,
         }
@@ -148,8 +473,8 @@ impl Instruction {
     /// or None if the instruction doesn't have an rs1 field.
     pub fn rs1(&self) -> Option<usize> {
         match self.format() {
-            B | I | R | S => {
-                Some((self.instr >> 15 & 0x1f) as usize)
+            Some(B | I | R | S) => {
+                Some(self.field_rs1())
             },
             _ => None,
         }
@@ -159,8 +484,8 @@ impl Instruction {
     /// or None if the instruction doesn't have an rs2 field.
     pub fn rs2(&self) -> Option<usize> {
         match self.format() {
-            B | R | S => {
-                Some((self.instr >> 20 & 0x1f) as usize)
+            Some(B | R | S) => {
+                Some(self.field_rs2())
             },
             _ => None,
         }
@@ -170,18 +495,179 @@ impl Instruction {
     /// or None if the instruction doesn't have an imm field.
     pub fn imm(&self) -> Option<i32> {
         match self.format() {
-            B => Some(self.imm_b()),
-            I => Some(self.imm_i()),
-            J => Some(self.imm_j()),
-            S => Some(self.imm_s()),
-            U => Some(self.imm_u()),
+            Some(B) => Some(self.imm_b()),
+            Some(I) => Some(self.imm_i()),
+            Some(J) => Some(self.imm_j()),
+            Some(S) => Some(self.imm_s()),
+            Some(U) => Some(self.imm_u()),
+            _ => None,
+        }
+    }
+
+    /// Returns the shift amount of a shift-immediate instruction, which is
+    /// `imm[4:0]`, or None if the instruction isn't I-type.
+    pub fn shamt(&self) -> Option<u32> {
+        match self.format() {
+            Some(I) => Some(self.instr >> 20 & 0x1f),
+            _ => None,
+        }
+    }
+
+    /// Returns `imm[11:5]` of an I-type instruction, which distinguishes
+    /// the shift-immediate instructions in the same way funct7 does for
+    /// R-type shifts, or None if the instruction isn't I-type.
+    pub fn imm_funct7(&self) -> Option<u8> {
+        match self.format() {
+            Some(I) => Some(self.field_funct7()),
+            _ => None,
+        }
+    }
+
+    /// Returns the `fm` (fence mode) field of a `fence`, `imm[11:8]`, or
+    /// None if the instruction isn't a `fence`.
+    pub fn fence_fm(&self) -> Option<u8> {
+        self.fence_field(28)
+    }
+
+    /// Returns the predecessor set of a `fence`, `imm[7:4]`, or None if the
+    /// instruction isn't a `fence`. The bits are `FENCE_I`, `FENCE_O`,
+    /// `FENCE_R` and `FENCE_W`.
+    pub fn fence_pred(&self) -> Option<u8> {
+        self.fence_field(24)
+    }
+
+    /// Returns the successor set of a `fence`, `imm[3:0]`, or None if the
+    /// instruction isn't a `fence`. The bits are as for `fence_pred`.
+    pub fn fence_succ(&self) -> Option<u8> {
+        self.fence_field(20)
+    }
+
+    /// Returns the 4-bit `fence` field starting at the given bit.
+    fn fence_field(&self, shift: u32) -> Option<u8> {
+        match Decoder::decode(self) {
+            Some(Op::Fence) => Some((self.instr >> shift & 0x0f) as u8),
+            _ => None,
+        }
+    }
+
+    /// Returns the instruction's fields resolved as a B-type instruction,
+    /// or None if the instruction isn't B-type.
+    pub fn as_b(&self) -> Option<BType> {
+        match self.format() {
+            Some(B) => Some(BType {
+                funct3: self.field_funct3(),
+                rs1: self.field_rs1(),
+                rs2: self.field_rs2(),
+                imm: self.imm_b(),
+            }),
+            _ => None,
+        }
+    }
+
+    /// Returns the instruction's fields resolved as an I-type instruction,
+    /// or None if the instruction isn't I-type.
+    pub fn as_i(&self) -> Option<IType> {
+        match self.format() {
+            Some(I) => Some(IType {
+                rd: self.field_rd(),
+                funct3: self.field_funct3(),
+                rs1: self.field_rs1(),
+                imm: self.imm_i(),
+            }),
+            _ => None,
+        }
+    }
+
+    /// Returns the instruction's fields resolved as a J-type instruction,
+    /// or None if the instruction isn't J-type.
+    pub fn as_j(&self) -> Option<JType> {
+        match self.format() {
+            Some(J) => Some(JType {
+                rd: self.field_rd(),
+                imm: self.imm_j(),
+            }),
+            _ => None,
+        }
+    }
+
+    /// Returns the instruction's fields resolved as an R-type instruction,
+    /// or None if the instruction isn't R-type.
+    pub fn as_r(&self) -> Option<RType> {
+        match self.format() {
+            Some(R) => Some(RType {
+                rd: self.field_rd(),
+                funct3: self.field_funct3(),
+                rs1: self.field_rs1(),
+                rs2: self.field_rs2(),
+                funct7: self.field_funct7(),
+            }),
             _ => None,
         }
     }
 
+    /// Returns the instruction's fields resolved as an S-type instruction,
+    /// or None if the instruction isn't S-type.
+    pub fn as_s(&self) -> Option<SType> {
+        match self.format() {
+            Some(S) => Some(SType {
+                funct3: self.field_funct3(),
+                rs1: self.field_rs1(),
+                rs2: self.field_rs2(),
+                imm: self.imm_s(),
+            }),
+            _ => None,
+        }
+    }
+
+    /// Returns the instruction's fields resolved as a U-type instruction,
+    /// or None if the instruction isn't U-type.
+    pub fn as_u(&self) -> Option<UType> {
+        match self.format() {
+            Some(U) => Some(UType {
+                rd: self.field_rd(),
+                imm: self.imm_u(),
+            }),
+            _ => None,
+        }
+    }
+
+    /// Returns the bits in the rd position, whatever the format. Useful
+    /// for instructions without a standard format, such as custom ones.
     #[inline]
-    fn imm_b(&self) -> i32 {
-        Instruction::sign_ext(
+    pub fn field_rd(&self) -> usize {
+        (self.instr >> 7 & 0x1f) as usize
+    }
+
+    /// Returns the bits in the funct3 position, whatever the format.
+    #[inline]
+    pub fn field_funct3(&self) -> u8 {
+        (self.instr >> 12 & 0x07) as u8
+    }
+
+    /// Returns the bits in the funct7 position, whatever the format.
+    #[inline]
+    pub fn field_funct7(&self) -> u8 {
+        (self.instr >> 25 & 0x7f) as u8
+    }
+
+    /// Returns the bits in the rs1 position, whatever the format.
+    #[inline]
+    pub fn field_rs1(&self) -> usize {
+        (self.instr >> 15 & 0x1f) as usize
+    }
+
+    /// Returns the bits in the rs2 position, whatever the format.
+    #[inline]
+    pub fn field_rs2(&self) -> usize {
+        (self.instr >> 20 & 0x1f) as usize
+    }
+
+    /// Returns the immediate as B-type encodes it, whatever the format:
+    /// a branch offset in multiples of two bytes, so the low bit is
+    /// always zero.
+    #[inline]
+    pub fn imm_b(&self) -> i32 {
+        sign_extend(
             // imm[1:4]
             (self.instr >> 8 & 0x0f) << 1
                 // imm[5:10]
@@ -190,21 +676,25 @@ impl Instruction {
                 | (self.instr >> 7 & 0x01) << 11
                 // imm[12]
                 | (self.instr >> 31 & 0x01) << 12,
-            12
+            13
         )
     }
 
+    /// Returns the immediate as I-type encodes it, whatever the format.
     #[inline]
-    fn imm_i(&self) -> i32 {
-        Instruction::sign_ext(
+    pub fn imm_i(&self) -> i32 {
+        sign_extend(
             self.instr >> 20 & 0xfff,
             12
         )
     }
 
+    /// Returns the immediate as J-type encodes it, whatever the format:
+    /// a jump offset in multiples of two bytes, so the low bit is always
+    /// zero.
     #[inline]
-    fn imm_j(&self) -> i32 {
-        Instruction::sign_ext(
+    pub fn imm_j(&self) -> i32 {
+        sign_extend(
             // imm[1:10]
             (self.instr >> 21 & 0x3ff) << 1
                 // imm[11]
@@ -213,13 +703,14 @@ impl Instruction {
                 | (self.instr >> 12 & 0xff) << 12
                 // imm[20]
                 | (self.instr >> 31 & 0x01) << 20,
-            20
+            21
         )
     }
 
+    /// Returns the immediate as S-type encodes it, whatever the format.
     #[inline]
-    fn imm_s(&self) -> i32 {
-        Instruction::sign_ext(
+    pub fn imm_s(&self) -> i32 {
+        sign_extend(
             // imm[0:4]
             (self.instr >> 7 & 0x1f)
                 // imm[5:11]
@@ -228,59 +719,66 @@ impl Instruction {
         )
     }
 
+    /// Returns the immediate as U-type encodes it, whatever the format:
+    /// the upper 20 bits, not shifted into place.
     #[inline]
-    fn imm_u(&self) -> i32 {
-        Instruction::sign_ext(
+    pub fn imm_u(&self) -> i32 {
+        sign_extend(
             self.instr >> 12 & 0xfffff,
             20
         )
     }
-
-    /// Sign-extend an instruction field.
-    fn sign_ext(value: u32, field_size: usize) -> i32 {
-        ((value << (32 - field_size)) as i32) >> (32 - field_size)
-    }
 }
 
 impl Display for Instruction {
     fn fmt(
         &self, 
-        f: &mut std::fmt::Formatter<'_>
-    ) -> std::fmt::Result
+        f: &mut core::fmt::Formatter<'_>
+    ) -> core::fmt::Result
     {
+        // Words that can't be decoded are rendered as data.
+        let (Some(format), Some(mnemonic)) = (self.format(), self.mnemonic()) else {
+            return write!(f, "{:<12} {:#010x}", ".word", self.instr);
+        };
+
         write!(
             f,
             "{}",
-            match self.format() {
+            match format {
+
                 B => format!(
                     // mnemonic rs1, rs2, imm
                     "{:<12} x{}, x{}, {:#010x}",
-                    self.mnemonic(),
-                    self.rs1().unwrap(),
-                    self.rs2().unwrap(),
-                    self.imm().unwrap(),
+                    mnemonic,
+                    self.field_rs1(),
+                    self.field_rs2(),
+                    self.imm_b(),
                 ),
 
                 I => {
                     match self.opcode() {
+                        // The system instructions other than the CSR
+                        // instructions take no operands.
+                        0x73 if self.has_no_operands() => mnemonic,
+
                         0x03 | 0x67 => {
                             format!(
                                 // mnemonic rd, imm(rs1)
                                 "{:<12} x{:}, {}(x{})",
-                                self.mnemonic(),
-                                self.rd().unwrap(),
-                                self.imm().unwrap(),
-                                self.rs1().unwrap(),
+                                mnemonic,
+                                self.field_rd(),
+                                self.imm_i(),
+                                self.field_rs1(),
                             )
                         }
                         _ => {
                             format!(
                                 // mnemonic rd, rs1, imm
                                 "{:<12} x{:}, x{}, {:#010x}",
-                                self.mnemonic(),
-                                self.rd().unwrap(),
-                                self.rs1().unwrap(),
-                                self.imm().unwrap(),
+                                mnemonic,
+                                self.field_rd(),
+                                self.field_rs1(),
+                                self.imm_i(),
                             )
                         }
                     }
@@ -289,35 +787,35 @@ impl Display for Instruction {
                 J => format!(
                     // mnemonic rd, imm
                     "{:<12} x{}, {:#010x}",
-                    self.mnemonic(),
-                    self.rd().unwrap(),
-                    self.imm().unwrap(),
+                    mnemonic,
+                    self.field_rd(),
+                    self.imm_j(),
                 ),
                 
                 R => format!(
                     // mnemonic rd, rs1, rs2
                     "{:<12} x{}, x{}, x{}",
-                    self.mnemonic(),
-                    self.rd().unwrap(),
-                    self.rs1().unwrap(),
-                    self.rs2().unwrap(),
+                    mnemonic,
+                    self.field_rd(),
+                    self.field_rs1(),
+                    self.field_rs2(),
                 ),
 
                 S => format!(
                     // mnemonic rs2, imm(rs1)
                     "{:<12} x{}, {}(x{})",
-                    self.mnemonic(),
-                    self.rs2().unwrap(),
-                    self.imm().unwrap(),
-                    self.rs1().unwrap(),
+                    mnemonic,
+                    self.field_rs2(),
+                    self.imm_s(),
+                    self.field_rs1(),
                 ),
                 
                 U => format!(
                     // mnemonic rd, imm
                     "{:<12} x{}, {:#010x}",
-                    self.mnemonic(),
-                    self.rd().unwrap(),
-                    self.imm().unwrap(),
+                    mnemonic,
+                    self.field_rd(),
+                    self.imm_u(),
                 ),
             },
         )
@@ -325,10 +823,18 @@ impl Display for Instruction {
 }
 
 #[cfg(test)]
+#[allow(clippy::bool_assert_comparison)]
 mod tests {
     use super::{
+        BType,
         Instruction,
+        InstructionFormat,
         InstructionFormat::*,
+        RType,
+        FENCE_I,
+        FENCE_O,
+        FENCE_R,
+        FENCE_W,
     };
 
     mod b_type {
@@ -346,7 +852,7 @@ mod tests {
         fn has_b_format() {
             assert_eq!(
                 Instruction::new(B_INSTR).format(),
-                B,
+                Some(B),
             );
         }
     
@@ -406,6 +912,15 @@ mod tests {
             );
         }
     
+        #[test]
+        fn does_not_sign_extend_from_imm_11() {
+            // beq x0, x0, 2048
+            assert_eq!(
+                Instruction::new(0x000000e3).imm(),
+                Some(2048),
+            );
+        }
+
         #[test]
         fn sign_extends_imm() {
             let imm = Instruction::new(0xfe529ae3).imm().unwrap();
@@ -415,6 +930,52 @@ mod tests {
                 true,
             );
         }
+
+        #[test]
+        fn decodes_scaled_offsets() {
+            for (instr, imm) in [
+                (0x00048a63, 20),       // beq x9, x0, 20
+                (0xfe529ae3, -12),      // bne x5, x5, -12
+                (0x7e000fe3, 4094),     // beq x0, x0, 4094
+                (0x80000063, -4096),    // beq x0, x0, -4096
+            ] {
+                assert_eq!(Instruction::new(instr).imm(), Some(imm));
+            }
+        }
+
+        #[test]
+        fn imm_is_always_even() {
+            // Every immediate bit set, then each bit on its own.
+            let instrs = (7 .. 32)
+                .map(|bit| 1 << bit)
+                .chain([0xffffff80])
+                .map(|bits: u32| Instruction::new(bits & !0x7f | 0x63));
+
+            for instr in instrs {
+                assert_eq!(instr.imm().unwrap() & 0x01, 0, "{:#010x}", instr.raw());
+            }
+        }
+
+        #[test]
+        fn resolves_as_b() {
+            assert_eq!(
+                Instruction::new(B_INSTR).as_b(),
+                Some(BType {
+                    funct3: 0x01,
+                    rs1: 0x09,
+                    rs2: 0x0b,
+                    imm: 0x14,
+                }),
+            );
+        }
+
+        #[test]
+        fn does_not_resolve_as_r() {
+            assert_eq!(
+                Instruction::new(B_INSTR).as_r(),
+                None,
+            );
+        }
     }
 
     mod i_type {
@@ -432,7 +993,7 @@ mod tests {
             fn has_i_format() {
                 assert_eq!(
                     Instruction::new(I_INSTR).format(),
-                    I,
+                    Some(I),
                 );
             }
         
@@ -503,6 +1064,108 @@ mod tests {
                     true,
                 );
             }
+
+            #[test]
+            fn decodes_scaled_offsets() {
+                for (instr, imm) in [
+                    (0x0400006f, 64),           // jal x0, 64
+                    (0xffdff06f, -4),           // jal x0, -4
+                    (0x7ffff06f, 0xffffe),      // jal x0, 1048574
+                    (0x8000006f, -0x100000),    // jal x0, -1048576
+                ] {
+                    assert_eq!(Instruction::new(instr).imm(), Some(imm));
+                }
+            }
+
+            #[test]
+            fn imm_is_always_even() {
+                // Every immediate bit set, then each bit on its own.
+                let instrs = (12 .. 32)
+                    .map(|bit| 1 << bit)
+                    .chain([0xfffff000])
+                    .map(|bits: u32| Instruction::new(bits | 0x6f));
+
+                for instr in instrs {
+                    assert_eq!(instr.imm().unwrap() & 0x01, 0, "{:#010x}", instr.raw());
+                }
+            }
+    }
+
+    mod shamt {
+        use super::*;
+
+        #[test]
+        fn is_low_five_bits_of_slli() {
+            // slli x1, x2, 13
+            assert_eq!(Instruction::new(0x00d11093).shamt(), Some(13));
+        }
+
+        #[test]
+        fn excludes_srai_funct7() {
+            // srai x1, x2, 31
+            let instr = Instruction::new(0x41f15093);
+            assert_eq!(instr.shamt(), Some(31));
+            assert_eq!(instr.imm_funct7(), Some(0x20));
+        }
+
+        #[test]
+        fn is_none_for_r_type() {
+            // sub x5, x7, x3
+            assert_eq!(Instruction::new(0x403382b3).shamt(), None);
+        }
+    }
+
+    mod typed_imm {
+        use super::*;
+
+        #[test]
+        fn b_type() {
+            // bne x9, x11, 20
+            assert_eq!(Instruction::new(0x00b49a63).imm_b(), 20);
+            // bne x5, x5, -12
+            assert_eq!(Instruction::new(0xfe529ae3).imm_b(), -12);
+        }
+
+        #[test]
+        fn i_type() {
+            // addi x10, x11, -12
+            assert_eq!(Instruction::new(0xff458513).imm_i(), -12);
+        }
+
+        #[test]
+        fn j_type() {
+            // jal x0, 64
+            assert_eq!(Instruction::new(0x0400006f).imm_j(), 64);
+        }
+
+        #[test]
+        fn s_type() {
+            // sw x6, 4(x12)
+            assert_eq!(Instruction::new(0x00662223).imm_s(), 4);
+        }
+
+        #[test]
+        fn u_type() {
+            // lui x10, 0xfffff
+            assert_eq!(Instruction::new(0xfffff537).imm_u(), -1);
+        }
+
+        #[test]
+        fn matches_imm_for_the_format() {
+            for instr in [0x00b49a63, 0xff458513, 0x0400006f, 0x00662223, 0xfffff537] {
+                let instr = Instruction::new(instr);
+
+                let typed = match instr.format() {
+                    Some(B) => instr.imm_b(),
+                    Some(I) => instr.imm_i(),
+                    Some(J) => instr.imm_j(),
+                    Some(S) => instr.imm_s(),
+                    _ => instr.imm_u(),
+                };
+
+                assert_eq!(instr.imm(), Some(typed));
+            }
+        }
     }
 
     mod j_type {
@@ -518,7 +1181,7 @@ mod tests {
             fn has_j_format() {
                 assert_eq!(
                     Instruction::new(J_INSTR).format(),
-                    J,
+                    Some(J),
                 );
             }
         
@@ -578,6 +1241,15 @@ mod tests {
                 );
             }
         
+            #[test]
+            fn does_not_sign_extend_from_imm_19() {
+                // jal x0, 524288
+                assert_eq!(
+                    Instruction::new(0x0008006f).imm(),
+                    Some(0x80000),
+                );
+            }
+
             #[test]
             fn sign_extends_imm() {
                 // jal x0, -391854
@@ -608,7 +1280,7 @@ mod tests {
             fn has_r_format() {
                 assert_eq!(
                     Instruction::new(R_INSTR).format(),
-                    R,
+                    Some(R),
                 );
             }
         
@@ -667,6 +1339,28 @@ mod tests {
                     None,
                 );
             }
+
+            #[test]
+            fn resolves_as_r() {
+                assert_eq!(
+                    Instruction::new(R_INSTR).as_r(),
+                    Some(RType {
+                        rd: 0x05,
+                        funct3: 0x00,
+                        rs1: 0x07,
+                        rs2: 0x03,
+                        funct7: 0x20,
+                    }),
+                );
+            }
+
+            #[test]
+            fn does_not_resolve_as_i() {
+                assert_eq!(
+                    Instruction::new(R_INSTR).as_i(),
+                    None,
+                );
+            }
     }
 
     mod s_type {
@@ -684,7 +1378,7 @@ mod tests {
         fn has_s_format() {
             assert_eq!(
                 Instruction::new(S_INSTR).format(),
-                S,
+                Some(S),
             );
         }
     
@@ -771,7 +1465,7 @@ mod tests {
         fn has_u_format() {
             assert_eq!(
                 Instruction::new(U_INSTR).format(),
-                U,
+                Some(U),
             );
         }
     
@@ -841,4 +1535,316 @@ mod tests {
             );
         }
     }
+
+    mod predicates {
+        use super::*;
+
+        #[test]
+        fn identifies_branch() {
+            // bne x9, x11, 20
+            let instr = Instruction::new(0x00b49a63);
+            assert!(instr.is_branch());
+            assert!(instr.is_control_flow());
+            assert!(!instr.is_jump());
+        }
+
+        #[test]
+        fn identifies_canonical_nop() {
+            assert!(Instruction::new(0x00000013).is_nop());
+            // addi x0, x0, 1
+            assert!(!Instruction::new(0x00100013).is_nop());
+            // addi x1, x0, 0
+            assert!(!Instruction::new(0x00000093).is_nop());
+        }
+
+        #[test]
+        fn identifies_jump() {
+            // jal x1, 16
+            assert!(Instruction::new(0x010000ef).is_jump());
+            // jalr x0, 0(x1)
+            assert!(Instruction::new(0x00008067).is_jump());
+        }
+
+        #[test]
+        fn identifies_load() {
+            // lw x3, 0(x1)
+            let instr = Instruction::new(0x0000a183);
+            assert!(instr.is_load());
+            assert!(!instr.is_store());
+        }
+
+        #[test]
+        fn identifies_store() {
+            // sw x2, 0(x1)
+            let instr = Instruction::new(0x0020a023);
+            assert!(instr.is_store());
+            assert!(!instr.is_load());
+        }
+
+        #[test]
+        fn unknown_opcode_matches_no_predicate() {
+            let instr = Instruction::new(0x00000000);
+            assert!(!instr.is_control_flow());
+            assert!(!instr.is_load());
+        }
+
+        #[test]
+        fn arithmetic_matches_no_predicate() {
+            // addi x1, x0, 5
+            let instr = Instruction::new(0x00500093);
+            assert!(!instr.is_control_flow());
+            assert!(!instr.is_load());
+            assert!(!instr.is_store());
+        }
+    }
+
+    mod registers {
+        use super::*;
+
+        #[test]
+        fn r_type_reads_rs1_and_rs2_and_writes_rd() {
+            // add x3, x1, x2
+            let instr = Instruction::new(0x002081b3);
+            assert_eq!(instr.source_regs(), [1, 2]);
+            assert_eq!(instr.dest_reg(), Some(3));
+        }
+
+        #[test]
+        fn store_reads_base_and_value_and_writes_nothing() {
+            // sw x2, 0(x1)
+            let instr = Instruction::new(0x0020a023);
+            assert_eq!(instr.source_regs(), [1, 2]);
+            assert_eq!(instr.dest_reg(), None);
+        }
+
+        #[test]
+        fn branch_reads_both_operands_and_writes_nothing() {
+            // bne x9, x11, 20
+            let instr = Instruction::new(0x00b49a63);
+            assert_eq!(instr.source_regs(), [9, 11]);
+            assert_eq!(instr.dest_reg(), None);
+        }
+
+        #[test]
+        fn load_reads_base_and_writes_rd() {
+            // lw x3, 0x40(x1)
+            let instr = Instruction::new(0x0400a183);
+            assert_eq!(instr.source_regs(), [1]);
+            assert_eq!(instr.dest_reg(), Some(3));
+        }
+
+        #[test]
+        fn immediate_csr_reads_no_register() {
+            // csrrsi x1, 0x300, 8
+            let instr = Instruction::new(0x300460f3);
+            assert!(instr.source_regs().is_empty());
+            assert_eq!(instr.dest_reg(), Some(1));
+        }
+
+        #[test]
+        fn repeated_source_is_listed_once() {
+            // add x3, x1, x1
+            assert_eq!(Instruction::new(0x001081b3).source_regs(), [1]);
+        }
+    }
+
+    mod fence {
+        use super::*;
+
+        #[test]
+        fn extracts_iorw_sets() {
+            // fence iorw, iorw
+            let instr = Instruction::new(0x0ff0000f);
+            assert_eq!(instr.fence_fm(), Some(0));
+            assert_eq!(instr.fence_pred(), Some(FENCE_I | FENCE_O | FENCE_R | FENCE_W));
+            assert_eq!(instr.fence_succ(), Some(FENCE_I | FENCE_O | FENCE_R | FENCE_W));
+        }
+
+        #[test]
+        fn extracts_distinct_sets() {
+            // fence r, w
+            let instr = Instruction::new(0x0210000f);
+            assert_eq!(instr.fence_pred(), Some(FENCE_R));
+            assert_eq!(instr.fence_succ(), Some(FENCE_W));
+
+            // fence.tso
+            assert_eq!(Instruction::new(0x8330000f).fence_fm(), Some(0x08));
+        }
+
+        #[test]
+        fn other_instructions_have_no_fence_fields() {
+            // addi x1, x0, 5
+            assert_eq!(Instruction::new(0x00500093).fence_pred(), None);
+        }
+    }
+
+    mod length {
+        use super::*;
+
+        #[test]
+        fn full_instruction_is_four_bytes() {
+            // addi x1, x0, 5
+            let instr = Instruction::new(0x00500093);
+            assert!(!instr.is_compressed());
+            assert_eq!(instr.byte_len(), 4);
+            assert_eq!(instr.next_pc(0x100), 0x104);
+        }
+
+        #[test]
+        fn compressed_instruction_is_two_bytes() {
+            // c.nop
+            let instr = Instruction::new(0x0001);
+            assert!(instr.is_compressed());
+            assert_eq!(instr.byte_len(), 2);
+            assert_eq!(instr.next_pc(0x100), 0x102);
+        }
+
+        #[test]
+        fn next_pc_wraps_around_address_space() {
+            assert_eq!(Instruction::new(0x00500093).next_pc(0xfffffffc), 0x00);
+        }
+    }
+
+    mod from_opcode {
+        use super::*;
+
+        #[test]
+        fn maps_known_opcodes_to_their_formats() {
+            for (opcode, format) in [
+                (0x03, I),
+                (0x0f, I),
+                (0x13, I),
+                (0x17, U),
+                (0x23, S),
+                (0x33, R),
+                (0x37, U),
+                (0x63, B),
+                (0x67, I),
+                (0x6f, J),
+                (0x73, I),
+            ] {
+                assert_eq!(InstructionFormat::from_opcode(opcode), Some(format));
+            }
+        }
+
+        #[test]
+        fn is_none_for_unknown_opcodes() {
+            assert_eq!(InstructionFormat::from_opcode(0x0b), None);
+            assert_eq!(InstructionFormat::from_opcode(0x7f), None);
+        }
+
+        #[test]
+        fn format_of_unknown_opcode_is_none() {
+            assert_eq!(Instruction::new(0x0000028b).format(), None);
+        }
+    }
+
+    mod explain {
+        use super::*;
+
+        #[test]
+        fn lists_r_type_fields() {
+            // sub x5, x7, x3
+            assert_eq!(
+                Instruction::new(0x403382b3).explain(),
+                "opcode[6:0]=0x33 rd[11:7]=x5 funct3[14:12]=0x0 rs1[19:15]=x7 \
+                 rs2[24:20]=x3 funct7[31:25]=0x20",
+            );
+        }
+
+        #[test]
+        fn lists_immediate_pieces_and_value() {
+            // sw x2, -4(x1)
+            assert_eq!(
+                Instruction::new(0xfe20ae23).explain(),
+                "opcode[6:0]=0x23 imm[11:7]=0x1c funct3[14:12]=0x2 rs1[19:15]=x1 \
+                 rs2[24:20]=x2 imm[31:25]=0x7f imm=-4",
+            );
+
+            // lui x10, 0x12345
+            assert_eq!(
+                Instruction::new(0x12345537).explain(),
+                "opcode[6:0]=0x37 rd[11:7]=x10 imm[31:12]=0x12345 imm=74565",
+            );
+        }
+
+        #[test]
+        fn explains_only_opcode_without_format() {
+            assert_eq!(Instruction::new(0x0000028b).explain(), "opcode[6:0]=0xb");
+        }
+    }
+
+    mod display {
+        use super::*;
+
+        #[test]
+        fn system_instructions_have_no_operands() {
+            assert_eq!(Instruction::new(0x30200073).to_string(), "mret");
+            assert_eq!(Instruction::new(0x10200073).to_string(), "sret");
+            assert_eq!(Instruction::new(0x10500073).to_string(), "wfi");
+            assert_eq!(Instruction::new(0x00000073).to_string(), "ecall");
+        }
+
+        #[test]
+        fn csr_instructions_keep_their_operands() {
+            // csrrw x1, 0x340, x2
+            assert_eq!(
+                Instruction::new(0x340110f3).to_string(),
+                "csrrw        x1, x2, 0x00000340",
+            );
+        }
+
+        #[test]
+        fn words_without_a_format_are_data() {
+            assert_eq!(
+                Instruction::new(0x0000028b).to_string(),
+                ".word        0x0000028b",
+            );
+        }
+
+        #[test]
+        fn illegal_words_are_data() {
+            assert_eq!(
+                Instruction::new(0x80000033).to_string(),
+                ".word        0x80000033",
+            );
+            assert_eq!(
+                Instruction::new(0x00004073).to_string(),
+                ".word        0x00004073",
+            );
+        }
+    }
+
+    mod same_operation {
+        use super::*;
+
+        #[test]
+        fn addi_to_x0_is_same_for_any_source() {
+            // addi x0, x1, 5 / addi x0, x2, 5
+            assert!(Instruction::new(0x00508013).same_operation(&Instruction::new(0x00510013)));
+        }
+
+        #[test]
+        fn different_immediates_are_not_same() {
+            // addi x1, x2, 5 / addi x1, x2, 6
+            assert!(!Instruction::new(0x00510093).same_operation(&Instruction::new(0x00610093)));
+        }
+
+        #[test]
+        fn shift_ignores_high_immediate_bits() {
+            // slli x1, x2, 3 / slli x1, x2, 3 with imm[11:5] set
+            assert!(Instruction::new(0x00311093).same_operation(&Instruction::new(0x40311093)));
+        }
+
+        #[test]
+        fn different_ops_are_not_same() {
+            // addi x1, x2, 5 / xori x1, x2, 5
+            assert!(!Instruction::new(0x00510093).same_operation(&Instruction::new(0x00514093)));
+        }
+
+        #[test]
+        fn undecodable_is_never_same() {
+            assert!(!Instruction::new(0x00000000).same_operation(&Instruction::new(0x00000000)));
+        }
+    }
 }
@@ -0,0 +1,221 @@
+//! ISA Extensions
+//! Identifies the standard extensions instructions belong to, and which of
+//! them a processor has enabled, along with the rest of the ISA profile: the
+//! register width and the byte order of data in memory.
+
+use core::fmt::Display;
+
+/// A standard extension to the base integer ISA.
+///
+/// `Zicsr`, `Zifencei` and `Zihintpause` are treated as part of the base
+/// ISA, as they are always enabled.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Extension {
+    /// The base integer ISA.
+    I,
+
+    /// The embedded base, which restricts the base integer ISA to the
+    /// 16 registers `x0`-`x15`.
+    E,
+
+    /// Integer multiplication and division.
+    M,
+
+    /// Atomic instructions.
+    A,
+
+    /// Single-precision floating point.
+    F,
+
+    /// Double-precision floating point.
+    D,
+
+    /// Compressed instructions.
+    C,
+}
+
+impl Extension {
+    /// Returns the extension's bit in an `Extensions` set, which is also
+    /// its bit in `misa`.
+    fn mask(&self) -> u32 {
+        let letter = match self {
+            Extension::I => b'I',
+            Extension::E => b'E',
+            Extension::M => b'M',
+            Extension::A => b'A',
+            Extension::F => b'F',
+            Extension::D => b'D',
+            Extension::C => b'C',
+        };
+
+        1 << (letter - b'A')
+    }
+}
+
+impl Display for Extension {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                Extension::I    => "I",
+                Extension::E    => "E",
+                Extension::M    => "M",
+                Extension::A    => "A",
+                Extension::F    => "F",
+                Extension::D    => "D",
+                Extension::C    => "C",
+            }
+        )
+    }
+}
+
+/// A set of enabled extensions. The base integer ISA is always enabled.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Extensions(u32);
+
+impl Extensions {
+    /// The base integer ISA only.
+    pub const RV32I: Extensions = Extensions(1 << 8);
+
+    /// The embedded base integer ISA, with 16 registers.
+    pub const RV32E: Extensions = Extensions(1 << 4);
+
+    /// The base integer ISA with integer multiplication and division.
+    pub const RV32IM: Extensions = Extensions(1 << 8 | 1 << 12);
+
+    /// Returns the set with the given extension enabled.
+    pub fn with(self, extension: Extension) -> Self {
+        Self(self.0 | extension.mask())
+    }
+
+    /// Returns whether the given extension is enabled.
+    pub fn contains(&self, extension: Extension) -> bool {
+        extension == Extension::I || self.0 & extension.mask() != 0
+    }
+
+    /// Returns the extensions field of `misa`, with a bit set for each
+    /// enabled extension.
+    pub fn misa_bits(&self) -> u32 {
+        self.0
+    }
+}
+
+impl Default for Extensions {
+    fn default() -> Self {
+        Self::RV32I
+    }
+}
+
+/// The width of the integer registers.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum Xlen {
+    #[default]
+    Rv32,
+    Rv64,
+}
+
+impl Xlen {
+    /// Returns the width in bits.
+    pub fn bits(&self) -> u32 {
+        match self {
+            Xlen::Rv32 => 32,
+            Xlen::Rv64 => 64,
+        }
+    }
+}
+
+/// The byte order of data in memory. Instructions are always stored
+/// little-endian, whatever the data byte order.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum Endianness {
+    #[default]
+    Little,
+    Big,
+}
+
+/// The shape of the ISA a machine implements: its register width, the
+/// byte order of its data and its enabled extensions.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct IsaProfile {
+    pub xlen: Xlen,
+    pub endianness: Endianness,
+    pub extensions: Extensions,
+}
+
+impl IsaProfile {
+    /// The little-endian 32-bit base integer ISA only, which is the default.
+    pub fn rv32i() -> Self {
+        Self {
+            xlen: Xlen::Rv32,
+            endianness: Endianness::Little,
+            extensions: Extensions::RV32I,
+        }
+    }
+
+    /// The little-endian 32-bit base integer ISA with integer
+    /// multiplication and division.
+    pub fn rv32im() -> Self {
+        Self {
+            extensions: Extensions::RV32IM,
+            ..Self::rv32i()
+        }
+    }
+
+    /// The little-endian 32-bit embedded base integer ISA, with 16
+    /// registers.
+    pub fn rv32e() -> Self {
+        Self {
+            extensions: Extensions::RV32E,
+            ..Self::rv32i()
+        }
+    }
+
+    /// Returns the profile with the given extension enabled.
+    pub fn with(self, extension: Extension) -> Self {
+        Self {
+            extensions: self.extensions.with(extension),
+            ..self
+        }
+    }
+}
+
+impl Default for IsaProfile {
+    fn default() -> Self {
+        Self::rv32i()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Endianness, Extension, Extensions, IsaProfile, Xlen};
+
+    #[test]
+    fn rv32i_excludes_m() {
+        assert!(Extensions::RV32I.contains(Extension::I));
+        assert!(!Extensions::RV32I.contains(Extension::M));
+        assert!(Extensions::RV32IM.contains(Extension::M));
+    }
+
+    #[test]
+    fn default_profile_is_little_endian_rv32i() {
+        assert_eq!(IsaProfile::default(), IsaProfile::rv32i());
+        assert_eq!(IsaProfile::default().xlen.bits(), 32);
+        assert_eq!(IsaProfile::default().endianness, Endianness::Little);
+        assert!(!IsaProfile::default().extensions.contains(Extension::M));
+        assert_eq!(IsaProfile::rv32i().with(Extension::M), IsaProfile::rv32im());
+        assert_ne!(IsaProfile { xlen: Xlen::Rv64, ..IsaProfile::rv32i() }, IsaProfile::rv32i());
+    }
+
+    #[test]
+    fn rv32e_is_the_embedded_base() {
+        assert!(IsaProfile::rv32e().extensions.contains(Extension::E));
+        assert!(!IsaProfile::rv32i().extensions.contains(Extension::E));
+        assert!(!IsaProfile::rv32e().extensions.contains(Extension::M));
+    }
+
+    #[test]
+    fn with_enables_extension() {
+        assert_eq!(Extensions::RV32I.with(Extension::M), Extensions::RV32IM);
+    }
+}
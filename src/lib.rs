@@ -1,9 +1,35 @@
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
+
+extern crate alloc;
+
+pub mod alu;
+pub mod analysis;
+pub mod asm;
+pub mod bits;
+pub mod bus;
+pub mod clint;
+pub mod decode;
+pub mod decode_cache;
+pub mod disasm;
+pub mod elf;
 pub mod emulator;
+pub mod encode;
+pub mod event_log;
+pub mod golden;
+pub mod instruction;
+pub mod isa;
+pub mod memory;
+pub mod op;
+pub mod processor;
+pub mod profile;
+pub mod sbi;
+pub mod syscall;
+pub mod test_finisher;
+pub mod timing;
+pub mod trace;
+pub mod trap;
+pub mod uart;
+pub mod undo;
 
-mod alu;
-mod decode;
-mod instruction;
-mod memory;
-mod op;
-mod processor;
-mod register;
\ No newline at end of file
+mod csr;
+mod register;
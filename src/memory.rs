@@ -1,4 +1,26 @@
+use alloc::vec;
+use alloc::vec::Vec;
+
+/// The FNV-1a offset basis and prime for 64-bit hashes.
+const FNV_OFFSET_BASIS: u64 = 0xcbf29ce484222325;
+const FNV_PRIME: u64 = 0x00000100000001b3;
+
+/// The magic number at the start of a memory image file.
+#[cfg(feature = "std")]
+const IMAGE_MAGIC: [u8; 8] = *b"RVMEMIMG";
+
+/// The size of the pages a memory image file is divided into.
+#[cfg(feature = "std")]
+const IMAGE_PAGE_SIZE: usize = 4096;
+
+/// The largest memory an image can hold, the whole 32-bit address space.
+#[cfg(feature = "std")]
+const IMAGE_MAX_SIZE: u64 = 1 << 32;
+
 /// Emulated byte-addressable memory.
+///
+/// Memory may have a size of zero, in which case reads return zeros and
+/// writes are ignored.
 #[derive(Debug)]
 pub struct Memory {
     data: Vec<u8>,
@@ -7,10 +29,6 @@ pub struct Memory {
 impl Memory {
     /// Creates a new instance of memory of a given size in bytes.
     pub fn new(size: usize) -> Self {
-        if size == 0 {
-            panic!("Memory size cannot be zero.");
-        }
-
         Self {
             data: vec![0x00; size],
         }
@@ -19,6 +37,10 @@ impl Memory {
     /// Reads one or more contiguous bytes from memory, starting from a base
     /// address. Addresses wrap around if the length exceeds the address space.
     pub fn read(&self, base_addr: usize, len: usize) -> Vec<u8> {
+        if self.data.is_empty() {
+            return vec![0x00; len];
+        }
+
         let mut result = Vec::with_capacity(len);
 
         for i in 0 .. len {
@@ -32,26 +54,192 @@ impl Memory {
         result
     }
 
+    /// Borrows `len` bytes starting from a base address without copying
+    /// them, or returns None if the range runs past the end of memory.
+    /// Use `read` for ranges that wrap around.
+    pub fn as_slice(&self, base_addr: usize, len: usize) -> Option<&[u8]> {
+        self.data.get(base_addr .. base_addr.checked_add(len)?)
+    }
+
     /// Writes one or more bytes to memory contiguously, starting from a base
     /// address. Addresses wrap around if the length exceeds the address space.
     pub fn write(&mut self, base_addr: usize, value: &[u8]) {
-        for i in 0 .. value.len() {
+        if self.data.is_empty() {
+            return;
+        }
+
+        for (i, byte) in value.iter().enumerate() {
             let index = self.wrap_addr(base_addr + i);
-            self.data[index] = value[i];
+            self.data[index] = *byte;
         }
     }
 
+    /// Sets `len` bytes starting from a base address to `byte`. Addresses
+    /// wrap around if the length exceeds the address space.
+    pub fn fill(&mut self, base_addr: usize, len: usize, byte: u8) {
+        if len >= self.data.len() {
+            self.data.fill(byte);
+            return;
+        }
+
+        let size = self.data.len();
+        let start = self.wrap_addr(base_addr);
+        let end = start + len;
+
+        if end <= size {
+            self.data[start .. end].fill(byte);
+        } else {
+            self.data[start ..].fill(byte);
+            self.data[.. end - size].fill(byte);
+        }
+    }
+
+    /// Copies `len` bytes from `src` to `dst`. The ranges may overlap, in
+    /// which case the copy behaves as if the source were read in full
+    /// before writing. Addresses wrap around if the length exceeds the
+    /// address space.
+    pub fn copy_within(&mut self, src: usize, dst: usize, len: usize) {
+        if self.data.is_empty() {
+            return;
+        }
+
+        let size = self.data.len();
+        let src = self.wrap_addr(src);
+        let dst = self.wrap_addr(dst);
+
+        if src + len <= size && dst + len <= size {
+            self.data.copy_within(src .. src + len, dst);
+        } else {
+            let block = self.read(src, len);
+            self.write(dst, &block);
+        }
+    }
+
+    /// Returns a 64-bit FNV-1a hash of the whole memory, a cheap fingerprint
+    /// for checking whether two memories hold the same bytes.
+    pub fn checksum(&self) -> u64 {
+        fnv1a(self.data.iter())
+    }
+
+    /// Returns a 64-bit FNV-1a hash of `len` bytes starting from a base
+    /// address, or None if the range doesn't lie within memory.
+    pub fn checksum_range(&self, base_addr: usize, len: usize) -> Option<u64> {
+        let end = base_addr.checked_add(len)?;
+        self.data.get(base_addr .. end).map(|range| fnv1a(range.iter()))
+    }
+
     /// Returns the size of the memory in bytes.
     pub fn len(&self) -> usize {
         self.data.len()
     }
 
-    /// Wraps an address value if it exceeds the address space.
+    /// Returns whether the memory has no bytes.
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    /// Writes the memory to a file as an image that `load_from_file` can
+    /// restore.
+    ///
+    /// Only pages holding a non-zero byte are written, so images of large,
+    /// mostly empty memories stay small. The file holds the magic number
+    /// `RVMEMIMG` and the memory size as a little-endian `u64`, followed by
+    /// each populated page as its little-endian `u64` index and its bytes.
+    /// Pages are `IMAGE_PAGE_SIZE` bytes, except that the last page of the
+    /// memory may be shorter.
+    #[cfg(feature = "std")]
+    pub fn save<P: AsRef<std::path::Path>>(&self, path: P) -> std::io::Result<()> {
+        use std::io::Write;
+
+        let mut file = std::io::BufWriter::new(std::fs::File::create(path)?);
+
+        file.write_all(&IMAGE_MAGIC)?;
+        file.write_all(&(self.data.len() as u64).to_le_bytes())?;
+
+        for (index, page) in self.data.chunks(IMAGE_PAGE_SIZE).enumerate() {
+            if page.iter().any(|&byte| byte != 0) {
+                file.write_all(&(index as u64).to_le_bytes())?;
+                file.write_all(page)?;
+            }
+        }
+
+        file.flush()
+    }
+
+    /// Reads a memory image written by `save`, returning an error of kind
+    /// `InvalidData` if the file isn't a valid image, or claims a size
+    /// larger than the 32-bit address space, and of kind `OutOfMemory` if
+    /// the memory can't be allocated.
+    #[cfg(feature = "std")]
+    pub fn load_from_file<P: AsRef<std::path::Path>>(path: P) -> std::io::Result<Self> {
+        let invalid = |message: &str| {
+            std::io::Error::new(std::io::ErrorKind::InvalidData, message)
+        };
+
+        let bytes = std::fs::read(path)?;
+
+        let (header, mut pages) = bytes
+            .split_at_checked(16)
+            .ok_or_else(|| invalid("memory image is truncated"))?;
+
+        if header[.. 8] != IMAGE_MAGIC {
+            return Err(invalid("not a memory image"));
+        }
+
+        let size = u64::from_le_bytes(header[8 ..].try_into().unwrap());
+
+        let size = usize::try_from(size)
+            .ok()
+            .filter(|_| size <= IMAGE_MAX_SIZE)
+            .ok_or_else(|| invalid("memory image is too large"))?;
+
+        // The size comes from the file, so a failed allocation is an error
+        // rather than an abort.
+        let mut data = Vec::new();
+        data.try_reserve_exact(size).map_err(|_| std::io::ErrorKind::OutOfMemory)?;
+        data.resize(size, 0x00);
+
+        let mut memory = Self { data };
+
+        while !pages.is_empty() {
+            let (index, rest) = pages
+                .split_at_checked(8)
+                .ok_or_else(|| invalid("memory image is truncated"))?;
+
+            let index = u64::from_le_bytes(index.try_into().unwrap());
+
+            let page = usize::try_from(index)
+                .ok()
+                .and_then(|index| index.checked_mul(IMAGE_PAGE_SIZE))
+                .filter(|&start| start < size)
+                .map(|start| start .. size.min(start + IMAGE_PAGE_SIZE))
+                .ok_or_else(|| invalid("memory image page is out of range"))?;
+
+            let (data, rest) = rest
+                .split_at_checked(page.len())
+                .ok_or_else(|| invalid("memory image is truncated"))?;
+
+            memory.data[page].copy_from_slice(data);
+            pages = rest;
+        }
+
+        Ok(memory)
+    }
+
+    /// Wraps an address value if it exceeds the address space. Must not be
+    /// called on empty memory, which has no addresses.
     fn wrap_addr(&self, addr: usize) -> usize {
         addr % self.data.len()
     }
 }
 
+/// Hashes bytes with 64-bit FNV-1a.
+fn fnv1a<'a>(bytes: impl Iterator<Item = &'a u8>) -> u64 {
+    bytes.fold(FNV_OFFSET_BASIS, |hash, &byte| {
+        (hash ^ byte as u64).wrapping_mul(FNV_PRIME)
+    })
+}
+
 #[cfg(test)]
 mod tests {
     use super::Memory;
@@ -64,9 +252,16 @@ mod tests {
     }
 
     #[test]
-    #[should_panic]
-    fn memory_size_cannot_be_zero() {
-        Memory::new(0);
+    fn zero_size_memory_reads_zeros_and_ignores_writes() {
+        let mut mem = Memory::new(0);
+        assert!(mem.is_empty());
+
+        mem.write(0x10, &[1, 2, 3]);
+        mem.fill(0x00, 4, 0xff);
+        mem.copy_within(0x00, 0x10, 4);
+
+        assert_eq!(mem.read(0x10, 3), &[0, 0, 0]);
+        assert_eq!(mem.checksum_range(0x10, 3), None);
     }
 
     #[test]
@@ -83,4 +278,159 @@ mod tests {
         mem.write(7, data);
         assert_eq!(mem.read(7, 4), data);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn as_slice_borrows_in_bounds_range() {
+        let mut mem = Memory::new(16);
+        mem.write(4, &[1, 2, 3, 4]);
+
+        assert_eq!(mem.as_slice(4, 4), Some(&[1, 2, 3, 4][..]));
+        assert_eq!(mem.as_slice(12, 4), Some(&[0, 0, 0, 0][..]));
+    }
+
+    #[test]
+    fn as_slice_rejects_wrapping_range() {
+        let mem = Memory::new(16);
+
+        assert_eq!(mem.as_slice(14, 4), None);
+        assert_eq!(mem.as_slice(16, 1), None);
+        assert_eq!(mem.as_slice(usize::MAX, 2), None);
+    }
+
+    #[test]
+    fn fill_sets_range() {
+        let mut mem = Memory::new(16);
+        mem.fill(4, 3, 0xaa);
+        assert_eq!(mem.read(3, 5), &[0x00, 0xaa, 0xaa, 0xaa, 0x00]);
+    }
+
+    #[test]
+    fn fill_wraps_around() {
+        let mut mem = Memory::new(16);
+        mem.fill(14, 4, 0xaa);
+        assert_eq!(mem.read(14, 4), &[0xaa; 4]);
+        assert_eq!(mem.read(2, 1), &[0x00]);
+    }
+
+    #[test]
+    fn copy_within_duplicates_block() {
+        let mut mem = Memory::new(16);
+        mem.write(0, &[1, 2, 3, 4]);
+        mem.copy_within(0, 8, 4);
+        assert_eq!(mem.read(8, 4), &[1, 2, 3, 4]);
+        assert_eq!(mem.read(0, 4), &[1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn copy_within_handles_overlap() {
+        let mut mem = Memory::new(16);
+        mem.write(0, &[1, 2, 3, 4, 5]);
+        mem.copy_within(0, 2, 5);
+        assert_eq!(mem.read(0, 7), &[1, 2, 1, 2, 3, 4, 5]);
+    }
+
+    #[test]
+    fn copy_within_wraps_around() {
+        let mut mem = Memory::new(16);
+        mem.write(14, &[1, 2, 3, 4]);
+        mem.copy_within(14, 4, 4);
+        assert_eq!(mem.read(4, 4), &[1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn write_changes_checksum_and_reverting_restores_it() {
+        let mut mem = Memory::new(64);
+        let checksum = mem.checksum();
+
+        mem.write(0x10, &[0x01]);
+        assert_ne!(mem.checksum(), checksum);
+
+        mem.write(0x10, &[0x00]);
+        assert_eq!(mem.checksum(), checksum);
+    }
+
+    #[test]
+    fn checksum_range_only_covers_range() {
+        let mut mem = Memory::new(64);
+        let checksum = mem.checksum_range(0x00, 0x10);
+
+        mem.write(0x20, &[0xff]);
+        assert_eq!(mem.checksum_range(0x00, 0x10), checksum);
+
+        mem.write(0x3f, &[0xff]);
+        assert_ne!(mem.checksum_range(0x3e, 2), mem.checksum_range(0x3d, 2));
+        assert_eq!(mem.checksum_range(0x00, 64), Some(mem.checksum()));
+    }
+
+    #[test]
+    fn checksum_range_rejects_ranges_outside_memory() {
+        let mem = Memory::new(64);
+
+        assert_eq!(mem.checksum_range(0x3f, 2), None);
+        assert_eq!(mem.checksum_range(0x40, 0), mem.checksum_range(0x00, 0));
+        assert_eq!(mem.checksum_range(0x41, 0), None);
+        assert_eq!(mem.checksum_range(0x3f, usize::MAX), None);
+    }
+
+    #[cfg(feature = "std")]
+    mod file {
+        use super::*;
+
+        use std::path::PathBuf;
+
+        use super::super::IMAGE_PAGE_SIZE;
+
+        /// Returns a path in the temporary directory unique to this process.
+        fn temp_path(name: &str) -> PathBuf {
+            std::env::temp_dir().join(format!("{name}-{}.img", std::process::id()))
+        }
+
+        #[test]
+        fn round_trips_scattered_writes() {
+            let path = temp_path("memory-round-trip");
+            let mut mem = Memory::new(IMAGE_PAGE_SIZE * 8 + 100);
+
+            mem.write(0x00, &[0x01]);
+            mem.write(IMAGE_PAGE_SIZE * 3 + 7, &[0xde, 0xad, 0xbe, 0xef]);
+            mem.write(IMAGE_PAGE_SIZE * 8 + 99, &[0x02]);
+
+            mem.save(&path).unwrap();
+            let size = std::fs::metadata(&path).unwrap().len();
+            let loaded = Memory::load_from_file(&path);
+            std::fs::remove_file(&path).unwrap();
+
+            let loaded = loaded.unwrap();
+            assert_eq!(loaded.len(), mem.len());
+            assert_eq!(loaded.checksum(), mem.checksum());
+
+            // Only the three populated pages are written, the last short.
+            assert_eq!(size, 16 + 3 * 8 + 2 * IMAGE_PAGE_SIZE as u64 + 100);
+        }
+
+        #[test]
+        fn rejects_forged_size_without_allocating() {
+            let path = temp_path("memory-forged-size");
+            let mut header = b"RVMEMIMG".to_vec();
+            header.extend_from_slice(&(1u64 << 40).to_le_bytes());
+            std::fs::write(&path, header).unwrap();
+
+            let result = Memory::load_from_file(&path);
+            std::fs::remove_file(&path).unwrap();
+
+            let err = result.unwrap_err();
+            assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+            assert_eq!(err.to_string(), "memory image is too large");
+        }
+
+        #[test]
+        fn rejects_files_that_are_not_images() {
+            let path = temp_path("memory-not-image");
+            std::fs::write(&path, b"not a memory image").unwrap();
+
+            let result = Memory::load_from_file(&path);
+            std::fs::remove_file(&path).unwrap();
+
+            assert_eq!(result.unwrap_err().kind(), std::io::ErrorKind::InvalidData);
+        }
+    }
+}
@@ -1,12 +1,60 @@
-use std::fmt::Display;
+use core::fmt::Display;
+
+use crate::isa::Extension;
 
 use Op::*;
 
-#[derive(Debug, Eq, PartialEq)]
+/// A broad grouping of operations by what they do.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum OpCategory {
+    /// Integer arithmetic and comparisons, including the upper-immediate
+    /// instructions.
+    Arithmetic,
+
+    /// Bitwise logical operations.
+    Logical,
+
+    /// Logical and arithmetic shifts.
+    Shift,
+
+    /// Conditional branches.
+    Branch,
+
+    /// Unconditional jumps.
+    Jump,
+
+    /// Memory loads.
+    Load,
+
+    /// Memory stores.
+    Store,
+
+    /// Memory ordering, environment calls and other system operations.
+    System,
+
+    /// Control and status register accesses.
+    Csr,
+
+    /// Integer multiplication, from the M extension.
+    Multiply,
+
+    /// Integer division and remainder, from the M extension.
+    Divide,
+}
+
+#[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd)]
 pub enum Op {
     AddUpperImmediateProgramCounter,
     ArithmeticAdd,
     ArithmeticAddImmediate,
+    ArithmeticDiv,
+    ArithmeticDivUnsigned,
+    ArithmeticMul,
+    ArithmeticMulHigh,
+    ArithmeticMulHighSignedUnsigned,
+    ArithmeticMulHighUnsigned,
+    ArithmeticRem,
+    ArithmeticRemUnsigned,
     ArithmeticSub,
     BranchEqual,
     BranchGreaterThanOrEqualTo,
@@ -14,12 +62,12 @@ pub enum Op {
     BranchLessThan,
     BranchLessThanUnsigned,
     BranchNotEqual,
-    // CsrReadClear,
-    // CsrReadClearImmediate,
-    // CsrReadSet,
-    // CsrReadSetImmediate,
-    // CsrReadWrite,
-    // CsrReadWriteImmediate,
+    CsrReadClear,
+    CsrReadClearImmediate,
+    CsrReadSet,
+    CsrReadSetImmediate,
+    CsrReadWrite,
+    CsrReadWriteImmediate,
     Fence,
     FenceI,
     JumpAndLink,
@@ -36,6 +84,9 @@ pub enum Op {
     LogicalExclusiveOrImmediate,
     LogicalOr,
     LogicalOrImmediate,
+    Pause,
+    SaturatingAdd,
+    SaturatingSub,
     SetLessThan,
     SetLessThanImmediate,
     SetLessThanImmediateUnsigned,
@@ -49,12 +100,246 @@ pub enum Op {
     StoreByte,
     StoreHalf,
     StoreWord,
-    // SystemEbreak,
-    // SystemEcall,
+    SystemEbreak,
+    SystemEcall,
+    SystemMachineReturn,
+    SystemSupervisorReturn,
+    SystemWaitForInterrupt,
+}
+
+impl Op {
+    /// Returns the operation with the given mnemonic, as rendered by
+    /// `Display`, or None if there isn't one.
+    pub fn from_mnemonic(mnemonic: &str) -> Option<Op> {
+        Some(match mnemonic {
+            "auipc"                             => AddUpperImmediateProgramCounter,
+            "add"                               => ArithmeticAdd,
+            "addi"                              => ArithmeticAddImmediate,
+            "div"                               => ArithmeticDiv,
+            "divu"                              => ArithmeticDivUnsigned,
+            "mul"                               => ArithmeticMul,
+            "mulh"                              => ArithmeticMulHigh,
+            "mulhsu"                            => ArithmeticMulHighSignedUnsigned,
+            "mulhu"                             => ArithmeticMulHighUnsigned,
+            "rem"                               => ArithmeticRem,
+            "remu"                              => ArithmeticRemUnsigned,
+            "sub"                               => ArithmeticSub,
+            "beq"                               => BranchEqual,
+            "bge"                               => BranchGreaterThanOrEqualTo,
+            "bgeu"                              => BranchGreaterThanOrEqualToUnsigned,
+            "blt"                               => BranchLessThan,
+            "bltu"                              => BranchLessThanUnsigned,
+            "bne"                               => BranchNotEqual,
+            "csrrc"                             => CsrReadClear,
+            "csrrci"                            => CsrReadClearImmediate,
+            "csrrs"                             => CsrReadSet,
+            "csrrsi"                            => CsrReadSetImmediate,
+            "csrrw"                             => CsrReadWrite,
+            "csrrwi"                            => CsrReadWriteImmediate,
+            "fence"                             => Fence,
+            "fence.i"                           => FenceI,
+            "jal"                               => JumpAndLink,
+            "jalr"                              => JumpAndLinkRegister,
+            "lb"                                => LoadByte,
+            "lbu"                               => LoadByteUnsigned,
+            "lh"                                => LoadHalf,
+            "lhu"                               => LoadHalfUnsigned,
+            "lui"                               => LoadUpperImmediate,
+            "lw"                                => LoadWord,
+            "and"                               => LogicalAnd,
+            "andi"                              => LogicalAndImmediate,
+            "xor"                               => LogicalExclusiveOr,
+            "xori"                              => LogicalExclusiveOrImmediate,
+            "or"                                => LogicalOr,
+            "ori"                               => LogicalOrImmediate,
+            "pause"                             => Pause,
+            "sadd"                              => SaturatingAdd,
+            "ssub"                              => SaturatingSub,
+            "slt"                               => SetLessThan,
+            "slti"                              => SetLessThanImmediate,
+            "sltiu"                             => SetLessThanImmediateUnsigned,
+            "sltu"                              => SetLessThanUnsigned,
+            "sll"                               => ShiftLeftLogical,
+            "slli"                              => ShiftLeftLogicalImmediate,
+            "sra"                               => ShiftRightArithmetic,
+            "srai"                              => ShiftRightArithmeticImmediate,
+            "srl"                               => ShiftRightLogical,
+            "srli"                              => ShiftRightLogicalImmediate,
+            "sb"                                => StoreByte,
+            "sh"                                => StoreHalf,
+            "sw"                                => StoreWord,
+            "ebreak"                            => SystemEbreak,
+            "ecall"                             => SystemEcall,
+            "mret"                              => SystemMachineReturn,
+            "sret"                              => SystemSupervisorReturn,
+            "wfi"                               => SystemWaitForInterrupt,
+            _                                   => return None,
+        })
+    }
+
+    /// Returns the extension the operation belongs to.
+    pub fn extension(&self) -> Extension {
+        match self.category() {
+            OpCategory::Multiply
+                | OpCategory::Divide => Extension::M,
+
+            _ => Extension::I,
+        }
+    }
+
+    /// Returns whether `imm` fits the operation's immediate field, so
+    /// encoding it won't silently truncate it. `imm` is interpreted as in
+    /// `Operands`.
+    ///
+    /// Branch and `jal` offsets must also be even, as bit 0 isn't encoded.
+    /// Operations without an immediate only accept zero.
+    pub fn imm_fits(&self, imm: i32) -> bool {
+        match self {
+            // imm[11:0], sign-extended.
+            ArithmeticAddImmediate
+                | JumpAndLinkRegister
+                | LoadByte
+                | LoadByteUnsigned
+                | LoadHalf
+                | LoadHalfUnsigned
+                | LoadWord
+                | LogicalAndImmediate
+                | LogicalExclusiveOrImmediate
+                | LogicalOrImmediate
+                | SetLessThanImmediate
+                | SetLessThanImmediateUnsigned
+                | StoreByte
+                | StoreHalf
+                | StoreWord => (-0x800 ..= 0x7ff).contains(&imm),
+
+            // shamt[4:0]
+            ShiftLeftLogicalImmediate
+                | ShiftRightArithmeticImmediate
+                | ShiftRightLogicalImmediate => (0 ..= 0x1f).contains(&imm),
+
+            // imm[12:1], sign-extended.
+            BranchEqual
+                | BranchGreaterThanOrEqualTo
+                | BranchGreaterThanOrEqualToUnsigned
+                | BranchLessThan
+                | BranchLessThanUnsigned
+                | BranchNotEqual => (-0x1000 ..= 0xffe).contains(&imm) && imm & 0x01 == 0,
+
+            // imm[20:1], sign-extended.
+            JumpAndLink => (-0x10_0000 ..= 0x0f_fffe).contains(&imm) && imm & 0x01 == 0,
+
+            // imm[31:12], written either signed or unsigned.
+            AddUpperImmediateProgramCounter
+                | LoadUpperImmediate => (-0x8_0000 ..= 0xf_ffff).contains(&imm),
+
+            // The CSR address, or the fence ordering fields.
+            CsrReadClear
+                | CsrReadClearImmediate
+                | CsrReadSet
+                | CsrReadSetImmediate
+                | CsrReadWrite
+                | CsrReadWriteImmediate
+                | Fence
+                | FenceI => (0 ..= 0xfff).contains(&imm),
+
+            _ => imm == 0,
+        }
+    }
+
+    /// Returns the category the operation belongs to.
+    pub fn category(&self) -> OpCategory {
+        match self {
+            AddUpperImmediateProgramCounter
+                | ArithmeticAdd
+                | ArithmeticAddImmediate
+                | ArithmeticSub
+                | LoadUpperImmediate
+                | SaturatingAdd
+                | SaturatingSub
+                | SetLessThan
+                | SetLessThanImmediate
+                | SetLessThanImmediateUnsigned
+                | SetLessThanUnsigned => OpCategory::Arithmetic,
+
+            LogicalAnd
+                | LogicalAndImmediate
+                | LogicalExclusiveOr
+                | LogicalExclusiveOrImmediate
+                | LogicalOr
+                | LogicalOrImmediate => OpCategory::Logical,
+
+            ShiftLeftLogical
+                | ShiftLeftLogicalImmediate
+                | ShiftRightArithmetic
+                | ShiftRightArithmeticImmediate
+                | ShiftRightLogical
+                | ShiftRightLogicalImmediate => OpCategory::Shift,
+
+            BranchEqual
+                | BranchGreaterThanOrEqualTo
+                | BranchGreaterThanOrEqualToUnsigned
+                | BranchLessThan
+                | BranchLessThanUnsigned
+                | BranchNotEqual => OpCategory::Branch,
+
+            JumpAndLink
+                | JumpAndLinkRegister => OpCategory::Jump,
+
+            LoadByte
+                | LoadByteUnsigned
+                | LoadHalf
+                | LoadHalfUnsigned
+                | LoadWord => OpCategory::Load,
+
+            StoreByte
+                | StoreHalf
+                | StoreWord => OpCategory::Store,
+
+            Fence
+                | FenceI
+                | Pause
+                | SystemEbreak
+                | SystemEcall
+                | SystemMachineReturn
+                | SystemSupervisorReturn
+                | SystemWaitForInterrupt => OpCategory::System,
+
+            CsrReadClear
+                | CsrReadClearImmediate
+                | CsrReadSet
+                | CsrReadSetImmediate
+                | CsrReadWrite
+                | CsrReadWriteImmediate => OpCategory::Csr,
+
+            ArithmeticMul
+                | ArithmeticMulHigh
+                | ArithmeticMulHighSignedUnsigned
+                | ArithmeticMulHighUnsigned => OpCategory::Multiply,
+
+            ArithmeticDiv
+                | ArithmeticDivUnsigned
+                | ArithmeticRem
+                | ArithmeticRemUnsigned => OpCategory::Divide,
+        }
+    }
+
+    /// Returns whether the operation ends a basic block: a branch, a jump,
+    /// or a system operation that transfers control elsewhere, such as
+    /// `ecall` trapping or `mret` returning from a trap.
+    pub fn is_terminator(&self) -> bool {
+        match self {
+            SystemEbreak
+                | SystemEcall
+                | SystemMachineReturn
+                | SystemSupervisorReturn => true,
+
+            _ => matches!(self.category(), OpCategory::Branch | OpCategory::Jump),
+        }
+    }
 }
 
 impl Display for Op {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         write!(
             f,
             "{}",
@@ -62,6 +347,14 @@ impl Display for Op {
                 AddUpperImmediateProgramCounter     => "auipc",
                 ArithmeticAdd                       => "add",
                 ArithmeticAddImmediate              => "addi",
+                ArithmeticDiv                       => "div",
+                ArithmeticDivUnsigned               => "divu",
+                ArithmeticMul                       => "mul",
+                ArithmeticMulHigh                   => "mulh",
+                ArithmeticMulHighSignedUnsigned     => "mulhsu",
+                ArithmeticMulHighUnsigned           => "mulhu",
+                ArithmeticRem                       => "rem",
+                ArithmeticRemUnsigned               => "remu",
                 ArithmeticSub                       => "sub",
                 BranchEqual                         => "beq",
                 BranchGreaterThanOrEqualTo          => "bge",
@@ -69,12 +362,12 @@ impl Display for Op {
                 BranchLessThan                      => "blt",
                 BranchLessThanUnsigned              => "bltu",
                 BranchNotEqual                      => "bne",
-                // CsrReadClear                        => "csrrc",
-                // CsrReadClearImmediate               => "csrrci",
-                // CsrReadSet                          => "csrrs",
-                // CsrReadSetImmediate                 => "csrrsi",
-                // CsrReadWrite                        => "csrw",
-                // CsrReadWriteImmediate               => "csrwi",
+                CsrReadClear                        => "csrrc",
+                CsrReadClearImmediate               => "csrrci",
+                CsrReadSet                          => "csrrs",
+                CsrReadSetImmediate                 => "csrrsi",
+                CsrReadWrite                        => "csrrw",
+                CsrReadWriteImmediate               => "csrrwi",
                 Fence                               => "fence",
                 FenceI                              => "fence.i",
                 JumpAndLink                         => "jal",
@@ -91,6 +384,9 @@ impl Display for Op {
                 LogicalExclusiveOrImmediate         => "xori",
                 LogicalOr                           => "or",
                 LogicalOrImmediate                  => "ori",
+                Pause                               => "pause",
+                SaturatingAdd                       => "sadd",
+                SaturatingSub                       => "ssub",
                 SetLessThan                         => "slt",
                 SetLessThanImmediate                => "slti",
                 SetLessThanImmediateUnsigned        => "sltiu",
@@ -104,9 +400,116 @@ impl Display for Op {
                 StoreByte                           => "sb",
                 StoreHalf                           => "sh",
                 StoreWord                           => "sw",
-                // SystemEbreak                        => "ebreak",
-                // SystemEcall                         => "ecall",
+                SystemEbreak                        => "ebreak",
+                SystemEcall                         => "ecall",
+                SystemMachineReturn                 => "mret",
+                SystemSupervisorReturn              => "sret",
+                SystemWaitForInterrupt              => "wfi",
             }
         )
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Extension, Op, Op::*, OpCategory};
+
+    #[test]
+    fn categorises_arithmetic() {
+        assert_eq!(ArithmeticAdd.category(), OpCategory::Arithmetic);
+    }
+
+    #[test]
+    fn categorises_logical() {
+        assert_eq!(LogicalExclusiveOrImmediate.category(), OpCategory::Logical);
+    }
+
+    #[test]
+    fn categorises_shift() {
+        assert_eq!(ShiftRightArithmeticImmediate.category(), OpCategory::Shift);
+    }
+
+    #[test]
+    fn categorises_branch() {
+        assert_eq!(BranchLessThanUnsigned.category(), OpCategory::Branch);
+    }
+
+    #[test]
+    fn categorises_jump() {
+        assert_eq!(JumpAndLinkRegister.category(), OpCategory::Jump);
+    }
+
+    #[test]
+    fn categorises_load() {
+        assert_eq!(LoadHalfUnsigned.category(), OpCategory::Load);
+    }
+
+    #[test]
+    fn categorises_store() {
+        assert_eq!(StoreByte.category(), OpCategory::Store);
+    }
+
+    #[test]
+    fn categorises_system() {
+        assert_eq!(FenceI.category(), OpCategory::System);
+        assert_eq!(SystemEcall.category(), OpCategory::System);
+    }
+
+    #[test]
+    fn categorises_csr() {
+        assert_eq!(CsrReadSetImmediate.category(), OpCategory::Csr);
+    }
+
+    #[test]
+    fn parses_mnemonic() {
+        assert_eq!(Op::from_mnemonic("fence.i"), Some(FenceI));
+        assert_eq!(Op::from_mnemonic("mulhsu"), Some(ArithmeticMulHighSignedUnsigned));
+        assert_eq!(Op::from_mnemonic("ssub"), Some(SaturatingSub));
+        assert_eq!(Op::from_mnemonic("nop"), None);
+    }
+
+    #[test]
+    fn tags_extension() {
+        assert_eq!(ArithmeticAdd.extension(), Extension::I);
+        assert_eq!(ArithmeticDivUnsigned.extension(), Extension::M);
+    }
+
+    #[test]
+    fn checks_i_type_immediate_range() {
+        assert!(ArithmeticAddImmediate.imm_fits(2047));
+        assert!(ArithmeticAddImmediate.imm_fits(-2048));
+        assert!(!ArithmeticAddImmediate.imm_fits(2048));
+        assert!(!LoadWord.imm_fits(-2049));
+    }
+
+    #[test]
+    fn checks_scaled_and_upper_immediate_ranges() {
+        assert!(BranchEqual.imm_fits(-4096));
+        assert!(!BranchEqual.imm_fits(4096));
+        assert!(!BranchEqual.imm_fits(6 + 1));
+        assert!(JumpAndLink.imm_fits(0x0f_fffe));
+        assert!(!JumpAndLink.imm_fits(0x10_0000));
+        assert!(LoadUpperImmediate.imm_fits(0xf_ffff));
+        assert!(!LoadUpperImmediate.imm_fits(0x10_0000));
+        assert!(!ShiftLeftLogicalImmediate.imm_fits(32));
+        assert!(!ArithmeticAdd.imm_fits(1));
+    }
+
+    #[test]
+    fn recognises_terminators() {
+        assert!(BranchLessThan.is_terminator());
+        assert!(JumpAndLinkRegister.is_terminator());
+        assert!(SystemEcall.is_terminator());
+        assert!(SystemMachineReturn.is_terminator());
+
+        assert!(!ArithmeticAdd.is_terminator());
+        assert!(!SystemWaitForInterrupt.is_terminator());
+        assert!(!FenceI.is_terminator());
+    }
+
+    #[test]
+    fn categorises_multiply_and_divide() {
+        assert_eq!(ArithmeticMulHighUnsigned.category(), OpCategory::Multiply);
+        assert_eq!(ArithmeticRem.category(), OpCategory::Divide);
+    }
+}
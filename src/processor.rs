@@ -1,45 +1,298 @@
-/// Processor
-/// The processor is currently only designed to support the
-/// RV32I variant of the ISA, meaning registers are 32 bits in size.
+//! Processor
+//! The processor is currently only designed to support the
+//! RV32I variant of the ISA and the M extension, meaning registers are
+//! 32 bits in size.
 
-// TODO:
-// I'm using unwrap() during prototyping, but these will need
-// to be replaced once it's clearer how errors should be handled.
-// Most fields on instructions return an Option, so I'd like to be
-// able to use error propagation (e.g. instr.funct3()?...).
+use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
 
-use crate::alu::Alu;
+use core::fmt::Display;
+
+use crate::alu::{Alu, AluOps};
+
+use crate::bus::Bus;
+
+use crate::csr::{
+    self,
+    MCAUSE,
+    MEDELEG,
+    MEPC,
+    MIDELEG,
+    MISA,
+    MISA_MXL_32,
+    MIE,
+    MIP,
+    MSTATUS,
+    MSTATUS_MIE,
+    MSTATUS_MPIE,
+    MSTATUS_MPP,
+    MSTATUS_SIE,
+    MSTATUS_SPIE,
+    MSTATUS_SPP,
+    MTVAL,
+    MTVEC,
+    MTVEC_MODE,
+    MTVEC_MODE_VECTORED,
+    SCAUSE,
+    SEPC,
+    STVAL,
+    STVEC,
+};
 
 use crate::decode::Decoder;
 
+use crate::decode_cache::{CodeCoherence, DecodeCache};
+
+use crate::emulator::HaltReason;
+
+use crate::event_log::{Event, EventLog};
+
+use crate::memory::Memory;
+
 use crate::instruction::{
     Instruction,
     InstructionFormat::*,
 };
 
+use crate::isa::{Endianness, Extension, Extensions};
+
 use crate::op::{
     Op,
     Op::*,
 };
 
+use crate::profile::Profile;
+
+use crate::timing::CostTable;
+
+use crate::trace::{BinaryTrace, TraceEncoder, TraceSink};
+
+use crate::undo::{UndoLog, UndoRecord};
+
 use crate::register::{
     AccessLevel,
+    RegistersCsr,
     RegistersX,
+    ABI_NAMES,
+};
+
+use crate::trap::{
+    Interrupt,
+    PendingTrap,
+    Trap,
 };
 
+/// The number of integer registers under RV32E.
+const RV32E_REGISTERS: usize = 16;
+
+/// The instruction alignment in bits without the C extension. With it,
+/// instructions only need to be aligned to 16 bits.
 const IALIGN: u32 = 32;
+#[allow(dead_code)]
 const XLEN: u32 = 32;
 
-//const HALFWORD: u32 = 16;
+const BYTE: u32 = 8;
+const HALFWORD: u32 = 16;
 const WORD: u32 = 32;
 //const DOUBLEWORD: u32 = 64;
 //const QUADWORD: u32 = 128;
 
+/// The outcome of a single step.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum StepResult {
+    /// The instruction executed normally.
+    Continued,
+
+    /// The processor can't continue.
+    Halted(HaltReason),
+
+    /// A trap was raised and the processor vectored to its handler.
+    Trapped(Trap),
+
+    /// An `ebreak` was executed in `EbreakMode::Debugger`. `pc` is left
+    /// pointing at the `ebreak`.
+    Breakpoint,
+
+    /// The processor is waiting for an interrupt, so nothing was
+    /// executed.
+    Idle,
+
+    /// The processor is halted until a software interrupt wakes it, so
+    /// nothing was executed.
+    Stopped,
+}
+
+/// Whether a processor is executing instructions.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum HartState {
+    /// Executing instructions.
+    #[default]
+    Running,
+
+    /// Stopped until woken by a software interrupt, such as a secondary
+    /// hart waiting for the boot hart.
+    Halted,
+
+    /// Stopped by `wfi` until an interrupt enabled in `mie` is pending.
+    WaitingForInterrupt,
+}
+
+/// The privilege level a processor is executing at.
+///
+/// Traps raise it, `mret` and `sret` lower it again, and it limits which
+/// CSRs can be accessed. `ecall` raises the cause for the current mode,
+/// and traps raised below M-mode can be delegated to S-mode.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum PrivilegeMode {
+    /// User mode, for applications.
+    User,
+
+    /// Supervisor mode, for operating systems.
+    Supervisor,
+
+    /// Machine mode, which has full access to the machine.
+    #[default]
+    Machine,
+}
+
+impl PrivilegeMode {
+    /// Returns the mode's encoding, as stored in `mstatus.MPP`.
+    pub fn bits(&self) -> u32 {
+        match self {
+            PrivilegeMode::User         => 0x0,
+            PrivilegeMode::Supervisor   => 0x1,
+            PrivilegeMode::Machine      => 0x3,
+        }
+    }
+
+    /// Returns the mode with the given encoding, as stored in
+    /// `mstatus.MPP`. The reserved encoding `0x2` is treated as M-mode.
+    pub fn from_bits(bits: u32) -> Self {
+        match bits & 0x3 {
+            0x0 => PrivilegeMode::User,
+            0x1 => PrivilegeMode::Supervisor,
+            _ => PrivilegeMode::Machine,
+        }
+    }
+}
+
+/// A processor's architectural context: the state a trap handler or
+/// scheduler saves and restores to switch between threads of execution.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Context {
+    /// The program counter.
+    pub pc: u32,
+
+    /// The privilege level.
+    pub privilege: PrivilegeMode,
+
+    /// The `x` registers, indexed by register number. `x0` is always zero.
+    pub x: [u32; 32],
+
+    /// The address and value of each writable CSR.
+    pub csr: [(usize, u32); csr::READ_WRITE.len()],
+}
+
+/// What executing `ebreak` does.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum EbreakMode {
+    /// Halt the processor with `HaltReason::Ebreak`.
+    #[default]
+    Halt,
+
+    /// Raise a breakpoint trap, vectoring to the handler in `mtvec`.
+    Trap,
+
+    /// Stop with `StepResult::Breakpoint` so an attached debugger can take
+    /// over.
+    Debugger,
+}
+
+/// The opcodes the ISA reserves for custom extensions, `custom-0` to
+/// `custom-3`. Only these can be given custom operations.
+pub const CUSTOM_OPCODES: [u8; 4] = [0x0b, 0x2b, 0x5b, 0x7b];
+
+/// Executes a custom instruction on the processor and bus. The processor
+/// advances to the next instruction if it returns `Ok`, and takes the trap
+/// otherwise.
+pub type CustomOpHandler = Box<dyn FnMut(&Instruction, &mut Processor, &mut Bus) -> Result<(), Trap>>;
+
+/// Whether a memory access reads or writes.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum AccessKind {
+    Read,
+    Write,
+}
+
+/// Observes a load or store, given its address, size in bytes and kind,
+/// before it's made, such as to drive a cache model.
+pub type MemoryAccessHook = Box<dyn FnMut(u32, usize, AccessKind)>;
+
+/// The hook observing loads and stores.
+struct MemoryHook(MemoryAccessHook);
+
+impl core::fmt::Debug for MemoryHook {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("MemoryHook").finish_non_exhaustive()
+    }
+}
+
+/// The custom operations, keyed on `(opcode, funct3, funct7)`.
+#[derive(Default)]
+struct CustomOps(BTreeMap<(u8, u8, u8), CustomOpHandler>);
+
+impl core::fmt::Debug for CustomOps {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_set().entries(self.0.keys()).finish()
+    }
+}
+
 #[derive(Debug)]
 pub struct Processor {
     /// Arithmetic Logic Unit (ALU)
-    /// Responsible for performing arithmetic operations.
-    pub alu: Alu,
+    /// Responsible for performing arithmetic operations. Defaults to `Alu`,
+    /// which wraps on overflow as the ISA specifies.
+    pub alu: Box<dyn AluOps>,
+
+    /// Control and Status Registers (CSRs)
+    /// Registers used for trap handling and machine configuration,
+    /// addressed by their CSR number.
+    pub csr: RegistersCsr,
+
+    /// Cycle Counter
+    /// The number of cycles the processor has run for, including
+    /// cycles spent stalled on slow bus accesses.
+    pub cycle: u64,
+
+    /// The number of cycles each operation adds to `cycle`.
+    pub cost_table: CostTable,
+
+    /// Instructions-Retired Counter
+    /// The number of instructions that have completed execution.
+    pub instret: u64,
+
+    /// What executing `ebreak` does.
+    pub ebreak_mode: EbreakMode,
+
+    /// Whether division and remainder by zero raise `Trap::DivideByZero`
+    /// rather than returning the result the ISA defines, to catch guest
+    /// bugs.
+    pub trap_on_divide_by_zero: bool,
+
+    /// Whether instructions with non-canonical values in reserved fields
+    /// raise `Trap::IllegalInstruction` rather than executing. See
+    /// `Decoder::is_reserved`.
+    pub strict_decode: bool,
+
+    /// Whether the processor is executing instructions.
+    pub state: HartState,
+
+    /// The privilege level the processor is executing at.
+    pub privilege: PrivilegeMode,
+
+    /// The extensions the processor executes. Instructions from other
+    /// extensions raise `Trap::UnsupportedExtension`.
+    pub extensions: Extensions,
 
     /// Program Counter (PC)
     /// Contains the address of the instruction being executed.
@@ -50,6 +303,40 @@ pub struct Processor {
     /// comprised of a zero register and 31 general-purpose
     /// registers.
     pub reg_x: RegistersX,
+
+    /// The trap taken during the most recent step, if any.
+    pending_trap: Option<PendingTrap>,
+
+    /// The log of recent steps, if enabled.
+    event_log: Option<EventLog>,
+
+    /// The binary trace of every step, if enabled.
+    trace: Option<BinaryTrace>,
+
+    /// The histogram of executed operations, if enabled.
+    profile: Option<Profile>,
+
+    /// The cache of fetched instructions, if enabled.
+    decode_cache: Option<DecodeCache>,
+
+    /// The hook observing loads and stores, if set.
+    memory_hook: Option<MemoryHook>,
+
+    /// The history of steps that can be undone, if enabled.
+    undo_log: Option<UndoLog>,
+
+    /// What the current step has overwritten, if undo is enabled.
+    undo_record: Option<UndoRecord>,
+
+    /// The length in bytes of the instruction being executed.
+    instr_len: u32,
+
+    /// The event being recorded for the current step, if the log is
+    /// enabled.
+    event: Option<Event>,
+
+    /// The handlers for instructions in the custom opcode space.
+    custom_ops: CustomOps,
 }
 
 impl Processor {
@@ -57,247 +344,3373 @@ impl Processor {
     pub fn new() -> Self {
         let mut reg_x = RegistersX::new();
 
-        // All general-purpose registers besides the zero register will 
+        // All general-purpose registers besides the zero register will
         // be read/write.
-        for i in 1 .. reg_x.len() - 1 {
+        for i in 1 .. reg_x.len() {
             reg_x.set_access_level(i, AccessLevel::ReadWrite);
         }
 
+        let mut csr = RegistersCsr::new();
+
+        for i in csr::READ_WRITE {
+            csr.set_access_level(i, AccessLevel::ReadWrite);
+        }
+
         Self {
-            alu: Alu::new(),
+            alu: Box::new(Alu::new()),
+            csr,
+            cycle: 0,
+            cost_table: CostTable::default(),
+            instret: 0,
+            ebreak_mode: EbreakMode::default(),
+            strict_decode: false,
+            trap_on_divide_by_zero: false,
+            extensions: Extensions::default(),
             pc: 0x00,
+            state: HartState::default(),
+            privilege: PrivilegeMode::default(),
             reg_x,
+            pending_trap: None,
+            event_log: None,
+            trace: None,
+            profile: None,
+            decode_cache: None,
+            memory_hook: None,
+            undo_log: None,
+            undo_record: None,
+            instr_len: 4,
+            event: None,
+            custom_ops: CustomOps::default(),
         }
     }
 
-    /// Executes an instruction.
-    pub fn execute(&mut self, instr: &Instruction) {
-        match instr.format() {
-            B => self.exec_instr_b(instr),
-            I => self.exec_instr_i(instr),
-            J => self.exec_instr_j(instr),
-            R => self.exec_instr_r(instr),
-            S => self.exec_instr_s(instr),
-            U => self.exec_instr_u(instr),
+    /// Fetches, decodes and executes the instruction at `pc`.
+    ///
+    /// If the instruction raises a trap and a handler is installed in
+    /// `mtvec`, the processor vectors to the handler and the trap is
+    /// returned as `Trapped`. Otherwise, the processor halts. `ebreak` is
+    /// handled according to `ebreak_mode`.
+    ///
+    /// Every step costs the operation's cycles from `cost_table`, or one
+    /// cycle if no operation was decoded, plus any cycles spent stalled on
+    /// bus accesses. `instret` only counts instructions that completed.
+    ///
+    /// A halted processor does nothing and returns `Stopped`. A processor
+    /// waiting for an interrupt spends a cycle idle, unless an interrupt
+    /// enabled in `mie` is pending, in which case it resumes running.
+    pub fn step(&mut self, bus: &mut Bus) -> StepResult {
+        self.pending_trap = None;
+
+        let undo_record = self.undo_log.is_some().then_some(UndoRecord {
+            pc: self.pc,
+            privilege: self.privilege,
+            state: self.state,
+            cycle: self.cycle,
+            instret: self.instret,
+            reg: None,
+            mem: None,
+        });
+
+        match self.state {
+            HartState::Running => (),
+            HartState::Halted => return StepResult::Stopped,
+
+            HartState::WaitingForInterrupt => {
+                if self.csr.read(MIP) & self.csr.read(MIE) == 0 {
+                    self.cycle += 1;
+                    return StepResult::Idle;
+                }
+
+                self.state = HartState::Running;
+            },
         }
-    }
-    
-    /// Executes a B-type instruction.
-    #[inline]
-    fn exec_instr_b(&mut self, instr: &Instruction) {
-        match Decoder::decode(instr) {
-            op @ Some(
-                BranchEqual
-                | BranchGreaterThanOrEqualTo
-                | BranchGreaterThanOrEqualToUnsigned
-                | BranchLessThan
-                | BranchLessThanUnsigned
-                | BranchNotEqual
-            ) => {
-                if let 1 = self.alu.run(
-                    &op.unwrap(),
-                    self.reg_x.read(
-                        instr.rs1().unwrap(),
-                    ) as i32, 
-                    self.reg_x.read(
-                        instr.rs2().unwrap(),
-                    ) as i32,
-                ) {
-                    // TODO:
-                    // The conditional branch instructions will generate an 
-                    // instruction-address-misaligned exception if the
-                    // target address is not aligned to a four-byte boundary
-                    // and the branch condition evaluates to true. If the
-                    // branch condition evaluates to false, the 
-                    // instruction-address-misaligned exception will not be raised.
-        
-                    // NOTE:
-                    // Instruction-address-misaligned exceptions are not possible
-                    // on machines that support extensions with 16-bit aligned 
-                    // instructions, such as the compressed instruction-set
-                    // extension, C.
-        
-                    self.pc = self.pc.wrapping_add_signed(
-                        instr.imm().unwrap(),
-                    );
+
+        if self.event_log.is_some() || self.trace.is_some() {
+            self.event = Some(Event::new(self.pc));
+        }
+
+        self.undo_record = undo_record;
+
+        let mut cost = 1;
+        let mut executed_op = None;
+        let mut executed_nop = false;
+
+        let result = match self.pending_interrupt() {
+            Some(interrupt) => self.take_trap(Trap::Interrupt(interrupt)),
+
+            None => {
+                let executed = self.fetch_cached(bus).and_then(|instr| {
+                    let compressed = match instr.is_compressed() {
+                        true => Some(instr.raw() as u16),
+                        false => None,
+                    };
+
+                    if let Some(event) = self.event.as_mut() {
+                        event.instr = Some(compressed.map_or(instr.raw(), u32::from));
+                    }
+
+                    executed_nop = instr.is_nop();
+
+                    if compressed.is_none() {
+                        if let Some((op, executed)) = self.execute_fast(&instr, bus) {
+                            cost = self.cost_table.cost(&op);
+                            executed_op = Some(op);
+                            return executed;
+                        }
+                    }
+
+                    let op = match compressed {
+                        Some(halfword) => Decoder::decode_compressed(halfword),
+                        None => Decoder::decode(&instr),
+                    };
+
+                    if let Some(op) = op {
+                        cost = self.cost_table.cost(&op);
+                    }
+
+                    executed_op = op;
+
+                    match compressed {
+                        Some(halfword) => self.execute_compressed(halfword, bus),
+                        None => self.execute(&instr, bus),
+                    }
+                });
+
+                match (executed, self.ebreak_mode) {
+                    (Err(Trap::Breakpoint(_)), EbreakMode::Halt) => {
+                        StepResult::Halted(HaltReason::Ebreak)
+                    },
+
+                    (Err(Trap::Breakpoint(_)), EbreakMode::Debugger) => {
+                        StepResult::Breakpoint
+                    },
+
+                    (Err(trap), _) => self.take_trap(trap),
+                    (Ok(()), _) => StepResult::Continued,
                 }
             },
+        };
 
-            _ => self.handle_illegal_instr(instr),
+        if let Some(mut event) = self.event.take() {
+            event.trap = self.pending_trap.map(|pending| pending.trap);
+
+            if let Some(trace) = self.trace.as_mut() {
+                (trace.sink)(&trace.encoder.encode(&event));
+            }
+
+            if let Some(log) = self.event_log.as_mut() {
+                log.push(event);
+            }
+        }
+
+        self.cycle += cost + bus.take_stall_cycles();
+
+        if let StepResult::Continued = result {
+            self.instret += 1;
+
+            match (self.profile.as_mut(), executed_op) {
+                (Some(profile), _) if executed_nop => profile.record_nop(),
+                (Some(profile), Some(op)) => profile.record(op),
+                _ => (),
+            }
+        }
+
+        if let (Some(log), Some(record)) = (self.undo_log.as_mut(), self.undo_record.take()) {
+            log.push(record);
         }
+
+        result
     }
-    
-    /// Executes an I-type instruction.
-    #[inline]
-    fn exec_instr_i(&mut self, instr: &Instruction) {            
-        match Decoder::decode(instr) {  
-            op @ Some(
-                ArithmeticAddImmediate 
-                | LogicalAndImmediate
-                | LogicalExclusiveOrImmediate
-                | LogicalOrImmediate
-                | SetLessThanImmediate
-                | SetLessThanImmediateUnsigned
-                | ShiftLeftLogicalImmediate
-                | ShiftRightArithmeticImmediate
-                | ShiftRightLogicalImmediate
-            ) => {
-                self.reg_x.write(
-                    instr.rd().unwrap(),
-                    self.alu.run(
-                        &op.unwrap(), 
-                        self.reg_x.read(
-                            instr.rs1().unwrap(),
-                        ) as i32,
-                        instr.imm().unwrap(),
-                    ) as u32,
-                );
-            },
 
-            op @ Some(
-                JumpAndLinkRegister,
-            ) => {
-                self.exec_jump(
-                    op.unwrap(),
-                    instr,
-                );
-            },
+    /// Resets the processor's architectural state: `pc` to `entry`, the
+    /// `x` registers and CSRs, the counters and the hart state. Its
+    /// configuration, such as `extensions` and `cost_table`, is kept, and
+    /// its event log, profile, undo history and decode cache are cleared.
+    pub fn reset(&mut self, entry: u32) {
+        self.pc = entry;
+        self.reg_x.reset();
+        self.csr.reset();
+        self.cycle = 0;
+        self.instret = 0;
+        self.state = HartState::Running;
+        self.privilege = PrivilegeMode::Machine;
+        self.pending_trap = None;
 
-            _ => self.handle_illegal_instr(instr),
+        if let Some(log) = self.event_log.as_mut() {
+            log.clear();
+        }
+
+        if let Some(profile) = self.profile.as_mut() {
+            profile.clear();
         }
+
+        if let Some(log) = self.undo_log.as_mut() {
+            log.clear();
+        }
+
+        self.flush_decode_cache();
     }
 
-    /// Executes a J-type instruction.
-    #[inline]
-    fn exec_instr_j(&mut self, instr: &Instruction) {
-        match Decoder::decode(instr) {
-            op @ Some(
-                JumpAndLink,
-            ) => {
-                self.exec_jump(
-                    op.unwrap(),
-                    instr,
-                );
-            },
+    /// Returns the processor's architectural context: `pc`, the privilege
+    /// level, the `x` registers and the writable CSRs.
+    pub fn save_context(&self) -> Context {
+        Context {
+            pc: self.pc,
+            privilege: self.privilege,
+            x: core::array::from_fn(|i| self.reg_x.read(i)),
+            csr: csr::READ_WRITE.map(|addr| (addr, self.csr.read(addr))),
+        }
+    }
 
-            _ => self.handle_illegal_instr(instr),
+    /// Restores an architectural context returned by `save_context`. The
+    /// counters and configuration are left as they are.
+    pub fn restore_context(&mut self, context: &Context) {
+        self.pc = context.pc;
+        self.privilege = context.privilege;
+
+        for (i, &value) in context.x.iter().enumerate().skip(1) {
+            self.reg_x.write_unchecked(i, value);
+        }
+
+        for &(addr, value) in &context.csr {
+            self.csr.write_unchecked(addr, value);
         }
     }
 
-    /// Executes an R-type instruction.
-    #[inline]
-    fn exec_instr_r(&mut self, instr: &Instruction) {
-        match Decoder::decode(instr) {
-            op @ Some(
-                ArithmeticAdd
-                | ArithmeticSub
-                | LogicalAnd
-                | LogicalExclusiveOr
-                | LogicalOr
-                | SetLessThan
-                | SetLessThanUnsigned
-                | ShiftLeftLogical
-                | ShiftRightArithmetic
-                | ShiftRightLogical
-            ) => {
-                self.reg_x.write(
-                    instr.rd().unwrap(),
-                    self.alu.run(
-                        &op.unwrap(), 
-                        self.reg_x.read(
-                            instr.rs1().unwrap(),
-                        ) as i32,
-                        self.reg_x.read(
-                            instr.rs2().unwrap(),
-                        ) as i32,
-                    ) as u32,
-                );
-            },
+    /// Returns the number of instructions retired per cycle, or `None` if
+    /// the processor hasn't run for any cycles.
+    pub fn ipc(&self) -> Option<f64> {
+        match self.cycle {
+            0 => None,
+            cycle => Some(self.instret as f64 / cycle as f64),
+        }
+    }
 
-            _ => self.handle_illegal_instr(instr),
+    /// Starts recording the last `capacity` steps to an event log,
+    /// replacing any existing log.
+    pub fn enable_event_log(&mut self, capacity: usize) {
+        self.event_log = Some(EventLog::new(capacity));
+    }
+
+    /// Returns the event log, if enabled.
+    pub fn event_log(&self) -> Option<&EventLog> {
+        self.event_log.as_ref()
+    }
+
+    /// Starts recording what each step overwrites, so the last `depth`
+    /// steps can be undone with `undo`, replacing any existing history.
+    pub fn enable_undo(&mut self, depth: usize) {
+        self.undo_log = Some(UndoLog::new(depth));
+    }
+
+    /// Undoes the most recent step that hasn't been undone, restoring
+    /// `pc`, the privilege level, the hart state, the counters, and the
+    /// register and memory the step wrote. Returns whether there was a
+    /// step to undo.
+    ///
+    /// CSRs, stores to devices, and the event log, trace and profile
+    /// aren't rolled back. See the `undo` module.
+    pub fn undo(&mut self, bus: &mut Bus) -> bool {
+        let Some(record) = self.undo_log.as_mut().and_then(UndoLog::pop) else {
+            return false;
+        };
+
+        self.pc = record.pc;
+        self.privilege = record.privilege;
+        self.state = record.state;
+        self.cycle = record.cycle;
+        self.instret = record.instret;
+        self.pending_trap = None;
+
+        if let Some((rd, value)) = record.reg {
+            self.reg_x.write(rd, value);
+        }
+
+        if let Some((addr, size, old)) = record.mem {
+            bus.memory.write(addr as usize, &old[.. size]);
+            self.flush_decode_cache();
         }
+
+        true
     }
 
-    /// Executes an S-type instruction.
-    #[inline]
-    fn exec_instr_s(&mut self, instr: &Instruction) {
-        todo!("exec_instr_s not yet implemented.");
+    /// Starts counting the operations the processor executes, replacing
+    /// any existing profile. Only instructions that complete are counted.
+    pub fn enable_profile(&mut self) {
+        self.profile = Some(Profile::new());
     }
 
-    /// Executes a U-type instruction.
-    #[inline]
-    fn exec_instr_u(&mut self, instr: &Instruction) {
-        match Decoder::decode(instr) {
-            op @ Some(
-                AddUpperImmediateProgramCounter
-                | LoadUpperImmediate
-            ) => {
-                let mut addr: u32 = self.alu.run(
-                    &ShiftLeftLogicalImmediate,
-                    instr.imm().unwrap(),
-                    12,
-                ) as u32;
-        
-                if let AddUpperImmediateProgramCounter = op.unwrap() {
-                    addr = self.alu.run(
-                        &ArithmeticAddImmediate,
-                        addr as i32,
-                        self.pc as i32,
-                    ) as u32;
-                }
-        
-                self.reg_x.write(
-                    instr.rd().unwrap(),
-                    addr,
-                );
-            },
+    /// Returns the profile, if enabled.
+    pub fn profile(&self) -> Option<&Profile> {
+        self.profile.as_ref()
+    }
 
-            _ => self.handle_illegal_instr(instr),
+    /// Starts caching fetched instructions, replacing any existing cache.
+    /// `coherence` decides whether the processor's own stores to cached
+    /// instructions are seen before a `fence.i`.
+    ///
+    /// Writes made other than by the processor's stores, such as by other
+    /// harts, aren't seen until the cache is flushed.
+    pub fn enable_decode_cache(&mut self, coherence: CodeCoherence) {
+        self.decode_cache = Some(DecodeCache::new(coherence));
+    }
+
+    /// Invalidates every cached instruction, so code is fetched from the
+    /// bus again.
+    pub fn flush_decode_cache(&mut self) {
+        if let Some(cache) = self.decode_cache.as_mut() {
+            cache.flush();
         }
     }
 
-    fn exec_jump(&mut self, op: Op, instr: &Instruction) {
-        // Write the return address to the destination register.
-        self.reg_x.write(
-            instr.rd().unwrap(),
-            self.pc + 0x04,
+    /// Calls `hook` before every load and store the processor makes,
+    /// replacing any existing hook. Instruction fetches aren't reported,
+    /// and accesses are reported even if they go on to fault.
+    pub fn set_memory_hook(&mut self, hook: MemoryAccessHook) {
+        self.memory_hook = Some(MemoryHook(hook));
+    }
+
+    /// Removes the hook set by `set_memory_hook`, if any.
+    pub fn clear_memory_hook(&mut self) {
+        self.memory_hook = None;
+    }
+
+    /// Starts writing a binary trace record of every step to `sink`,
+    /// replacing any existing trace.
+    pub fn enable_trace(&mut self, sink: TraceSink) {
+        self.trace = Some(BinaryTrace {
+            encoder: TraceEncoder::new(),
+            sink,
+        });
+    }
+
+    /// Registers `handler` to execute the instructions with the given
+    /// opcode, funct3 and funct7 fields, replacing any existing handler.
+    /// Instructions in the custom opcode space without a handler are
+    /// illegal.
+    ///
+    /// Panics if `opcode` isn't one of `CUSTOM_OPCODES`, so standard
+    /// instructions can't be overridden.
+    pub fn register_custom_op<F>(&mut self, opcode: u8, funct3: u8, funct7: u8, handler: F)
+    where
+        F: FnMut(&Instruction, &mut Processor, &mut Bus) -> Result<(), Trap> + 'static,
+    {
+        assert!(
+            CUSTOM_OPCODES.contains(&opcode),
+            "opcode {opcode:#04x} isn't reserved for custom extensions",
         );
 
-        // Calculate the branch target and set the program counter.
-        self.pc = match op {
-            // target = pc + imm
-            JumpAndLink => {
-                self.pc.wrapping_add_signed(
-                    instr.imm().unwrap(),
-                )
+        self.custom_ops.0.insert((opcode, funct3, funct7), Box::new(handler));
+    }
+
+    /// Registers the saturating operations `sadd` and `ssub` of the DSP
+    /// extension, which clamp to the range of `i32` rather than wrapping.
+    /// They're R-type instructions in `custom-0`, with funct3 zero and
+    /// funct7 `0x00` and `0x20` respectively, and are performed by `alu`.
+    pub fn enable_saturating_ops(&mut self) {
+        for (funct7, op) in [(0x00, SaturatingAdd), (0x20, SaturatingSub)] {
+            self.register_custom_op(CUSTOM_OPCODES[0], 0x00, funct7, move |instr, proc, _bus| {
+                let x = proc.reg_x.read(instr.field_rs1()) as i32;
+                let y = proc.reg_x.read(instr.field_rs2()) as i32;
+                let value = proc.alu.run(&op, x, y);
+
+                proc.write_rd(instr.field_rd(), value as u32);
+                Ok(())
+            });
+        }
+    }
+
+    /// Resumes a halted or waiting processor.
+    pub fn wake(&mut self) {
+        self.state = HartState::Running;
+    }
+
+    /// Marks an interrupt as pending by setting its bit in `mip`. It will
+    /// be taken at the start of the next step if it is enabled.
+    pub fn raise_interrupt(&mut self, interrupt: Interrupt) {
+        self.csr.write(MIP, self.csr.read(MIP) | interrupt.mask());
+    }
+
+    /// Clears a pending interrupt's bit in `mip`.
+    pub fn clear_interrupt(&mut self, interrupt: Interrupt) {
+        self.csr.write(MIP, self.csr.read(MIP) & !interrupt.mask());
+    }
+
+    /// Returns the highest-priority interrupt that is both pending and
+    /// enabled, if interrupts are globally enabled. They always are below
+    /// M-mode, and in M-mode only if `mstatus.MIE` is set.
+    fn pending_interrupt(&self) -> Option<Interrupt> {
+        if self.privilege == PrivilegeMode::Machine && self.csr.read(MSTATUS) & MSTATUS_MIE == 0 {
+            return None;
+        }
+
+        let pending = self.csr.read(MIP) & self.csr.read(MIE);
+
+        Interrupt::PRIORITY
+            .into_iter()
+            .find(|interrupt| pending & interrupt.mask() != 0)
+    }
+
+    /// Returns the trap taken during the most recent step, along with the
+    /// address it occurred at, or `None` if the step completed normally.
+    pub fn pending_trap(&self) -> Option<&PendingTrap> {
+        self.pending_trap.as_ref()
+    }
+
+    /// Executes an instruction, advancing the program counter to the
+    /// next instruction or to the target of a taken branch or jump.
+    pub fn execute(&mut self, instr: &Instruction, bus: &mut Bus) -> Result<(), Trap> {
+        if self.strict_decode && Decoder::is_reserved(instr) {
+            return Err(Trap::IllegalInstruction(instr.raw()));
+        }
+
+        if let Some(extension) = Decoder::extension(instr) {
+            if !self.extensions.contains(extension) {
+                return Err(Trap::UnsupportedExtension(extension, instr.raw()));
+            }
+        }
+
+        self.exec_instr(instr, 0x04, bus)
+    }
+
+    /// Executes the most common RV32I instructions, `add`, `addi`, `lw`,
+    /// `sw` and the branches, straight from their opcode and funct fields,
+    /// skipping the format and decode dispatch of `execute`. Returns the
+    /// operation executed and its result, or None if the instruction must
+    /// take the general path.
+    ///
+    /// The effects are identical to executing the same instruction with
+    /// `execute`.
+    #[inline]
+    fn execute_fast(&mut self, instr: &Instruction, bus: &mut Bus) -> Option<(Op, Result<(), Trap>)> {
+        self.instr_len = 0x04;
+
+        // The canonical nop, such as padding, only advances pc. It's
+        // reported as an addi, but profiled as a nop.
+        if instr.is_nop() {
+            self.pc = self.next_pc();
+            return Some((ArithmeticAddImmediate, Ok(())));
+        }
+
+        // RV32E must check the register numbers, which this skips.
+        if self.extensions.contains(Extension::E) {
+            return None;
+        }
+
+        let rs1 = self.reg_x.read(instr.field_rs1());
+
+        let (op, next) = match (instr.opcode(), instr.field_funct3()) {
+            // addi rd, rs1, imm
+            (0x13, 0x0) => {
+                let value = self.alu.run(&ArithmeticAddImmediate, rs1 as i32, instr.imm_i());
+                self.write_rd(instr.field_rd(), value as u32);
+                (ArithmeticAddImmediate, Ok(self.next_pc()))
+            },
+
+            // add rd, rs1, rs2
+            (0x33, 0x0) if instr.field_funct7() == 0x00 => {
+                let rs2 = self.reg_x.read(instr.field_rs2());
+                let value = self.alu.run(&ArithmeticAdd, rs1 as i32, rs2 as i32);
+                self.write_rd(instr.field_rd(), value as u32);
+                (ArithmeticAdd, Ok(self.next_pc()))
+            },
+
+            // lw rd, imm(rs1)
+            (0x03, 0x2) => {
+                let addr = rs1.wrapping_add_signed(instr.imm_i());
+                let next = self.exec_load(LoadWord, addr, bus).map(|value| {
+                    self.write_rd(instr.field_rd(), value);
+                    self.next_pc()
+                });
+
+                (LoadWord, next)
+            },
+
+            // sw rs2, imm(rs1)
+            (0x23, 0x2) => {
+                let addr = rs1.wrapping_add_signed(instr.imm_s());
+                let value = self.reg_x.read(instr.field_rs2());
+
+                self.record_store_for_undo(bus, addr, (WORD / 8) as usize);
+                self.notify_access(addr, (WORD / 8) as usize, AccessKind::Write);
+
+                let next = match bus.write(addr, (WORD / 8) as usize, value as u64) {
+                    Ok(()) => {
+                        if let Some(event) = self.event.as_mut() {
+                            event.mem_write = Some((addr, (WORD / 8) as usize, value));
+                        }
+
+                        if let Some(cache) = self.decode_cache.as_mut() {
+                            cache.store(addr, (WORD / 8) as usize);
+                        }
+
+                        Ok(self.next_pc())
+                    },
+
+                    Err(_) => Err(Trap::StoreAccessFault(addr)),
+                };
+
+                (StoreWord, next)
             },
-            
-            // target = (rs1 + imm) & !1
-            JumpAndLinkRegister => {
-                (
-                    self.reg_x
-                        .read(
-                            instr.rs1().unwrap(),
-                        )
-                        .wrapping_add_signed(
-                            instr.imm().unwrap(),
-                        )
-                ) & !0x01
+
+            // bxx rs1, rs2, imm
+            (0x63, funct3 @ (0x0 | 0x1 | 0x4 | 0x5 | 0x6 | 0x7)) => {
+                let rs2 = self.reg_x.read(instr.field_rs2());
+
+                let op = match funct3 {
+                    0x0 => BranchEqual,
+                    0x1 => BranchNotEqual,
+                    0x4 => BranchLessThan,
+                    0x5 => BranchGreaterThanOrEqualTo,
+                    0x6 => BranchLessThanUnsigned,
+                    _ => BranchGreaterThanOrEqualToUnsigned,
+                };
+
+                match self.alu.run(&op, rs1 as i32, rs2 as i32) == 1 {
+                    true => (op, self.check_target(self.pc.wrapping_add_signed(instr.imm_b()))),
+                    false => (op, Ok(self.next_pc())),
+                }
             },
 
-            _ => self.pc
+            _ => return None,
         };
+
+        Some((op, next.map(|pc| self.pc = pc)))
     }
 
-    /// Fetches and returns the next instruction to execute from memory.
-    pub fn fetch(&self) -> Instruction {
-        todo!();
+    /// Executes a compressed (16-bit) instruction by expanding it to its
+    /// 32-bit equivalent, advancing the program counter by two bytes
+    /// rather than four.
+    pub fn execute_compressed(&mut self, halfword: u16, bus: &mut Bus) -> Result<(), Trap> {
+        // The all-zero halfword is illegal whether or not C is enabled.
+        if halfword != 0 && !self.extensions.contains(Extension::C) {
+            return Err(Trap::UnsupportedExtension(Extension::C, halfword as u32));
+        }
+
+        match Decoder::expand(halfword) {
+            Some(instr) => self.exec_instr(&instr, 0x02, bus),
+            None => Err(Trap::IllegalInstruction(halfword as u32)),
+        }
     }
 
-    /// Handles an illegal instruction by raising an illegal instruction
+    /// Executes a 32-bit instruction that is `len` bytes long in memory.
+    #[inline]
+    fn exec_instr(&mut self, instr: &Instruction, len: u32, bus: &mut Bus) -> Result<(), Trap> {
+        self.instr_len = len;
+
+        if self.extensions.contains(Extension::E)
+            && !Decoder::uses_registers_below(instr, RV32E_REGISTERS)
+        {
+            return Err(Trap::IllegalInstruction(instr.raw()));
+        }
+
+        // Words with an unknown opcode, including the canonical illegal
+        // instructions 0x00000000 and 0xffffffff, have no format.
+        self.pc = match instr.format() {
+            Some(B) => self.exec_instr_b(instr),
+            Some(I) => self.exec_instr_i(instr, bus),
+            Some(J) => self.exec_instr_j(instr),
+            Some(R) => self.exec_instr_r(instr),
+            Some(S) => self.exec_instr_s(instr, bus),
+            Some(U) => self.exec_instr_u(instr),
+            None => self.exec_custom(instr, bus),
+        }?;
+
+        Ok(())
+    }
+
+    /// Executes a B-type instruction.
+    #[inline]
+    fn exec_instr_b(&mut self, instr: &Instruction) -> Result<u32, Trap> {
+        let Some(fields) = instr.as_b() else {
+            return self.handle_illegal_instr(instr);
+        };
+
+        match Decoder::decode(instr) {
+            Some(
+                op @ (
+                    BranchEqual
+                    | BranchGreaterThanOrEqualTo
+                    | BranchGreaterThanOrEqualToUnsigned
+                    | BranchLessThan
+                    | BranchLessThanUnsigned
+                    | BranchNotEqual
+                )
+            ) => {
+                if let 1 = self.alu.run(
+                    &op,
+                    self.reg_x.read(fields.rs1) as i32,
+                    self.reg_x.read(fields.rs2) as i32,
+                ) {
+                    // Only taken branches check their target, so a branch
+                    // to a misaligned address that isn't taken doesn't trap.
+                    return self.check_target(self.pc.wrapping_add_signed(fields.imm));
+                }
+
+                Ok(self.next_pc())
+            },
+
+            _ => self.handle_illegal_instr(instr),
+        }
+    }
+
+    /// Executes an I-type instruction.
+    #[inline]
+    fn exec_instr_i(&mut self, instr: &Instruction, bus: &mut Bus) -> Result<u32, Trap> {
+        let Some(fields) = instr.as_i() else {
+            return self.handle_illegal_instr(instr);
+        };
+
+        match Decoder::decode(instr) {
+            Some(
+                op @ (
+                    ArithmeticAddImmediate
+                    | LogicalAndImmediate
+                    | LogicalExclusiveOrImmediate
+                    | LogicalOrImmediate
+                    | SetLessThanImmediate
+                    | SetLessThanImmediateUnsigned
+                )
+            ) => {
+                let value = self.alu.run(
+                    &op,
+                    self.reg_x.read(fields.rs1) as i32,
+                    fields.imm,
+                );
+
+                self.write_rd(fields.rd, value as u32);
+
+                Ok(self.next_pc())
+            },
+
+            Some(
+                op @ (
+                    ShiftLeftLogicalImmediate
+                    | ShiftRightArithmeticImmediate
+                    | ShiftRightLogicalImmediate
+                )
+            ) => {
+                let Some(shamt) = instr.shamt() else {
+                    return self.handle_illegal_instr(instr);
+                };
+
+                let value = self.alu.run(
+                    &op,
+                    self.reg_x.read(fields.rs1) as i32,
+                    shamt as i32,
+                );
+
+                self.write_rd(fields.rd, value as u32);
+
+                Ok(self.next_pc())
+            },
+
+            Some(
+                op @ (
+                    LoadByte
+                    | LoadByteUnsigned
+                    | LoadHalf
+                    | LoadHalfUnsigned
+                    | LoadWord
+                )
+            ) => {
+                // addr = rs1 + imm
+                let addr = self.reg_x
+                    .read(fields.rs1)
+                    .wrapping_add_signed(fields.imm);
+
+                let value = self.exec_load(op, addr, bus)?;
+
+                self.write_rd(fields.rd, value);
+
+                Ok(self.next_pc())
+            },
+
+            Some(Pause) => {
+                // A hint to reduce energy while spinning, which has no
+                // architectural effect.
+                Ok(self.next_pc())
+            },
+
+            Some(FenceI) => {
+                // Later fetches must see earlier stores to instruction
+                // memory.
+                self.flush_decode_cache();
+                Ok(self.next_pc())
+            },
+
+            Some(SystemEbreak) => Err(Trap::Breakpoint(self.pc)),
+
+            Some(SystemEcall) => Err(match self.privilege {
+                PrivilegeMode::User => Trap::EnvironmentCallFromUMode,
+                PrivilegeMode::Supervisor => Trap::EnvironmentCallFromSMode,
+                PrivilegeMode::Machine => Trap::EnvironmentCallFromMMode,
+            }),
+
+            Some(
+                op @ (
+                    CsrReadClear
+                    | CsrReadSet
+                    | CsrReadWrite
+                )
+            ) => {
+                // csrrs and csrrc with rs1 = x0 only read the CSR.
+                let writes = op == CsrReadWrite || fields.rs1 != 0;
+                let source = self.reg_x.read(fields.rs1);
+
+                self.exec_csr(instr, op, fields.rd, source, writes)
+            },
+
+            Some(
+                op @ (
+                    CsrReadClearImmediate
+                    | CsrReadSetImmediate
+                    | CsrReadWriteImmediate
+                )
+            ) => {
+                // The rs1 field holds a zero-extended 5-bit immediate
+                // rather than a register number. csrrsi and csrrci with a
+                // zero immediate only read the CSR.
+                let uimm = fields.rs1 as u32;
+                let writes = op == CsrReadWriteImmediate || uimm != 0;
+
+                self.exec_csr(instr, op, fields.rd, uimm, writes)
+            },
+
+            Some(SystemWaitForInterrupt) => {
+                self.state = HartState::WaitingForInterrupt;
+                Ok(self.next_pc())
+            },
+
+            Some(SystemMachineReturn) => self.exec_mret(instr),
+            Some(SystemSupervisorReturn) => self.exec_sret(instr),
+
+            Some(JumpAndLinkRegister) => {
+                // target = (rs1 + imm) & !1
+                let target = self.check_target(
+                    self.reg_x.read(fields.rs1).wrapping_add_signed(fields.imm) & !0x01,
+                )?;
+
+                Ok(self.exec_jump(fields.rd, target))
+            },
+
+            _ => self.handle_illegal_instr(instr),
+        }
+    }
+
+    /// Executes a J-type instruction.
+    #[inline]
+    fn exec_instr_j(&mut self, instr: &Instruction) -> Result<u32, Trap> {
+        let Some(fields) = instr.as_j() else {
+            return self.handle_illegal_instr(instr);
+        };
+
+        match Decoder::decode(instr) {
+            Some(JumpAndLink) => {
+                // target = pc + imm
+                let target = self.check_target(self.pc.wrapping_add_signed(fields.imm))?;
+
+                Ok(self.exec_jump(fields.rd, target))
+            },
+
+            _ => self.handle_illegal_instr(instr),
+        }
+    }
+
+    /// Executes an R-type instruction.
+    #[inline]
+    fn exec_instr_r(&mut self, instr: &Instruction) -> Result<u32, Trap> {
+        let Some(fields) = instr.as_r() else {
+            return self.handle_illegal_instr(instr);
+        };
+
+        match Decoder::decode(instr) {
+            Some(
+                op @ (
+                    ArithmeticAdd
+                    | ArithmeticDiv
+                    | ArithmeticDivUnsigned
+                    | ArithmeticMul
+                    | ArithmeticMulHigh
+                    | ArithmeticMulHighSignedUnsigned
+                    | ArithmeticMulHighUnsigned
+                    | ArithmeticRem
+                    | ArithmeticRemUnsigned
+                    | ArithmeticSub
+                    | LogicalAnd
+                    | LogicalExclusiveOr
+                    | LogicalOr
+                    | SetLessThan
+                    | SetLessThanUnsigned
+                    | ShiftLeftLogical
+                    | ShiftRightArithmetic
+                    | ShiftRightLogical
+                )
+            ) => {
+                let divisor = self.reg_x.read(fields.rs2);
+
+                let divides = matches!(
+                    op,
+                    ArithmeticDiv
+                        | ArithmeticDivUnsigned
+                        | ArithmeticRem
+                        | ArithmeticRemUnsigned
+                );
+
+                if self.trap_on_divide_by_zero && divides && divisor == 0 {
+                    return Err(Trap::DivideByZero(instr.raw()));
+                }
+
+                // Register shifts shift by the unsigned `rs2[4:0]` only.
+                let operand = match op {
+                    ShiftLeftLogical
+                        | ShiftRightArithmetic
+                        | ShiftRightLogical => divisor & 0x1f,
+
+                    _ => divisor,
+                };
+
+                let value = self.alu.run(
+                    &op,
+                    self.reg_x.read(fields.rs1) as i32,
+                    operand as i32,
+                );
+
+                self.write_rd(fields.rd, value as u32);
+
+                Ok(self.next_pc())
+            },
+
+            _ => self.handle_illegal_instr(instr),
+        }
+    }
+
+    /// Executes an S-type instruction.
+    #[inline]
+    fn exec_instr_s(&mut self, instr: &Instruction, bus: &mut Bus) -> Result<u32, Trap> {
+        let Some(fields) = instr.as_s() else {
+            return self.handle_illegal_instr(instr);
+        };
+
+        match Decoder::decode(instr) {
+            Some(
+                op @ (
+                    StoreByte
+                    | StoreHalf
+                    | StoreWord
+                )
+            ) => {
+                let size = match op {
+                    StoreByte => BYTE,
+                    StoreHalf => HALFWORD,
+                    _ => WORD,
+                } / 8;
+
+                // addr = rs1 + imm
+                let addr = self.reg_x
+                    .read(fields.rs1)
+                    .wrapping_add_signed(fields.imm);
+
+                let value = self.reg_x.read(fields.rs2);
+
+                self.record_store_for_undo(bus, addr, size as usize);
+                self.notify_access(addr, size as usize, AccessKind::Write);
+
+                bus
+                    .write(addr, size as usize, value as u64)
+                    .map_err(|_| Trap::StoreAccessFault(addr))?;
+
+                if let Some(event) = self.event.as_mut() {
+                    event.mem_write = Some((addr, size as usize, value));
+                }
+
+                if let Some(cache) = self.decode_cache.as_mut() {
+                    cache.store(addr, size as usize);
+                }
+
+                Ok(self.next_pc())
+            },
+
+            _ => self.handle_illegal_instr(instr),
+        }
+    }
+
+    /// Executes a U-type instruction.
+    #[inline]
+    fn exec_instr_u(&mut self, instr: &Instruction) -> Result<u32, Trap> {
+        let Some(fields) = instr.as_u() else {
+            return self.handle_illegal_instr(instr);
+        };
+
+        match Decoder::decode(instr) {
+            Some(
+                op @ (
+                    AddUpperImmediateProgramCounter
+                    | LoadUpperImmediate
+                )
+            ) => {
+                let mut addr: u32 = self.alu.run(
+                    &ShiftLeftLogicalImmediate,
+                    fields.imm,
+                    12,
+                ) as u32;
+
+                if let AddUpperImmediateProgramCounter = op {
+                    addr = self.alu.run(
+                        &ArithmeticAddImmediate,
+                        addr as i32,
+                        self.pc as i32,
+                    ) as u32;
+                }
+
+                self.write_rd(fields.rd, addr);
+
+                Ok(self.next_pc())
+            },
+
+            _ => self.handle_illegal_instr(instr),
+        }
+    }
+
+    /// Executes a CSR instruction on the CSR addressed by the instruction,
+    /// with `source` as the value to write, set or clear. The CSR's old
+    /// value is written to `rd`.
+    ///
+    /// Both happen as one atomic step, so `csrrw sp, mscratch, sp` swaps
+    /// `sp` and `mscratch`. If `writes` is false the CSR is only read.
+    /// Accessing an unimplemented CSR, or writing a read-only one, is an
+    /// illegal instruction, except for `misa`, whose writes are ignored, as
+    /// is accessing a CSR of a higher privilege level than the current one.
+    fn exec_csr(
+        &mut self,
+        instr: &Instruction,
+        op: Op,
+        rd: usize,
+        source: u32,
+        writes: bool,
+    ) -> Result<u32, Trap> {
+        let addr = (instr.raw() >> 20) as usize;
+
+        if !csr::is_implemented(addr)
+            || self.privilege.bits() < csr::min_privilege(addr)
+            || (writes && addr != MISA && self.csr.is_read_only(addr))
+        {
+            return self.handle_illegal_instr(instr);
+        }
+
+        let old = match addr {
+            MISA => MISA_MXL_32 | self.extensions.misa_bits(),
+            _ => self.csr.read(addr),
+        };
+
+        if writes {
+            self.csr.write(addr, match op {
+                CsrReadClear | CsrReadClearImmediate => old & !source,
+                CsrReadSet | CsrReadSetImmediate => old | source,
+                _ => source,
+            });
+        }
+
+        self.write_rd(rd, old);
+
+        Ok(self.next_pc())
+    }
+
+    /// Executes `mret`, returning from an M-mode trap handler to `mepc` in
+    /// the mode saved in `mstatus.MPP`. `MIE` is restored from `MPIE`,
+    /// `MPIE` is set, and `MPP` is reset to U-mode. Illegal below M-mode.
+    fn exec_mret(&mut self, instr: &Instruction) -> Result<u32, Trap> {
+        if self.privilege != PrivilegeMode::Machine {
+            return self.handle_illegal_instr(instr);
+        }
+
+        let mstatus = self.csr.read(MSTATUS);
+        let mie = if mstatus & MSTATUS_MPIE != 0 { MSTATUS_MIE } else { 0 };
+
+        self.csr.write(
+            MSTATUS,
+            (mstatus & !(MSTATUS_MIE | MSTATUS_MPP)) | mie | MSTATUS_MPIE,
+        );
+        self.privilege = PrivilegeMode::from_bits(
+            (mstatus & MSTATUS_MPP) >> MSTATUS_MPP.trailing_zeros(),
+        );
+
+        Ok(self.trap_return_target(MEPC))
+    }
+
+    /// Executes `sret`, returning from an S-mode trap handler to `sepc` in
+    /// S-mode if `mstatus.SPP` is set and U-mode otherwise. `SIE` is
+    /// restored from `SPIE`, `SPIE` is set, and `SPP` is cleared. Illegal
+    /// in U-mode.
+    fn exec_sret(&mut self, instr: &Instruction) -> Result<u32, Trap> {
+        if self.privilege == PrivilegeMode::User {
+            return self.handle_illegal_instr(instr);
+        }
+
+        let mstatus = self.csr.read(MSTATUS);
+        let sie = if mstatus & MSTATUS_SPIE != 0 { MSTATUS_SIE } else { 0 };
+
+        self.csr.write(
+            MSTATUS,
+            (mstatus & !(MSTATUS_SIE | MSTATUS_SPP)) | sie | MSTATUS_SPIE,
+        );
+        self.privilege = match mstatus & MSTATUS_SPP != 0 {
+            true => PrivilegeMode::Supervisor,
+            false => PrivilegeMode::User,
+        };
+
+        Ok(self.trap_return_target(SEPC))
+    }
+
+    /// Returns the address held in `mepc` or `sepc`, whose low bits below
+    /// `IALIGN` always read as zero.
+    fn trap_return_target(&self, epc: usize) -> u32 {
+        self.csr.read(epc) & !(self.ialign() / 8 - 1)
+    }
+
+    /// Executes a load from the given address, returning the loaded value
+    /// extended to the register width.
+    fn exec_load(&mut self, op: Op, addr: u32, bus: &mut Bus) -> Result<u32, Trap> {
+        let mut read = |size: u32| {
+            self.notify_access(addr, (size / 8) as usize, AccessKind::Read);
+
+            bus
+                .try_read(addr, (size / 8) as usize)
+                .map_err(|_| Trap::LoadAccessFault(addr))
+        };
+
+        Ok(match op {
+            LoadByte => read(BYTE)? as i8 as u32,
+            LoadByteUnsigned => read(BYTE)? as u8 as u32,
+            LoadHalf => read(HALFWORD)? as i16 as u32,
+            LoadHalfUnsigned => read(HALFWORD)? as u16 as u32,
+            _ => read(WORD)? as u32,
+        })
+    }
+
+    /// Writes the result of an instruction to its destination register.
+    ///
+    /// Writes to `x0` are dropped, so instructions can target it to discard
+    /// their result. Every execute path writes `rd` through this.
+    #[inline]
+    fn write_rd(&mut self, rd: usize, value: u32) {
+        if rd != 0 {
+            if let Some(record) = self.undo_record.as_mut() {
+                if record.reg.is_none() {
+                    record.reg = Some((rd, self.reg_x.read(rd)));
+                }
+            }
+
+            self.reg_x.write(rd, value);
+
+            if let Some(event) = self.event.as_mut() {
+                event.reg_write = Some((rd, value));
+            }
+        }
+    }
+
+    /// Reports a load or store to the memory hook, if one is set.
+    #[inline]
+    fn notify_access(&mut self, addr: u32, size: usize, kind: AccessKind) {
+        if let Some(MemoryHook(hook)) = self.memory_hook.as_mut() {
+            hook(addr, size, kind);
+        }
+    }
+
+    /// Records the memory a store is about to overwrite, if undo is
+    /// enabled and the store is to main memory.
+    fn record_store_for_undo(&mut self, bus: &Bus, addr: u32, size: usize) {
+        if let Some(record) = self.undo_record.as_mut() {
+            if bus.is_memory(addr) {
+                let mut old = [0; 4];
+                old[.. size].copy_from_slice(&bus.memory.read(addr as usize, size));
+                record.mem = Some((addr, size, old));
+            }
+        }
+    }
+
+    /// Executes a jump by writing the return address to `rd`, returning
+    /// the jump target.
+    ///
+    /// The target must be calculated before calling this, in case `rd` is
+    /// also the source register.
+    fn exec_jump(&mut self, rd: usize, target: u32) -> u32 {
+        self.write_rd(rd, self.next_pc());
+        target
+    }
+
+    /// Returns `target` if it's aligned to `IALIGN`. Otherwise, the jump or
+    /// taken branch to it raises `Trap::InstructionAddressMisaligned`, so
+    /// the trap is reported at the jump, before `rd` is written, rather
+    /// than at the fetch from the target.
+    fn check_target(&self, target: u32) -> Result<u32, Trap> {
+        match target.is_multiple_of(self.ialign() / 8) {
+            true => Ok(target),
+            false => Err(Trap::InstructionAddressMisaligned(target)),
+        }
+    }
+
+    /// Returns the address of the instruction after the one being
+    /// executed.
+    ///
+    /// This is `Instruction::next_pc` for the fetched instruction, but uses
+    /// the length recorded when it was executed, as compressed instructions
+    /// are executed as their 32-bit expansions.
+    #[inline]
+    fn next_pc(&self) -> u32 {
+        self.pc.wrapping_add(self.instr_len)
+    }
+
+    /// Returns the number of integer registers, which is 16 under RV32E
+    /// and 32 otherwise.
+    pub fn register_count(&self) -> usize {
+        match self.extensions.contains(Extension::E) {
+            true => RV32E_REGISTERS,
+            false => self.reg_x.len(),
+        }
+    }
+
+    /// Sets `pc`, such as to a reset vector, or returns
+    /// `Trap::InstructionAddressMisaligned` without changing it if `pc`
+    /// isn't aligned to `IALIGN`.
+    pub fn set_pc(&mut self, pc: u32) -> Result<(), Trap> {
+        self.pc = self.check_target(pc)?;
+        Ok(())
+    }
+
+    /// Returns the alignment of instructions in bits, `IALIGN`, which is 32
+    /// unless the C extension is enabled, in which case it's 16.
+    pub fn ialign(&self) -> u32 {
+        match self.extensions.contains(Extension::C) {
+            true => HALFWORD,
+            false => IALIGN,
+        }
+    }
+
+    /// Fetches and returns the next instruction to execute from memory.
+    ///
+    /// Fetching from a `pc` that isn't aligned to `IALIGN` raises
+    /// `Trap::InstructionAddressMisaligned`.
+    pub fn fetch(&self, bus: &mut Bus) -> Result<Instruction, Trap> {
+        if !self.pc.is_multiple_of(self.ialign() / 8) {
+            return Err(Trap::InstructionAddressMisaligned(self.pc));
+        }
+
+        // Instructions are little-endian whatever the byte order of data.
+        let big_endian = bus.endianness == Endianness::Big;
+
+        // A compressed instruction at the end of memory is followed by
+        // an unmapped halfword, which doesn't belong to it.
+        let raw = match bus.try_read(self.pc, (WORD / 8) as usize) {
+            Ok(raw) if big_endian => (raw as u32).swap_bytes(),
+            Ok(raw) => raw as u32,
+
+            Err(_) => bus
+                .try_read(self.pc, (HALFWORD / 8) as usize)
+                .ok()
+                .map(|halfword| match big_endian {
+                    true => (halfword as u16).swap_bytes() as u32,
+                    false => halfword as u32,
+                })
+                .filter(|&halfword| Instruction::new(halfword).is_compressed())
+                .ok_or(Trap::InstructionAccessFault(self.pc))?,
+        };
+
+        Ok(Instruction::new(raw))
+    }
+
+    /// Fetches the instruction at `pc` like `fetch`, but from the decode
+    /// cache if it's enabled and holds the instruction.
+    fn fetch_cached(&mut self, bus: &mut Bus) -> Result<Instruction, Trap> {
+        if let Some(instr) = self.decode_cache.as_ref().and_then(|cache| cache.get(self.pc)) {
+            return Ok(instr);
+        }
+
+        let instr = self.fetch(bus)?;
+
+        if let Some(cache) = self.decode_cache.as_mut() {
+            cache.insert(self.pc, instr);
+        }
+
+        Ok(instr)
+    }
+
+    /// Fetches and decodes the instruction at `addr` in memory without
+    /// executing it, returning the instruction, its operation if it can be
+    /// decoded, and its length in bytes.
+    ///
+    /// Compressed (16-bit) encodings are returned as the raw halfword with
+    /// a length of two, along with the operation they expand to.
+    pub fn decode_at(memory: &Memory, addr: u32) -> (Instruction, Option<Op>, u32) {
+        let bytes = memory.read(addr as usize, 4);
+
+        let instr = Instruction::new(
+            u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+        );
+
+        if instr.is_compressed() {
+            let halfword = instr.raw() as u16;
+            let instr = Instruction::new(halfword as u32);
+            return (instr, Decoder::decode_compressed(halfword), instr.byte_len());
+        }
+
+        (instr, Decoder::decode(&instr), instr.byte_len())
+    }
+
+    /// Takes a trap by recording the cause in the machine trap CSRs and
+    /// vectoring to the handler in `mtvec`. If no handler is installed,
+    /// the trap is returned to the caller instead.
+    ///
+    /// A trap raised in S-mode or U-mode whose bit is set in `medeleg`, or
+    /// `mideleg` for interrupts, is delegated: it's recorded in the
+    /// supervisor trap CSRs instead, and vectors to the handler in `stvec`
+    /// in S-mode.
+    fn take_trap(&mut self, trap: Trap) -> StepResult {
+        let delegated = self.privilege != PrivilegeMode::Machine && {
+            let (deleg, code) = match trap {
+                Trap::Interrupt(interrupt) => (MIDELEG, interrupt.code()),
+                _ => (MEDELEG, trap.cause()),
+            };
+
+            self.csr.read(deleg) & 1 << code != 0
+        };
+
+        let (tvec, epc, cause, tval) = match delegated {
+            true => (STVEC, SEPC, SCAUSE, STVAL),
+            false => (MTVEC, MEPC, MCAUSE, MTVAL),
+        };
+
+        let tvec = self.csr.read(tvec);
+        let base = tvec & !MTVEC_MODE;
+
+        let handler = match trap {
+            // Interrupts jump to BASE + 4 * cause in vectored mode.
+            Trap::Interrupt(interrupt)
+                if tvec & MTVEC_MODE == MTVEC_MODE_VECTORED =>
+            {
+                base.wrapping_add(4 * interrupt.code())
+            },
+
+            _ => base,
+        };
+
+        self.pending_trap = Some(PendingTrap {
+            trap,
+            pc: self.pc,
+            handler: (base != 0).then_some(handler),
+        });
+
+        if base == 0 {
+            return StepResult::Halted(HaltReason::UnhandledTrap(trap));
+        }
+
+        let mstatus = self.csr.read(MSTATUS);
+
+        match delegated {
+            // Save SIE to SPIE and the previous mode to SPP, and disable
+            // S-mode interrupts.
+            true => {
+                let spie = if mstatus & MSTATUS_SIE != 0 { MSTATUS_SPIE } else { 0 };
+                let spp = match self.privilege {
+                    PrivilegeMode::Supervisor => MSTATUS_SPP,
+                    _ => 0,
+                };
+
+                self.csr.write(
+                    MSTATUS,
+                    (mstatus & !(MSTATUS_SIE | MSTATUS_SPIE | MSTATUS_SPP)) | spie | spp,
+                );
+                self.privilege = PrivilegeMode::Supervisor;
+            },
+
+            // Save MIE to MPIE and the previous mode to MPP, and disable
+            // interrupts.
+            false => {
+                let mpie = if mstatus & MSTATUS_MIE != 0 { MSTATUS_MPIE } else { 0 };
+                let mpp = self.privilege.bits() << MSTATUS_MPP.trailing_zeros();
+
+                self.csr.write(
+                    MSTATUS,
+                    (mstatus & !(MSTATUS_MIE | MSTATUS_MPIE | MSTATUS_MPP)) | mpie | mpp,
+                );
+                self.privilege = PrivilegeMode::Machine;
+            },
+        }
+
+        self.csr.write(epc, self.pc);
+        self.csr.write(cause, trap.cause());
+        self.csr.write(tval, trap.value());
+        self.pc = handler;
+
+        StepResult::Trapped(trap)
+    }
+
+    /// Executes an instruction without a standard format using its custom
+    /// operation handler, if one is registered.
+    fn exec_custom(&mut self, instr: &Instruction, bus: &mut Bus) -> Result<u32, Trap> {
+        let key = (instr.opcode(), instr.field_funct3(), instr.field_funct7());
+
+        // The handler is taken out while it runs, as it borrows the
+        // processor mutably.
+        let Some(mut handler) = self.custom_ops.0.remove(&key) else {
+            return self.handle_illegal_instr(instr);
+        };
+
+        let result = handler(instr, self, bus);
+        self.custom_ops.0.entry(key).or_insert(handler);
+        result?;
+
+        Ok(self.next_pc())
+    }
+
+    /// Handles an illegal instruction by raising an illegal instruction
     /// exception.
     #[cold]
-    fn handle_illegal_instr(&self, instr: &Instruction) {
-        todo!();
+    fn handle_illegal_instr(&self, instr: &Instruction) -> Result<u32, Trap> {
+        Err(Trap::IllegalInstruction(instr.raw()))
+    }
+}
+
+/// Dumps the machine state: `pc`, the `instret` and `cycle` counters, and
+/// the `x` registers in two columns, labelled with their ABI names.
+impl Display for Processor {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        writeln!(f, "pc       {:#010x} / {}", self.pc, self.pc)?;
+        writeln!(f, "instret  {}", self.instret)?;
+        writeln!(f, "cycle    {}", self.cycle)?;
+        writeln!(f)?;
+
+        let half = self.reg_x.len() / 2;
+
+        for i in 0 .. half {
+            writeln!(
+                f,
+                "x{:<2} {:<4}  ( 0x{:08x} )    x{:<2} {:<4}  ( 0x{:08x} )",
+                i,
+                ABI_NAMES[i],
+                self.reg_x.read(i),
+                i + half,
+                ABI_NAMES[i + half],
+                self.reg_x.read(i + half),
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Default for Processor {
+    fn default() -> Self {
+        Processor::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::memory::Memory;
+
+    use super::{
+        AccessKind,
+        Bus,
+        EbreakMode,
+        HaltReason,
+        HartState,
+        Instruction,
+        Interrupt,
+        PrivilegeMode,
+        Processor,
+        StepResult,
+        Trap,
+        MCAUSE,
+        MEPC,
+        MIE,
+        MIP,
+        MSTATUS,
+        MSTATUS_MIE,
+        MSTATUS_MPIE,
+        MSTATUS_MPP,
+        MTVAL,
+        MTVEC,
+    };
+
+    /// Creates a processor and a memory containing the given instructions,
+    /// starting at address zero.
+    fn setup(instrs: &[u32]) -> (Processor, Bus) {
+        let mut mem = Memory::new(1024);
+
+        for (i, instr) in instrs.iter().enumerate() {
+            mem.write(i * 4, &instr.to_le_bytes());
+        }
+
+        (Processor::new(), Bus::new(mem))
+    }
+
+    mod step {
+        use super::*;
+
+        #[test]
+        fn advances_pc_to_next_instruction() {
+            // addi x1, x0, 5
+            let (mut proc, mut bus) = setup(&[0x00500093]);
+            assert_eq!(proc.step(&mut bus), StepResult::Continued);
+            assert_eq!(proc.pc, 0x04);
+            assert_eq!(proc.reg_x.read(1), 5);
+        }
+
+        #[test]
+        fn jumps_to_branch_target() {
+            // jal x1, 16
+            let (mut proc, mut bus) = setup(&[0x010000ef]);
+            assert_eq!(proc.step(&mut bus), StepResult::Continued);
+            assert_eq!(proc.pc, 0x10);
+            assert_eq!(proc.reg_x.read(1), 0x04);
+        }
+
+        #[test]
+        fn discards_writes_to_x0() {
+            let (mut proc, mut bus) = setup(&[
+                0x00500093, // addi x1, x0, 5
+                0x00700113, // addi x2, x0, 7
+                0x00208033, // add x0, x1, x2
+            ]);
+
+            for _ in 0 .. 3 {
+                assert_eq!(proc.step(&mut bus), StepResult::Continued);
+            }
+
+            assert_eq!(proc.reg_x.read(0), 0);
+        }
+
+        #[test]
+        fn writes_x31() {
+            // addi x31, x0, 9
+            let (mut proc, mut bus) = setup(&[0x00900f93]);
+            assert_eq!(proc.step(&mut bus), StepResult::Continued);
+            assert_eq!(proc.reg_x.read(31), 9);
+        }
+
+        #[test]
+        fn shifts_right_arithmetic_by_shamt() {
+            let (mut proc, mut bus) = setup(&[
+                0xf0000093, // addi x1, x0, -256
+                0x4040d113, // srai x2, x1, 4
+            ]);
+
+            assert_eq!(proc.step(&mut bus), StepResult::Continued);
+            assert_eq!(proc.step(&mut bus), StepResult::Continued);
+            assert_eq!(proc.reg_x.read(2) as i32, -16);
+        }
+
+        #[test]
+        fn treats_pause_as_no_op() {
+            // pause
+            let (mut proc, mut bus) = setup(&[0x0100000f]);
+            assert_eq!(proc.step(&mut bus), StepResult::Continued);
+            assert_eq!(proc.pc, 0x04);
+        }
+
+        #[test]
+        fn returns_trap_when_no_handler_is_installed() {
+            // add x0, x0, x0 with a reserved funct7 (unsupported)
+            let (mut proc, mut bus) = setup(&[0x80000033]);
+            assert_eq!(
+                proc.step(&mut bus),
+                StepResult::Halted(
+                    HaltReason::UnhandledTrap(Trap::IllegalInstruction(0x80000033)),
+                ),
+            );
+        }
+
+        #[test]
+        fn all_zero_word_is_illegal() {
+            let (mut proc, mut bus) = setup(&[0x00000000]);
+            assert_eq!(
+                proc.step(&mut bus),
+                StepResult::Halted(
+                    HaltReason::UnhandledTrap(Trap::IllegalInstruction(0x00000000)),
+                ),
+            );
+        }
+
+        #[test]
+        fn all_ones_word_is_illegal() {
+            let (mut proc, mut bus) = setup(&[0xffffffff]);
+            proc.csr.write(MTVEC, 0x100);
+            assert_eq!(
+                proc.step(&mut bus),
+                StepResult::Trapped(Trap::IllegalInstruction(0xffffffff)),
+            );
+            assert_eq!(proc.csr.read(MTVAL), 0xffffffff);
+        }
+
+        #[test]
+        fn vectors_to_handler_on_trap() {
+            let (mut proc, mut bus) = setup(&[0x00000013, 0x80000033]);
+            proc.csr.write(MTVEC, 0x100);
+            assert_eq!(proc.step(&mut bus), StepResult::Continued);
+            assert_eq!(
+                proc.step(&mut bus),
+                StepResult::Trapped(Trap::IllegalInstruction(0x80000033)),
+            );
+            assert_eq!(proc.pc, 0x100);
+            assert_eq!(proc.csr.read(MEPC), 0x04);
+            assert_eq!(proc.csr.read(MCAUSE), 2);
+        }
+
+        #[test]
+        fn strict_decode_rejects_reserved_fields() {
+            // slli x1, x1, 32
+            let (mut proc, mut bus) = setup(&[0x02009093]);
+            assert_eq!(proc.step(&mut bus), StepResult::Continued);
+
+            let (mut proc, mut bus) = setup(&[0x02009093]);
+            proc.strict_decode = true;
+            assert_eq!(
+                proc.step(&mut bus),
+                StepResult::Halted(
+                    HaltReason::UnhandledTrap(Trap::IllegalInstruction(0x02009093)),
+                ),
+            );
+        }
+    }
+
+    mod fetch {
+        use crate::isa::{Extension, Extensions};
+
+        use super::*;
+
+        #[test]
+        fn misaligned_pc_faults_under_ialign_32() {
+            let (mut proc, mut bus) = setup(&[0x00000013, 0x00000013]);
+            proc.pc = 0x02;
+
+            assert_eq!(proc.ialign(), 32);
+            assert_eq!(proc.fetch(&mut bus), Err(Trap::InstructionAddressMisaligned(0x02)));
+        }
+
+        #[test]
+        fn halfword_aligned_pc_is_fetched_under_ialign_16() {
+            let (mut proc, mut bus) = setup(&[0x00000013, 0x00000013]);
+            proc.extensions = Extensions::RV32IM.with(Extension::C);
+            proc.pc = 0x02;
+
+            assert_eq!(proc.ialign(), 16);
+            assert_eq!(proc.fetch(&mut bus).map(|instr| instr.raw()), Ok(0x00130000));
+        }
+
+        #[test]
+        fn set_pc_rejects_misaligned_address() {
+            let (mut proc, _) = setup(&[]);
+
+            assert_eq!(proc.set_pc(0x101), Err(Trap::InstructionAddressMisaligned(0x101)));
+            assert_eq!(proc.set_pc(0x102), Err(Trap::InstructionAddressMisaligned(0x102)));
+            assert_eq!(proc.pc, 0x00);
+
+            proc.extensions = Extensions::RV32IM.with(Extension::C);
+            assert_eq!(proc.set_pc(0x102), Ok(()));
+            assert_eq!(proc.pc, 0x102);
+        }
+
+        #[test]
+        fn misaligned_jump_traps_without_linking() {
+            // jal x1, 2
+            let (mut proc, mut bus) = setup(&[0x002000ef]);
+            proc.csr.write(MTVEC, 0x100);
+
+            assert_eq!(
+                proc.step(&mut bus),
+                StepResult::Trapped(Trap::InstructionAddressMisaligned(0x02)),
+            );
+            assert_eq!(proc.csr.read(MEPC), 0x00);
+            assert_eq!(proc.reg_x.read(1), 0);
+        }
+
+        #[test]
+        fn jalr_clears_low_bit_before_checking_target() {
+            // jalr x1, 3(x0)
+            let (mut proc, mut bus) = setup(&[0x003000e7]);
+            proc.csr.write(MTVEC, 0x100);
+
+            assert_eq!(
+                proc.step(&mut bus),
+                StepResult::Trapped(Trap::InstructionAddressMisaligned(0x02)),
+            );
+            assert_eq!(proc.csr.read(MEPC), 0x00);
+            assert_eq!(proc.csr.read(MTVAL), 0x02);
+            assert_eq!(proc.reg_x.read(1), 0);
+        }
+
+        #[test]
+        fn misaligned_taken_branch_traps() {
+            let (mut proc, mut bus) = setup(&[
+                0x00000163, // beq x0, x0, 2
+                0x00101163, // bne x0, x1, 2
+            ]);
+            proc.csr.write(MTVEC, 0x100);
+            proc.pc = 0x04;
+
+            // Not taken, so the target isn't checked.
+            assert_eq!(proc.step(&mut bus), StepResult::Continued);
+
+            proc.pc = 0x00;
+            assert_eq!(
+                proc.step(&mut bus),
+                StepResult::Trapped(Trap::InstructionAddressMisaligned(0x02)),
+            );
+            assert_eq!(proc.csr.read(MEPC), 0x00);
+        }
+
+        #[test]
+        fn step_traps_on_misaligned_fetch() {
+            let (mut proc, mut bus) = setup(&[0x00000013, 0x00000013]);
+            proc.csr.write(MTVEC, 0x100);
+            proc.pc = 0x02;
+
+            assert_eq!(
+                proc.step(&mut bus),
+                StepResult::Trapped(Trap::InstructionAddressMisaligned(0x02)),
+            );
+            assert_eq!(proc.csr.read(MTVAL), 0x02);
+        }
+    }
+
+    mod display {
+        use super::*;
+
+        #[test]
+        fn dumps_pc_counters_and_registers() {
+            // addi x1, x0, 5
+            let (mut proc, mut bus) = setup(&[0x00500093]);
+            proc.reg_x.write(31, 0xdeadbeef);
+            proc.step(&mut bus);
+
+            let dump = proc.to_string();
+            let lines: Vec<&str> = dump.lines().collect();
+
+            assert_eq!(lines[0], "pc       0x00000004 / 4");
+            assert_eq!(lines[1], "instret  1");
+            assert_eq!(lines[2], "cycle    1");
+            assert_eq!(
+                lines[5],
+                "x1  ra    ( 0x00000005 )    x17 a7    ( 0x00000000 )",
+            );
+            assert_eq!(
+                lines[19],
+                "x15 a5    ( 0x00000000 )    x31 t6    ( 0xdeadbeef )",
+            );
+        }
+    }
+
+    mod csr {
+        use crate::csr::MSCRATCH;
+        use crate::isa::{Extensions, IsaProfile};
+
+        use super::*;
+
+        #[test]
+        fn csrrw_swaps_sp_with_mscratch() {
+            // csrrw sp, mscratch, sp
+            let (mut proc, mut bus) = setup(&[0x34011173]);
+            proc.csr.write(MSCRATCH, 0x8000);
+            proc.reg_x.write(2, 0x0400);
+
+            assert_eq!(proc.step(&mut bus), StepResult::Continued);
+            assert_eq!(proc.reg_x.read(2), 0x8000);
+            assert_eq!(proc.csr.read(MSCRATCH), 0x0400);
+        }
+
+        #[test]
+        fn csrrs_and_csrrc_set_and_clear_bits() {
+            let (mut proc, mut bus) = setup(&[
+                0x3400a173, // csrrs sp, mscratch, x1
+                0x3400b1f3, // csrrc gp, mscratch, x1
+            ]);
+
+            proc.csr.write(MSCRATCH, 0xf0);
+            proc.reg_x.write(1, 0x0f);
+
+            proc.step(&mut bus);
+            assert_eq!(proc.reg_x.read(2), 0xf0);
+            assert_eq!(proc.csr.read(MSCRATCH), 0xff);
+
+            proc.step(&mut bus);
+            assert_eq!(proc.reg_x.read(3), 0xff);
+            assert_eq!(proc.csr.read(MSCRATCH), 0xf0);
+        }
+
+        #[test]
+        fn csrrwi_writes_zero_extended_immediate() {
+            // csrrwi x1, mscratch, 5
+            let (mut proc, mut bus) = setup(&[0x3402d0f3]);
+            proc.csr.write(MSCRATCH, 0xffff0000);
+
+            assert_eq!(proc.step(&mut bus), StepResult::Continued);
+            assert_eq!(proc.reg_x.read(1), 0xffff0000);
+            assert_eq!(proc.csr.read(MSCRATCH), 5);
+        }
+
+        #[test]
+        fn csrrsi_and_csrrci_set_and_clear_immediate_bits() {
+            let (mut proc, mut bus) = setup(&[
+                0x34006173, // csrrsi sp, mscratch, 0
+                0x3401f1f3, // csrrci gp, mscratch, 3
+            ]);
+
+            proc.csr.write(MSCRATCH, 0x0f);
+
+            proc.step(&mut bus);
+            assert_eq!(proc.reg_x.read(2), 0x0f);
+            assert_eq!(proc.csr.read(MSCRATCH), 0x0f);
+
+            proc.step(&mut bus);
+            assert_eq!(proc.reg_x.read(3), 0x0f);
+            assert_eq!(proc.csr.read(MSCRATCH), 0x0c);
+        }
+
+        #[test]
+        fn misa_reports_xlen_and_extensions() {
+            // csrrs x1, misa, x0
+            let (mut proc, mut bus) = setup(&[0x301020f3]);
+            proc.extensions = IsaProfile::rv32im().extensions;
+
+            assert_eq!(proc.step(&mut bus), StepResult::Continued);
+
+            let misa = proc.reg_x.read(1);
+            let bit = |letter: u8| misa & 1 << (letter - b'A') != 0;
+
+            assert_eq!(misa >> 30, 1);
+            assert!(bit(b'I') && bit(b'M'));
+            assert!(!bit(b'A') && !bit(b'C'));
+        }
+
+        #[test]
+        fn misa_ignores_writes() {
+            // csrrw x0, misa, sp
+            let (mut proc, mut bus) = setup(&[0x30111073]);
+            proc.reg_x.write(2, 0);
+
+            assert_eq!(proc.step(&mut bus), StepResult::Continued);
+            assert_eq!(proc.pc, 4);
+            assert_eq!(proc.extensions, Extensions::default());
+        }
+
+        #[test]
+        fn unimplemented_csr_is_illegal() {
+            // csrrs x1, 0x7c0, x0
+            let (mut proc, mut bus) = setup(&[0x7c0020f3]);
+            proc.csr.write(MTVEC, 0x100);
+
+            assert_eq!(
+                proc.step(&mut bus),
+                StepResult::Trapped(Trap::IllegalInstruction(0x7c0020f3)),
+            );
+        }
+    }
+
+    mod delegation {
+        use crate::csr::{MEDELEG, MSTATUS_SIE, MSTATUS_SPIE, MSTATUS_SPP, SCAUSE, SEPC, STVEC};
+
+        use super::*;
+
+        /// Creates a processor about to execute an `ecall` in `privilege`,
+        /// with M-mode and S-mode handlers installed.
+        fn setup_ecall(privilege: PrivilegeMode) -> (Processor, Bus) {
+            let (mut proc, bus) = setup(&[0x00000073]);
+            proc.privilege = privilege;
+            proc.csr.write(MTVEC, 0x100);
+            proc.csr.write(STVEC, 0x200);
+            (proc, bus)
+        }
+
+        #[test]
+        fn delegated_user_ecall_vectors_to_stvec() {
+            let (mut proc, mut bus) = setup_ecall(PrivilegeMode::User);
+            proc.csr.write(MEDELEG, 1 << 8);
+
+            assert_eq!(
+                proc.step(&mut bus),
+                StepResult::Trapped(Trap::EnvironmentCallFromUMode),
+            );
+            assert_eq!(proc.pc, 0x200);
+            assert_eq!(proc.privilege, PrivilegeMode::Supervisor);
+            assert_eq!(proc.csr.read(SEPC), 0x00);
+            assert_eq!(proc.csr.read(SCAUSE), 8);
+            assert_eq!(proc.csr.read(MCAUSE), 0);
+            assert_eq!(proc.csr.read(MSTATUS) & MSTATUS_SPP, 0);
+        }
+
+        #[test]
+        fn undelegated_user_ecall_vectors_to_mtvec() {
+            let (mut proc, mut bus) = setup_ecall(PrivilegeMode::User);
+
+            assert_eq!(
+                proc.step(&mut bus),
+                StepResult::Trapped(Trap::EnvironmentCallFromUMode),
+            );
+            assert_eq!(proc.pc, 0x100);
+            assert_eq!(proc.privilege, PrivilegeMode::Machine);
+            assert_eq!(proc.csr.read(MCAUSE), 8);
+            assert_eq!(proc.csr.read(MSTATUS) & MSTATUS_MPP, 0);
+        }
+
+        #[test]
+        fn machine_mode_traps_are_never_delegated() {
+            let (mut proc, mut bus) = setup_ecall(PrivilegeMode::Machine);
+            proc.csr.write(MEDELEG, 1 << 11);
+
+            assert_eq!(
+                proc.step(&mut bus),
+                StepResult::Trapped(Trap::EnvironmentCallFromMMode),
+            );
+            assert_eq!(proc.pc, 0x100);
+            assert_eq!(proc.csr.read(SCAUSE), 0);
+        }
+
+        #[test]
+        fn mret_returns_to_previous_mode_and_restores_mie() {
+            let (mut proc, mut bus) = setup_ecall(PrivilegeMode::User);
+            bus.memory.write(0x100, &0x30200073u32.to_le_bytes()); // mret
+            proc.csr.write(MSTATUS, MSTATUS_MIE);
+
+            assert_eq!(
+                proc.step(&mut bus),
+                StepResult::Trapped(Trap::EnvironmentCallFromUMode),
+            );
+            assert_eq!(proc.csr.read(MSTATUS), MSTATUS_MPIE);
+
+            // The handler returns past the ecall.
+            proc.csr.write(MEPC, 0x04);
+
+            assert_eq!(proc.step(&mut bus), StepResult::Continued);
+            assert_eq!(proc.pc, 0x04);
+            assert_eq!(proc.privilege, PrivilegeMode::User);
+            assert_eq!(proc.csr.read(MSTATUS), MSTATUS_MIE | MSTATUS_MPIE);
+        }
+
+        #[test]
+        fn sret_returns_to_previous_mode_and_restores_sie() {
+            let (mut proc, mut bus) = setup_ecall(PrivilegeMode::User);
+            bus.memory.write(0x200, &0x10200073u32.to_le_bytes()); // sret
+            proc.csr.write(MEDELEG, 1 << 8);
+            proc.csr.write(MSTATUS, MSTATUS_SIE);
+
+            assert_eq!(
+                proc.step(&mut bus),
+                StepResult::Trapped(Trap::EnvironmentCallFromUMode),
+            );
+            assert_eq!(proc.privilege, PrivilegeMode::Supervisor);
+            assert_eq!(proc.csr.read(MSTATUS), MSTATUS_SPIE);
+
+            proc.csr.write(SEPC, 0x04);
+
+            assert_eq!(proc.step(&mut bus), StepResult::Continued);
+            assert_eq!(proc.pc, 0x04);
+            assert_eq!(proc.privilege, PrivilegeMode::User);
+            assert_eq!(proc.csr.read(MSTATUS), MSTATUS_SIE | MSTATUS_SPIE);
+        }
+
+        #[test]
+        fn sret_returns_to_supervisor_mode_when_spp_is_set() {
+            let (mut proc, mut bus) = setup(&[0x10200073]); // sret
+            proc.csr.write(MSTATUS, MSTATUS_SPP);
+            proc.csr.write(SEPC, 0x40);
+
+            assert_eq!(proc.step(&mut bus), StepResult::Continued);
+            assert_eq!(proc.pc, 0x40);
+            assert_eq!(proc.privilege, PrivilegeMode::Supervisor);
+            assert_eq!(proc.csr.read(MSTATUS) & MSTATUS_SPP, 0);
+        }
+
+        #[test]
+        fn returns_below_current_privilege_are_illegal() {
+            let (mut proc, mut bus) = setup(&[
+                0x30200073, // mret
+                0x10200073, // sret
+            ]);
+            proc.csr.write(MTVEC, 0x100);
+
+            proc.privilege = PrivilegeMode::Supervisor;
+            assert_eq!(
+                proc.step(&mut bus),
+                StepResult::Trapped(Trap::IllegalInstruction(0x30200073)),
+            );
+
+            proc.pc = 0x04;
+            proc.privilege = PrivilegeMode::User;
+            assert_eq!(
+                proc.step(&mut bus),
+                StepResult::Trapped(Trap::IllegalInstruction(0x10200073)),
+            );
+        }
+
+        #[test]
+        fn csr_access_requires_its_privilege() {
+            let (mut proc, mut bus) = setup(&[
+                0x302022f3, // csrrs x5, medeleg, x0
+                0x141022f3, // csrrs x5, sepc, x0
+                0x141022f3, // csrrs x5, sepc, x0
+            ]);
+            proc.csr.write(MTVEC, 0x100);
+            proc.csr.write(SEPC, 0x40);
+
+            proc.privilege = PrivilegeMode::Supervisor;
+            assert_eq!(
+                proc.step(&mut bus),
+                StepResult::Trapped(Trap::IllegalInstruction(0x302022f3)),
+            );
+
+            proc.pc = 0x04;
+            proc.privilege = PrivilegeMode::Supervisor;
+            assert_eq!(proc.step(&mut bus), StepResult::Continued);
+            assert_eq!(proc.reg_x.read(5), 0x40);
+
+            proc.privilege = PrivilegeMode::User;
+            assert_eq!(
+                proc.step(&mut bus),
+                StepResult::Trapped(Trap::IllegalInstruction(0x141022f3)),
+            );
+        }
+
+        #[test]
+        fn machine_interrupts_are_enabled_below_machine_mode() {
+            let (mut proc, mut bus) = setup(&[0x00000013]);
+            proc.csr.write(MTVEC, 0x100);
+            proc.csr.write(MIE, Interrupt::MachineSoftware.mask());
+            proc.privilege = PrivilegeMode::User;
+            proc.raise_interrupt(Interrupt::MachineSoftware);
+
+            assert_eq!(
+                proc.step(&mut bus),
+                StepResult::Trapped(Trap::Interrupt(Interrupt::MachineSoftware)),
+            );
+            assert_eq!(proc.privilege, PrivilegeMode::Machine);
+        }
+
+        #[test]
+        fn delegated_supervisor_trap_records_previous_mode() {
+            let (mut proc, mut bus) = setup_ecall(PrivilegeMode::Supervisor);
+            proc.csr.write(MEDELEG, 1 << 9);
+
+            assert_eq!(
+                proc.step(&mut bus),
+                StepResult::Trapped(Trap::EnvironmentCallFromSMode),
+            );
+            assert_eq!(proc.pc, 0x200);
+            assert_eq!(proc.csr.read(MSTATUS) & MSTATUS_SPP, MSTATUS_SPP);
+        }
+    }
+
+    mod custom_ops {
+        use crate::encode::{encode, Operands};
+        use crate::op::Op::*;
+
+        use super::*;
+
+        #[test]
+        fn saturating_add_clamps_where_add_wraps() {
+            let (mut proc, mut bus) = setup(&[
+                encode(&SaturatingAdd, &Operands::r(3, 1, 2)),
+                encode(&ArithmeticAdd, &Operands::r(4, 1, 2)),
+            ]);
+
+            proc.enable_saturating_ops();
+            proc.reg_x.write(1, i32::MAX as u32);
+            proc.reg_x.write(2, 1);
+
+            assert_eq!(proc.step(&mut bus), StepResult::Continued);
+            assert_eq!(proc.step(&mut bus), StepResult::Continued);
+
+            assert_eq!(proc.reg_x.read(3), i32::MAX as u32);
+            assert_eq!(proc.reg_x.read(4), i32::MIN as u32);
+        }
+
+        #[test]
+        fn saturating_sub_clamps_at_minimum() {
+            let (mut proc, mut bus) = setup(&[encode(&SaturatingSub, &Operands::r(3, 1, 2))]);
+
+            proc.enable_saturating_ops();
+            proc.reg_x.write(1, i32::MIN as u32);
+            proc.reg_x.write(2, 1);
+
+            assert_eq!(proc.step(&mut bus), StepResult::Continued);
+            assert_eq!(proc.reg_x.read(3), i32::MIN as u32);
+        }
+
+        #[test]
+        fn saturating_ops_are_illegal_until_enabled() {
+            let sadd = encode(&SaturatingAdd, &Operands::r(3, 1, 2));
+            let (mut proc, mut bus) = setup(&[sadd]);
+            proc.csr.write(MTVEC, 0x100);
+
+            assert_eq!(proc.step(&mut bus), StepResult::Trapped(Trap::IllegalInstruction(sadd)));
+        }
+
+        #[test]
+        fn handler_writes_constant_to_rd() {
+            // custom-0 x5 (funct3 0, funct7 0)
+            let (mut proc, mut bus) = setup(&[0x0000028b]);
+
+            proc.register_custom_op(0x0b, 0, 0, |instr, proc, _bus| {
+                proc.reg_x.write(instr.field_rd(), 42);
+                Ok(())
+            });
+
+            assert_eq!(proc.step(&mut bus), StepResult::Continued);
+            assert_eq!(proc.reg_x.read(5), 42);
+            assert_eq!(proc.pc, 0x04);
+        }
+
+        #[test]
+        fn unregistered_custom_instruction_is_illegal() {
+            // custom-0 x5 (funct3 1, funct7 0)
+            let (mut proc, mut bus) = setup(&[0x0000128b]);
+            proc.register_custom_op(0x0b, 0, 0, |_, _, _| Ok(()));
+
+            assert_eq!(
+                proc.step(&mut bus),
+                StepResult::Halted(HaltReason::UnhandledTrap(Trap::IllegalInstruction(0x0000128b))),
+            );
+        }
+
+        #[test]
+        fn handler_trap_is_taken() {
+            let (mut proc, mut bus) = setup(&[0x0000028b]);
+            proc.register_custom_op(0x0b, 0, 0, |instr, _, _| {
+                Err(Trap::IllegalInstruction(instr.raw()))
+            });
+            proc.csr.write(MTVEC, 0x100);
+
+            assert_eq!(
+                proc.step(&mut bus),
+                StepResult::Trapped(Trap::IllegalInstruction(0x0000028b)),
+            );
+            assert_eq!(proc.csr.read(MEPC), 0x00);
+        }
+
+        #[test]
+        #[should_panic]
+        fn standard_opcodes_cannot_be_overridden() {
+            Processor::new().register_custom_op(0x33, 0, 0, |_, _, _| Ok(()));
+        }
+    }
+
+    mod rv32e {
+        use crate::isa::Extensions;
+
+        use super::*;
+
+        #[test]
+        fn has_16_registers() {
+            let mut proc = Processor::new();
+            assert_eq!(proc.register_count(), 32);
+
+            proc.extensions = Extensions::RV32E;
+            assert_eq!(proc.register_count(), 16);
+        }
+
+        #[test]
+        fn instruction_using_upper_register_is_illegal() {
+            // addi x20, x0, 1
+            let (mut proc, mut bus) = setup(&[0x00100a13]);
+            proc.extensions = Extensions::RV32E;
+            proc.csr.write(MTVEC, 0x100);
+
+            assert_eq!(
+                proc.step(&mut bus),
+                StepResult::Trapped(Trap::IllegalInstruction(0x00100a13)),
+            );
+            assert_eq!(proc.reg_x.read(20), 0);
+        }
+
+        #[test]
+        fn instruction_using_lower_registers_executes() {
+            // addi x15, x0, 1
+            let (mut proc, mut bus) = setup(&[0x00100793]);
+            proc.extensions = Extensions::RV32E;
+
+            assert_eq!(proc.step(&mut bus), StepResult::Continued);
+            assert_eq!(proc.reg_x.read(15), 1);
+        }
+    }
+
+    mod divide_by_zero {
+        use crate::isa::Extensions;
+
+        use super::*;
+
+        const DIVIDES: [u32; 4] = [
+            0x0220c1b3, // div x3, x1, x2
+            0x0220e233, // rem x4, x1, x2
+            0x0220d2b3, // divu x5, x1, x2
+            0x0220f333, // remu x6, x1, x2
+        ];
+
+        fn setup_m(instrs: &[u32]) -> (Processor, Bus) {
+            let (mut proc, bus) = setup(instrs);
+            proc.extensions = Extensions::RV32IM;
+            (proc, bus)
+        }
+
+        #[test]
+        fn returns_the_defined_results_by_default() {
+            let (mut proc, mut bus) = setup_m(&DIVIDES);
+            proc.reg_x.write(1, 7);
+
+            for _ in 0 .. 4 {
+                assert_eq!(proc.step(&mut bus), StepResult::Continued);
+            }
+
+            assert_eq!(proc.reg_x.read(3), u32::MAX);
+            assert_eq!(proc.reg_x.read(4), 7);
+            assert_eq!(proc.reg_x.read(5), u32::MAX);
+            assert_eq!(proc.reg_x.read(6), 7);
+        }
+
+        #[test]
+        fn traps_when_enabled() {
+            for instr in DIVIDES {
+                let (mut proc, mut bus) = setup_m(&[instr]);
+                proc.trap_on_divide_by_zero = true;
+                proc.reg_x.write(1, 7);
+                proc.csr.write(MTVEC, 0x100);
+
+                assert_eq!(
+                    proc.step(&mut bus),
+                    StepResult::Trapped(Trap::DivideByZero(instr)),
+                );
+                assert_eq!(proc.csr.read(MCAUSE), 2);
+                assert_eq!(proc.csr.read(MTVAL), instr);
+            }
+        }
+
+        #[test]
+        fn divides_normally_by_nonzero_divisor_when_enabled() {
+            let (mut proc, mut bus) = setup_m(&DIVIDES[.. 1]);
+            proc.trap_on_divide_by_zero = true;
+            proc.reg_x.write(1, 7);
+            proc.reg_x.write(2, 2);
+
+            assert_eq!(proc.step(&mut bus), StepResult::Continued);
+            assert_eq!(proc.reg_x.read(3), 3);
+        }
+    }
+
+    mod alu {
+        use super::*;
+
+        use std::cell::RefCell;
+        use std::rc::Rc;
+
+        use crate::alu::{Alu, AluOps};
+        use crate::op::Op;
+
+        /// An ALU that records each operation and its second operand.
+        #[derive(Debug)]
+        struct RecordingAlu {
+            operands: Rc<RefCell<Vec<(Op, i32)>>>,
+        }
+
+        impl AluOps for RecordingAlu {
+            fn run(&mut self, op: &Op, x: i32, y: i32) -> i32 {
+                self.operands.borrow_mut().push((*op, y));
+                Alu::new().run(op, x, y)
+            }
+        }
+
+        #[test]
+        fn register_shift_uses_low_five_bits_of_rs2() {
+            let (mut proc, mut bus) = setup(&[
+                0xfc000093, // addi x1, x0, -64
+                0x02100113, // addi x2, x0, 33
+                0x4020d1b3, // sra x3, x1, x2
+                0xfe100113, // addi x2, x0, -31
+                0x4020d1b3, // sra x3, x1, x2
+            ]);
+
+            let operands = Rc::new(RefCell::new(Vec::new()));
+            proc.alu = Box::new(RecordingAlu { operands: operands.clone() });
+
+            for _ in 0 .. 3 {
+                proc.step(&mut bus);
+            }
+
+            assert_eq!(proc.reg_x.read(3), -32i32 as u32);
+
+            // -31 is 0xffffffe1, whose low five bits are also 1.
+            proc.step(&mut bus);
+            proc.step(&mut bus);
+
+            assert_eq!(proc.reg_x.read(3), -32i32 as u32);
+
+            let shifts: Vec<i32> = operands
+                .borrow()
+                .iter()
+                .filter(|&&(op, _)| op == Op::ShiftRightArithmetic)
+                .map(|&(_, y)| y)
+                .collect();
+
+            assert_eq!(shifts, [1, 1]);
+        }
+    }
+
+    mod link_register {
+        use super::*;
+
+        #[test]
+        fn jal_links_into_rd() {
+            // jal x5, 16
+            let (mut proc, mut bus) = setup(&[0x010002ef]);
+            assert_eq!(proc.step(&mut bus), StepResult::Continued);
+            assert_eq!(proc.pc, 0x10);
+            assert_eq!(proc.reg_x.read(5), 0x04);
+            assert_eq!(proc.reg_x.read(1), 0);
+        }
+
+        #[test]
+        fn jalr_links_into_rd() {
+            // jalr x5, 0(x6)
+            let (mut proc, mut bus) = setup(&[0x000302e7]);
+            proc.reg_x.write(6, 0x40);
+            assert_eq!(proc.step(&mut bus), StepResult::Continued);
+            assert_eq!(proc.pc, 0x40);
+            assert_eq!(proc.reg_x.read(5), 0x04);
+            assert_eq!(proc.reg_x.read(1), 0);
+        }
+
+        #[test]
+        fn jalr_reads_rs1_before_linking_into_it() {
+            // jalr x5, 0(x5)
+            let (mut proc, mut bus) = setup(&[0x000282e7]);
+            proc.reg_x.write(5, 0x40);
+            assert_eq!(proc.step(&mut bus), StepResult::Continued);
+            assert_eq!(proc.pc, 0x40);
+            assert_eq!(proc.reg_x.read(5), 0x04);
+        }
+    }
+
+    mod address_materialization {
+        use super::*;
+
+        use crate::encode::{encode, Operands};
+        use crate::op::{Op, Op::*};
+
+        /// Splits a 32-bit value into the upper immediate of `lui`/`auipc`
+        /// and the sign-extended low immediate of `addi`/`jalr`, adding
+        /// 0x800 to the upper part to cancel a negative low part, as an
+        /// assembler does for `%hi` and `%lo`.
+        fn hi_lo(value: u32) -> (i32, i32) {
+            let hi = value.wrapping_add(0x800) >> 12;
+            let lo = (value as i32) << 20 >> 20;
+            (hi as i32, lo)
+        }
+
+        /// Runs two instructions at `base` and returns the processor.
+        fn run_pair(base: u32, first: (Op, Operands), second: (Op, Operands)) -> Processor {
+            let (mut proc, mut bus) = setup(&[]);
+            bus.memory.write(base as usize, &encode(&first.0, &first.1).to_le_bytes());
+            bus.memory.write(base as usize + 4, &encode(&second.0, &second.1).to_le_bytes());
+            proc.pc = base;
+
+            assert_eq!(proc.step(&mut bus), StepResult::Continued);
+            assert_eq!(proc.step(&mut bus), StepResult::Continued);
+            proc
+        }
+
+        fn lui_addi(addr: u32) -> u32 {
+            let (hi, lo) = hi_lo(addr);
+
+            run_pair(
+                0x00,
+                (LoadUpperImmediate, Operands::u(5, hi)),
+                (ArithmeticAddImmediate, Operands::i(5, 5, lo)),
+            ).reg_x.read(5)
+        }
+
+        #[test]
+        fn lui_addi_with_positive_low_bits() {
+            assert_eq!(hi_lo(0x12345678), (0x12345, 0x678));
+            assert_eq!(lui_addi(0x12345678), 0x12345678);
+        }
+
+        #[test]
+        fn lui_addi_with_negative_low_bits() {
+            assert_eq!(hi_lo(0x12345abc), (0x12346, -0x544));
+            assert_eq!(lui_addi(0x12345abc), 0x12345abc);
+        }
+
+        #[test]
+        fn lui_addi_carry_wraps_upper_bits() {
+            assert_eq!(hi_lo(0xfffff800), (0x00000, -0x800));
+            assert_eq!(lui_addi(0xfffff800), 0xfffff800);
+            assert_eq!(lui_addi(0x7ffff800), 0x7ffff800);
+        }
+
+        #[test]
+        fn auipc_addi_with_positive_and_negative_low_bits() {
+            const BASE: u32 = 0x100;
+
+            for target in [0x00002345, 0x00002a00] {
+                let (hi, lo) = hi_lo(target - BASE);
+
+                let proc = run_pair(
+                    BASE,
+                    (AddUpperImmediateProgramCounter, Operands::u(6, hi)),
+                    (ArithmeticAddImmediate, Operands::i(6, 6, lo)),
+                );
+
+                assert_eq!(proc.reg_x.read(6), target);
+            }
+        }
+
+        #[test]
+        fn auipc_jalr_jumps_to_target() {
+            const BASE: u32 = 0x100;
+            const TARGET: u32 = 0x2e0;
+
+            let (hi, lo) = hi_lo(TARGET - BASE);
+
+            let proc = run_pair(
+                BASE,
+                (AddUpperImmediateProgramCounter, Operands::u(1, hi)),
+                (JumpAndLinkRegister, Operands::i(0, 1, lo)),
+            );
+
+            assert_eq!(proc.pc, TARGET);
+        }
+    }
+
+    mod fast_path {
+        use super::*;
+
+        #[test]
+        fn matches_general_path() {
+            for instr in [
+                0xff908193, // addi x3, x1, -7
+                0x00108013, // addi x0, x1, 1
+                0x002081b3, // add x3, x1, x2
+                0x04002183, // lw x3, 0x40(x0)
+                0x04202023, // sw x2, 0x40(x0)
+                0x00208863, // beq x1, x2, 16
+                0x00209863, // bne x1, x2, 16
+                0x0020c863, // blt x1, x2, 16
+                0x0020d863, // bge x1, x2, 16
+                0x0020e863, // bltu x1, x2, 16
+                0x0020f863, // bgeu x1, x2, 16
+                0xfe108ce3, // beq x1, x1, -8
+            ] {
+                let run = |fast: bool| {
+                    let (mut proc, mut bus) = setup(&[0, 0, 0, 0, instr]);
+                    bus.memory.write(0x40, &0x12345678u32.to_le_bytes());
+                    proc.pc = 0x10;
+                    proc.reg_x.write(1, -3i32 as u32);
+                    proc.reg_x.write(2, 5);
+
+                    match fast {
+                        true => assert_eq!(proc.step(&mut bus), StepResult::Continued),
+                        false => proc.execute(&Instruction::new(instr), &mut bus).unwrap(),
+                    }
+
+                    let regs: Vec<u32> = (0 .. 32).map(|i| proc.reg_x.read(i)).collect();
+                    (proc.pc, regs, bus.read(0x40, 4))
+                };
+
+                assert_eq!(run(true), run(false), "{instr:#010x}");
+            }
+        }
+
+        #[test]
+        fn store_fault_traps() {
+            // sw x2, 0x40(x0)
+            let (mut proc, mut bus) = setup(&[0x04202023]);
+            bus.protect(0x40, 0x44);
+            proc.csr.write(MTVEC, 0x08);
+
+            assert_eq!(
+                proc.step(&mut bus),
+                StepResult::Trapped(Trap::StoreAccessFault(0x40)),
+            );
+            assert_eq!(proc.csr.read(MEPC), 0x00);
+            assert_eq!(proc.pc, 0x08);
+        }
+    }
+
+    mod ebreak {
+        use super::*;
+
+        #[test]
+        fn halts_by_default_even_with_handler() {
+            // ebreak
+            let (mut proc, mut bus) = setup(&[0x00100073]);
+            proc.csr.write(MTVEC, 0x100);
+            assert_eq!(proc.step(&mut bus), StepResult::Halted(HaltReason::Ebreak));
+            assert_eq!(proc.pc, 0x00);
+        }
+
+        #[test]
+        fn stops_for_debugger_in_debugger_mode() {
+            // ebreak
+            let (mut proc, mut bus) = setup(&[0x00100073]);
+            proc.ebreak_mode = EbreakMode::Debugger;
+            assert_eq!(proc.step(&mut bus), StepResult::Breakpoint);
+            assert_eq!(proc.pc, 0x00);
+        }
+
+        #[test]
+        fn traps_to_handler_in_trap_mode() {
+            // ebreak
+            let (mut proc, mut bus) = setup(&[0x00100073]);
+            proc.ebreak_mode = EbreakMode::Trap;
+            proc.csr.write(MTVEC, 0x100);
+            assert_eq!(proc.step(&mut bus), StepResult::Trapped(Trap::Breakpoint(0x00)));
+            assert_eq!(proc.pc, 0x100);
+            assert_eq!(proc.csr.read(MCAUSE), 3);
+        }
+
+        #[test]
+        fn is_unhandled_in_trap_mode_without_handler() {
+            // ebreak
+            let (mut proc, mut bus) = setup(&[0x00100073]);
+            proc.ebreak_mode = EbreakMode::Trap;
+            assert_eq!(
+                proc.step(&mut bus),
+                StepResult::Halted(HaltReason::UnhandledTrap(Trap::Breakpoint(0x00))),
+            );
+        }
+    }
+
+    mod pending_trap {
+        use super::*;
+
+        #[test]
+        fn is_none_after_normal_step() {
+            let (mut proc, mut bus) = setup(&[0x00000013]);
+            assert_eq!(proc.step(&mut bus), StepResult::Continued);
+            assert_eq!(proc.pending_trap(), None);
+        }
+
+        #[test]
+        fn records_handled_illegal_instruction() {
+            let (mut proc, mut bus) = setup(&[0x00000013, 0x80000033]);
+            proc.csr.write(MTVEC, 0x100);
+            assert_eq!(proc.step(&mut bus), StepResult::Continued);
+            assert_eq!(
+                proc.step(&mut bus),
+                StepResult::Trapped(Trap::IllegalInstruction(0x80000033)),
+            );
+
+            let pending = proc.pending_trap().unwrap();
+            assert_eq!(pending.trap, Trap::IllegalInstruction(0x80000033));
+            assert_eq!(pending.pc, 0x04);
+            assert_eq!(pending.handler, Some(0x100));
+        }
+
+        #[test]
+        fn is_cleared_by_the_next_step() {
+            let (mut proc, mut bus) = setup(&[0x80000033]);
+            proc.csr.write(MTVEC, 0x100);
+            assert_eq!(
+                proc.step(&mut bus),
+                StepResult::Trapped(Trap::IllegalInstruction(0x80000033)),
+            );
+            assert!(proc.pending_trap().is_some());
+
+            // The handler at 0x100 is zeroed memory, so write a nop there.
+            bus.memory.write(0x100, &0x00000013u32.to_le_bytes());
+            assert_eq!(proc.step(&mut bus), StepResult::Continued);
+            assert_eq!(proc.pending_trap(), None);
+        }
+    }
+
+    mod load_store {
+        use std::cell::RefCell;
+        use std::rc::Rc;
+
+        use crate::bus::tests::{FaultyDevice, MockDevice};
+
+        use super::*;
+
+        #[test]
+        fn stores_and_loads_word() {
+            let (mut proc, mut bus) = setup(&[
+                0x10000093, // addi x1, x0, 0x100
+                0xffe00113, // addi x2, x0, -2
+                0x0020a023, // sw x2, 0(x1)
+                0x0000a183, // lw x3, 0(x1)
+            ]);
+
+            for _ in 0 .. 4 {
+                assert_eq!(proc.step(&mut bus), StepResult::Continued);
+            }
+
+            assert_eq!(bus.memory.read(0x100, 4), &[0xfe, 0xff, 0xff, 0xff]);
+            assert_eq!(proc.reg_x.read(3), 0xfffffffe);
+        }
+
+        #[test]
+        fn load_byte_sign_extends() {
+            let (mut proc, mut bus) = setup(&[
+                0x10000093, // addi x1, x0, 0x100
+                0x00008183, // lb x3, 0(x1)
+            ]);
+
+            bus.memory.write(0x100, &[0x80]);
+            assert_eq!(proc.step(&mut bus), StepResult::Continued);
+            assert_eq!(proc.step(&mut bus), StepResult::Continued);
+            assert_eq!(proc.reg_x.read(3), 0xffffff80);
+        }
+
+        #[test]
+        fn load_byte_unsigned_zero_extends() {
+            let (mut proc, mut bus) = setup(&[
+                0x10000093, // addi x1, x0, 0x100
+                0x0000c183, // lbu x3, 0(x1)
+            ]);
+
+            bus.memory.write(0x100, &[0x80]);
+            assert_eq!(proc.step(&mut bus), StepResult::Continued);
+            assert_eq!(proc.step(&mut bus), StepResult::Continued);
+            assert_eq!(proc.reg_x.read(3), 0x80);
+        }
+
+        #[test]
+        fn load_half_sign_extends() {
+            let (mut proc, mut bus) = setup(&[
+                0x10000093, // addi x1, x0, 0x100
+                0x00009183, // lh x3, 0(x1)
+            ]);
+
+            bus.memory.write(0x100, &[0x00, 0x80]);
+            assert_eq!(proc.step(&mut bus), StepResult::Continued);
+            assert_eq!(proc.step(&mut bus), StepResult::Continued);
+            assert_eq!(proc.reg_x.read(3), 0xffff8000);
+            assert_eq!(proc.reg_x.read(3) as i32, -32768);
+        }
+
+        #[test]
+        fn load_half_unsigned_zero_extends() {
+            let (mut proc, mut bus) = setup(&[
+                0x10000093, // addi x1, x0, 0x100
+                0x0000d183, // lhu x3, 0(x1)
+            ]);
+
+            bus.memory.write(0x100, &[0x00, 0x80]);
+            assert_eq!(proc.step(&mut bus), StepResult::Continued);
+            assert_eq!(proc.step(&mut bus), StepResult::Continued);
+            assert_eq!(proc.reg_x.read(3), 0x00008000);
+        }
+
+        #[test]
+        fn load_half_positive_is_unchanged_by_sign_extension() {
+            let (mut proc, mut bus) = setup(&[
+                0x10000093, // addi x1, x0, 0x100
+                0x00009183, // lh x3, 0(x1)
+            ]);
+
+            bus.memory.write(0x100, &[0xff, 0x7f]);
+            assert_eq!(proc.step(&mut bus), StepResult::Continued);
+            assert_eq!(proc.step(&mut bus), StepResult::Continued);
+            assert_eq!(proc.reg_x.read(3), 0x00007fff);
+        }
+
+        #[test]
+        fn load_half_crosses_word_boundary() {
+            let (mut proc, mut bus) = setup(&[
+                0x10000093, // addi x1, x0, 0x100
+                0x00309183, // lh x3, 3(x1)
+                0x0030d203, // lhu x4, 3(x1)
+            ]);
+
+            // The half-word at 0x103 spans the words at 0x100 and 0x104.
+            bus.memory.write(0x100, &[0x11, 0x22, 0x33, 0x44, 0x85, 0x66]);
+
+            for _ in 0 .. 3 {
+                assert_eq!(proc.step(&mut bus), StepResult::Continued);
+            }
+
+            assert_eq!(proc.reg_x.read(3), 0xffff8544);
+            assert_eq!(proc.reg_x.read(4), 0x00008544);
+        }
+
+        #[test]
+        fn load_word_crosses_word_boundary() {
+            let (mut proc, mut bus) = setup(&[
+                0x10000093, // addi x1, x0, 0x100
+                0x0020a183, // lw x3, 2(x1)
+            ]);
+
+            bus.memory.write(0x100, &[0x11, 0x22, 0x33, 0x44, 0x55, 0x66]);
+            assert_eq!(proc.step(&mut bus), StepResult::Continued);
+            assert_eq!(proc.step(&mut bus), StepResult::Continued);
+            assert_eq!(proc.reg_x.read(3), 0x66554433);
+        }
+
+        #[test]
+        fn load_from_unmapped_address_faults() {
+            let (mut proc, mut bus) = setup(&[
+                0x00100093, // addi x1, x0, 1
+                0x01409093, // slli x1, x1, 20
+                0x0000a103, // lw x2, 0(x1)
+            ]);
+
+            proc.csr.write(MTVEC, 0x100);
+            proc.step(&mut bus);
+            proc.step(&mut bus);
+
+            assert_eq!(
+                proc.step(&mut bus),
+                StepResult::Trapped(Trap::LoadAccessFault(0x0010_0000)),
+            );
+            assert_eq!(proc.csr.read(MTVAL), 0x0010_0000);
+        }
+
+        #[test]
+        fn fetch_from_unmapped_address_faults() {
+            let (mut proc, mut bus) = setup(&[]);
+            proc.pc = 0x1000;
+
+            assert_eq!(proc.fetch(&mut bus), Err(Trap::InstructionAccessFault(0x1000)));
+        }
+
+        #[test]
+        fn device_error_is_an_access_fault() {
+            let (mut proc, mut bus) = setup(&[
+                0x000100b7, // lui x1, 0x10
+                0x0020a223, // sw x2, 4(x1)
+                0x0040a183, // lw x3, 4(x1)
+            ]);
+
+            bus.register(0x10000, 0x100, Box::new(FaultyDevice { fault_offset: 0x04 }));
+            proc.csr.write(MTVEC, 0x100);
+
+            assert_eq!(proc.step(&mut bus), StepResult::Continued);
+            assert_eq!(
+                proc.step(&mut bus),
+                StepResult::Trapped(Trap::StoreAccessFault(0x10004)),
+            );
+            assert_eq!(proc.csr.read(MTVAL), 0x10004);
+
+            proc.pc = 0x08;
+            assert_eq!(
+                proc.step(&mut bus),
+                StepResult::Trapped(Trap::LoadAccessFault(0x10004)),
+            );
+        }
+
+        #[test]
+        fn load_straddling_memory_and_device_faults() {
+            let (mut proc, mut bus) = setup(&[
+                0x3fe02183, // lw x3, 0x3fe(x0)
+                0x3e202f23, // sw x2, 0x3fe(x0)
+            ]);
+
+            bus.register(0x400, 0x100, Box::new(FaultyDevice { fault_offset: 0xff }));
+            proc.csr.write(MTVEC, 0x100);
+
+            assert_eq!(proc.step(&mut bus), StepResult::Trapped(Trap::LoadAccessFault(0x3fe)));
+
+            proc.pc = 0x04;
+            assert_eq!(proc.step(&mut bus), StepResult::Trapped(Trap::StoreAccessFault(0x3fe)));
+        }
+
+        #[test]
+        fn store_to_protected_range_faults() {
+            let (mut proc, mut bus) = setup(&[
+                0x10000093, // addi x1, x0, 0x100
+                0x0020a023, // sw x2, 0(x1)
+                0x0020a023, // sw x2, 0(x1)
+            ]);
+
+            bus.protect(0x100, 0x104);
+            proc.csr.write(MTVEC, 0x08);
+
+            assert_eq!(proc.step(&mut bus), StepResult::Continued);
+            assert_eq!(
+                proc.step(&mut bus),
+                StepResult::Trapped(Trap::StoreAccessFault(0x100)),
+            );
+            assert_eq!(proc.csr.read(MTVAL), 0x100);
+
+            bus.unprotect(0x100, 0x104);
+            assert_eq!(proc.step(&mut bus), StepResult::Continued);
+        }
+
+        #[test]
+        fn dispatches_to_mmio_device() {
+            let writes = Rc::new(RefCell::new(Vec::new()));
+
+            let (mut proc, mut bus) = setup(&[
+                0x000100b7, // lui x1, 0x10
+                0xffe00113, // addi x2, x0, -2
+                0x0020a023, // sw x2, 0(x1)
+                0x0000a183, // lw x3, 0(x1)
+            ]);
+
+            bus.register(
+                0x10000,
+                0x100,
+                Box::new(MockDevice { writes: writes.clone(), read_value: 0x1234 }),
+            );
+
+            for _ in 0 .. 4 {
+                assert_eq!(proc.step(&mut bus), StepResult::Continued);
+            }
+
+            assert_eq!(*writes.borrow(), &[(0x00, 4, 0xfffffffe)]);
+            assert_eq!(proc.reg_x.read(3), 0x1234);
+        }
+    }
+
+    mod compressed {
+        use crate::isa::{Extension, Extensions};
+
+        use super::*;
+
+        /// Creates a processor with the C extension enabled and memory
+        /// containing the given halfwords.
+        fn setup_compressed(halfwords: &[u16]) -> (Processor, Bus) {
+            let (mut proc, mut bus) = setup(&[]);
+            proc.extensions = Extensions::RV32IM.with(Extension::C);
+
+            for (i, halfword) in halfwords.iter().enumerate() {
+                bus.memory.write(i * 2, &halfword.to_le_bytes());
+            }
+
+            (proc, bus)
+        }
+
+        #[test]
+        fn c_nop_only_advances_pc_by_two() {
+            let (mut proc, mut bus) = setup_compressed(&[0x0001, 0x0001]);
+            assert_eq!(proc.step(&mut bus), StepResult::Continued);
+            assert_eq!(proc.step(&mut bus), StepResult::Continued);
+            assert_eq!(proc.pc, 0x04);
+            assert!((0 .. 32).all(|i| proc.reg_x.read(i) == 0));
+        }
+
+        #[test]
+        fn c_jalr_links_next_halfword_and_c_ret_returns() {
+            // c.jalr x5; c.nop; c.jr x1
+            let (mut proc, mut bus) = setup_compressed(&[0x9282, 0x0001, 0x8082]);
+            proc.reg_x.write(5, 0x04);
+
+            assert_eq!(proc.step(&mut bus), StepResult::Continued);
+            assert_eq!(proc.pc, 0x04);
+            assert_eq!(proc.reg_x.read(1), 0x02);
+
+            assert_eq!(proc.step(&mut bus), StepResult::Continued);
+            assert_eq!(proc.pc, 0x02);
+        }
+
+        #[test]
+        fn c_ebreak_halts() {
+            let (mut proc, mut bus) = setup_compressed(&[0x9002]);
+            assert_eq!(proc.step(&mut bus), StepResult::Halted(HaltReason::Ebreak));
+        }
+
+        #[test]
+        fn is_unsupported_without_c_extension() {
+            let (mut proc, mut bus) = setup(&[0x00000001]);
+            assert_eq!(
+                proc.step(&mut bus),
+                StepResult::Halted(HaltReason::UnhandledTrap(
+                    Trap::UnsupportedExtension(Extension::C, 0x0001),
+                )),
+            );
+        }
+    }
+
+    mod decode_at {
+        use crate::op::Op::*;
+
+        use super::*;
+
+        #[test]
+        fn decodes_consecutive_instructions() {
+            let (_, bus) = setup(&[
+                0x00500093, // addi x1, x0, 5
+                0x0020a023, // sw x2, 0(x1)
+            ]);
+
+            let (instr, op, len) = Processor::decode_at(&bus.memory, 0x00);
+            assert_eq!(instr.raw(), 0x00500093);
+            assert_eq!(op, Some(ArithmeticAddImmediate));
+            assert_eq!(len, 4);
+
+            let (instr, op, len) = Processor::decode_at(&bus.memory, len);
+            assert_eq!(instr.raw(), 0x0020a023);
+            assert_eq!(op, Some(StoreWord));
+            assert_eq!(len, 4);
+        }
+
+        #[test]
+        fn reports_compressed_length() {
+            let (_, mut bus) = setup(&[]);
+
+            // c.nop
+            bus.memory.write(0x10, &0x0001u16.to_le_bytes());
+
+            let (instr, op, len) = Processor::decode_at(&bus.memory, 0x10);
+            assert_eq!(instr.raw(), 0x0001);
+            assert_eq!(op, Some(ArithmeticAddImmediate));
+            assert_eq!(len, 2);
+        }
+
+        #[test]
+        fn undecodable_word_has_no_op() {
+            let (_, mut bus) = setup(&[]);
+            bus.memory.write(0x10, &0xffffffffu32.to_le_bytes());
+            assert_eq!(Processor::decode_at(&bus.memory, 0x10).1, None);
+        }
+    }
+
+    mod event_log {
+        use crate::event_log::Event;
+
+        use super::*;
+
+        #[test]
+        fn identifies_canonical_nop_but_not_real_addi() {
+            let (mut proc, mut bus) = setup(&[
+                0x00000013, // nop
+                0x00100093, // addi x1, x0, 1
+            ]);
+
+            proc.enable_event_log(2);
+            proc.step(&mut bus);
+            proc.step(&mut bus);
+
+            let events: Vec<Event> = proc.event_log().unwrap().events().copied().collect();
+
+            assert!(events[0].is_nop());
+            assert!(!events[1].is_nop());
+            assert_eq!(proc.pc, 0x08);
+            assert_eq!(proc.instret, 2);
+        }
+
+        #[test]
+        fn records_tail_of_short_run() {
+            let (mut proc, mut bus) = setup(&[
+                0x10000093, // addi x1, x0, 0x100
+                0xffe00113, // addi x2, x0, -2
+                0x0020a023, // sw x2, 0(x1)
+                0x80000033, // add x0, x0, x0 with a reserved funct7 (unsupported)
+            ]);
+
+            proc.enable_event_log(3);
+
+            for _ in 0 .. 4 {
+                proc.step(&mut bus);
+            }
+
+            let events: Vec<Event> = proc.event_log().unwrap().events().copied().collect();
+
+            assert_eq!(
+                events,
+                [
+                    Event {
+                        pc: 0x04,
+                        instr: Some(0xffe00113),
+                        reg_write: Some((2, 0xfffffffe)),
+                        ..Event::default()
+                    },
+                    Event {
+                        pc: 0x08,
+                        instr: Some(0x0020a023),
+                        mem_write: Some((0x100, 4, 0xfffffffe)),
+                        ..Event::default()
+                    },
+                    Event {
+                        pc: 0x0c,
+                        instr: Some(0x80000033),
+                        trap: Some(Trap::IllegalInstruction(0x80000033)),
+                        ..Event::default()
+                    },
+                ],
+            );
+        }
+
+        #[test]
+        fn is_disabled_by_default() {
+            let (mut proc, mut bus) = setup(&[0x00000013]);
+            proc.step(&mut bus);
+            assert!(proc.event_log().is_none());
+        }
+    }
+
+    mod decode_cache {
+        use crate::decode_cache::CodeCoherence;
+
+        use super::*;
+
+        /// Calls a routine at 0x14 that sets `x5` to 1, overwrites it with
+        /// one that sets `x5` to 2, and calls it again.
+        const STORE_THEN_EXECUTE: [u32; 7] = [
+            0x014000ef, // jal x1, 20
+            0x00200137, // lui x2, 0x200
+            0x29310113, // addi x2, x2, 0x293 (addi x5, x0, 2)
+            0x00202a23, // sw x2, 20(x0)
+            0x004000ef, // jal x1, 4
+            0x00100293, // addi x5, x0, 1
+            0x00008067, // jalr x0, 0(x1)
+        ];
+
+        /// Runs `STORE_THEN_EXECUTE` and returns the final value of `x5`.
+        fn run_store_then_execute(coherence: CodeCoherence) -> u32 {
+            let (mut proc, mut bus) = setup(&STORE_THEN_EXECUTE);
+            proc.enable_decode_cache(coherence);
+
+            for _ in 0 .. 8 {
+                assert_eq!(proc.step(&mut bus), StepResult::Continued);
+            }
+
+            proc.reg_x.read(5)
+        }
+
+        #[test]
+        fn relaxed_executes_new_code_without_fence_i() {
+            assert_eq!(run_store_then_execute(CodeCoherence::Relaxed), 2);
+        }
+
+        #[test]
+        fn strict_executes_stale_code_without_fence_i() {
+            assert_eq!(run_store_then_execute(CodeCoherence::Strict), 1);
+        }
+
+        #[test]
+        fn strict_executes_new_code_after_fence_i() {
+            let (mut proc, mut bus) = setup(&[
+                0x018000ef, // jal x1, 24
+                0x00200137, // lui x2, 0x200
+                0x29310113, // addi x2, x2, 0x293 (addi x5, x0, 2)
+                0x00202c23, // sw x2, 24(x0)
+                0x0000100f, // fence.i
+                0x004000ef, // jal x1, 4
+                0x00100293, // addi x5, x0, 1
+                0x00008067, // jalr x0, 0(x1)
+            ]);
+            proc.enable_decode_cache(CodeCoherence::Strict);
+
+            for _ in 0 .. 9 {
+                assert_eq!(proc.step(&mut bus), StepResult::Continued);
+            }
+
+            assert_eq!(proc.reg_x.read(5), 2);
+        }
+    }
+
+    mod profile {
+        use crate::op::Op::*;
+
+        use super::*;
+
+        #[test]
+        fn counts_completed_operations() {
+            let (mut proc, mut bus) = setup(&[
+                0x00500093, // addi x1, x0, 5
+                0x00108093, // addi x1, x1, 1
+                0x001080b3, // add x1, x1, x1
+                0x00000000, // illegal
+            ]);
+            proc.enable_profile();
+
+            for _ in 0 .. 4 {
+                proc.step(&mut bus);
+            }
+
+            let profile = proc.profile().unwrap();
+            assert_eq!(profile.count(ArithmeticAddImmediate), 2);
+            assert_eq!(profile.count(ArithmeticAdd), 1);
+            assert_eq!(profile.total(), 3);
+        }
+
+        #[test]
+        fn counts_canonical_nop_apart_from_addi() {
+            let (mut proc, mut bus) = setup(&[
+                0x00000013, // nop
+                0x00000013, // nop
+                0x00100093, // addi x1, x0, 1
+            ]);
+            proc.enable_profile();
+
+            for _ in 0 .. 3 {
+                proc.step(&mut bus);
+            }
+
+            let profile = proc.profile().unwrap();
+            assert_eq!(profile.nops(), 2);
+            assert_eq!(profile.count(ArithmeticAddImmediate), 1);
+        }
+
+        #[test]
+        fn is_disabled_by_default() {
+            assert!(Processor::new().profile().is_none());
+        }
+    }
+
+    mod trace {
+        use std::cell::RefCell;
+        use std::rc::Rc;
+
+        use crate::trace::TraceReader;
+
+        use super::*;
+
+        #[test]
+        fn writes_a_record_per_step() {
+            let (mut proc, mut bus) = setup(&[
+                0x00500093, // addi x1, x0, 5
+                0x0000006f, // jal x0, 0
+            ]);
+
+            let bytes = Rc::new(RefCell::new(Vec::new()));
+            let sink = bytes.clone();
+            proc.enable_trace(Box::new(move |record| sink.borrow_mut().extend(record)));
+
+            for _ in 0 .. 3 {
+                proc.step(&mut bus);
+            }
+
+            let bytes = bytes.borrow();
+            let steps: Vec<_> = TraceReader::new(&bytes)
+                .map(|event| (event.pc, event.instr, event.reg_write))
+                .collect();
+
+            assert_eq!(
+                steps,
+                [
+                    (0x00, Some(0x00500093), Some((1, 5))),
+                    (0x04, Some(0x0000006f), None),
+                    (0x04, Some(0x0000006f), None),
+                ],
+            );
+        }
+    }
+
+    mod memory_hook {
+        use std::cell::RefCell;
+        use std::rc::Rc;
+
+        use super::*;
+        use AccessKind::*;
+
+        type Accesses = Rc<RefCell<Vec<(u32, usize, AccessKind)>>>;
+
+        fn record_accesses(proc: &mut Processor) -> Accesses {
+            let accesses = Accesses::default();
+            let sink = accesses.clone();
+
+            proc.set_memory_hook(Box::new(move |addr, size, kind| {
+                sink.borrow_mut().push((addr, size, kind));
+            }));
+
+            accesses
+        }
+
+        #[test]
+        fn reports_load_then_store() {
+            let (mut proc, mut bus) = setup(&[
+                0x10002083, // lw x1, 0x100(x0)
+                0x10102223, // sw x1, 0x104(x0)
+            ]);
+
+            let accesses = record_accesses(&mut proc);
+            proc.step(&mut bus);
+            proc.step(&mut bus);
+
+            assert_eq!(*accesses.borrow(), [(0x100, 4, Read), (0x104, 4, Write)]);
+        }
+
+        #[test]
+        fn reports_access_sizes() {
+            let (mut proc, mut bus) = setup(&[
+                0x101001a3, // sb x1, 0x103(x0)
+                0x10204103, // lbu x2, 0x102(x0)
+            ]);
+
+            let accesses = record_accesses(&mut proc);
+            proc.step(&mut bus);
+            proc.step(&mut bus);
+
+            assert_eq!(*accesses.borrow(), [(0x103, 1, Write), (0x102, 1, Read)]);
+        }
+
+        #[test]
+        fn ignores_fetches_and_stops_when_cleared() {
+            let (mut proc, mut bus) = setup(&[
+                0x00500093, // addi x1, x0, 5
+                0x10002083, // lw x1, 0x100(x0)
+            ]);
+
+            let accesses = record_accesses(&mut proc);
+            proc.step(&mut bus);
+            proc.clear_memory_hook();
+            proc.step(&mut bus);
+
+            assert!(accesses.borrow().is_empty());
+        }
+    }
+
+    mod undo {
+        use super::*;
+
+        #[test]
+        fn restores_register_and_pc() {
+            // addi x1, x0, 5
+            let (mut proc, mut bus) = setup(&[0x00500093]);
+            proc.enable_undo(1);
+            proc.reg_x.write(1, 0x42);
+
+            proc.step(&mut bus);
+            assert_eq!(proc.reg_x.read(1), 5);
+
+            assert!(proc.undo(&mut bus));
+            assert_eq!(proc.reg_x.read(1), 0x42);
+            assert_eq!(proc.pc, 0x00);
+            assert_eq!(proc.instret, 0);
+            assert_eq!(proc.cycle, 0);
+        }
+
+        #[test]
+        fn restores_stored_memory() {
+            // sw x1, 0x100(x0)
+            let (mut proc, mut bus) = setup(&[0x10102023]);
+            proc.enable_undo(1);
+            proc.reg_x.write(1, 0xdeadbeef);
+            bus.memory.write(0x100, &[1, 2, 3, 4]);
+
+            proc.step(&mut bus);
+            assert!(proc.undo(&mut bus));
+
+            assert_eq!(bus.memory.read(0x100, 4), [1, 2, 3, 4]);
+            assert_eq!(proc.pc, 0x00);
+        }
+
+        #[test]
+        fn undoes_up_to_depth_steps() {
+            let (mut proc, mut bus) = setup(&[
+                0x00500093, // addi x1, x0, 5
+                0x00108093, // addi x1, x1, 1
+                0x00108093, // addi x1, x1, 1
+            ]);
+
+            proc.enable_undo(2);
+
+            for _ in 0 .. 3 {
+                proc.step(&mut bus);
+            }
+
+            assert!(proc.undo(&mut bus));
+            assert!(proc.undo(&mut bus));
+            assert!(!proc.undo(&mut bus));
+
+            assert_eq!(proc.reg_x.read(1), 5);
+            assert_eq!(proc.pc, 0x04);
+        }
+
+        #[test]
+        fn does_nothing_when_disabled() {
+            let (mut proc, mut bus) = setup(&[0x00500093]);
+
+            proc.step(&mut bus);
+
+            assert!(!proc.undo(&mut bus));
+            assert_eq!(proc.pc, 0x04);
+        }
+    }
+
+    mod context {
+        use super::*;
+
+        #[test]
+        fn restores_saved_state_exactly() {
+            let (mut proc, mut bus) = setup(&[
+                0x00500093, // addi x1, x0, 5
+                0x00700113, // addi x2, x0, 7
+            ]);
+            proc.reg_x.write(31, 0xdeadbeef);
+            proc.csr.write(MEPC, 0x40);
+            proc.csr.write(MTVEC, 0x100);
+            proc.privilege = PrivilegeMode::Supervisor;
+
+            let context = proc.save_context();
+
+            assert_eq!(proc.step(&mut bus), StepResult::Continued);
+            assert_eq!(proc.step(&mut bus), StepResult::Continued);
+            proc.reg_x.write(31, 0);
+            proc.csr.write(MEPC, 0);
+            proc.privilege = PrivilegeMode::Machine;
+
+            proc.restore_context(&context);
+
+            assert_eq!(proc.save_context(), context);
+            assert_eq!(proc.pc, 0x00);
+            assert_eq!(proc.reg_x.read(1), 0);
+            assert_eq!(proc.reg_x.read(31), 0xdeadbeef);
+            assert_eq!(proc.csr.read(MEPC), 0x40);
+            assert_eq!(proc.privilege, PrivilegeMode::Supervisor);
+        }
+
+        #[test]
+        fn keeps_x0_zero() {
+            let mut proc = Processor::new();
+            let mut context = proc.save_context();
+            context.x[0] = 1;
+
+            proc.restore_context(&context);
+            assert_eq!(proc.reg_x.read(0), 0);
+        }
+    }
+
+    mod counters {
+        use crate::bus::tests::MockDevice;
+
+        use super::*;
+
+        #[test]
+        fn count_one_cycle_per_instruction_by_default() {
+            let (mut proc, mut bus) = setup(&[0x00000013, 0x00000013]);
+            proc.step(&mut bus);
+            proc.step(&mut bus);
+            assert_eq!(proc.cycle, 2);
+            assert_eq!(proc.instret, 2);
+        }
+
+        #[test]
+        fn high_latency_region_advances_cycle_more_than_instret() {
+            let (mut proc, mut bus) = setup(&[
+                0x000100b7, // lui x1, 0x10
+                0x0000a183, // lw x3, 0(x1)
+            ]);
+
+            bus.register_with_latency(
+                0x10000,
+                0x100,
+                20,
+                Box::new(MockDevice { writes: Default::default(), read_value: 0x00 }),
+            );
+
+            proc.step(&mut bus);
+            proc.step(&mut bus);
+            assert_eq!(proc.instret, 2);
+            assert_eq!(proc.cycle, 21);
+        }
+
+        #[test]
+        fn trapped_instruction_is_not_retired() {
+            // add x0, x0, x0 with a reserved funct7 (unsupported)
+            let (mut proc, mut bus) = setup(&[0x80000033]);
+            proc.csr.write(MTVEC, 0x100);
+            proc.step(&mut bus);
+            assert_eq!(proc.cycle, 1);
+            assert_eq!(proc.instret, 0);
+        }
+    }
+
+    mod interrupt {
+        use super::*;
+
+        /// Creates a processor with a handler installed and machine
+        /// software interrupts enabled.
+        fn setup_enabled(mtvec: u32) -> (Processor, Bus) {
+            let (mut proc, bus) = setup(&[0x00000013]);
+            proc.csr.write(MTVEC, mtvec);
+            proc.csr.write(MIE, Interrupt::MachineSoftware.mask());
+            proc.csr.write(MSTATUS, MSTATUS_MIE);
+            (proc, bus)
+        }
+
+        #[test]
+        fn wfi_idles_until_enabled_interrupt_is_pending() {
+            // wfi
+            let (mut proc, mut bus) = setup(&[0x10500073, 0x00000013]);
+            proc.csr.write(MIE, Interrupt::MachineSoftware.mask());
+
+            assert_eq!(proc.step(&mut bus), StepResult::Continued);
+            assert_eq!(proc.state, HartState::WaitingForInterrupt);
+            assert_eq!(proc.step(&mut bus), StepResult::Idle);
+            assert_eq!(proc.pc, 0x04);
+
+            // Interrupts are globally disabled, so the processor resumes
+            // after the `wfi` rather than taking the interrupt.
+            proc.raise_interrupt(Interrupt::MachineSoftware);
+            assert_eq!(proc.step(&mut bus), StepResult::Continued);
+            assert_eq!(proc.state, HartState::Running);
+            assert_eq!(proc.pc, 0x08);
+            assert_eq!(proc.instret, 2);
+        }
+
+        #[test]
+        fn halted_processor_does_nothing() {
+            let (mut proc, mut bus) = setup(&[0x00000013]);
+            proc.state = HartState::Halted;
+            assert_eq!(proc.step(&mut bus), StepResult::Stopped);
+            assert_eq!(proc.pc, 0x00);
+        }
+
+        #[test]
+        fn raise_sets_pending_bit() {
+            let mut proc = Processor::new();
+            proc.raise_interrupt(Interrupt::MachineTimer);
+            assert_eq!(proc.csr.read(MIP), 1 << 7);
+            proc.clear_interrupt(Interrupt::MachineTimer);
+            assert_eq!(proc.csr.read(MIP), 0);
+        }
+
+        #[test]
+        fn enabled_software_interrupt_vectors_to_mtvec() {
+            let (mut proc, mut bus) = setup_enabled(0x100);
+            proc.raise_interrupt(Interrupt::MachineSoftware);
+            assert_eq!(
+                proc.step(&mut bus),
+                StepResult::Trapped(Trap::Interrupt(Interrupt::MachineSoftware)),
+            );
+
+            assert_eq!(proc.pc, 0x100);
+            assert_eq!(proc.csr.read(MEPC), 0x00);
+            assert_eq!(proc.csr.read(MCAUSE), 0x80000003);
+            assert_eq!(
+                proc.pending_trap().unwrap().trap,
+                Trap::Interrupt(Interrupt::MachineSoftware),
+            );
+        }
+
+        #[test]
+        fn trap_entry_disables_interrupts() {
+            let (mut proc, mut bus) = setup_enabled(0x100);
+            proc.raise_interrupt(Interrupt::MachineSoftware);
+            assert_eq!(
+                proc.step(&mut bus),
+                StepResult::Trapped(Trap::Interrupt(Interrupt::MachineSoftware)),
+            );
+            assert_eq!(proc.csr.read(MSTATUS), MSTATUS_MPIE | MSTATUS_MPP);
+        }
+
+        #[test]
+        fn vectored_mode_offsets_handler_by_cause() {
+            let (mut proc, mut bus) = setup_enabled(0x101);
+            proc.raise_interrupt(Interrupt::MachineSoftware);
+            assert_eq!(
+                proc.step(&mut bus),
+                StepResult::Trapped(Trap::Interrupt(Interrupt::MachineSoftware)),
+            );
+            assert_eq!(proc.pc, 0x10c);
+        }
+
+        #[test]
+        fn is_not_taken_when_globally_disabled() {
+            let (mut proc, mut bus) = setup_enabled(0x100);
+            proc.csr.write(MSTATUS, 0x00);
+            proc.raise_interrupt(Interrupt::MachineSoftware);
+            assert_eq!(proc.step(&mut bus), StepResult::Continued);
+            assert_eq!(proc.pc, 0x04);
+        }
+
+        #[test]
+        fn is_not_taken_when_not_enabled_in_mie() {
+            let (mut proc, mut bus) = setup_enabled(0x100);
+            proc.raise_interrupt(Interrupt::MachineTimer);
+            assert_eq!(proc.step(&mut bus), StepResult::Continued);
+            assert_eq!(proc.pc, 0x04);
+        }
     }
 }
@@ -0,0 +1,150 @@
+//! Profile
+//! Counts how many times a processor executed each operation, for
+//! characterizing workloads.
+//!
+//! The canonical `nop` is counted on its own rather than as an `addi`, so
+//! padding doesn't inflate the count of real additions.
+
+use alloc::collections::BTreeMap;
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+
+use crate::op::Op;
+
+/// A histogram of the operations a processor has executed.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Profile {
+    counts: BTreeMap<Op, u64>,
+
+    /// The number of canonical `nop`s executed.
+    nops: u64,
+}
+
+impl Profile {
+    /// Creates an empty profile.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records one execution of `op`.
+    pub fn record(&mut self, op: Op) {
+        *self.counts.entry(op).or_insert(0) += 1;
+    }
+
+    /// Records one execution of the canonical `nop`.
+    pub fn record_nop(&mut self) {
+        self.nops += 1;
+    }
+
+    /// Returns the number of times `op` was executed, not counting `nop`s.
+    pub fn count(&self, op: Op) -> u64 {
+        self.counts.get(&op).copied().unwrap_or(0)
+    }
+
+    /// Returns the number of canonical `nop`s executed.
+    pub fn nops(&self) -> u64 {
+        self.nops
+    }
+
+    /// Returns the total number of operations executed, including `nop`s.
+    pub fn total(&self) -> u64 {
+        self.counts.values().sum::<u64>() + self.nops
+    }
+
+    /// Returns each executed operation and its count, most frequent
+    /// first. Operations with equal counts are ordered by mnemonic.
+    pub fn sorted(&self) -> Vec<(Op, u64)> {
+        let mut sorted: Vec<(Op, u64)> = self.counts
+            .iter()
+            .map(|(&op, &count)| (op, count))
+            .collect();
+
+        sorted.sort_by_cached_key(|&(op, count)| (u64::MAX - count, op.to_string()));
+        sorted
+    }
+
+    /// Returns the profile as CSV, with a `mnemonic,count` header and a
+    /// row per operation, most frequent first and then by mnemonic. `nop`s
+    /// have a row of their own if any were executed.
+    pub fn to_csv(&self) -> String {
+        let mut rows: Vec<(String, u64)> = self.sorted()
+            .into_iter()
+            .map(|(op, count)| (op.to_string(), count))
+            .collect();
+
+        if self.nops > 0 {
+            rows.push(("nop".to_string(), self.nops));
+            rows.sort_by(|(a, a_count), (b, b_count)| b_count.cmp(a_count).then(a.cmp(b)));
+        }
+
+        let mut csv = String::from("mnemonic,count\n");
+
+        for (mnemonic, count) in rows {
+            csv += &format!("{mnemonic},{count}\n");
+        }
+
+        csv
+    }
+
+    /// Discards all recorded counts.
+    pub fn clear(&mut self) {
+        self.counts.clear();
+        self.nops = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Op::*, Profile};
+
+    fn profile(ops: &[super::Op]) -> Profile {
+        let mut profile = Profile::new();
+
+        for &op in ops {
+            profile.record(op);
+        }
+
+        profile
+    }
+
+    #[test]
+    fn counts_each_operation() {
+        let profile = profile(&[ArithmeticAdd, LoadWord, ArithmeticAdd]);
+
+        assert_eq!(profile.count(ArithmeticAdd), 2);
+        assert_eq!(profile.count(LoadWord), 1);
+        assert_eq!(profile.count(StoreWord), 0);
+        assert_eq!(profile.total(), 3);
+    }
+
+    #[test]
+    fn sorts_by_count_then_mnemonic() {
+        let profile = profile(&[StoreWord, LoadWord, ArithmeticAdd, LoadWord]);
+
+        assert_eq!(
+            profile.sorted(),
+            [(LoadWord, 2), (ArithmeticAdd, 1), (StoreWord, 1)],
+        );
+    }
+
+    #[test]
+    fn writes_csv_rows_in_sorted_order() {
+        let profile = profile(&[BranchNotEqual, ArithmeticAddImmediate, ArithmeticAddImmediate]);
+
+        assert_eq!(profile.to_csv(), "mnemonic,count\naddi,2\nbne,1\n");
+        assert_eq!(Profile::new().to_csv(), "mnemonic,count\n");
+    }
+
+    #[test]
+    fn counts_nops_apart_from_addi() {
+        let mut profile = profile(&[ArithmeticAddImmediate, BranchNotEqual]);
+        profile.record_nop();
+        profile.record_nop();
+
+        assert_eq!(profile.count(ArithmeticAddImmediate), 1);
+        assert_eq!(profile.nops(), 2);
+        assert_eq!(profile.total(), 4);
+        assert_eq!(profile.to_csv(), "mnemonic,count\nnop,2\naddi,1\nbne,1\n");
+    }
+}
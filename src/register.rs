@@ -1,6 +1,20 @@
+use alloc::boxed::Box;
+
 /// An alias for the RISC-V general purpose registers.
 pub type RegistersX = Registers<u32, 32>;
 
+/// The ABI names of the integer registers, indexed by register number.
+pub(crate) const ABI_NAMES: [&str; 32] = [
+    "zero", "ra", "sp", "gp", "tp", "t0", "t1", "t2",
+    "s0", "s1", "a0", "a1", "a2", "a3", "a4", "a5",
+    "a6", "a7", "s2", "s3", "s4", "s5", "s6", "s7",
+    "s8", "s9", "s10", "s11", "t3", "t4", "t5", "t6",
+];
+
+/// An alias for the RISC-V control and status registers, addressed by
+/// their 12-bit CSR number.
+pub type RegistersCsr = Registers<u32, 4096>;
+
 /// The read/write access level of a register.
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
 pub enum AccessLevel {
@@ -9,10 +23,25 @@ pub enum AccessLevel {
 }
 
 /// A group of registers of a generic size and quantity.
+///
+/// A group can optionally have a second bank of values, such as shadow
+/// registers for another privilege mode. Reads and writes go to the active
+/// bank, and both banks share the same access levels.
 #[derive(Debug)]
 pub struct Registers<T, const U: usize> {
     access_levels: [AccessLevel; U],
+
+    /// The values of the active bank.
     values: [T; U],    
+
+    /// The value `reset` fills writable registers with, if set.
+    poison: Option<T>,
+
+    /// The values of the inactive bank, if banking is enabled.
+    shadow: Option<Box<[T; U]>>,
+
+    /// Whether the second bank is the active one.
+    alternate: bool,
 }
 
 impl<T: Copy + Default, const U: usize> Registers<T, U> {
@@ -21,6 +50,9 @@ impl<T: Copy + Default, const U: usize> Registers<T, U> {
         Self {
             access_levels: [AccessLevel::Read; U],
             values: [T::default(); U],
+            poison: None,
+            shadow: None,
+            alternate: false,
         }
     }
 
@@ -36,9 +68,86 @@ impl<T: Copy + Default, const U: usize> Registers<T, U> {
         }
     }
 
-    /// Resets all registers to their default values.
+    /// Writes a value to a register at a given index, regardless of its
+    /// access level.
+    ///
+    /// This is intended for emulator infrastructure such as trap entry and
+    /// debuggers, not for guest instruction execution. Note that the zero
+    /// register is only hardwired through its access level, so this will
+    /// write to it too.
+    pub fn write_unchecked(&mut self, index: usize, value: T) {
+        self.values[index] = value;
+    }
+
+    /// Resets all registers in both banks to their default values, or
+    /// writable registers to the poison pattern if one is set.
     pub fn reset(&mut self) {
-        self.values.fill(T::default());
+        self.reset_bank(false);
+
+        if self.shadow.is_some() {
+            self.reset_bank(true);
+        }
+    }
+
+    /// Resets the registers of the active bank, or the inactive one if
+    /// `inactive` is set.
+    fn reset_bank(&mut self, inactive: bool) {
+        let values = match (inactive, self.shadow.as_mut()) {
+            (true, Some(shadow)) => &mut **shadow,
+            _ => &mut self.values,
+        };
+
+        values.fill(T::default());
+
+        if let Some(poison) = self.poison {
+            for (index, value) in values.iter_mut().enumerate() {
+                if self.access_levels[index] != AccessLevel::Read {
+                    *value = poison;
+                }
+            }
+        }
+    }
+
+    /// Adds a second bank of registers, reset like the first. Does nothing
+    /// if banking is already enabled.
+    pub fn enable_banking(&mut self) {
+        if self.shadow.is_none() {
+            self.shadow = Some(Box::new([T::default(); U]));
+            self.reset_bank(true);
+        }
+    }
+
+    /// Selects the second bank if `alternate` is set, or the first bank
+    /// otherwise.
+    ///
+    /// Panics if selecting the second bank when banking isn't enabled.
+    pub fn select_bank(&mut self, alternate: bool) {
+        if alternate == self.alternate {
+            return;
+        }
+
+        match self.shadow.as_mut() {
+            Some(shadow) => core::mem::swap(&mut self.values, shadow),
+            None => panic!("Register banking is not enabled."),
+        }
+
+        self.alternate = alternate;
+    }
+
+    /// Returns whether the second bank is the active one.
+    pub fn is_alternate_bank(&self) -> bool {
+        self.alternate
+    }
+
+    /// Sets the pattern writable registers are reset to, or `None` to reset
+    /// them to their default value, then resets the registers.
+    ///
+    /// Poisoning makes reads of registers that were never written stand
+    /// out, rather than silently reading as zero. Read-only registers, such
+    /// as the zero register, keep their default value.
+    pub fn set_poison(&mut self, poison: Option<T>) {
+        self.poison = poison;
+        self.reset();
     }
 
     /// Returns the number of registers in the collection.
@@ -55,9 +164,29 @@ impl<T: Copy + Default, const U: usize> Registers<T, U> {
     pub fn set_access_level(&mut self, index: usize, access_level: AccessLevel) {
         self.access_levels[index] = access_level;
     }
+
+    /// Returns a copy of the values of the active bank, to compare against
+    /// later with `diff`.
+    pub fn snapshot(&self) -> [T; U] {
+        self.values
+    }
+}
+
+impl<T: Copy + PartialEq, const U: usize> Registers<T, U> {
+    /// Returns the index, old value and new value of each register in the
+    /// active bank whose value differs from a `snapshot`, in index order.
+    pub fn diff<'a>(&'a self, before: &'a [T; U]) -> impl Iterator<Item = (usize, T, T)> + 'a {
+        before
+            .iter()
+            .zip(&self.values)
+            .enumerate()
+            .filter(|(_, (old, new))| old != new)
+            .map(|(index, (&old, &new))| (index, old, new))
+    }
 }
 
 #[cfg(test)]
+#[allow(clippy::bool_assert_comparison)]
 mod tests {
     use super::{AccessLevel, Registers};
 
@@ -97,6 +226,50 @@ mod tests {
         );
     }
 
+    #[test]
+    fn poison_fills_only_writable_registers() {
+        let mut regs = Registers::<u32, REG_COUNT>::new();
+
+        for i in 1 .. REG_COUNT {
+            regs.set_access_level(i, AccessLevel::ReadWrite);
+        }
+
+        regs.set_poison(Some(0xdeadbeef));
+        assert_eq!(regs.read(0), 0x00);
+        assert_eq!(regs.read(5), 0xdeadbeef);
+
+        regs.write(5, 0x01);
+        assert_eq!(regs.read(5), 0x01);
+
+        regs.reset();
+        assert_eq!(regs.read(5), 0xdeadbeef);
+    }
+
+    #[test]
+    fn banks_hold_independent_values() {
+        let mut regs = Registers::<u32, REG_COUNT>::new();
+        regs.set_access_level(1, AccessLevel::ReadWrite);
+        regs.enable_banking();
+
+        regs.write(1, 0x11);
+        regs.select_bank(true);
+        assert!(regs.is_alternate_bank());
+        assert_eq!(regs.read(1), 0x00);
+
+        regs.write(1, 0x22);
+        regs.select_bank(false);
+        assert_eq!(regs.read(1), 0x11);
+
+        regs.select_bank(true);
+        assert_eq!(regs.read(1), 0x22);
+    }
+
+    #[test]
+    #[should_panic]
+    fn panics_on_selecting_second_bank_without_banking() {
+        Registers::<u32, REG_COUNT>::new().select_bank(true);
+    }
+
     #[test]
     fn ignores_write_attempt_to_register_with_read_only_access() {
         let mut regs = Registers::<u32, REG_COUNT>::new();
@@ -115,6 +288,21 @@ mod tests {
         assert_eq!(regs.read(INDEX), VALUE);
     }
 
+    #[test]
+    fn write_unchecked_ignores_read_only_access() {
+        let mut regs = Registers::<u32, REG_COUNT>::new();
+        regs.write(4, 0xff);
+        assert_eq!(regs.read(4), 0x00);
+        regs.write_unchecked(4, 0xff);
+        assert_eq!(regs.read(4), 0xff);
+    }
+
+    #[test]
+    #[should_panic]
+    fn panics_on_attempt_to_write_unchecked_to_nonexistent_register() {
+        Registers::<u32, REG_COUNT>::new().write_unchecked(REG_COUNT, 0x00);
+    }
+
     #[test]
     fn resets_register_values() {
         const INDEX: usize = 15;
@@ -128,6 +316,20 @@ mod tests {
         assert_eq!(regs.read(INDEX), u32::default());
     }
 
+    #[test]
+    fn diff_reports_registers_changed_since_snapshot() {
+        let mut regs = Registers::<u32, REG_COUNT>::new();
+        regs.set_access_level(3, AccessLevel::ReadWrite);
+        regs.set_access_level(7, AccessLevel::ReadWrite);
+        regs.write(7, 0x10);
+
+        let before = regs.snapshot();
+        regs.write(3, 0x01);
+        regs.write(7, 0x10);
+
+        assert_eq!(regs.diff(&before).collect::<Vec<_>>(), [(3, 0x00, 0x01)]);
+    }
+
     #[test]
     fn len_returns_correct_register_count() {
         assert_eq!(
@@ -0,0 +1,153 @@
+//! Supervisor Binary Interface
+//! Emulates the SBI firmware that S-mode software calls with `ecall`, so
+//! kernels can run without M-mode firmware of their own.
+//!
+//! Following the SBI calling convention, the extension ID is passed in
+//! `a7`, the function ID in `a6` and the arguments in `a0`-`a5`. The error
+//! code is returned in `a0` and the value in `a1`.
+
+use crate::bus::Bus;
+use crate::uart::UartOutput;
+
+/// The legacy `sbi_console_putchar` extension, which writes `a0` to the
+/// console.
+const LEGACY_CONSOLE_PUTCHAR: u32 = 0x01;
+
+/// The highest legacy extension ID. Legacy extensions return their result
+/// in `a0` alone and leave every other register untouched.
+const LEGACY_EXTENSION_MAX: u32 = 0x0f;
+
+/// Whether `extension` is one of the legacy extensions, which return only
+/// `a0`.
+pub fn is_legacy_extension(extension: u32) -> bool {
+    extension <= LEGACY_EXTENSION_MAX
+}
+
+/// The call completed successfully.
+pub const SBI_SUCCESS: i32 = 0;
+
+/// The extension or function isn't implemented.
+pub const SBI_ERR_NOT_SUPPORTED: i32 = -2;
+
+/// The result of an SBI call.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct SbiRet {
+    /// The error code, returned in `a0`. Legacy extensions return their
+    /// result here.
+    pub error: i32,
+
+    /// The value, returned in `a1`. Ignored for legacy extensions.
+    pub value: u32,
+}
+
+impl SbiRet {
+    /// A successful result with the given value.
+    pub fn success(value: u32) -> Self {
+        Self {
+            error: SBI_SUCCESS,
+            value,
+        }
+    }
+
+    /// The result of calling an extension or function that isn't
+    /// implemented.
+    pub fn not_supported() -> Self {
+        Self {
+            error: SBI_ERR_NOT_SUPPORTED,
+            value: 0,
+        }
+    }
+}
+
+/// Handles SBI calls made with `ecall` from S-mode.
+pub trait SbiHandler {
+    /// Handles function `function` of extension `extension` with the
+    /// arguments from `a0`-`a5`.
+    fn sbi_call(&mut self, extension: u32, function: u32, args: [u32; 6], bus: &mut Bus) -> SbiRet;
+}
+
+/// A minimal SBI implementation providing only the legacy console, which
+/// writes characters to an output sink. Every other call returns
+/// `SBI_ERR_NOT_SUPPORTED`.
+pub struct SbiConsole {
+    output: UartOutput,
+}
+
+impl SbiConsole {
+    /// Creates a console that writes characters to `output`.
+    pub fn new(output: UartOutput) -> Self {
+        Self { output }
+    }
+
+    /// Creates a console that writes characters to the host's stdout.
+    #[cfg(feature = "std")]
+    pub fn stdio() -> Self {
+        use alloc::boxed::Box;
+        use std::io::Write;
+
+        Self::new(Box::new(|byte| {
+            // Output is best-effort, as there's nowhere to report a failure.
+            let mut stdout = std::io::stdout();
+            let _ = stdout.write_all(&[byte]).and_then(|()| stdout.flush());
+        }))
+    }
+}
+
+impl SbiHandler for SbiConsole {
+    fn sbi_call(&mut self, extension: u32, _function: u32, args: [u32; 6], _bus: &mut Bus) -> SbiRet {
+        match extension {
+            LEGACY_CONSOLE_PUTCHAR => {
+                (self.output)(args[0] as u8);
+                SbiRet::success(0)
+            },
+
+            _ => SbiRet::not_supported(),
+        }
+    }
+}
+
+impl core::fmt::Debug for SbiConsole {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("SbiConsole").finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    use crate::memory::Memory;
+
+    use super::{Bus, SbiConsole, SbiHandler, SbiRet, LEGACY_CONSOLE_PUTCHAR};
+
+    fn console() -> (SbiConsole, Rc<RefCell<Vec<u8>>>) {
+        let output = Rc::new(RefCell::new(Vec::new()));
+        let sink = output.clone();
+
+        (SbiConsole::new(Box::new(move |byte| sink.borrow_mut().push(byte))), output)
+    }
+
+    #[test]
+    fn console_putchar_writes_low_byte_of_a0() {
+        let (mut console, output) = console();
+        let mut bus = Bus::new(Memory::new(0));
+
+        let ret = console.sbi_call(LEGACY_CONSOLE_PUTCHAR, 0, [0x141, 0, 0, 0, 0, 0], &mut bus);
+
+        assert_eq!(ret, SbiRet::success(0));
+        assert_eq!(*output.borrow(), b"A");
+    }
+
+    #[test]
+    fn other_extensions_are_not_supported() {
+        let (mut console, output) = console();
+        let mut bus = Bus::new(Memory::new(0));
+
+        // The base extension.
+        let ret = console.sbi_call(0x10, 0, [0; 6], &mut bus);
+
+        assert_eq!(ret, SbiRet::not_supported());
+        assert!(output.borrow().is_empty());
+    }
+}
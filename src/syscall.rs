@@ -0,0 +1,217 @@
+//! System Calls
+//! Emulates system calls made with `ecall` when no trap handler is
+//! installed, so simple user programs can run without an operating system.
+//!
+//! Following the Linux RISC-V convention, the system call number is passed
+//! in `a7`, the arguments in `a0`-`a5`, and the return value in `a0`.
+
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+
+use crate::bus::Bus;
+
+/// Linux system call numbers.
+const WRITE: u32 = 64;
+const EXIT: u32 = 93;
+const EXIT_GROUP: u32 = 94;
+const BRK: u32 = 214;
+
+/// `EBADF`, returned (negated) for writes to an unsupported file.
+const EBADF: i32 = 9;
+
+/// `ENOSYS`, returned (negated) for unsupported system calls.
+const ENOSYS: i32 = 38;
+
+/// What the emulator should do after a system call.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum SyscallOutcome {
+    /// Write the value to `a0` and continue after the `ecall`.
+    Return(u32),
+
+    /// Halt with the given exit code.
+    Exit(u32),
+
+    /// The system call isn't handled, so the `ecall` is an unhandled trap.
+    Unsupported,
+}
+
+/// Handles system calls made with `ecall`.
+pub trait SyscallHandler {
+    /// Handles system call `number` with the arguments from `a0`-`a5`.
+    fn syscall(&mut self, number: u32, args: [u32; 6], bus: &mut Bus) -> SyscallOutcome;
+}
+
+/// Where `write` sends its output. Called with the file descriptor and
+/// the bytes written.
+pub type OutputSink = Box<dyn FnMut(u32, &[u8])>;
+
+/// A minimal subset of the Linux system calls: `write` to stdout and
+/// stderr, `exit`, `exit_group` and `brk`.
+pub struct LinuxSyscalls {
+    sink: OutputSink,
+
+    /// The lowest address the program break can be set to.
+    heap_start: u32,
+
+    /// The current program break.
+    brk: u32,
+}
+
+impl LinuxSyscalls {
+    /// Creates a handler whose heap starts at `heap_start` and whose output
+    /// goes to `sink`.
+    pub fn new(heap_start: u32, sink: OutputSink) -> Self {
+        Self {
+            sink,
+            heap_start,
+            brk: heap_start,
+        }
+    }
+
+    /// Creates a handler whose heap starts at `heap_start` and whose output
+    /// goes to the host's stdout and stderr.
+    #[cfg(feature = "std")]
+    pub fn stdio(heap_start: u32) -> Self {
+        use std::io::Write;
+
+        Self::new(heap_start, Box::new(|fd, bytes| {
+            // Output is best-effort, as there's nowhere to report a failure.
+            let _ = match fd {
+                1 => std::io::stdout().write_all(bytes),
+                _ => std::io::stderr().write_all(bytes),
+            };
+        }))
+    }
+
+    /// `write(fd, buf, count)`
+    fn write(&mut self, fd: u32, buf: u32, count: u32, bus: &mut Bus) -> u32 {
+        if !matches!(fd, 1 | 2) {
+            return (-EBADF) as u32;
+        }
+
+        let bytes: Vec<u8> = (0 .. count)
+            .map(|i| bus.read(buf.wrapping_add(i), 1) as u8)
+            .collect();
+
+        (self.sink)(fd, &bytes);
+        count
+    }
+
+    /// `brk(addr)`
+    ///
+    /// Returns the new program break, or the current one if `addr` is zero
+    /// or outside `[heap_start, memory size]`.
+    fn brk(&mut self, addr: u32, bus: &Bus) -> u32 {
+        if addr >= self.heap_start && addr as usize <= bus.memory.len() {
+            self.brk = addr;
+        }
+
+        self.brk
+    }
+}
+
+impl SyscallHandler for LinuxSyscalls {
+    fn syscall(&mut self, number: u32, args: [u32; 6], bus: &mut Bus) -> SyscallOutcome {
+        match number {
+            WRITE => SyscallOutcome::Return(self.write(args[0], args[1], args[2], bus)),
+            EXIT | EXIT_GROUP => SyscallOutcome::Exit(args[0]),
+            BRK => SyscallOutcome::Return(self.brk(args[0], bus)),
+            _ => SyscallOutcome::Return((-ENOSYS) as u32),
+        }
+    }
+}
+
+impl core::fmt::Debug for LinuxSyscalls {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("LinuxSyscalls")
+            .field("heap_start", &self.heap_start)
+            .field("brk", &self.brk)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    use crate::memory::Memory;
+
+    use super::{
+        Bus,
+        LinuxSyscalls,
+        SyscallHandler,
+        SyscallOutcome,
+        BRK,
+        EXIT_GROUP,
+        WRITE,
+    };
+
+    /// The output recorded by the test sink, as `(fd, bytes)`.
+    type Output = Rc<RefCell<Vec<(u32, Vec<u8>)>>>;
+
+    fn linux(heap_start: u32) -> (LinuxSyscalls, Output) {
+        let output: Output = Rc::default();
+        let sink = output.clone();
+
+        let syscalls = LinuxSyscalls::new(heap_start, Box::new(move |fd, bytes| {
+            sink.borrow_mut().push((fd, bytes.to_vec()));
+        }));
+
+        (syscalls, output)
+    }
+
+    #[test]
+    fn write_emits_bytes_to_sink() {
+        let (mut syscalls, output) = linux(0x100);
+        let mut bus = Bus::new(Memory::new(256));
+        bus.memory.write(0x20, b"hello");
+
+        assert_eq!(
+            syscalls.syscall(WRITE, [1, 0x20, 5, 0, 0, 0], &mut bus),
+            SyscallOutcome::Return(5),
+        );
+        assert_eq!(*output.borrow(), [(1, b"hello".to_vec())]);
+    }
+
+    #[test]
+    fn write_to_other_fd_fails() {
+        let (mut syscalls, output) = linux(0x100);
+        let mut bus = Bus::new(Memory::new(256));
+
+        assert_eq!(
+            syscalls.syscall(WRITE, [3, 0x20, 5, 0, 0, 0], &mut bus),
+            SyscallOutcome::Return(-9i32 as u32),
+        );
+        assert!(output.borrow().is_empty());
+    }
+
+    #[test]
+    fn exit_group_exits_with_code() {
+        let (mut syscalls, _) = linux(0x100);
+        let mut bus = Bus::new(Memory::new(256));
+
+        assert_eq!(
+            syscalls.syscall(EXIT_GROUP, [3, 0, 0, 0, 0, 0], &mut bus),
+            SyscallOutcome::Exit(3),
+        );
+    }
+
+    #[test]
+    fn brk_moves_break_within_memory() {
+        let (mut syscalls, _) = linux(0x80);
+        let mut bus = Bus::new(Memory::new(256));
+
+        assert_eq!(syscalls.syscall(BRK, [0; 6], &mut bus), SyscallOutcome::Return(0x80));
+        assert_eq!(
+            syscalls.syscall(BRK, [0xc0, 0, 0, 0, 0, 0], &mut bus),
+            SyscallOutcome::Return(0xc0),
+        );
+
+        // Past the end of memory.
+        assert_eq!(
+            syscalls.syscall(BRK, [0x200, 0, 0, 0, 0, 0], &mut bus),
+            SyscallOutcome::Return(0xc0),
+        );
+    }
+}
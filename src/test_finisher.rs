@@ -0,0 +1,93 @@
+//! Test Finisher
+//! The SiFive test finisher, a memory-mapped register that bare-metal test
+//! programs write to power off the machine with a pass or fail code. It's
+//! mapped at the same address as on QEMU's `virt` machine.
+
+use crate::bus::{DeviceError, MmioDevice};
+use crate::emulator::HaltReason;
+
+/// The base address the test finisher is conventionally mapped at.
+pub const TEST_FINISHER_BASE: u32 = 0x0010_0000;
+
+/// The size of the test finisher's address range in bytes.
+pub const TEST_FINISHER_SIZE: u32 = 0x1000;
+
+/// Powers off with exit code zero.
+const FINISHER_PASS: u32 = 0x5555;
+
+/// Powers off with the exit code in the upper 16 bits.
+const FINISHER_FAIL: u32 = 0x3333;
+
+/// Resets the machine.
+const FINISHER_RESET: u32 = 0x7777;
+
+/// The SiFive test finisher. A 32-bit write at offset zero of one of the
+/// magic values in its low 16 bits requests that the emulator halt.
+/// Other writes are ignored, and reads return zero.
+#[derive(Debug, Default)]
+pub struct TestFinisher {
+    halt: Option<HaltReason>,
+}
+
+impl TestFinisher {
+    /// Creates a test finisher with no halt requested.
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl MmioDevice for TestFinisher {
+    fn read(&mut self, _offset: u32, _size: usize) -> Result<u64, DeviceError> {
+        Ok(0)
+    }
+
+    fn write(&mut self, offset: u32, size: usize, value: u64) -> Result<(), DeviceError> {
+        if offset != 0 || size != 4 {
+            return Ok(());
+        }
+
+        let value = value as u32;
+
+        self.halt = match value & 0xffff {
+            FINISHER_PASS => Some(HaltReason::Exit(0)),
+            FINISHER_FAIL => Some(HaltReason::Exit(value >> 16)),
+            FINISHER_RESET => Some(HaltReason::Reset),
+            _ => self.halt,
+        };
+
+        Ok(())
+    }
+
+    fn take_halt(&mut self) -> Option<HaltReason> {
+        self.halt.take()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{HaltReason, MmioDevice, TestFinisher};
+
+    #[test]
+    fn decodes_magic_values() {
+        let mut finisher = TestFinisher::new();
+
+        finisher.write(0, 4, 0x5555).unwrap();
+        assert_eq!(finisher.take_halt(), Some(HaltReason::Exit(0)));
+        assert_eq!(finisher.take_halt(), None);
+
+        finisher.write(0, 4, 0x0003_3333).unwrap();
+        assert_eq!(finisher.take_halt(), Some(HaltReason::Exit(3)));
+
+        finisher.write(0, 4, 0x7777).unwrap();
+        assert_eq!(finisher.take_halt(), Some(HaltReason::Reset));
+    }
+
+    #[test]
+    fn ignores_other_writes() {
+        let mut finisher = TestFinisher::new();
+        finisher.write(0, 4, 0x1234).unwrap();
+        finisher.write(4, 4, 0x5555).unwrap();
+        finisher.write(0, 2, 0x5555).unwrap();
+        assert_eq!(finisher.take_halt(), None);
+    }
+}
@@ -0,0 +1,104 @@
+//! Timing
+//! A rough model of instruction timing that gives each operation a nominal
+//! cycle cost by category. It's meant for experimenting with instruction
+//! mixes and estimating IPC, not for modelling a real microarchitecture.
+
+use crate::op::{Op, OpCategory};
+
+/// The number of cycles an operation in each category costs to execute,
+/// not counting cycles spent stalled on the bus.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct CostTable {
+    pub arithmetic: u64,
+    pub logical: u64,
+    pub shift: u64,
+    pub branch: u64,
+    pub jump: u64,
+    pub load: u64,
+    pub store: u64,
+    pub system: u64,
+    pub csr: u64,
+    pub multiply: u64,
+    pub divide: u64,
+}
+
+impl CostTable {
+    /// A table where every operation costs one cycle.
+    pub const fn uniform() -> Self {
+        Self {
+            arithmetic: 1,
+            logical: 1,
+            shift: 1,
+            branch: 1,
+            jump: 1,
+            load: 1,
+            store: 1,
+            system: 1,
+            csr: 1,
+            multiply: 1,
+            divide: 1,
+        }
+    }
+
+    /// A table loosely modelled on a simple in-order core, where memory
+    /// accesses and control flow cost more than ALU operations, and
+    /// multiplication and division cost more still.
+    pub const fn nominal() -> Self {
+        Self {
+            arithmetic: 1,
+            logical: 1,
+            shift: 1,
+            branch: 2,
+            jump: 2,
+            load: 3,
+            store: 2,
+            system: 1,
+            csr: 2,
+            multiply: 4,
+            divide: 32,
+        }
+    }
+
+    /// Returns the number of cycles the operation costs.
+    pub fn cost(&self, op: &Op) -> u64 {
+        match op.category() {
+            OpCategory::Arithmetic => self.arithmetic,
+            OpCategory::Logical => self.logical,
+            OpCategory::Shift => self.shift,
+            OpCategory::Branch => self.branch,
+            OpCategory::Jump => self.jump,
+            OpCategory::Load => self.load,
+            OpCategory::Store => self.store,
+            OpCategory::System => self.system,
+            OpCategory::Csr => self.csr,
+            OpCategory::Multiply => self.multiply,
+            OpCategory::Divide => self.divide,
+        }
+    }
+}
+
+impl Default for CostTable {
+    fn default() -> Self {
+        Self::uniform()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::op::Op::*;
+
+    use super::CostTable;
+
+    #[test]
+    fn costs_by_category() {
+        let table = CostTable::nominal();
+        assert_eq!(table.cost(&ArithmeticAdd), 1);
+        assert_eq!(table.cost(&LoadWord), 3);
+        assert_eq!(table.cost(&ArithmeticRemUnsigned), 32);
+    }
+
+    #[test]
+    fn default_is_uniform() {
+        assert_eq!(CostTable::default().cost(&ArithmeticDiv), 1);
+    }
+}
@@ -0,0 +1,189 @@
+//! Binary Trace
+//! A compact, fixed-size record of every step a processor takes, for
+//! tracing runs far too long to log as text. Traces can be re-expanded to
+//! text afterwards with `TraceReader` and `to_text`.
+//!
+//! Each record is `RECORD_SIZE` (13) bytes, with every field little-endian:
+//!
+//! | Offset | Size | Field                                                  |
+//! |--------|------|--------------------------------------------------------|
+//! | 0      | 4    | `pc` minus the previous record's `pc` (signed)          |
+//! | 4      | 4    | The raw instruction, or zero if none was fetched        |
+//! | 8      | 1    | The index of the register written, or zero if none was  |
+//! | 9      | 4    | The value written to the register, or zero              |
+//!
+//! The first record's `pc` delta is relative to address zero. As `x0` is
+//! never written, a register index of zero always means no write.
+
+use alloc::boxed::Box;
+use alloc::format;
+use alloc::string::String;
+
+use crate::decode::Decoder;
+use crate::disasm::{disassemble, disassemble_compressed};
+use crate::event_log::Event;
+use crate::instruction::Instruction;
+
+/// The size of a record in bytes.
+pub const RECORD_SIZE: usize = 13;
+
+/// Where encoded records are sent, such as a file.
+pub type TraceSink = Box<dyn FnMut(&[u8; RECORD_SIZE])>;
+
+/// Encodes events as records, tracking the `pc` of the previous one.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct TraceEncoder {
+    pc: u32,
+}
+
+impl TraceEncoder {
+    /// Creates an encoder for the start of a trace.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Encodes the next event in the trace.
+    pub fn encode(&mut self, event: &Event) -> [u8; RECORD_SIZE] {
+        let delta = event.pc.wrapping_sub(self.pc);
+        let (reg, value) = event.reg_write.unwrap_or((0, 0));
+        self.pc = event.pc;
+
+        let mut record = [0; RECORD_SIZE];
+        record[0 .. 4].copy_from_slice(&delta.to_le_bytes());
+        record[4 .. 8].copy_from_slice(&event.instr.unwrap_or(0).to_le_bytes());
+        record[8] = reg as u8;
+        record[9 .. 13].copy_from_slice(&value.to_le_bytes());
+        record
+    }
+}
+
+/// Decodes a trace back into events, in order. Trailing bytes that don't
+/// make up a whole record are ignored.
+///
+/// Memory writes and traps aren't part of the trace, so they're always
+/// `None` in the decoded events.
+#[derive(Clone, Debug)]
+pub struct TraceReader<'a> {
+    bytes: &'a [u8],
+    pc: u32,
+}
+
+impl<'a> TraceReader<'a> {
+    /// Creates a reader over an encoded trace.
+    pub fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes, pc: 0 }
+    }
+}
+
+impl Iterator for TraceReader<'_> {
+    type Item = Event;
+
+    fn next(&mut self) -> Option<Event> {
+        let (record, rest) = self.bytes.split_first_chunk::<RECORD_SIZE>()?;
+        self.bytes = rest;
+
+        let word = |offset: usize| u32::from_le_bytes([
+            record[offset],
+            record[offset + 1],
+            record[offset + 2],
+            record[offset + 3],
+        ]);
+
+        self.pc = self.pc.wrapping_add(word(0));
+
+        Some(Event {
+            pc: self.pc,
+            instr: Some(word(4)).filter(|&instr| instr != 0),
+            reg_write: Some((record[8] as usize, word(9))).filter(|&(reg, _)| reg != 0),
+            ..Event::default()
+        })
+    }
+}
+
+/// A binary trace being written to a sink.
+pub(crate) struct BinaryTrace {
+    pub(crate) encoder: TraceEncoder,
+    pub(crate) sink: TraceSink,
+}
+
+impl core::fmt::Debug for BinaryTrace {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("BinaryTrace")
+            .field("encoder", &self.encoder)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Returns a sink that writes records to a file through a buffer.
+#[cfg(feature = "std")]
+pub fn file_sink(file: std::fs::File) -> TraceSink {
+    use std::io::Write;
+
+    let mut writer = std::io::BufWriter::new(file);
+
+    Box::new(move |record| {
+        // Tracing is best-effort, as there's nowhere to report a failure.
+        let _ = writer.write_all(record);
+    })
+}
+
+/// Renders an event as a line of text: its address, the disassembled
+/// instruction, and the register written, if any.
+pub fn to_text(event: &Event) -> String {
+    let instr = match event.instr {
+        None => String::from("(no instruction)"),
+
+        // 32-bit instructions have the low two bits set.
+        Some(raw) if raw & 0x03 != 0x03 => disassemble_compressed(raw as u16)
+            .unwrap_or_else(|| format!("(compressed {raw:#06x})")),
+
+        Some(raw) => match Decoder::decode(&Instruction::new(raw)) {
+            Some(_) => disassemble(&Instruction::new(raw)),
+            None => format!("(illegal {raw:#010x})"),
+        },
+    };
+
+    match event.reg_write {
+        Some((reg, value)) => format!("{:#010x}: {instr:<32} x{reg} = {value:#010x}", event.pc),
+        None => format!("{:#010x}: {instr}", event.pc),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{to_text, Event, TraceEncoder, TraceReader, RECORD_SIZE};
+
+    #[test]
+    fn round_trips_events() {
+        let events = [
+            Event { pc: 0x100, instr: Some(0x00500093), reg_write: Some((1, 5)), ..Event::default() },
+            Event { pc: 0x104, instr: Some(0x0020a023), ..Event::default() },
+            Event { pc: 0x0fc, instr: Some(0xffdff06f), ..Event::default() },
+            Event { pc: 0x200, ..Event::default() },
+        ];
+
+        let mut encoder = TraceEncoder::new();
+        let bytes: Vec<u8> = events
+            .iter()
+            .flat_map(|event| encoder.encode(event))
+            .collect();
+
+        assert_eq!(bytes.len(), events.len() * RECORD_SIZE);
+        assert_eq!(TraceReader::new(&bytes).collect::<Vec<_>>(), events);
+    }
+
+    #[test]
+    fn renders_events_as_text() {
+        let event = Event {
+            pc: 0x100,
+            instr: Some(0x00500093),
+            reg_write: Some((1, 5)),
+            ..Event::default()
+        };
+
+        assert_eq!(
+            to_text(&event).split_whitespace().collect::<Vec<_>>(),
+            ["0x00000100:", "addi", "x1,", "x0,", "0x00000005", "x1", "=", "0x00000005"],
+        );
+    }
+}
@@ -0,0 +1,362 @@
+use core::fmt::Display;
+
+use crate::decode::Decoder;
+use crate::instruction::Instruction;
+use crate::isa::Extension;
+
+/// An asynchronous interrupt that can be pending on a processor.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Interrupt {
+    SupervisorSoftware,
+    MachineSoftware,
+    SupervisorTimer,
+    MachineTimer,
+    SupervisorExternal,
+    MachineExternal,
+}
+
+impl Interrupt {
+    /// All interrupts, in decreasing order of priority.
+    pub const PRIORITY: [Interrupt; 6] = [
+        Interrupt::MachineExternal,
+        Interrupt::MachineSoftware,
+        Interrupt::MachineTimer,
+        Interrupt::SupervisorExternal,
+        Interrupt::SupervisorSoftware,
+        Interrupt::SupervisorTimer,
+    ];
+
+    /// Returns the interrupt's exception code, which is also its bit
+    /// position in `mip` and `mie`.
+    pub fn code(&self) -> u32 {
+        match self {
+            Interrupt::SupervisorSoftware   => 1,
+            Interrupt::MachineSoftware      => 3,
+            Interrupt::SupervisorTimer      => 5,
+            Interrupt::MachineTimer         => 7,
+            Interrupt::SupervisorExternal   => 9,
+            Interrupt::MachineExternal      => 11,
+        }
+    }
+
+    /// Returns the interrupt's bit mask in `mip` and `mie`.
+    pub fn mask(&self) -> u32 {
+        1 << self.code()
+    }
+}
+
+impl Display for Interrupt {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                Interrupt::SupervisorSoftware   => "supervisor software interrupt",
+                Interrupt::MachineSoftware      => "machine software interrupt",
+                Interrupt::SupervisorTimer      => "supervisor timer interrupt",
+                Interrupt::MachineTimer         => "machine timer interrupt",
+                Interrupt::SupervisorExternal   => "supervisor external interrupt",
+                Interrupt::MachineExternal      => "machine external interrupt",
+            }
+        )
+    }
+}
+
+/// A trap raised while executing an instruction, or an interrupt taken
+/// between instructions.
+///
+/// Each exception carries the trap value that is written to `mtval` when
+/// the trap is taken, such as the faulting address or instruction.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Trap {
+    /// The target address of a fetch was not aligned to `IALIGN`.
+    InstructionAddressMisaligned(u32),
+
+    /// A fetch from the given address failed.
+    InstructionAccessFault(u32),
+
+    /// The given instruction is illegal or not supported.
+    IllegalInstruction(u32),
+
+    /// The given instruction belongs to an extension that isn't enabled.
+    /// This is reported to the guest as an illegal instruction.
+    UnsupportedExtension(Extension, u32),
+
+    /// An `ebreak` was executed at the given address.
+    Breakpoint(u32),
+
+    /// The given division or remainder instruction had a divisor of zero,
+    /// with the processor set to trap on it rather than return the result
+    /// the ISA defines. This is reported to the guest as an illegal
+    /// instruction, as no standard exception covers it.
+    DivideByZero(u32),
+
+    /// A load from the given address was misaligned.
+    LoadAddressMisaligned(u32),
+
+    /// A load from the given address failed.
+    LoadAccessFault(u32),
+
+    /// A store to the given address was misaligned.
+    StoreAddressMisaligned(u32),
+
+    /// A store to the given address failed.
+    StoreAccessFault(u32),
+
+    /// An `ecall` was executed from user mode.
+    EnvironmentCallFromUMode,
+
+    /// An `ecall` was executed from supervisor mode.
+    EnvironmentCallFromSMode,
+
+    /// An `ecall` was executed from machine mode.
+    EnvironmentCallFromMMode,
+
+    /// An enabled interrupt was pending.
+    Interrupt(Interrupt),
+}
+
+impl Trap {
+    /// Returns whether the trap is an interrupt rather than an exception.
+    pub fn is_interrupt(&self) -> bool {
+        matches!(self, Trap::Interrupt(_))
+    }
+
+    /// Returns the value written to `mcause` when the trap is taken. The
+    /// most significant bit is set for interrupts.
+    pub fn cause(&self) -> u32 {
+        match self {
+            Trap::InstructionAddressMisaligned(_)   => 0,
+            Trap::InstructionAccessFault(_)         => 1,
+            Trap::IllegalInstruction(_)             => 2,
+            Trap::UnsupportedExtension(_, _)        => 2,
+            Trap::Breakpoint(_)                     => 3,
+            Trap::DivideByZero(_)                   => 2,
+            Trap::LoadAddressMisaligned(_)          => 4,
+            Trap::LoadAccessFault(_)                => 5,
+            Trap::StoreAddressMisaligned(_)         => 6,
+            Trap::StoreAccessFault(_)               => 7,
+            Trap::EnvironmentCallFromUMode          => 8,
+            Trap::EnvironmentCallFromSMode          => 9,
+            Trap::EnvironmentCallFromMMode          => 11,
+            Trap::Interrupt(interrupt)              => 1 << 31 | interrupt.code(),
+        }
+    }
+
+    /// Returns the trap value written to `mtval` when the trap is taken.
+    pub fn value(&self) -> u32 {
+        match *self {
+            Trap::InstructionAddressMisaligned(value)
+                | Trap::InstructionAccessFault(value)
+                | Trap::IllegalInstruction(value)
+                | Trap::UnsupportedExtension(_, value)
+                | Trap::Breakpoint(value)
+                | Trap::DivideByZero(value)
+                | Trap::LoadAddressMisaligned(value)
+                | Trap::LoadAccessFault(value)
+                | Trap::StoreAddressMisaligned(value)
+                | Trap::StoreAccessFault(value) => value,
+
+            Trap::EnvironmentCallFromUMode
+                | Trap::EnvironmentCallFromSMode
+                | Trap::EnvironmentCallFromMMode
+                | Trap::Interrupt(_) => 0,
+        }
+    }
+}
+
+impl Display for Trap {
+    /// Renders the standard cause text followed by the trap value, if the
+    /// trap has one.
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match *self {
+            Trap::InstructionAddressMisaligned(addr) => {
+                write!(f, "instruction address misaligned at {addr:#010x}")
+            },
+
+            Trap::InstructionAccessFault(addr) => {
+                write!(f, "instruction access fault at {addr:#010x}")
+            },
+
+            Trap::IllegalInstruction(instr) => {
+                write!(f, "illegal instruction {instr:#010x}")
+            },
+
+            Trap::UnsupportedExtension(extension, instr) => {
+                match Decoder::decode(&Instruction::new(instr)) {
+                    Some(op) => write!(
+                        f,
+                        "unsupported extension: {extension} ({op} {instr:#010x})",
+                    ),
+
+                    None => write!(
+                        f,
+                        "unsupported extension: {extension} ({instr:#010x})",
+                    ),
+                }
+            },
+
+            Trap::Breakpoint(addr) => {
+                write!(f, "breakpoint at {addr:#010x}")
+            },
+
+            Trap::DivideByZero(instr) => {
+                match Decoder::decode(&Instruction::new(instr)) {
+                    Some(op) => write!(f, "divide by zero ({op} {instr:#010x})"),
+                    None => write!(f, "divide by zero ({instr:#010x})"),
+                }
+            },
+
+            Trap::LoadAddressMisaligned(addr) => {
+                write!(f, "load address misaligned at {addr:#010x}")
+            },
+
+            Trap::LoadAccessFault(addr) => {
+                write!(f, "load access fault at {addr:#010x}")
+            },
+
+            Trap::StoreAddressMisaligned(addr) => {
+                write!(f, "store address misaligned at {addr:#010x}")
+            },
+
+            Trap::StoreAccessFault(addr) => {
+                write!(f, "store access fault at {addr:#010x}")
+            },
+
+            Trap::EnvironmentCallFromUMode => {
+                write!(f, "environment call from U-mode")
+            },
+
+            Trap::EnvironmentCallFromSMode => {
+                write!(f, "environment call from S-mode")
+            },
+
+            Trap::EnvironmentCallFromMMode => {
+                write!(f, "environment call from M-mode")
+            },
+
+            Trap::Interrupt(interrupt) => write!(f, "{interrupt}"),
+        }
+    }
+}
+
+/// A record of the most recent trap taken by a processor.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct PendingTrap {
+    /// The trap that was raised.
+    pub trap: Trap,
+
+    /// The address of the instruction that raised the trap.
+    pub pc: u32,
+
+    /// The address of the handler the processor vectored to, or `None` if
+    /// no handler was installed in `mtvec`, or in `stvec` for a trap
+    /// delegated to S-mode.
+    pub handler: Option<u32>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Extension, Interrupt, Trap};
+
+    #[test]
+    fn illegal_instruction_has_cause_two() {
+        assert_eq!(
+            Trap::IllegalInstruction(0x00).cause(),
+            2,
+        );
+    }
+
+    #[test]
+    fn interrupt_cause_has_interrupt_bit_set() {
+        assert_eq!(
+            Trap::Interrupt(Interrupt::MachineTimer).cause(),
+            0x80000007,
+        );
+    }
+
+    #[test]
+    fn value_is_the_carried_payload() {
+        assert_eq!(
+            Trap::LoadAccessFault(0x1040).value(),
+            0x1040,
+        );
+    }
+
+    #[test]
+    fn environment_call_cause_depends_on_mode() {
+        assert_eq!(Trap::EnvironmentCallFromUMode.cause(), 8);
+        assert_eq!(Trap::EnvironmentCallFromSMode.cause(), 9);
+        assert_eq!(Trap::EnvironmentCallFromMMode.cause(), 11);
+    }
+
+    #[test]
+    fn divide_by_zero_is_reported_as_illegal_instruction() {
+        assert_eq!(Trap::DivideByZero(0x0220c1b3).cause(), 2);
+        assert_eq!(Trap::DivideByZero(0x0220c1b3).value(), 0x0220c1b3);
+    }
+
+    #[test]
+    fn environment_call_has_no_value() {
+        assert_eq!(
+            Trap::EnvironmentCallFromMMode.value(),
+            0,
+        );
+    }
+
+    mod display {
+        use super::*;
+
+        #[test]
+        fn renders_illegal_instruction() {
+            assert_eq!(
+                Trap::IllegalInstruction(0x02000033).to_string(),
+                "illegal instruction 0x02000033",
+            );
+        }
+
+        #[test]
+        fn renders_unsupported_extension() {
+            assert_eq!(
+                Trap::UnsupportedExtension(Extension::M, 0x027302b3).to_string(),
+                "unsupported extension: M (mul 0x027302b3)",
+            );
+            assert_eq!(
+                Trap::UnsupportedExtension(Extension::A, 0x003120af).to_string(),
+                "unsupported extension: A (0x003120af)",
+            );
+        }
+
+        #[test]
+        fn renders_divide_by_zero() {
+            assert_eq!(
+                Trap::DivideByZero(0x0220c1b3).to_string(),
+                "divide by zero (div 0x0220c1b3)",
+            );
+        }
+
+        #[test]
+        fn renders_load_address_misaligned() {
+            assert_eq!(
+                Trap::LoadAddressMisaligned(0x1041).to_string(),
+                "load address misaligned at 0x00001041",
+            );
+        }
+
+        #[test]
+        fn renders_environment_call() {
+            assert_eq!(
+                Trap::EnvironmentCallFromMMode.to_string(),
+                "environment call from M-mode",
+            );
+        }
+
+        #[test]
+        fn renders_interrupt() {
+            assert_eq!(
+                Trap::Interrupt(Interrupt::MachineTimer).to_string(),
+                "machine timer interrupt",
+            );
+        }
+    }
+}
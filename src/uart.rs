@@ -0,0 +1,251 @@
+//! UART
+//! A minimal NS16550A-compatible UART, the serial port of QEMU's `virt`
+//! machine. Bytes written to the transmit register go to an output sink,
+//! and the receive register reads bytes from an input source, so guest
+//! programs can interact with the host.
+
+use alloc::boxed::Box;
+
+use crate::bus::{DeviceError, MmioDevice};
+
+/// The base address the UART is conventionally mapped at.
+pub const UART_BASE: u32 = 0x1000_0000;
+
+/// The size of the UART's address range in bytes.
+pub const UART_SIZE: u32 = 0x100;
+
+/// The receive buffer register (RBR) when read, and the transmit holding
+/// register (THR) when written.
+const RBR_THR: u32 = 0;
+
+/// The line status register (LSR).
+const LSR: u32 = 5;
+
+/// LSR bits: a received byte is waiting in RBR, THR can take a byte, and
+/// the transmitter is idle.
+const LSR_DATA_READY: u64 = 0x01;
+const LSR_THR_EMPTY: u64 = 0x20;
+const LSR_TRANSMITTER_EMPTY: u64 = 0x40;
+
+/// Where the UART receives bytes from. Returns the next byte, or None if
+/// no data is available.
+pub type UartInput = Box<dyn FnMut() -> Option<u8>>;
+
+/// Where the UART sends transmitted bytes.
+pub type UartOutput = Box<dyn FnMut(u8)>;
+
+/// An NS16550A-compatible UART. Only the receive, transmit and line status
+/// registers are implemented. The others read as zero and ignore writes,
+/// and the divisor latch is never selected.
+pub struct Uart {
+    input: UartInput,
+    output: UartOutput,
+
+    /// The byte taken from the input but not yet read from RBR.
+    received: Option<u8>,
+}
+
+impl Uart {
+    /// Creates a UART that receives from `input` and transmits to `output`.
+    pub fn new(input: UartInput, output: UartOutput) -> Self {
+        Self {
+            input,
+            output,
+            received: None,
+        }
+    }
+
+    /// Creates a UART that receives from the host's stdin and transmits to
+    /// its stdout. Stdin is read on a background thread, so polling the
+    /// line status never blocks the guest waiting for the host to type.
+    #[cfg(feature = "std")]
+    pub fn stdio() -> Self {
+        Self::new(threaded_input(std::io::stdin()), stdout_output())
+    }
+
+    /// Returns the byte waiting in RBR, taking one from the input if there
+    /// isn't one.
+    fn poll(&mut self) -> Option<u8> {
+        if self.received.is_none() {
+            self.received = (self.input)();
+        }
+
+        self.received
+    }
+}
+
+impl MmioDevice for Uart {
+    fn read(&mut self, offset: u32, _size: usize) -> Result<u64, DeviceError> {
+        Ok(
+            match offset {
+                RBR_THR => {
+                    self.poll();
+                    self.received.take().unwrap_or(0) as u64
+                },
+
+                LSR => {
+                    let ready = match self.poll() {
+                        Some(_) => LSR_DATA_READY,
+                        None => 0,
+                    };
+
+                    ready | LSR_THR_EMPTY | LSR_TRANSMITTER_EMPTY
+                },
+
+                _ => 0,
+            }
+        )
+    }
+
+    fn write(&mut self, offset: u32, _size: usize, value: u64) -> Result<(), DeviceError> {
+        if offset == RBR_THR {
+            (self.output)(value as u8);
+        }
+
+        Ok(())
+    }
+}
+
+impl core::fmt::Debug for Uart {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("Uart")
+            .field("received", &self.received)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Returns an input that reads bytes from `reader` one at a time. The end
+/// of the reader, or a failed read, means no data is available.
+#[cfg(feature = "std")]
+pub fn reader_input(mut reader: impl std::io::Read + 'static) -> UartInput {
+    Box::new(move || {
+        let mut byte = [0];
+
+        match reader.read(&mut byte) {
+            Ok(1) => Some(byte[0]),
+            _ => None,
+        }
+    })
+}
+
+/// Returns an output that writes each byte to the host's stdout.
+#[cfg(feature = "std")]
+pub fn stdout_output() -> UartOutput {
+    use std::io::Write;
+
+    Box::new(|byte| {
+        // Output is best-effort, as there's nowhere to report a failure.
+        let mut stdout = std::io::stdout();
+        let _ = stdout.write_all(&[byte]).and_then(|()| stdout.flush());
+    })
+}
+
+/// Returns an input that reads bytes from `reader` on a background thread,
+/// so that it never blocks: if no byte has arrived yet, no data is
+/// available. The thread stops at the end of the reader, or a failed read.
+///
+/// The thread only starts when the guest first polls the UART, so guests
+/// that never use it leave the reader to others, such as `read` system
+/// calls on stdin.
+#[cfg(feature = "std")]
+pub fn threaded_input(reader: impl std::io::Read + Send + 'static) -> UartInput {
+    let mut reader = Some(reader);
+    let mut receiver = None;
+
+    Box::new(move || {
+        let receiver = receiver.get_or_insert_with(|| {
+            let (sender, receiver) = std::sync::mpsc::channel();
+            let mut reader = reader.take().expect("reader is only taken once");
+
+            std::thread::spawn(move || {
+                let mut byte = [0];
+
+                while let Ok(1) = reader.read(&mut byte) {
+                    if sender.send(byte[0]).is_err() {
+                        break;
+                    }
+                }
+            });
+
+            receiver
+        });
+
+        receiver.try_recv().ok()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::io::{Cursor, Write};
+    use std::rc::Rc;
+
+    use super::{
+        reader_input,
+        threaded_input,
+        MmioDevice,
+        Uart,
+        LSR,
+        LSR_DATA_READY,
+        RBR_THR,
+    };
+
+    fn uart(input: &[u8]) -> (Uart, Rc<RefCell<Vec<u8>>>) {
+        let output = Rc::new(RefCell::new(Vec::new()));
+        let sink = output.clone();
+
+        let uart = Uart::new(
+            reader_input(Cursor::new(input.to_vec())),
+            Box::new(move |byte| sink.borrow_mut().push(byte)),
+        );
+
+        (uart, output)
+    }
+
+    #[test]
+    fn receives_bytes_in_order() {
+        let (mut uart, _) = uart(b"hi");
+
+        assert_eq!(uart.read(RBR_THR, 1), Ok(b'h' as u64));
+        assert_eq!(uart.read(RBR_THR, 1), Ok(b'i' as u64));
+    }
+
+    #[test]
+    fn line_status_reports_when_data_is_ready() {
+        let (mut uart, _) = uart(b"x");
+
+        assert_ne!(uart.read(LSR, 1).unwrap() & LSR_DATA_READY, 0);
+        assert_eq!(uart.read(RBR_THR, 1), Ok(b'x' as u64));
+        assert_eq!(uart.read(LSR, 1).unwrap() & LSR_DATA_READY, 0);
+        assert_eq!(uart.read(RBR_THR, 1), Ok(0));
+    }
+
+    #[test]
+    fn threaded_input_does_not_block_without_data() {
+        let (reader, mut writer) = std::io::pipe().unwrap();
+        let mut uart = Uart::new(threaded_input(reader), Box::new(|_| ()));
+
+        // Nothing has been written, so data isn't ready, rather than the
+        // read blocking until it is.
+        assert_eq!(uart.read(LSR, 1).unwrap() & LSR_DATA_READY, 0);
+
+        writer.write_all(b"z").unwrap();
+
+        while uart.read(LSR, 1).unwrap() & LSR_DATA_READY == 0 {
+            std::thread::yield_now();
+        }
+
+        assert_eq!(uart.read(RBR_THR, 1), Ok(b'z' as u64));
+    }
+
+    #[test]
+    fn transmits_written_bytes() {
+        let (mut uart, output) = uart(b"");
+
+        uart.write(RBR_THR, 1, b'o' as u64).unwrap();
+        uart.write(LSR, 1, b'x' as u64).unwrap();
+        uart.write(RBR_THR, 1, b'k' as u64).unwrap();
+
+        assert_eq!(*output.borrow(), b"ok");
+    }
+}
@@ -0,0 +1,127 @@
+//! Undo
+//! Records what each step is about to overwrite, so the most recent steps
+//! can be undone, as a debugger's "step back" does. This is much lighter
+//! than snapshotting the whole machine, as a step writes at most one
+//! register and one memory location.
+//!
+//! CSRs aren't recorded, so undoing a step that wrote a CSR or took a
+//! trap leaves the CSRs as they are. Stores to devices can't be undone
+//! either, as reading a device's old value may have side effects.
+
+use alloc::collections::VecDeque;
+
+use crate::processor::{HartState, PrivilegeMode};
+
+/// The state a single step overwrote.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct UndoRecord {
+    /// The value of `pc` before the step.
+    pub pc: u32,
+
+    /// The privilege level before the step.
+    pub privilege: PrivilegeMode,
+
+    /// The hart state before the step.
+    pub state: HartState,
+
+    /// The cycle and retired instruction counts before the step.
+    pub cycle: u64,
+    pub instret: u64,
+
+    /// The register the step wrote and its old value, if any.
+    pub reg: Option<(usize, u32)>,
+
+    /// The address, size in bytes and old bytes of the memory the step
+    /// stored to, if any.
+    pub mem: Option<(u32, usize, [u8; 4])>,
+}
+
+/// A fixed-depth history of steps that can be undone. Once full,
+/// recording a step discards the oldest one.
+#[derive(Clone, Debug)]
+pub struct UndoLog {
+    records: VecDeque<UndoRecord>,
+    depth: usize,
+}
+
+impl UndoLog {
+    /// Creates an empty log that can undo up to `depth` steps.
+    pub fn new(depth: usize) -> Self {
+        Self {
+            records: VecDeque::with_capacity(depth),
+            depth,
+        }
+    }
+
+    /// Records a step, discarding the oldest one if the log is full.
+    pub fn push(&mut self, record: UndoRecord) {
+        if self.depth == 0 {
+            return;
+        }
+
+        if self.records.len() == self.depth {
+            self.records.pop_front();
+        }
+
+        self.records.push_back(record);
+    }
+
+    /// Removes and returns the most recent step, if any.
+    pub fn pop(&mut self) -> Option<UndoRecord> {
+        self.records.pop_back()
+    }
+
+    /// Returns the number of steps that can be undone.
+    pub fn len(&self) -> usize {
+        self.records.len()
+    }
+
+    /// Returns whether there are no steps to undo.
+    pub fn is_empty(&self) -> bool {
+        self.records.is_empty()
+    }
+
+    /// Discards every recorded step.
+    pub fn clear(&mut self) {
+        self.records.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{HartState, PrivilegeMode, UndoLog, UndoRecord};
+
+    fn record(pc: u32) -> UndoRecord {
+        UndoRecord {
+            pc,
+            privilege: PrivilegeMode::Machine,
+            state: HartState::Running,
+            cycle: 0,
+            instret: 0,
+            reg: None,
+            mem: None,
+        }
+    }
+
+    #[test]
+    fn pops_most_recent_first_and_discards_oldest() {
+        let mut log = UndoLog::new(2);
+
+        for pc in [0x00, 0x04, 0x08] {
+            log.push(record(pc));
+        }
+
+        assert_eq!(log.len(), 2);
+        assert_eq!(log.pop().map(|record| record.pc), Some(0x08));
+        assert_eq!(log.pop().map(|record| record.pc), Some(0x04));
+        assert_eq!(log.pop(), None);
+    }
+
+    #[test]
+    fn zero_depth_records_nothing() {
+        let mut log = UndoLog::new(0);
+        log.push(record(0x00));
+
+        assert!(log.is_empty());
+    }
+}
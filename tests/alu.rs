@@ -0,0 +1,70 @@
+use std::cell::Cell;
+use std::rc::Rc;
+
+use riscv_emulator::alu::{Alu, AluOps};
+use riscv_emulator::bus::Bus;
+use riscv_emulator::memory::Memory;
+use riscv_emulator::op::Op;
+use riscv_emulator::processor::Processor;
+
+/// An ALU that counts its operations.
+#[derive(Debug)]
+struct CountingAlu {
+    runs: Rc<Cell<usize>>,
+}
+
+impl AluOps for CountingAlu {
+    fn run(&mut self, op: &Op, x: i32, y: i32) -> i32 {
+        self.runs.set(self.runs.get() + 1);
+        Alu::new().run(op, x, y)
+    }
+}
+
+/// Runs `program` to its end on a processor with a `CountingAlu`,
+/// returning the processor and the number of ALU operations.
+fn run_counting(program: &[u32]) -> (Processor, usize) {
+    let mut mem = Memory::new(1024);
+
+    for (i, instr) in program.iter().enumerate() {
+        mem.write(i * 4, &instr.to_le_bytes());
+    }
+
+    let mut bus = Bus::new(mem);
+    let mut proc = Processor::new();
+
+    let runs = Rc::new(Cell::new(0));
+    proc.alu = Box::new(CountingAlu { runs: runs.clone() });
+
+    for _ in 0 .. program.len() {
+        proc.step(&mut bus);
+    }
+
+    (proc, runs.get())
+}
+
+#[test]
+fn processor_uses_plugged_in_alu() {
+    let (proc, runs) = run_counting(&[
+        0x00500093, // addi x1, x0, 5
+        0x00300113, // addi x2, x0, 3
+        0x002081b3, // add x3, x1, x2
+        0x40208233, // sub x4, x1, x2
+    ]);
+
+    // The addis and add take the fast path, which uses the ALU too.
+    assert_eq!(runs, 4);
+    assert_eq!(proc.reg_x.read(3), 8);
+    assert_eq!(proc.reg_x.read(4), 2);
+}
+
+#[test]
+fn fast_path_branches_use_plugged_in_alu() {
+    let (proc, runs) = run_counting(&[
+        0x00500093, // addi x1, x0, 5
+        0x00008463, // beq x1, x0, 8
+        0x0000c463, // blt x1, x0, 8
+    ]);
+
+    assert_eq!(runs, 3);
+    assert_eq!(proc.pc, 0x0c);
+}